@@ -2,7 +2,33 @@
 
 mod utils;
 use tauri::Manager;
-use utils::api::{delete_path_contents, get_app_version, start_tts, send_api_request};
+use utils::anki::export_vocabulary;
+use utils::api::{get_app_version, start_tts, send_api_request};
+use utils::audio_cache::get_word_audio;
+use utils::backup::{
+    create_backup, get_backup_settings, list_backups, restore_backup, set_backup_settings, Backups,
+};
+use utils::i18n::set_locale;
+use utils::lesson::{delete_lesson, save_lesson_words};
+use utils::logging::get_recent_logs;
+use utils::package::{export_lessons, import_lessons};
+use utils::text_import::parse_lesson_file;
+use utils::storage::{delete_path_contents, get_data_root, move_data_root, restore_trash_item, DataDir};
+use utils::secrets::{
+    delete_provider_key, get_secret_status, reset_secrets, set_provider_key, set_secret_passphrase, unlock_secrets,
+    SecretStore,
+};
+use utils::ai::models::ModelCache;
+use utils::ai::jobs::{AnnotationJobs, RateLimiter};
+use utils::ai::{
+    ai_chat, analyze_sentence, annotate_words, cancel_annotation_job, clear_annotation_cache,
+    continue_chat_session, dismiss_legacy_prompt, estimate_ai_cost, get_ai_prices, get_ai_usage,
+    get_annotation_cache, get_chat_session, get_failover_chain, get_prompt_templates,
+    get_sentence_analyses, list_chat_sessions, list_models, parse_annotation_reply, render_prompt,
+    save_prompt_template, set_ai_prices, set_annotation_cache_limit, set_failover_chain,
+    set_local_ai_endpoint, set_native_language, start_annotation_job, start_chat_session,
+};
+use utils::db::Database;
 
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -14,6 +40,59 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .setup(|app| {
+            // 日志初始化失败不影响程序运行，改为输出到标准错误
+            if let Err(e) = utils::logging::init(app.path().app_log_dir()?) {
+                utils::logging::init_stderr();
+                tracing::error!(error = %e, "日志文件初始化失败");
+            }
+
+            // 加密文件损坏时以锁定状态启动，错误通过 get_secret_status 告知前端
+            let secrets_dir = app.path().app_data_dir()?;
+            let secrets = SecretStore::open(secrets_dir.clone()).unwrap_or_else(|e| {
+                tracing::error!(error = %e, "打开密钥存储失败");
+                SecretStore::locked(secrets_dir, &e)
+            });
+            // 与前端 tauri-plugin-sql 使用同一个数据库文件，数据表在这里创建和升级，必须早于前端连接数据库
+            let db = Database::open(app.path().app_config_dir()?)?;
+            utils::ai::provider::load_local_endpoint(&db)?;
+            if let Err(e) = secrets.migrate_legacy_keys(&db) {
+                tracing::error!(error = %e, "移动旧的明文密钥失败，下次启动时继续");
+            }
+            if let Err(e) = utils::ai::template::migrate_legacy_prompts(&db) {
+                tracing::error!(error = %e, "迁移旧的自定义提示词失败，下次启动时继续");
+            }
+            app.manage(secrets);
+            // 课文的音频和字幕默认保存在 app 数据目录下，旧版本保存在资源目录下的继续沿用
+            let data_dir = DataDir::load(
+                &db,
+                app.path().app_data_dir()?.join("datas"),
+                app.path().resource_dir()?.join("datas"),
+            )?;
+            utils::storage::allow_frontend_access(app.handle(), &data_dir.root())?;
+            app.manage(db);
+            app.manage(data_dir);
+            app.manage(Backups::open(app.path().app_data_dir()?.join("backups"))?);
+            utils::backup::spawn_scheduler(app.handle().clone());
+            // 旧版本存在数据库中的单词音频移到缓存文件夹，需要数据目录，所以不放在数据库升级中
+            utils::audio_cache::spawn_legacy_migration(app.handle().clone());
+            app.manage(ModelCache::default());
+            app.manage(RateLimiter::default());
+            app.manage(AnnotationJobs::default());
+
+            // 调试时设置环境变量 LEARN_LANGUAGE_AI_MOCK 后，所有AI请求都发到本地的模拟服务，
+            // 值为JSON文件路径时从该文件读取预设响应
+            #[cfg(debug_assertions)]
+            if let Ok(script) = std::env::var("LEARN_LANGUAGE_AI_MOCK") {
+                let server = tauri::async_runtime::block_on(utils::ai::mock::MockServer::start())?;
+                let script = std::path::Path::new(&script);
+                if script.is_file() {
+                    let count = server.load_script(script)?;
+                    tracing::info!(count, script = %script.display(), "已读取模拟AI服务的预设响应");
+                }
+                utils::ai::provider::set_base_url_override(Some(server.url()));
+                app.manage(server);
+            }
+
             #[cfg(debug_assertions)]
             {
                 let window = app.get_webview_window("main").unwrap();
@@ -30,7 +109,57 @@ pub fn run() {
             start_tts,
             get_app_version,
             delete_path_contents,
-            send_api_request
+            send_api_request,
+            set_locale,
+            get_recent_logs,
+            set_provider_key,
+            delete_provider_key,
+            get_secret_status,
+            unlock_secrets,
+            set_secret_passphrase,
+            reset_secrets,
+            ai_chat,
+            list_models,
+            annotate_words,
+            start_annotation_job,
+            cancel_annotation_job,
+            set_local_ai_endpoint,
+            estimate_ai_cost,
+            get_ai_usage,
+            get_ai_prices,
+            set_ai_prices,
+            get_annotation_cache,
+            clear_annotation_cache,
+            set_annotation_cache_limit,
+            start_chat_session,
+            continue_chat_session,
+            list_chat_sessions,
+            get_chat_session,
+            analyze_sentence,
+            get_sentence_analyses,
+            get_prompt_templates,
+            save_prompt_template,
+            dismiss_legacy_prompt,
+            set_native_language,
+            render_prompt,
+            parse_annotation_reply,
+            get_failover_chain,
+            set_failover_chain,
+            save_lesson_words,
+            delete_lesson,
+            restore_trash_item,
+            get_data_root,
+            move_data_root,
+            export_lessons,
+            import_lessons,
+            create_backup,
+            list_backups,
+            restore_backup,
+            get_backup_settings,
+            set_backup_settings,
+            get_word_audio,
+            export_vocabulary,
+            parse_lesson_file
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
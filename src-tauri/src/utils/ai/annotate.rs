@@ -0,0 +1,377 @@
+use crate::utils::ai::failover::{chat_with_failover, Route};
+use crate::utils::ai::{parse_json_output, AiContext};
+use crate::utils::ai::provider::{ChatMessage, Provider};
+use crate::utils::ai::template::{context_vars, PromptKind, Template};
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::{debug, warn};
+
+/// 单词词性，取值与前端 `oartOfSpeech` 使用的词性代码一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartOfSpeech {
+    Noun,
+    Numeral,
+    MeasureWord,
+    Verb,
+    Adjective,
+    DistinguishingWord,
+    Adverb,
+    Conjunction,
+    Preposition,
+    Auxiliary,
+    ModalParticle,
+    Phrase,
+    SentenceFragment,
+    Pronoun,
+    Interjection,
+    Onomatopoeia,
+    Morpheme,
+    Other,
+}
+
+impl PartOfSpeech {
+    pub const ALL: [&'static str; 18] = [
+        "noun",
+        "numeral",
+        "measure_word",
+        "verb",
+        "adjective",
+        "distinguishing_word",
+        "adverb",
+        "conjunction",
+        "preposition",
+        "auxiliary",
+        "modal_particle",
+        "phrase",
+        "sentence_fragment",
+        "pronoun",
+        "interjection",
+        "onomatopoeia",
+        "morpheme",
+        "other",
+    ];
+
+    /// 宽松解析，兼容大小写、空格和连字符，例如 `Measure Word`、`measure-word`
+    fn parse(text: &str) -> Option<Self> {
+        let normalized = text.trim().to_ascii_lowercase().replace([' ', '-'], "_");
+        serde_json::from_value(json!(normalized)).ok()
+    }
+}
+
+/// 单词的标注结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordAnnotation {
+    /// 单词
+    pub content: String,
+    /// 词性，至少一个
+    pub part_of_speech: Vec<PartOfSpeech>,
+    /// 读音
+    pub pronunciation: String,
+    /// 释义
+    pub interpretation: String,
+    /// 附加说明
+    pub notes: String,
+    /// 适用性，0只适用本课，1适用本语言全部课文
+    pub applicability: u8,
+}
+
+/// 单个单词的标注结果，成功时 `annotation` 有值，失败时 `error` 有值
+#[derive(Debug, Clone, Serialize)]
+pub struct AnnotationOutcome {
+    pub word: String,
+    pub ok: bool,
+    pub annotation: Option<WordAnnotation>,
+    pub error: Option<String>,
+    /// 是否来自标注缓存
+    pub cached: bool,
+    /// 给出结果的平台和模型，首选平台故障时可能来自备用链，失败时为空
+    pub answered_by: Option<Route>,
+}
+
+impl AnnotationOutcome {
+    fn success(word: String, annotation: WordAnnotation, route: Route) -> Self {
+        Self {
+            word,
+            ok: true,
+            annotation: Some(annotation),
+            error: None,
+            cached: false,
+            answered_by: Some(route),
+        }
+    }
+
+    pub(crate) fn from_cache(word: String, annotation: WordAnnotation, route: Route) -> Self {
+        Self {
+            cached: true,
+            ..Self::success(word, annotation, route)
+        }
+    }
+
+    pub(crate) fn failure(word: String, error: String) -> Self {
+        Self {
+            word,
+            ok: false,
+            annotation: None,
+            error: Some(error),
+            cached: false,
+            answered_by: None,
+        }
+    }
+}
+
+/// `annotate_batch` 的结果，请求中途失败时保留已经得到的结果
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// 已有结论的单词（标注成功或校验失败），顺序与请求的单词一致
+    pub outcomes: Vec<AnnotationOutcome>,
+    /// 请求失败时尚未得到结论的单词，以及失败的原因
+    pub interrupted: Option<(Vec<String>, AppError)>,
+}
+
+impl BatchOutcome {
+    /// 把尚未得到结论的单词记为失败，合并成完整的结果
+    pub fn into_outcomes(self) -> Vec<AnnotationOutcome> {
+        let mut outcomes = self.outcomes;
+        if let Some((pending, error)) = self.interrupted {
+            let message = error.to_string();
+            outcomes.extend(pending.into_iter().map(|word| AnnotationOutcome::failure(word, message.clone())));
+        }
+        outcomes
+    }
+}
+
+/// Gemini `responseSchema` 使用的结构描述
+pub fn annotation_schema() -> Value {
+    json!({
+        "type": "OBJECT",
+        "properties": {
+            "words": {
+                "type": "ARRAY",
+                "items": {
+                    "type": "OBJECT",
+                    "properties": {
+                        "content": {"type": "STRING"},
+                        "part_of_speech": {
+                            "type": "ARRAY",
+                            "items": {"type": "STRING", "enum": PartOfSpeech::ALL},
+                        },
+                        "pronunciation": {"type": "STRING"},
+                        "interpretation": {"type": "STRING"},
+                        "notes": {"type": "STRING"},
+                        "applicability": {"type": "INTEGER", "enum": [0, 1]},
+                    },
+                    "required": [
+                        "content", "part_of_speech", "pronunciation",
+                        "interpretation", "notes", "applicability"
+                    ],
+                },
+            },
+        },
+        "required": ["words"],
+    })
+}
+
+/// 标注提示词，模板和变量只准备一次，每批单词单独渲染
+pub struct AnnotationPrompt {
+    template: Template,
+    vars: Map<String, Value>,
+}
+
+impl AnnotationPrompt {
+    /// 读取当前的标注模板和课文相关的变量
+    pub fn load(db: &Database, lesson_id: Option<i64>) -> Result<Self, AppError> {
+        Ok(Self {
+            template: PromptKind::Annotation.load(db)?,
+            vars: context_vars(db, lesson_id)?,
+        })
+    }
+
+    pub fn render(&self, words: &[String]) -> String {
+        let mut vars = self.vars.clone();
+        vars.insert("words".into(), json!(words));
+        self.template.render(&vars)
+    }
+}
+
+/// 批量标注单词
+///
+/// 要求模型输出JSON，逐项校验为 `WordAnnotation`。能修正的格式问题（词性写成字符串、适用性写成字符串等）直接修正；
+/// 无法修正或模型漏掉的单词会单独重试，超过 `max_retries` 次后记为失败。平台故障时按备用链切换平台。
+/// 请求失败（包括重试时失败）不会丢弃已经得到的结果，尚未得到结论的单词放在 `interrupted` 中。
+///
+/// # Arguments
+///
+/// * `ctx` - 密钥存储、数据库和所属课文
+/// * `provider` - AI平台
+/// * `model` - 模型名称
+/// * `words` - 需要标注的单词
+/// * `proxy` - 可选的代理地址
+/// * `max_retries` - 失败单词的最大重试次数
+///
+/// # Returns
+///
+/// * 成功返回 `BatchOutcome`，请求失败（网络、密钥等）时 `interrupted` 中为尚未得到结论的单词
+/// * 读取提示词失败返回 `Err(AppError)`
+pub async fn annotate_batch(
+    ctx: AiContext<'_>,
+    provider: Provider,
+    model: &str,
+    words: &[String],
+    proxy: Option<&str>,
+    max_retries: u32,
+) -> Result<BatchOutcome, AppError> {
+    let schema = annotation_schema();
+    let prompt = AnnotationPrompt::load(ctx.db, ctx.lesson_id)?;
+    let mut outcomes: Vec<Option<AnnotationOutcome>> = vec![None; words.len()];
+    let mut pending: Vec<usize> = (0..words.len()).collect();
+    let mut attempt = 0;
+    let mut interrupted = None;
+
+    while !pending.is_empty() {
+        let batch: Vec<String> = pending.iter().map(|&i| words[i].clone()).collect();
+        let messages = [ChatMessage::user(prompt.render(&batch))];
+        let reply = match chat_with_failover(ctx, provider, model, &messages, Some(&schema), proxy).await {
+            Ok(reply) => reply,
+            Err(error) => {
+                warn!(attempt, pending = batch.len(), error = %error, "标注请求失败，保留已有结果");
+                interrupted = Some((batch, error));
+                break;
+            }
+        };
+        let items = extract_items(&reply.text);
+        debug!(attempt, requested = batch.len(), returned = items.len(), "解析标注结果");
+
+        let mut still_pending = Vec::new();
+        for &index in &pending {
+            let word = &words[index];
+            let error = match find_item(&items, word) {
+                Some(item) => match repair(item, word) {
+                    Ok(annotation) => {
+                        outcomes[index] = Some(AnnotationOutcome::success(word.clone(), annotation, reply.route.clone()));
+                        continue;
+                    }
+                    Err(error) => error,
+                },
+                None => Msg::new("annotate.missing_word"),
+            };
+
+            if attempt < max_retries {
+                still_pending.push(index);
+            } else {
+                let error = error.render();
+                warn!(word = %word, error = %error, "单词标注失败");
+                outcomes[index] = Some(AnnotationOutcome::failure(word.clone(), error));
+            }
+        }
+
+        pending = still_pending;
+        attempt += 1;
+    }
+
+    Ok(BatchOutcome {
+        outcomes: outcomes.into_iter().flatten().collect(),
+        interrupted,
+    })
+}
+
+/// 解析用户从AI网页复制回来的标注结果（半自动标注），校验和修正方式与 `annotate_batch` 相同
+///
+/// # Returns
+///
+/// * 每个单词的结果，顺序与 `words` 一致，`answered_by` 为空
+pub fn parse_reply(text: &str, words: &[String]) -> Vec<AnnotationOutcome> {
+    let items = extract_items(text);
+    words
+        .iter()
+        .map(|word| {
+            let result = find_item(&items, word)
+                .ok_or_else(|| Msg::new("annotate.missing_word"))
+                .and_then(|item| repair(item, word));
+            match result {
+                Ok(annotation) => AnnotationOutcome {
+                    word: word.clone(),
+                    ok: true,
+                    annotation: Some(annotation),
+                    error: None,
+                    cached: false,
+                    answered_by: None,
+                },
+                Err(error) => AnnotationOutcome::failure(word.clone(), error.render()),
+            }
+        })
+        .collect()
+}
+
+/// 从模型输出中取出标注数组，兼容 ```json 代码块、顶层数组和 `{"words": [...]}`
+fn extract_items(text: &str) -> Vec<Value> {
+    match parse_json_output(text) {
+        Ok(Value::Array(items)) => items,
+        Ok(value) => value["words"].as_array().cloned().unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn find_item<'a>(items: &'a [Value], word: &str) -> Option<&'a Value> {
+    let content = |item: &Value| item["content"].as_str().unwrap_or("").trim().to_string();
+    items
+        .iter()
+        .find(|item| content(item) == word)
+        .or_else(|| items.iter().find(|item| content(item).to_lowercase() == word.to_lowercase()))
+}
+
+/// 把模型返回的一项修正为 `WordAnnotation`
+fn repair(item: &Value, word: &str) -> Result<WordAnnotation, Msg> {
+    let text = |key: &str| match &item[key] {
+        Value::String(s) => s.trim().to_string(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+
+    // 词性可能是数组，也可能是用 / , + 分隔的字符串
+    let raw_parts: Vec<String> = match &item["part_of_speech"] {
+        Value::Array(parts) => parts.iter().filter_map(|p| p.as_str().map(str::to_string)).collect(),
+        Value::String(s) => s.split(['/', ',', '+']).map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+    let mut part_of_speech = Vec::new();
+    for raw in raw_parts.iter().filter(|p| !p.trim().is_empty()) {
+        match PartOfSpeech::parse(raw) {
+            Some(part) if !part_of_speech.contains(&part) => part_of_speech.push(part),
+            Some(_) => {}
+            None => return Err(Msg::new("annotate.unknown_part_of_speech").arg("value", raw)),
+        }
+    }
+    if part_of_speech.is_empty() {
+        return Err(Msg::new("annotate.missing_part_of_speech"));
+    }
+
+    let applicability = match &item["applicability"] {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.trim().parse::<u64>().ok(),
+        Value::Bool(b) => Some(*b as u64),
+        _ => None,
+    };
+    let applicability = match applicability {
+        Some(0) => 0,
+        Some(1) => 1,
+        _ => return Err(Msg::new("annotate.invalid_applicability")),
+    };
+
+    let interpretation = text("interpretation");
+    if interpretation.is_empty() {
+        return Err(Msg::new("annotate.missing_interpretation"));
+    }
+
+    Ok(WordAnnotation {
+        content: word.to_string(),
+        part_of_speech,
+        pronunciation: text("pronunciation"),
+        interpretation,
+        notes: text("notes"),
+        applicability,
+    })
+}
@@ -0,0 +1,320 @@
+use crate::utils::ai::annotate::{annotate_batch, annotation_schema, AnnotationOutcome, WordAnnotation};
+use crate::utils::ai::template::{context_vars, PromptKind};
+use crate::utils::ai::failover::Route;
+use crate::utils::ai::provider::Provider;
+use crate::utils::ai::AiContext;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{debug, warn};
+
+/// `appSetting` 中保存缓存数量上限的键
+const MAX_ENTRIES_KEY: &str = "annotationCache.maxEntries";
+/// 默认最多缓存的单词数量
+const DEFAULT_MAX_ENTRIES: u64 = 20_000;
+
+/// 当前标注提示词模板的哈希
+///
+/// 模板或输出结构变化后哈希随之变化，`prompt_hash` 以它开头，据此区分旧模板的缓存
+pub fn template_hash(db: &Database) -> Result<String, AppError> {
+    let mut hasher = Sha256::new();
+    hasher.update(PromptKind::Annotation.source(db)?);
+    hasher.update(annotation_schema().to_string());
+    Ok(hex::encode(&hasher.finalize()[..8]))
+}
+
+/// 标注 `lesson_id` 中的单词时使用的提示词的哈希，格式为 `{模板哈希}-{变量哈希}`
+///
+/// 除单词外，模板中用到的变量（母语、课文语言和标题等）都计入哈希，
+/// 同一个单词在不同语言的课文中不会命中彼此的缓存
+pub fn prompt_hash(db: &Database, lesson_id: Option<i64>) -> Result<String, AppError> {
+    let template = PromptKind::Annotation.load(db)?;
+    let vars = context_vars(db, lesson_id)?;
+    let mut hasher = Sha256::new();
+    for name in template.variables().iter().filter(|name| *name != "words") {
+        let value = vars.get(name).map(|value| value.to_string()).unwrap_or_default();
+        hasher.update(name);
+        hasher.update([0]);
+        hasher.update(value);
+        hasher.update([0]);
+    }
+    Ok(format!("{}-{}", template_hash(db)?, hex::encode(&hasher.finalize()[..8])))
+}
+
+/// 查询缓存的标注结果，命中的条目会更新命中次数和最后使用时间
+///
+/// 仅适用于某篇课文的标注（`applicability` 为0）不会复用
+/// # Returns
+///
+/// * 单词到标注结果的映射，只包含命中的单词
+pub fn lookup(
+    db: &Database,
+    provider: Provider,
+    model: &str,
+    hash: &str,
+    words: &[String],
+) -> Result<HashMap<String, WordAnnotation>, AppError> {
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        let mut found = HashMap::new();
+        {
+            let mut select = tx.prepare(
+                "SELECT id, annotation FROM aiCache WHERE provider = ?1 AND model = ?2 AND promptHash = ?3 AND word = ?4",
+            )?;
+            let mut touch = tx.prepare(
+                "UPDATE aiCache SET hits = hits + 1, lastUsedTime = datetime('now', 'localtime') WHERE id = ?1",
+            )?;
+            for word in words {
+                if found.contains_key(word) {
+                    continue;
+                }
+                let mut rows = select.query(params![provider.name(), model, hash, word])?;
+                if let Some(row) = rows.next()? {
+                    let id: i64 = row.get(0)?;
+                    let annotation: String = row.get(1)?;
+                    // 结构变化导致无法解析的旧数据视为未命中，稍后会被新结果覆盖
+                    let annotation = serde_json::from_str::<WordAnnotation>(&annotation)
+                        .ok()
+                        .filter(|annotation| annotation.applicability != 0);
+                    if let Some(annotation) = annotation {
+                        touch.execute([id])?;
+                        found.insert(word.clone(), annotation);
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(found)
+    })
+}
+
+/// 保存成功的标注结果，并按数量上限删除最久未使用的条目
+///
+/// 结果按实际答复的平台和模型保存，仅适用于本课文的标注（`applicability` 为0）不保存
+pub fn store(db: &Database, hash: &str, outcomes: &[AnnotationOutcome]) -> Result<(), AppError> {
+    let max_entries = max_entries(db)?;
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO aiCache (provider, model, promptHash, word, annotation) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(provider, model, promptHash, word)
+                 DO UPDATE SET annotation = excluded.annotation, lastUsedTime = datetime('now', 'localtime')",
+            )?;
+            for outcome in outcomes.iter().filter(|o| o.ok && !o.cached) {
+                if let (Some(annotation), Some(route)) = (&outcome.annotation, &outcome.answered_by) {
+                    if annotation.applicability == 0 {
+                        continue;
+                    }
+                    let json = serde_json::to_string(annotation)?;
+                    insert.execute(params![route.provider.name(), route.model, hash, outcome.word, json])?;
+                }
+            }
+        }
+        prune(&tx, max_entries)?;
+        tx.commit()?;
+        Ok(())
+    })
+}
+
+/// 删除超出上限的条目，返回删除的数量
+fn prune(conn: &rusqlite::Connection, max_entries: u64) -> Result<usize, AppError> {
+    Ok(conn.execute(
+        "DELETE FROM aiCache WHERE id IN (
+            SELECT id FROM aiCache ORDER BY lastUsedTime DESC, id DESC LIMIT -1 OFFSET ?1
+        )",
+        [max_entries],
+    )?)
+}
+
+/// 缓存数量上限
+pub fn max_entries(db: &Database) -> Result<u64, AppError> {
+    Ok(db
+        .get_setting(MAX_ENTRIES_KEY)?
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ENTRIES))
+}
+
+/// 设置缓存数量上限并立即删除超出的条目，返回删除的数量
+pub fn set_max_entries(db: &Database, max_entries: u64) -> Result<usize, AppError> {
+    db.set_setting(MAX_ENTRIES_KEY, &max_entries.to_string())?;
+    db.with_conn(|conn| prune(conn, max_entries))
+}
+
+/// 缓存查询和清除的条件，都为空时匹配全部条目
+#[derive(Debug, Default, Deserialize)]
+pub struct CacheFilter {
+    pub provider: Option<Provider>,
+    pub model: Option<String>,
+    pub word: Option<String>,
+    /// 为 `true` 时只匹配当前提示词模板之外的旧条目
+    #[serde(default)]
+    pub stale_only: bool,
+}
+
+impl CacheFilter {
+    /// 条件的SQL片段和参数，参数顺序固定为 `?1`-`?4`
+    fn sql(&self, db: &Database) -> Result<(&'static str, [Option<String>; 4]), AppError> {
+        let stale_hash = match self.stale_only {
+            true => Some(format!("{}-%", template_hash(db)?)),
+            false => None,
+        };
+        Ok((
+            "(?1 IS NULL OR provider = ?1) AND (?2 IS NULL OR model = ?2) AND (?3 IS NULL OR word = ?3) AND (?4 IS NULL OR promptHash NOT LIKE ?4)",
+            [
+                self.provider.map(|p| p.name().to_string()),
+                self.model.clone(),
+                self.word.clone(),
+                stale_hash,
+            ],
+        ))
+    }
+}
+
+/// 一条缓存
+#[derive(Debug, Serialize)]
+pub struct CacheEntry {
+    pub provider: String,
+    pub model: String,
+    pub prompt_hash: String,
+    pub word: String,
+    pub annotation: serde_json::Value,
+    pub hits: u64,
+    pub last_used_time: String,
+    pub create_time: String,
+}
+
+/// 分页查询缓存，按最后使用时间倒序
+///
+/// # Returns
+///
+/// * 当前页的条目和符合条件的总数
+pub fn list(db: &Database, filter: &CacheFilter, limit: u64, offset: u64) -> Result<(Vec<CacheEntry>, u64), AppError> {
+    let (condition, args) = filter.sql(db)?;
+    db.with_conn(|conn| {
+        let total: u64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM aiCache WHERE {}", condition),
+            params![args[0], args[1], args[2], args[3]],
+            |row| row.get(0),
+        )?;
+
+        let mut statement = conn.prepare(&format!(
+            "SELECT provider, model, promptHash, word, annotation, hits, lastUsedTime, createTime
+             FROM aiCache WHERE {} ORDER BY lastUsedTime DESC, id DESC LIMIT ?5 OFFSET ?6",
+            condition
+        ))?;
+        let rows = statement.query_map(
+            params![args[0], args[1], args[2], args[3], limit, offset],
+            |row| {
+                let annotation: String = row.get(4)?;
+                Ok(CacheEntry {
+                    provider: row.get(0)?,
+                    model: row.get(1)?,
+                    prompt_hash: row.get(2)?,
+                    word: row.get(3)?,
+                    annotation: serde_json::from_str(&annotation).unwrap_or(serde_json::Value::Null),
+                    hits: row.get(5)?,
+                    last_used_time: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+                    create_time: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+                })
+            },
+        )?;
+
+        Ok((rows.collect::<Result<Vec<_>, _>>()?, total))
+    })
+}
+
+/// 删除符合条件的缓存，返回删除的数量
+pub fn invalidate(db: &Database, filter: &CacheFilter) -> Result<usize, AppError> {
+    let (condition, args) = filter.sql(db)?;
+    db.with_conn(|conn| {
+        Ok(conn.execute(
+            &format!("DELETE FROM aiCache WHERE {}", condition),
+            params![args[0], args[1], args[2], args[3]],
+        )?)
+    })
+}
+
+/// 先查缓存再标注
+///
+/// 命中缓存的单词直接返回（`cached` 为 `true`），其余单词交给 `annotate_batch`，成功的结果写入缓存。
+/// 缓存读写失败只记录日志，不影响标注。
+///
+/// # Arguments
+///
+/// * `ctx` - 密钥存储、数据库和所属课文
+/// * `provider` - AI平台
+/// * `model` - 模型名称
+/// * `words` - 需要标注的单词
+/// * `proxy` - 可选的代理地址
+/// * `max_retries` - 失败单词的最大重试次数
+/// * `use_cache` - 为 `false` 时跳过缓存查询，但仍会写入新结果
+///
+/// # Returns
+///
+/// * 每个单词的结果，顺序与 `words` 一致，请求中途失败时尚未得到结论的单词记为失败
+/// * 没有命中缓存且第一次请求就失败时返回 `Err(AppError)`
+pub async fn annotate_cached(
+    ctx: AiContext<'_>,
+    provider: Provider,
+    model: &str,
+    words: &[String],
+    proxy: Option<&str>,
+    max_retries: u32,
+    use_cache: bool,
+) -> Result<Vec<AnnotationOutcome>, AppError> {
+    let hash = prompt_hash(ctx.db, ctx.lesson_id)?;
+    let cached = if use_cache {
+        lookup(ctx.db, provider, model, &hash, words).unwrap_or_else(|e| {
+            warn!("读取标注缓存失败：{}", e);
+            HashMap::new()
+        })
+    } else {
+        HashMap::new()
+    };
+
+    let pending: Vec<String> = words
+        .iter()
+        .filter(|word| !cached.contains_key(*word))
+        .cloned()
+        .collect();
+    debug!(cached = words.len() - pending.len(), pending = pending.len(), "查询标注缓存");
+
+    let fresh = if pending.is_empty() {
+        Vec::new()
+    } else {
+        let batch = annotate_batch(ctx, provider, model, &pending, proxy, max_retries).await?;
+        match batch.interrupted {
+            // 一个单词都没有结论时按请求失败处理，前端可以根据错误种类提示用户
+            Some((_, error)) if batch.outcomes.is_empty() && cached.is_empty() => return Err(error),
+            _ => batch.into_outcomes(),
+        }
+    };
+    if let Err(e) = store(ctx.db, &hash, &fresh) {
+        warn!("写入标注缓存失败：{}", e);
+    }
+
+    Ok(merge(words, cached, fresh, &Route::new(provider, model)))
+}
+
+/// 按 `words` 的顺序合并缓存结果和新结果，`route` 为查询缓存使用的平台和模型
+pub fn merge(
+    words: &[String],
+    cached: HashMap<String, WordAnnotation>,
+    fresh: Vec<AnnotationOutcome>,
+    route: &Route,
+) -> Vec<AnnotationOutcome> {
+    // 同一个单词出现多次时复用同一个结果
+    let fresh: HashMap<String, AnnotationOutcome> =
+        fresh.into_iter().map(|outcome| (outcome.word.clone(), outcome)).collect();
+    words
+        .iter()
+        .filter_map(|word| match cached.get(word) {
+            Some(annotation) => Some(AnnotationOutcome::from_cache(word.clone(), annotation.clone(), route.clone())),
+            None => fresh.get(word).cloned(),
+        })
+        .collect()
+}
@@ -0,0 +1,389 @@
+use crate::utils::ai::models::ModelCache;
+use crate::utils::ai::provider::{ChatMessage, Provider};
+use crate::utils::ai::template::{context_vars, PromptKind};
+use crate::utils::ai::usage::{count_tokens, ModelFamily};
+use crate::utils::ai::failover::{chat_with_failover, Reply, Route};
+use crate::utils::ai::AiContext;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+
+/// 为模型输出预留的token数上限
+const MAX_OUTPUT_RESERVE: u64 = 2048;
+/// 每条消息额外的格式开销，与 `usage` 中的估算一致
+const TOKENS_PER_MESSAGE: u64 = 4;
+
+#[derive(Deserialize)]
+pub struct StartSessionRequest {
+    pub provider: Provider,
+    pub model: String,
+    /// 练习的语言，例如 `日语`、`English`
+    pub target_language: String,
+    /// 围绕哪篇课文练习，课文标题和正文会放进系统提示词
+    pub lesson_id: Option<i64>,
+    pub title: Option<String>,
+    /// 角色扮演场景，例如 `在咖啡店点单`
+    pub scenario: Option<String>,
+    /// 自定义系统提示词模板，不传时使用对话练习的提示词模板
+    pub system_prompt: Option<String>,
+    /// 覆盖模型的上下文长度（token）
+    pub context_tokens: Option<u64>,
+    /// 学习者的第一句话，传了会立即得到回复
+    pub opening: Option<String>,
+    pub proxy: Option<String>,
+}
+
+/// 一个对话练习
+#[derive(Debug, Serialize)]
+pub struct ChatSession {
+    pub id: i64,
+    pub lesson_id: Option<i64>,
+    pub provider: Provider,
+    pub model: String,
+    pub title: String,
+    pub target_language: String,
+    pub scenario: Option<String>,
+    pub system_prompt: String,
+    pub context_tokens: Option<u64>,
+    pub message_count: u64,
+    pub update_time: String,
+    pub create_time: String,
+}
+
+/// 保存的一条消息
+#[derive(Debug, Serialize)]
+pub struct StoredMessage {
+    pub id: i64,
+    pub role: String,
+    pub content: String,
+    pub tokens: u64,
+    pub create_time: String,
+}
+
+/// 一次对话的结果
+#[derive(Debug, Serialize)]
+pub struct ChatTurn {
+    pub reply: String,
+    /// 实际答复的平台和模型，首选平台故障时可能来自备用链
+    pub answered_by: Route,
+    /// 因超出上下文长度没有发送给模型的历史消息数量
+    pub trimmed: usize,
+}
+
+const SESSION_COLUMNS: &str = "s.id, s.classId, s.provider, s.model, s.title, s.targetLanguage, s.scenario, s.systemPrompt,
+    s.contextTokens, (SELECT COUNT(*) FROM chatMessage m WHERE m.sessionId = s.id), s.updateTime, s.createTime";
+
+fn session_from_row(row: &Row) -> rusqlite::Result<ChatSession> {
+    let provider: String = row.get(2)?;
+    let provider = serde_json::from_value(json!(provider)).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    Ok(ChatSession {
+        id: row.get(0)?,
+        lesson_id: row.get(1)?,
+        provider,
+        model: row.get(3)?,
+        title: row.get(4)?,
+        target_language: row.get(5)?,
+        scenario: row.get(6)?,
+        system_prompt: row.get(7)?,
+        context_tokens: row.get(8)?,
+        message_count: row.get(9)?,
+        update_time: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+        create_time: row.get::<_, Option<String>>(11)?.unwrap_or_default(),
+    })
+}
+
+/// 模型的上下文长度，优先使用模型列表返回的值，没有时按模型家族估计
+pub fn context_window(cache: &ModelCache, provider: Provider, model: &str) -> u64 {
+    if let Some(length) = cache.context_length(provider, model) {
+        return length;
+    }
+
+    let model = model.to_ascii_lowercase();
+    match ModelFamily::of(provider, &model) {
+        ModelFamily::Gemini => 1_000_000,
+        ModelFamily::Gpt if model.starts_with("gpt-3.5") => 16_385,
+        ModelFamily::Gpt | ModelFamily::Glm => 128_000,
+        ModelFamily::DeepSeek => 64_000,
+        ModelFamily::Open if provider == Provider::Local => 8_192,
+        ModelFamily::Open => 32_768,
+    }
+}
+
+/// 按上下文长度裁剪历史消息
+///
+/// 系统提示词和最新一条消息总是保留，其余消息从新到旧放入，直到超出预算。
+/// 裁剪后开头的助手消息也会去掉，保证对话从用户消息开始（Gemini 要求）。
+///
+/// # Arguments
+///
+/// * `family` - 模型家族，用于估算token数
+/// * `system_prompt` - 系统提示词
+/// * `history` - 历史消息，最后一条是本次发送的消息
+/// * `context_window` - 模型的上下文长度
+///
+/// # Returns
+///
+/// * 发送给模型的消息和被裁剪掉的消息数量
+pub fn trim_context(
+    family: ModelFamily,
+    system_prompt: &str,
+    history: &[ChatMessage],
+    context_window: u64,
+) -> (Vec<ChatMessage>, usize) {
+    let reserve = (context_window / 4).min(MAX_OUTPUT_RESERVE);
+    let cost = |m: &ChatMessage| count_tokens(family, &m.content) + TOKENS_PER_MESSAGE;
+    let mut budget = context_window
+        .saturating_sub(reserve)
+        .saturating_sub(count_tokens(family, system_prompt) + TOKENS_PER_MESSAGE);
+
+    let mut start = history.len();
+    for (index, message) in history.iter().enumerate().rev() {
+        let tokens = cost(message);
+        if tokens > budget && start < history.len() {
+            break;
+        }
+        budget = budget.saturating_sub(tokens);
+        start = index;
+    }
+    while start + 1 < history.len() && history[start].role != "user" {
+        start += 1;
+    }
+
+    let mut messages = vec![ChatMessage {
+        role: "system".to_string(),
+        content: system_prompt.to_string(),
+    }];
+    messages.extend_from_slice(&history[start..]);
+    (messages, start)
+}
+
+/// 新建对话练习
+///
+/// # Returns
+///
+/// * 新建的对话
+/// * 课文不存在或写入失败返回 `Err(AppError)`
+pub fn create_session(db: &Database, request: &StartSessionRequest) -> Result<ChatSession, AppError> {
+    let lesson_title = match request.lesson_id {
+        Some(lesson_id) => Some(db.with_conn(|conn| {
+            conn.query_row("SELECT title FROM `class` WHERE id = ?1", [lesson_id], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?
+            .ok_or_else(|| AppError::InvalidArgument(Msg::new("conversation.lesson_not_found").arg("id", lesson_id)))
+        })?),
+        None => None,
+    };
+
+    // 自定义的系统提示词同样按模板渲染
+    let template = match request.system_prompt.as_deref().filter(|p| !p.trim().is_empty()) {
+        Some(source) => PromptKind::Conversation.compile(source)?,
+        None => PromptKind::Conversation.load(db)?,
+    };
+    let mut vars = context_vars(db, request.lesson_id)?;
+    vars.insert("language".into(), json!(request.target_language));
+    if let Some(scenario) = request.scenario.as_deref() {
+        vars.insert("scenario".into(), json!(scenario.trim()));
+    }
+    let system_prompt = template.render(&vars);
+    let title = request
+        .title
+        .clone()
+        .filter(|t| !t.trim().is_empty())
+        .or_else(|| request.scenario.clone().filter(|s| !s.trim().is_empty()))
+        .or(lesson_title)
+        .unwrap_or_else(|| request.target_language.clone());
+
+    let id = db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO chatSession (classId, provider, model, title, targetLanguage, scenario, systemPrompt, contextTokens)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                request.lesson_id,
+                request.provider.name(),
+                request.model,
+                title,
+                request.target_language,
+                request.scenario,
+                system_prompt,
+                request.context_tokens
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })?;
+
+    get_session(db, id)
+}
+
+/// 读取对话
+pub fn get_session(db: &Database, session_id: i64) -> Result<ChatSession, AppError> {
+    db.with_conn(|conn| {
+        conn.query_row(
+            &format!("SELECT {} FROM chatSession s WHERE s.id = ?1", SESSION_COLUMNS),
+            [session_id],
+            session_from_row,
+        )
+        .optional()?
+        .ok_or_else(|| AppError::InvalidArgument(Msg::new("conversation.not_found").arg("id", session_id)))
+    })
+}
+
+/// 对话列表，按最后更新时间倒序
+pub fn list_sessions(db: &Database, lesson_id: Option<i64>) -> Result<Vec<ChatSession>, AppError> {
+    db.with_conn(|conn| {
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM chatSession s WHERE (?1 IS NULL OR s.classId = ?1) ORDER BY s.updateTime DESC, s.id DESC",
+            SESSION_COLUMNS
+        ))?;
+        let rows = statement.query_map([lesson_id], session_from_row)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    })
+}
+
+/// 对话的全部消息，按时间顺序
+pub fn session_messages(db: &Database, session_id: i64) -> Result<Vec<StoredMessage>, AppError> {
+    db.with_conn(|conn| {
+        let mut statement = conn.prepare(
+            "SELECT id, role, content, tokens, createTime FROM chatMessage WHERE sessionId = ?1 ORDER BY id",
+        )?;
+        let rows = statement.query_map([session_id], |row| {
+            Ok(StoredMessage {
+                id: row.get(0)?,
+                role: row.get(1)?,
+                content: row.get(2)?,
+                tokens: row.get(3)?,
+                create_time: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    })
+}
+
+/// 发送一条消息并保存模型的回复
+///
+/// 用户消息和回复在模型返回后一起写入，请求失败时不会留下没有回复的消息
+///
+/// # Arguments
+///
+/// * `ctx` - 密钥存储和数据库，`lesson_id` 会替换为对话所属的课文
+/// * `session` - 对话
+/// * `content` - 学习者的消息
+/// * `context_window` - 模型的上下文长度，对话设置了 `context_tokens` 时以对话为准
+/// * `proxy` - 可选的代理地址
+pub async fn send_message(
+    ctx: AiContext<'_>,
+    session: &ChatSession,
+    content: &str,
+    context_window: u64,
+    proxy: Option<&str>,
+) -> Result<ChatTurn, AppError> {
+    if content.trim().is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("conversation.empty_message")));
+    }
+
+    let family = ModelFamily::of(session.provider, &session.model);
+    let mut history: Vec<ChatMessage> = session_messages(ctx.db, session.id)?
+        .into_iter()
+        .map(|m| ChatMessage {
+            role: m.role,
+            content: m.content,
+        })
+        .collect();
+    history.push(ChatMessage::user(content));
+
+    let window = session.context_tokens.unwrap_or(context_window);
+    let (messages, trimmed) = trim_context(family, &session.system_prompt, &history, window);
+    debug!(session_id = session.id, history = history.len(), trimmed, "发送对话");
+
+    let ctx = AiContext {
+        lesson_id: session.lesson_id,
+        ..ctx
+    };
+    let Reply { text: reply, route } =
+        chat_with_failover(ctx, session.provider, &session.model, &messages, None, proxy).await?;
+
+    ctx.db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        for (role, text) in [("user", content), ("assistant", reply.as_str())] {
+            tx.execute(
+                "INSERT INTO chatMessage (sessionId, role, content, tokens) VALUES (?1, ?2, ?3, ?4)",
+                params![session.id, role, text, count_tokens(family, text)],
+            )?;
+        }
+        tx.execute(
+            "UPDATE chatSession SET updateTime = datetime('now', 'localtime') WHERE id = ?1",
+            [session.id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    })?;
+
+    Ok(ChatTurn {
+        reply,
+        answered_by: route,
+        trimmed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 `ModelFamily::Gpt` 估算，`len` 个ASCII字符的消息占 `len / 4 + TOKENS_PER_MESSAGE` 个token
+    fn message(role: &str, len: usize) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: "a".repeat(len),
+        }
+    }
+
+    fn roles(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.role.as_str()).collect()
+    }
+
+    #[test]
+    fn trim_context_keeps_newest_messages_within_budget() {
+        // 上下文100，预留四分之一给输出，系统提示词占4，剩余71，每条消息占14，最多放5条
+        let history: Vec<_> = (0..6).map(|i| message(if i % 2 == 0 { "user" } else { "assistant" }, 40)).collect();
+        let (messages, trimmed) = trim_context(ModelFamily::Gpt, "", &history, 100);
+        // 放得下的第一条是助手消息，也要去掉
+        assert_eq!(trimmed, 2);
+        assert_eq!(roles(&messages), ["system", "user", "assistant", "user", "assistant"]);
+
+        let (messages, trimmed) = trim_context(ModelFamily::Gpt, "", &history, 1_000);
+        assert_eq!(trimmed, 0);
+        assert_eq!(messages.len(), 7);
+    }
+
+    #[test]
+    fn trim_context_reserves_room_for_the_output() {
+        // 上下文10000，预留上限2048，剩余7948；最新消息占7940后放不下占14的消息
+        let history = [message("user", 40), message("assistant", 40), message("user", 4 * 7936)];
+        let (messages, trimmed) = trim_context(ModelFamily::Gpt, "", &history, 10_000);
+        assert_eq!(trimmed, 2);
+        assert_eq!(roles(&messages), ["system", "user"]);
+
+        // 系统提示词同样计入预算
+        let history = [message("user", 40), message("assistant", 40), message("user", 40)];
+        assert_eq!(trim_context(ModelFamily::Gpt, "", &history, 100).1, 0);
+        let (messages, trimmed) = trim_context(ModelFamily::Gpt, &"s".repeat(4 * 33), &history, 100);
+        assert_eq!(trimmed, 2);
+        assert_eq!(roles(&messages), ["system", "user"]);
+    }
+
+    #[test]
+    fn trim_context_always_sends_the_newest_message() {
+        let history = [message("user", 40), message("assistant", 40), message("user", 4_000)];
+        let (messages, trimmed) = trim_context(ModelFamily::Gpt, "system", &history, 100);
+        assert_eq!(trimmed, 2);
+        assert_eq!(roles(&messages), ["system", "user"]);
+        assert_eq!(messages[0].content, "system");
+        assert_eq!(messages[1].content.len(), 4_000);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::utils::ai::provider::{ChatMessage, Provider};
+use crate::utils::ai::{chat, provider_key, AiContext};
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// `appSetting` 中保存备用链的键
+const CHAIN_KEY: &str = "ai.failoverChain";
+
+/// 一个平台和模型
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Route {
+    pub provider: Provider,
+    pub model: String,
+}
+
+impl Route {
+    pub fn new(provider: Provider, model: &str) -> Self {
+        Self {
+            provider,
+            model: model.to_string(),
+        }
+    }
+}
+
+/// 一次对话请求的结果
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub text: String,
+    /// 实际答复的平台和模型
+    pub route: Route,
+}
+
+/// 备用链，按顺序排列
+///
+/// 保存的内容无法解析时视为没有配置
+pub fn chain(db: &Database) -> Result<Vec<Route>, AppError> {
+    let Some(value) = db.get_setting(CHAIN_KEY)? else {
+        return Ok(Vec::new());
+    };
+    Ok(serde_json::from_str(&value).unwrap_or_else(|e| {
+        warn!("备用链无法解析，忽略：{}", e);
+        Vec::new()
+    }))
+}
+
+/// 保存备用链，为空时关闭备用
+pub fn set_chain(db: &Database, chain: &[Route]) -> Result<(), AppError> {
+    if chain.iter().any(|route| route.model.trim().is_empty()) {
+        return Err(AppError::InvalidArgument(Msg::new("failover.empty_model")));
+    }
+    db.set_setting(CHAIN_KEY, &serde_json::to_string(chain)?)
+}
+
+/// 换一个平台可能成功的错误：密钥被拒绝、额度不足、限流、网络故障和服务端故障
+fn should_fail_over(error: &AppError) -> bool {
+    match error {
+        AppError::Network(_)
+        | AppError::Timeout(_)
+        | AppError::ProviderAuth { .. }
+        | AppError::RateLimited { .. } => true,
+        AppError::HttpStatus { status, .. } => *status == 402 || *status >= 500,
+        _ => false,
+    }
+}
+
+/// 先请求指定的平台，遇到平台本身的问题时按备用链依次尝试
+///
+/// 没有配置密钥的备用平台直接跳过。每一步的决定都记入日志，全部失败时返回最后一个错误。
+/// `ctx.limiter` 有值时每次请求前先取令牌，收到 429 时按 `Retry-After` 暂停该平台。
+///
+/// # Arguments
+///
+/// * `ctx` - 密钥存储、数据库和所属课文
+/// * `provider` - 首选的AI平台
+/// * `model` - 首选的模型
+/// * `messages` - 对话消息
+/// * `json_schema` - 需要JSON输出时传入结构描述
+/// * `proxy` - 可选的代理地址
+///
+/// # Returns
+///
+/// * 成功返回模型输出和实际答复的平台
+/// * 请求失败且不适合切换平台（参数错误、响应无法解析等）时直接返回 `Err(AppError)`
+pub async fn chat_with_failover(
+    ctx: AiContext<'_>,
+    provider: Provider,
+    model: &str,
+    messages: &[ChatMessage],
+    json_schema: Option<&Value>,
+    proxy: Option<&str>,
+) -> Result<Reply, AppError> {
+    let primary = Route::new(provider, model);
+    let mut routes = vec![primary.clone()];
+    for route in chain(ctx.db)? {
+        if !routes.contains(&route) {
+            routes.push(route);
+        }
+    }
+
+    let mut trail = Vec::new();
+    let mut last_error = None;
+    for (index, route) in routes.iter().enumerate() {
+        // 首选平台缺少密钥时仍按原样报错，备用平台缺少密钥时跳过
+        if index > 0 {
+            if let Err(e) = provider_key(ctx.secrets, route.provider) {
+                trail.push(format!("{}/{}：跳过，{}", route.provider.name(), route.model, e));
+                continue;
+            }
+        }
+
+        if let Some(limiter) = ctx.limiter {
+            limiter.acquire(route.provider).await?;
+        }
+        let result = chat(ctx, route.provider, &route.model, messages, json_schema, proxy).await;
+        if let (Some(limiter), Err(AppError::RateLimited { retry_after: Some(secs), .. })) = (ctx.limiter, &result) {
+            limiter.block_for(route.provider, Duration::from_secs(*secs));
+        }
+
+        match result {
+            Ok(text) => {
+                if index > 0 {
+                    info!(
+                        provider = route.provider.name(),
+                        model = %route.model,
+                        trail = %trail.join("；"),
+                        "由备用平台完成"
+                    );
+                }
+                return Ok(Reply {
+                    text,
+                    route: route.clone(),
+                });
+            }
+            Err(e) if index + 1 < routes.len() && should_fail_over(&e) => {
+                warn!(
+                    provider = route.provider.name(),
+                    model = %route.model,
+                    error = %e,
+                    "平台调用失败，切换到下一个平台"
+                );
+                trail.push(format!("{}/{}：{}", route.provider.name(), route.model, e));
+                last_error = Some(e);
+            }
+            Err(e) => {
+                if index > 0 {
+                    warn!(trail = %trail.join("；"), error = %e, "备用平台调用失败，不再切换");
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    // 最后几个备用平台都因缺少密钥被跳过
+    warn!(trail = %trail.join("；"), "备用平台全部失败");
+    Err(last_error.expect("首选平台总会被尝试"))
+}
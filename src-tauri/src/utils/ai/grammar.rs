@@ -0,0 +1,285 @@
+use crate::utils::ai::provider::{ChatMessage, Provider};
+use crate::utils::ai::template::{context_vars, PromptKind};
+use crate::utils::ai::failover::{chat_with_failover, Route};
+use crate::utils::ai::{parse_json_output, AiContext};
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+/// 模型输出无法解析时的最大尝试次数
+const MAX_ATTEMPTS: u32 = 2;
+
+/// 句子难度，按 CEFR 等级
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    A1,
+    A2,
+    B1,
+    B2,
+    C1,
+    C2,
+}
+
+impl Difficulty {
+    pub const ALL: [&'static str; 6] = ["A1", "A2", "B1", "B2", "C1", "C2"];
+
+    /// 宽松解析，兼容小写和多余的空格
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        serde_json::from_value(json!(text.trim().to_ascii_uppercase())).ok()
+    }
+}
+
+/// 句子中的一个分句
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Clause {
+    /// 分句原文
+    pub text: String,
+    /// 分句的作用，例如主句、定语从句
+    pub role: String,
+    /// 分句的中文解释
+    pub explanation: String,
+}
+
+/// 一个语法点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrammarPoint {
+    /// 句中对应的原文或句型
+    pub pattern: String,
+    /// 语法说明
+    pub explanation: String,
+}
+
+/// 句子的语法分析结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SentenceAnalysis {
+    pub sentence: String,
+    /// 地道的中文翻译
+    pub translation: String,
+    pub clauses: Vec<Clause>,
+    pub grammar_points: Vec<GrammarPoint>,
+    pub difficulty: Difficulty,
+}
+
+/// 保存的分析结果
+#[derive(Debug, Serialize)]
+pub struct StoredAnalysis {
+    pub lesson_id: i64,
+    pub sentence_index: i64,
+    pub provider: String,
+    pub model: String,
+    pub analysis: SentenceAnalysis,
+    pub create_time: String,
+}
+
+#[derive(Deserialize)]
+pub struct AnalyzeSentenceRequest {
+    pub provider: Provider,
+    pub model: String,
+    /// 课题ID
+    pub lesson_id: i64,
+    /// 句子在课文字幕时间轴中的序号
+    pub sentence_index: i64,
+    pub sentence: String,
+    pub proxy: Option<String>,
+    /// 为 `true` 时忽略已保存的结果重新分析
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Gemini `responseSchema` 使用的结构描述
+pub fn analysis_schema() -> Value {
+    let text_object = |fields: &[&str]| {
+        let properties: serde_json::Map<String, Value> = fields
+            .iter()
+            .map(|field| (field.to_string(), json!({"type": "STRING"})))
+            .collect();
+        json!({"type": "OBJECT", "properties": properties, "required": fields})
+    };
+    json!({
+        "type": "OBJECT",
+        "properties": {
+            "translation": {"type": "STRING"},
+            "clauses": {"type": "ARRAY", "items": text_object(&["text", "role", "explanation"])},
+            "grammar_points": {"type": "ARRAY", "items": text_object(&["pattern", "explanation"])},
+            "difficulty": {"type": "STRING", "enum": Difficulty::ALL},
+        },
+        "required": ["translation", "clauses", "grammar_points", "difficulty"],
+    })
+}
+
+/// 按当前的语法分析模板生成提示词
+pub fn analysis_prompt(db: &Database, lesson_id: Option<i64>, sentence: &str) -> Result<String, AppError> {
+    let mut vars = context_vars(db, lesson_id)?;
+    vars.insert("sentence".into(), json!(sentence));
+    Ok(PromptKind::Grammar.load(db)?.render(&vars))
+}
+
+/// 请AI分析句子的语法
+///
+/// 输出无法解析时会重试一次，平台故障时按备用链切换平台
+///
+/// # Arguments
+///
+/// * `ctx` - 密钥存储、数据库和所属课文
+/// * `provider` - AI平台
+/// * `model` - 模型名称
+/// * `sentence` - 句子
+/// * `proxy` - 可选的代理地址
+///
+/// # Returns
+///
+/// * 成功返回分析结果和实际答复的平台
+/// * 请求失败或多次无法解析时返回 `Err(AppError)`
+pub async fn analyze(
+    ctx: AiContext<'_>,
+    provider: Provider,
+    model: &str,
+    sentence: &str,
+    proxy: Option<&str>,
+) -> Result<(SentenceAnalysis, Route), AppError> {
+    let sentence = sentence.trim();
+    if sentence.is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("grammar.empty_sentence")));
+    }
+
+    let schema = analysis_schema();
+    let messages = [ChatMessage::user(analysis_prompt(ctx.db, ctx.lesson_id, sentence)?)];
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let reply = chat_with_failover(ctx, provider, model, &messages, Some(&schema), proxy).await?;
+        match parse_analysis(&reply.text, sentence) {
+            Ok(analysis) => return Ok((analysis, reply.route)),
+            Err(error) if attempt < MAX_ATTEMPTS => {
+                warn!(attempt, error = %error.render(), "语法分析结果无法解析，重试");
+            }
+            Err(error) => return Err(AppError::Parse(error)),
+        }
+    }
+}
+
+/// 把模型输出修正为 `SentenceAnalysis`
+pub(crate) fn parse_analysis(text: &str, sentence: &str) -> Result<SentenceAnalysis, Msg> {
+    let value = parse_json_output(text).map_err(|e| Msg::new("grammar.invalid_response").arg("error", e))?;
+
+    let text = |item: &Value, key: &str| item[key].as_str().unwrap_or("").trim().to_string();
+
+    let translation = text(&value, "translation");
+    if translation.is_empty() {
+        return Err(Msg::new("grammar.missing_translation"));
+    }
+    let difficulty = value["difficulty"]
+        .as_str()
+        .and_then(Difficulty::parse)
+        .ok_or_else(|| Msg::new("grammar.invalid_difficulty"))?;
+
+    let items = |key: &str| value[key].as_array().cloned().unwrap_or_default();
+    let clauses = items("clauses")
+        .iter()
+        .map(|item| Clause {
+            text: text(item, "text"),
+            role: text(item, "role"),
+            explanation: text(item, "explanation"),
+        })
+        .filter(|clause| !clause.text.is_empty())
+        .collect();
+    let grammar_points = items("grammar_points")
+        .iter()
+        .map(|item| GrammarPoint {
+            pattern: text(item, "pattern"),
+            explanation: text(item, "explanation"),
+        })
+        .filter(|point| !point.explanation.is_empty())
+        .collect();
+
+    Ok(SentenceAnalysis {
+        sentence: sentence.to_string(),
+        translation,
+        clauses,
+        grammar_points,
+        difficulty,
+    })
+}
+
+fn stored_from_row(row: &rusqlite::Row) -> rusqlite::Result<Option<StoredAnalysis>> {
+    let analysis: String = row.get(4)?;
+    // 结构变化导致无法解析的旧数据视为没有结果
+    let Ok(analysis) = serde_json::from_str(&analysis) else {
+        return Ok(None);
+    };
+    Ok(Some(StoredAnalysis {
+        lesson_id: row.get(0)?,
+        sentence_index: row.get(1)?,
+        provider: row.get(2)?,
+        model: row.get(3)?,
+        analysis,
+        create_time: row.get::<_, Option<String>>(5)?.unwrap_or_default(),
+    }))
+}
+
+/// 读取已保存的分析结果，句子内容变化后视为没有结果
+pub fn find(db: &Database, lesson_id: i64, sentence_index: i64, sentence: &str) -> Result<Option<StoredAnalysis>, AppError> {
+    db.with_conn(|conn| {
+        Ok(conn
+            .query_row(
+                "SELECT classId, sentenceIndex, provider, model, analysis, createTime FROM sentenceGrammar
+                 WHERE classId = ?1 AND sentenceIndex = ?2 AND sentence = ?3",
+                params![lesson_id, sentence_index, sentence.trim()],
+                stored_from_row,
+            )
+            .optional()?
+            .flatten())
+    })
+}
+
+/// 课文的全部分析结果，按句子序号排序
+pub fn list(db: &Database, lesson_id: i64) -> Result<Vec<StoredAnalysis>, AppError> {
+    db.with_conn(|conn| {
+        let mut statement = conn.prepare(
+            "SELECT classId, sentenceIndex, provider, model, analysis, createTime FROM sentenceGrammar
+             WHERE classId = ?1 ORDER BY sentenceIndex",
+        )?;
+        let rows = statement.query_map([lesson_id], stored_from_row)?;
+        let mut analyses = Vec::new();
+        for row in rows {
+            analyses.extend(row?);
+        }
+        Ok(analyses)
+    })
+}
+
+/// 保存分析结果，同一课文同一序号只保留最新的结果
+pub fn save(
+    db: &Database,
+    lesson_id: i64,
+    sentence_index: i64,
+    route: &Route,
+    analysis: &SentenceAnalysis,
+) -> Result<(), AppError> {
+    let json = serde_json::to_string(analysis)?;
+    let difficulty = serde_json::to_value(analysis.difficulty)?;
+    db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO sentenceGrammar (classId, sentenceIndex, sentence, provider, model, analysis, difficulty)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(classId, sentenceIndex) DO UPDATE SET
+                sentence = excluded.sentence, provider = excluded.provider, model = excluded.model,
+                analysis = excluded.analysis, difficulty = excluded.difficulty,
+                createTime = datetime('now', 'localtime')",
+            params![
+                lesson_id,
+                sentence_index,
+                analysis.sentence,
+                route.provider.name(),
+                route.model,
+                json,
+                difficulty.as_str()
+            ],
+        )?;
+        Ok(())
+    })
+}
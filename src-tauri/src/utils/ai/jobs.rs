@@ -0,0 +1,522 @@
+use crate::utils::ai::annotate::{annotate_batch, AnnotationOutcome};
+use crate::utils::ai::{cache, AiContext};
+use crate::utils::ai::failover::Route;
+use crate::utils::ai::provider::Provider;
+use crate::utils::error::AppError;
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tracing::{info, warn};
+
+/// 进度事件名称
+pub const PROGRESS_EVENT: &str = "annotation-progress";
+/// 默认每批单词数量，与前端 `annotationNumber` 的默认值一致
+const DEFAULT_BATCH_SIZE: usize = 20;
+/// 默认同时进行的批次数量
+const DEFAULT_CONCURRENCY: usize = 2;
+/// 每批遇到限流、超时等可重试错误时的最大尝试次数
+const MAX_BATCH_ATTEMPTS: u32 = 5;
+/// 服务端没有给出 `Retry-After` 时的初始等待时间，之后每次翻倍
+const BASE_BACKOFF: Duration = Duration::from_secs(2);
+/// 等待期间检查任务是否被取消的间隔
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Deserialize)]
+pub struct AnnotationJobRequest {
+    /// 任务ID，用于区分进度事件，不传则自动生成
+    pub job_id: Option<String>,
+    pub provider: Provider,
+    pub model: String,
+    pub words: Vec<String>,
+    pub proxy: Option<String>,
+    pub batch_size: Option<usize>,
+    pub concurrency: Option<usize>,
+    /// 本任务自己的每分钟请求数上限，在平台共享的额度之外另行限制
+    pub requests_per_minute: Option<u32>,
+    /// 单词校验失败时的最大重试次数
+    pub max_retries: Option<u32>,
+    /// 所属课题ID，用于按课文统计用量
+    pub lesson_id: Option<i64>,
+    /// 是否使用标注缓存，默认使用
+    pub use_cache: Option<bool>,
+}
+
+/// 进度事件内容
+#[derive(Clone, Serialize)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub total: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+/// 令牌桶
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    /// 每秒补充的令牌数
+    refill_rate: f64,
+    last_refill: Instant,
+    /// 收到 429 后，在此时间之前不再发出请求
+    blocked_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn new(requests_per_minute: u32) -> Self {
+        let capacity = (requests_per_minute as f64 / 10.0).clamp(1.0, 10.0);
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_rate: requests_per_minute as f64 / 60.0,
+            last_refill: Instant::now(),
+            blocked_until: None,
+        }
+    }
+
+    /// 尝试取出一个令牌，取不到时返回需要等待的时间
+    fn try_take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return Err(until - now);
+            }
+            self.blocked_until = None;
+        }
+
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.refill_rate))
+        }
+    }
+}
+
+/// 按平台限制请求频率，作为 tauri 的托管状态使用，所有任务共享同一个平台的额度
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<Provider, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// 各平台免费额度下的默认每分钟请求数
+    fn default_rpm(provider: Provider) -> u32 {
+        match provider {
+            Provider::Google => 15,
+            Provider::Groq => 30,
+            // 本地模型不限流，由并发数控制负载
+            Provider::Local => 600,
+            _ => 60,
+        }
+    }
+
+    /// 尝试从该平台的令牌桶取出一个令牌，取不到时返回需要等待的时间
+    fn try_take(&self, provider: Provider) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets
+            .entry(provider)
+            .or_insert_with(|| TokenBucket::new(Self::default_rpm(provider)))
+            .try_take()
+    }
+
+    /// 暂停向该平台发送请求
+    pub fn block_for(&self, provider: Provider, duration: Duration) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets
+            .entry(provider)
+            .or_insert_with(|| TokenBucket::new(Self::default_rpm(provider)));
+        let until = Instant::now() + duration;
+        bucket.blocked_until = Some(bucket.blocked_until.map_or(until, |u| u.max(until)));
+    }
+}
+
+/// 正在运行的标注任务，作为 tauri 的托管状态使用，用于取消任务
+#[derive(Default)]
+pub struct AnnotationJobs {
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl AnnotationJobs {
+    /// 登记任务，返回任务的取消标记
+    fn register(&self, job_id: &str) -> Arc<AtomicBool> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running.insert(job_id.to_string(), cancelled.clone());
+        cancelled
+    }
+
+    fn finish(&self, job_id: &str) {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running.remove(job_id);
+    }
+
+    /// 取消任务，任务不存在或已经结束时返回 `false`
+    ///
+    /// 已经发出的请求会等待其完成，尚未发出的请求不再发出，对应的单词记为失败
+    pub fn cancel(&self, job_id: &str) -> bool {
+        let running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        match running.get(job_id) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 一个任务发出请求前的关卡：平台共享的额度、任务自己的额度和取消标记
+///
+/// 放在 `AiContext` 中，由 `chat_with_failover` 在每次HTTP请求前调用 `acquire`，
+/// 所以标注重试和切换到备用平台的请求同样受限。
+pub struct JobLimiter<'a> {
+    shared: &'a RateLimiter,
+    /// 任务指定了 `requests_per_minute` 时才有，不影响其他任务
+    own: Option<Mutex<TokenBucket>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<'a> JobLimiter<'a> {
+    pub fn new(shared: &'a RateLimiter, requests_per_minute: Option<u32>, cancelled: Arc<AtomicBool>) -> Self {
+        Self {
+            shared,
+            own: requests_per_minute.map(|rpm| Mutex::new(TokenBucket::new(rpm.max(1)))),
+            cancelled,
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<(), AppError> {
+        match self.cancelled.load(Ordering::SeqCst) {
+            true => Err(AppError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// 等待直到可以向该平台发出一个请求，先取任务自己的令牌，再取平台共享的令牌
+    ///
+    /// # Returns
+    ///
+    /// * 任务被取消时返回 `Err(AppError::Cancelled)`
+    pub async fn acquire(&self, provider: Provider) -> Result<(), AppError> {
+        if let Some(own) = &self.own {
+            loop {
+                self.check_cancelled()?;
+                let wait = own.lock().unwrap_or_else(|e| e.into_inner()).try_take();
+                match wait {
+                    Ok(()) => break,
+                    Err(wait) => self.sleep(wait).await?,
+                }
+            }
+        }
+        loop {
+            self.check_cancelled()?;
+            match self.shared.try_take(provider) {
+                Ok(()) => return Ok(()),
+                Err(wait) => self.sleep(wait).await?,
+            }
+        }
+    }
+
+    /// 收到 429 后暂停向该平台发送请求，所有任务共享
+    pub fn block_for(&self, provider: Provider, duration: Duration) {
+        self.shared.block_for(provider, duration);
+    }
+
+    /// 等待一段时间，期间任务被取消时提前返回 `Err(AppError::Cancelled)`
+    pub async fn sleep(&self, duration: Duration) -> Result<(), AppError> {
+        let until = Instant::now() + duration;
+        loop {
+            self.check_cancelled()?;
+            let now = Instant::now();
+            if now >= until {
+                return Ok(());
+            }
+            tokio::time::sleep((until - now).min(CANCEL_CHECK_INTERVAL)).await;
+        }
+    }
+}
+
+/// 运行批量标注任务
+///
+/// 先查询标注缓存，未命中的单词按 `batch_size` 分批，最多 `concurrency` 批同时进行，每次HTTP请求前从平台的令牌桶取令牌。
+/// 遇到 429 时按 `Retry-After` 暂停整个平台，超时和网络错误按指数退避重试，重试时只请求尚未得到结论的单词；
+/// 每批完成后把成功的结果写入缓存，并发送 `annotation-progress` 事件。
+/// 任务可以通过 `AnnotationJobs::cancel` 取消，尚未发出请求的单词记为失败。
+///
+/// # Arguments
+///
+/// * `app` - 用于发送进度事件
+/// * `ctx` - 密钥存储、数据库和所属课文
+/// * `limiter` - 平台共享的频率限制
+/// * `jobs` - 正在运行的任务，用于取消
+/// * `request` - 任务参数
+///
+/// # Returns
+///
+/// * 任务ID和每个单词的结果，顺序与 `request.words` 一致
+pub async fn run_annotation_job(
+    app: &AppHandle,
+    ctx: AiContext<'_>,
+    limiter: &RateLimiter,
+    jobs: &AnnotationJobs,
+    request: AnnotationJobRequest,
+) -> (String, Vec<AnnotationOutcome>) {
+    let job_id = request
+        .job_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let job_limiter = JobLimiter::new(limiter, request.requests_per_minute, jobs.register(&job_id));
+    let ctx = AiContext {
+        limiter: Some(&job_limiter),
+        ..ctx
+    };
+    let batch_size = request.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+    let concurrency = request.concurrency.unwrap_or(DEFAULT_CONCURRENCY).max(1);
+    let total = request.words.len();
+
+    let hash = cache::prompt_hash(ctx.db, ctx.lesson_id)
+        .map_err(|e| warn!("读取标注提示词失败，不使用缓存：{}", e))
+        .ok();
+    let cached = match &hash {
+        Some(hash) if request.use_cache.unwrap_or(true) => {
+            cache::lookup(ctx.db, request.provider, &request.model, hash, &request.words).unwrap_or_else(|e| {
+                warn!("读取标注缓存失败：{}", e);
+                HashMap::new()
+            })
+        }
+        _ => HashMap::new(),
+    };
+    let pending: Vec<String> = request
+        .words
+        .iter()
+        .filter(|word| !cached.contains_key(*word))
+        .cloned()
+        .collect();
+
+    let done = AtomicUsize::new(total - pending.len());
+    let failed = AtomicUsize::new(0);
+    info!(job_id = %job_id, total, cached = total - pending.len(), batch_size, concurrency, "开始批量标注");
+
+    let batches: Vec<&[String]> = pending.chunks(batch_size).collect();
+    let results: Vec<Vec<AnnotationOutcome>> = stream::iter(batches)
+        .map(|batch| {
+            let request = &request;
+            let (done, failed, job_id, hash, job_limiter) = (&done, &failed, &job_id, &hash, &job_limiter);
+            async move {
+                let outcomes = run_batch(ctx, job_limiter, request, batch).await;
+                if let Some(hash) = hash {
+                    if let Err(e) = cache::store(ctx.db, hash, &outcomes) {
+                        warn!("写入标注缓存失败：{}", e);
+                    }
+                }
+
+                let batch_failed = outcomes.iter().filter(|o| !o.ok).count();
+                let progress = JobProgress {
+                    job_id: job_id.clone(),
+                    total,
+                    done: done.fetch_add(outcomes.len(), Ordering::SeqCst) + outcomes.len(),
+                    failed: failed.fetch_add(batch_failed, Ordering::SeqCst) + batch_failed,
+                };
+                if let Err(e) = app.emit(PROGRESS_EVENT, progress) {
+                    warn!("发送进度事件失败：{}", e);
+                }
+                outcomes
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    jobs.finish(&job_id);
+    info!(
+        job_id = %job_id,
+        failed = failed.load(Ordering::SeqCst),
+        "批量标注完成"
+    );
+    let fresh = results.into_iter().flatten().collect();
+    let route = Route::new(request.provider, &request.model);
+    (job_id, cache::merge(&request.words, cached, fresh, &route))
+}
+
+/// 标注一批单词，可重试的错误会等待后只重试尚未得到结论的单词，最终失败时这些单词记为失败
+async fn run_batch(
+    ctx: AiContext<'_>,
+    limiter: &JobLimiter<'_>,
+    request: &AnnotationJobRequest,
+    batch: &[String],
+) -> Vec<AnnotationOutcome> {
+    let mut outcomes = Vec::new();
+    let mut pending = batch.to_vec();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = annotate_batch(
+            ctx,
+            request.provider,
+            &request.model,
+            &pending,
+            request.proxy.as_deref(),
+            request.max_retries.unwrap_or(1),
+        )
+        .await;
+
+        let error = match result {
+            Ok(batch) => {
+                outcomes.extend(batch.outcomes);
+                match batch.interrupted {
+                    Some((rest, error)) => {
+                        pending = rest;
+                        error
+                    }
+                    None => return outcomes,
+                }
+            }
+            Err(error) => error,
+        };
+
+        let backoff = BASE_BACKOFF * 2u32.pow(attempt - 1);
+        let wait = match &error {
+            // 平台已在 `chat_with_failover` 中按 `Retry-After` 暂停，这里等待相同的时间
+            AppError::RateLimited { retry_after, .. } => {
+                Some(retry_after.map(Duration::from_secs).unwrap_or(backoff))
+            }
+            AppError::Timeout(_) | AppError::Network(_) => Some(backoff),
+            AppError::HttpStatus { status, .. } if *status >= 500 => Some(backoff),
+            _ => None,
+        };
+
+        let error = match wait {
+            Some(wait) if attempt < MAX_BATCH_ATTEMPTS => {
+                warn!(attempt, pending = pending.len(), wait_secs = wait.as_secs(), error = %error, "批次失败，等待后重试");
+                match limiter.sleep(wait).await {
+                    Ok(()) => continue,
+                    Err(cancelled) => cancelled,
+                }
+            }
+            _ => error,
+        };
+
+        warn!(attempt, pending = pending.len(), error = %error, "批次失败");
+        let message = error.to_string();
+        outcomes.extend(
+            pending
+                .into_iter()
+                .map(|word| AnnotationOutcome::failure(word, message.clone())),
+        );
+        return outcomes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ai::mock::{start_exclusive, MockResponse};
+    use crate::utils::db::Database;
+    use crate::utils::secrets::SecretStore;
+    use serde_json::json;
+
+    fn reply(words: &[&str]) -> String {
+        let items: Vec<_> = words
+            .iter()
+            .map(|word| {
+                json!({
+                    "content": word,
+                    "part_of_speech": ["noun"],
+                    "pronunciation": "",
+                    "interpretation": "释义",
+                    "notes": "",
+                    "applicability": 1,
+                })
+            })
+            .collect();
+        json!({ "words": items }).to_string()
+    }
+
+    fn request(words: &[&str]) -> AnnotationJobRequest {
+        serde_json::from_value(json!({
+            "provider": "ChatGPT",
+            "model": "gpt-test",
+            "words": words,
+            "max_retries": 1,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn batch_waits_for_retry_after_and_retries_pending_words() {
+        let (_guard, server) = start_exclusive().await;
+        let db = Database::memory().unwrap();
+        let secrets = SecretStore::memory();
+        secrets.set("ChatGPT", "sk-test").unwrap();
+        let shared = RateLimiter::default();
+        let limiter = JobLimiter::new(&shared, None, Arc::new(AtomicBool::new(false)));
+        let ctx = AiContext {
+            secrets: &secrets,
+            db: &db,
+            lesson_id: None,
+            limiter: Some(&limiter),
+        };
+
+        // 第一次漏掉一个单词，重试时被限流，等待后只重试漏掉的单词
+        server.push_reply(reply(&["quokka"]));
+        server.push("ChatGPT", MockResponse::rate_limited(1));
+        server.push_reply(reply(&["wombat"]));
+
+        let request = request(&["quokka", "wombat"]);
+        let started = Instant::now();
+        let outcomes = run_batch(ctx, &limiter, &request, &request.words).await;
+
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|o| o.ok));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        let last_prompt = requests[2].body["messages"][0]["content"].as_str().unwrap();
+        assert!(last_prompt.contains("wombat") && !last_prompt.contains("quokka"));
+    }
+
+    #[tokio::test]
+    async fn cancelled_job_sends_no_requests() {
+        let (_guard, server) = start_exclusive().await;
+        let db = Database::memory().unwrap();
+        let secrets = SecretStore::memory();
+        secrets.set("ChatGPT", "sk-test").unwrap();
+        let jobs = AnnotationJobs::default();
+        let shared = RateLimiter::default();
+        let limiter = JobLimiter::new(&shared, Some(60), jobs.register("job"));
+        let ctx = AiContext {
+            secrets: &secrets,
+            db: &db,
+            lesson_id: None,
+            limiter: Some(&limiter),
+        };
+
+        assert!(jobs.cancel("job"));
+        assert!(!jobs.cancel("other"));
+
+        let request = request(&["quokka"]);
+        let outcomes = run_batch(ctx, &limiter, &request, &request.words).await;
+        assert!(!outcomes[0].ok);
+        assert_eq!(outcomes[0].error, Some(AppError::Cancelled.to_string()));
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn job_limit_does_not_change_shared_bucket() {
+        let shared = RateLimiter::default();
+        let limiter = JobLimiter::new(&shared, Some(600), Arc::new(AtomicBool::new(false)));
+        limiter.acquire(Provider::Google).await.unwrap();
+
+        // 任务自己的额度只限制本任务，共享的桶仍按平台默认的每分钟15次补充
+        let buckets = shared.buckets.lock().unwrap();
+        assert_eq!(buckets[&Provider::Google].refill_rate, 15.0 / 60.0);
+    }
+}
@@ -0,0 +1,443 @@
+//! 本地模拟AI服务，仅在调试构建中可用
+//!
+//! 提供 OpenAI 兼容（`/chat/completions`、`/models`）、Gemini 兼容（`/models/{model}:generateContent`、`/models`）
+//! 和 Ollama 兼容（`/api/chat`、`/api/tags`）接口，
+//! 开发时不需要真实的API密钥，可以按顺序预设返回内容、错误状态码和延迟（见 `MockServer::push`），
+//! 调试运行时也可以从脚本文件读取预设（见 `MockServer::load_script`）。
+//! 没有预设时，模型回复是根据提示词中的单词数组生成的标注JSON，标注功能可以直接走通。
+//!
+//! 每个平台的地址为 `{url}/{平台名称}`，例如 `http://127.0.0.1:12345/Google`，
+//! 启动后调用 `provider::set_base_url_override(Some(server.url()))` 即可让所有平台请求都发到这里。
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// 预设的响应内容
+#[derive(Clone)]
+pub enum MockBody {
+    /// 模型回复的文本，按请求的接口风格包装成对应格式
+    Reply(String),
+    /// 原样返回的JSON
+    Json(Value),
+    /// 为提示词中最后一个字符串数组（标注模板中的 `{{words}}`）里的每个单词生成标注，按模型回复包装
+    Annotation,
+}
+
+/// 预设的响应
+#[derive(Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub body: MockBody,
+    pub headers: Vec<(String, String)>,
+    /// 返回前等待的时间，用于模拟响应慢的平台
+    pub delay: Duration,
+}
+
+impl MockResponse {
+    /// 成功的模型回复
+    pub fn reply(text: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            body: MockBody::Reply(text.into()),
+            headers: Vec::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// 根据提示词生成的标注回复，没有匹配的预设时使用
+    pub fn annotation() -> Self {
+        Self {
+            body: MockBody::Annotation,
+            ..Self::reply("")
+        }
+    }
+
+    /// 指定状态码和JSON内容的响应，一般用于模拟错误
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            body: MockBody::Json(body),
+            headers: Vec::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// 模拟 429 限流
+    #[cfg(test)]
+    pub fn rate_limited(retry_after_secs: u64) -> Self {
+        Self::json(429, json!({"error": {"message": "Rate limit reached"}}))
+            .with_header("Retry-After", retry_after_secs.to_string())
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// 等待 `delay` 后再返回
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// 脚本文件中的一条预设，例如
+/// `{"path": "/chat/completions", "status": 200, "reply": "...", "delayMs": 3000}`，
+/// `reply` 和 `json` 二选一，都不写时返回根据提示词生成的标注
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScriptRule {
+    #[serde(default)]
+    path: String,
+    #[serde(default = "default_status")]
+    status: u16,
+    reply: Option<String>,
+    json: Option<Value>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    delay_ms: u64,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+/// 收到的请求，用于检查调用方发出的内容
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Value,
+}
+
+struct Rule {
+    /// 路径包含该字符串时匹配，为空时匹配任意请求
+    path_contains: String,
+    response: MockResponse,
+}
+
+struct MockState {
+    /// 预设的响应，按顺序匹配，每条只使用一次
+    rules: VecDeque<Rule>,
+    /// 模型列表接口返回的模型
+    models: Vec<String>,
+    requests: Vec<RecordedRequest>,
+}
+
+/// 模拟AI服务
+pub struct MockServer {
+    addr: SocketAddr,
+    /// 用于预设响应和查看收到的请求
+    state: Arc<Mutex<MockState>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// 在 `127.0.0.1` 的随机端口启动模拟服务，需要在 tokio 运行时中调用
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState {
+            rules: VecDeque::new(),
+            models: vec!["mock-model".to_string()],
+            requests: Vec::new(),
+        }));
+
+        let task_state = state.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let state = task_state.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(stream, state).await {
+                                warn!("模拟AI服务处理请求失败：{}", e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("模拟AI服务接受连接失败：{}", e),
+                }
+            }
+        });
+        debug!(%addr, "模拟AI服务已启动");
+
+        Ok(Self { addr, state, task })
+    }
+
+    /// 服务地址，例如 `http://127.0.0.1:12345`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// 追加一条预设响应，路径包含 `path_contains` 的下一个请求会收到它
+    pub fn push(&self, path_contains: impl Into<String>, response: MockResponse) {
+        self.lock().rules.push_back(Rule {
+            path_contains: path_contains.into(),
+            response,
+        });
+    }
+
+    /// 追加一条任意路径都匹配的模型回复
+    #[cfg(test)]
+    pub fn push_reply(&self, text: impl Into<String>) {
+        self.push("", MockResponse::reply(text));
+    }
+
+    /// 目前为止收到的全部请求
+    #[cfg(test)]
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.lock().requests.clone()
+    }
+
+    /// 从JSON文件读取预设响应，文件内容为 `ScriptRule` 数组，按顺序追加
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回读取的预设数量
+    pub fn load_script(&self, path: &Path) -> io::Result<usize> {
+        let rules: Vec<ScriptRule> = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let count = rules.len();
+        for rule in rules {
+            let mut response = match (rule.json, rule.reply) {
+                (Some(body), _) => MockResponse::json(rule.status, body),
+                (None, Some(reply)) => MockResponse { status: rule.status, ..MockResponse::reply(reply) },
+                (None, None) => MockResponse { status: rule.status, ..MockResponse::annotation() },
+            };
+            for (name, value) in rule.headers {
+                response = response.with_header(name, value);
+            }
+            self.push(rule.path, response.with_delay(Duration::from_millis(rule.delay_ms)));
+        }
+        Ok(count)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 接口地址覆盖是全局的，测试需要依次使用模拟服务
+#[cfg(test)]
+static EXCLUSIVE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// 启动模拟服务并把所有平台的请求都转发到这里，供测试使用
+///
+/// 返回的守卫释放前，其他测试会等待，不会改动接口地址覆盖
+#[cfg(test)]
+pub async fn start_exclusive() -> (tokio::sync::MutexGuard<'static, ()>, MockServer) {
+    let guard = EXCLUSIVE.lock().await;
+    let server = MockServer::start().await.expect("启动模拟AI服务失败");
+    crate::utils::ai::provider::set_base_url_override(Some(server.url()));
+    (guard, server)
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: Arc<Mutex<MockState>>) -> io::Result<()> {
+    let request = read_request(&mut stream).await?;
+    debug!(method = %request.method, path = %request.path, "模拟AI服务收到请求");
+
+    let (response, models) = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requests.push(request.clone());
+        let position = state
+            .rules
+            .iter()
+            .position(|rule| request.path.contains(&rule.path_contains));
+        let response = position.and_then(|i| state.rules.remove(i)).map(|rule| rule.response);
+        (response, state.models.clone())
+    };
+
+    let path = request.path.split('?').next().unwrap_or("");
+    let style = if path.ends_with("/api/chat") || path.ends_with("/api/tags") {
+        Style::Ollama
+    } else if path.contains(":generateContent") || path.starts_with("/Google/") {
+        Style::Gemini
+    } else {
+        Style::OpenAi
+    };
+    let response = response.unwrap_or_else(|| {
+        if request.method == "GET" && (path.ends_with("/models") || path.ends_with("/api/tags")) {
+            MockResponse::json(200, models_body(&models, style))
+        } else {
+            MockResponse::annotation()
+        }
+    });
+    if !response.delay.is_zero() {
+        tokio::time::sleep(response.delay).await;
+    }
+
+    let body = match &response.body {
+        MockBody::Json(body) => body.clone(),
+        MockBody::Reply(text) => reply_body(text, style),
+        MockBody::Annotation => reply_body(&annotation_reply(&request.body), style),
+    };
+    let body = body.to_string();
+
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// 按请求路径判断的接口风格
+#[derive(Clone, Copy)]
+enum Style {
+    OpenAi,
+    Gemini,
+    Ollama,
+}
+
+/// 按接口风格包装模型回复的文本
+fn reply_body(text: &str, style: Style) -> Value {
+    match style {
+        Style::OpenAi => json!({
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": text}}],
+            "usage": {"prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0},
+        }),
+        Style::Gemini => json!({
+            "candidates": [{"content": {"role": "model", "parts": [{"text": text}]}}],
+            "usageMetadata": {"promptTokenCount": 0, "candidatesTokenCount": 0},
+        }),
+        Style::Ollama => json!({
+            "message": {"role": "assistant", "content": text},
+            "done": true,
+            "prompt_eval_count": 0,
+            "eval_count": 0,
+        }),
+    }
+}
+
+fn models_body(models: &[String], style: Style) -> Value {
+    match style {
+        Style::Gemini => json!({
+            "models": models.iter().map(|id| json!({
+                "name": format!("models/{}", id),
+                "displayName": id,
+                "inputTokenLimit": 32768,
+                "supportedGenerationMethods": ["generateContent"],
+            })).collect::<Vec<_>>()
+        }),
+        Style::OpenAi => json!({
+            "object": "list",
+            "data": models.iter().map(|id| json!({"id": id, "object": "model"})).collect::<Vec<_>>()
+        }),
+        Style::Ollama => json!({
+            "models": models.iter().map(|id| json!({"name": id, "model": id})).collect::<Vec<_>>()
+        }),
+    }
+}
+
+fn annotation_reply(body: &Value) -> String {
+    let words = last_prompt(body).map(|prompt| word_list(&prompt)).unwrap_or_default();
+    let items: Vec<Value> = words
+        .iter()
+        .map(|word| {
+            json!({
+                "content": word,
+                "part_of_speech": ["noun"],
+                "pronunciation": "",
+                "interpretation": format!("{}（模拟释义）", word),
+                "notes": "",
+                "applicability": 1,
+            })
+        })
+        .collect();
+    json!({ "words": items }).to_string()
+}
+
+/// 请求中最后一条消息的文本，兼容 OpenAI/Ollama 的 `messages` 和 Gemini 的 `contents`
+fn last_prompt(body: &Value) -> Option<String> {
+    if let Some(message) = body["messages"].as_array().and_then(|messages| messages.last()) {
+        return message["content"].as_str().map(str::to_string);
+    }
+    let content = body["contents"].as_array()?.last()?;
+    let parts = content["parts"].as_array()?;
+    Some(parts.iter().filter_map(|part| part["text"].as_str()).collect())
+}
+
+fn word_list(prompt: &str) -> Vec<String> {
+    prompt
+        .rmatch_indices('[')
+        .find_map(|(start, _)| {
+            serde_json::Deserializer::from_str(&prompt[start..])
+                .into_iter::<Vec<String>>()
+                .next()
+                .and_then(Result::ok)
+        })
+        .unwrap_or_default()
+}
+
+/// 读取一个 HTTP/1.1 请求，只支持 `Content-Length` 形式的请求体
+async fn read_request(stream: &mut TcpStream) -> io::Result<RecordedRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "连接已关闭"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_string();
+    let path = request_line.next().unwrap_or("").to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let body = serde_json::from_slice(&buffer[header_end..]).unwrap_or(Value::Null);
+    Ok(RecordedRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
@@ -0,0 +1,999 @@
+pub mod annotate;
+pub mod cache;
+pub mod conversation;
+pub mod failover;
+pub mod grammar;
+pub mod jobs;
+#[cfg(any(test, debug_assertions))]
+pub mod mock;
+pub mod models;
+pub mod provider;
+pub mod template;
+#[cfg(test)]
+mod tests;
+pub mod usage;
+
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use crate::utils::http::{build_client, send_json};
+use crate::utils::secrets::SecretStore;
+use annotate::AnnotationPrompt;
+use cache::{annotate_cached, CacheFilter};
+use failover::{chat_with_failover, Route};
+use conversation::StartSessionRequest;
+use grammar::AnalyzeSentenceRequest;
+use jobs::{run_annotation_job, AnnotationJobRequest, AnnotationJobs, JobLimiter, RateLimiter};
+use models::ModelCache;
+use provider::{ChatMessage, LocalApi, LocalEndpoint, Provider};
+use template::{context_vars, PromptKind};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tauri::{AppHandle, State, Url};
+use tracing::{info, warn};
+use usage::{count_message_tokens, count_tokens, ModelFamily, ModelPrice, Usage, UsageFilter, UsageGroup};
+
+#[derive(Deserialize)]
+pub struct AiChatRequest {
+    pub provider: Provider,
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub proxy: Option<String>,
+    /// 所属课题ID，用于按课文统计用量
+    pub lesson_id: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct AnnotateRequest {
+    pub provider: Provider,
+    pub model: String,
+    pub words: Vec<String>,
+    pub proxy: Option<String>,
+    /// 失败单词的最大重试次数，默认1次
+    pub max_retries: Option<u32>,
+    /// 所属课题ID，用于按课文统计用量
+    pub lesson_id: Option<i64>,
+    /// 是否使用标注缓存，默认使用
+    pub use_cache: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct EstimateCostRequest {
+    pub provider: Provider,
+    pub model: String,
+    /// 对话或翻译的消息
+    #[serde(default)]
+    pub messages: Vec<ChatMessage>,
+    /// 需要标注的单词，按批次构造实际的提示词来估算
+    #[serde(default)]
+    pub words: Vec<String>,
+    /// 标注的每批单词数量，默认20
+    pub batch_size: Option<usize>,
+    /// 单词所属的课题ID，用于渲染提示词中的课文变量
+    pub lesson_id: Option<i64>,
+    /// 预计的输出token数，不传时对话按与输入等长估算（适合翻译），标注按每个单词固定数量估算
+    pub output_tokens: Option<u64>,
+}
+
+/// 标注时每个单词输出的JSON大约占用的token数
+const ANNOTATION_TOKENS_PER_WORD: u64 = 80;
+
+/// 一次AI调用需要的托管状态，以及用于统计的归属信息
+#[derive(Clone, Copy)]
+pub struct AiContext<'a> {
+    pub secrets: &'a SecretStore,
+    pub db: &'a Database,
+    /// 所属课题ID
+    pub lesson_id: Option<i64>,
+    /// 批量任务的频率限制和取消标记，每次HTTP请求前取令牌，单次调用时为 `None`
+    pub limiter: Option<&'a JobLimiter<'a>>,
+}
+
+/// 读取平台的密钥
+///
+/// 本地模型服务的密钥是可选的，没有配置或密钥存储被锁定时返回 `None`
+pub(crate) fn provider_key(secrets: &SecretStore, provider: Provider) -> Result<Option<String>, AppError> {
+    if provider.requires_key() {
+        secrets.get(provider.name()).map(Some)
+    } else {
+        Ok(secrets.get(provider.name()).ok())
+    }
+}
+
+/// 解析模型输出的JSON，兼容 ```json 代码块
+pub(crate) fn parse_json_output(text: &str) -> Result<Value, serde_json::Error> {
+    let trimmed = text.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(body.trim())
+}
+
+/// 调用AI平台的对话接口
+///
+/// 密钥按平台名称从 `SecretStore` 中读取，不经过前端。响应中的token用量会记入账本，
+/// 平台没有返回用量时按估算值记录。
+///
+/// # Arguments
+///
+/// * `ctx` - 密钥存储、数据库和所属课文
+/// * `provider` - AI平台
+/// * `model` - 模型名称
+/// * `messages` - 对话消息
+/// * `json_schema` - 需要JSON输出时传入结构描述，见 `Provider::chat_body`
+/// * `proxy` - 可选的代理地址
+///
+/// # Returns
+///
+/// * 成功返回模型输出的文本
+/// * 缺少密钥、请求失败或响应格式不正确时返回 `Err(AppError)`
+pub async fn chat(
+    ctx: AiContext<'_>,
+    provider: Provider,
+    model: &str,
+    messages: &[ChatMessage],
+    json_schema: Option<&Value>,
+    proxy: Option<&str>,
+) -> Result<String, AppError> {
+    let api_key = provider_key(ctx.secrets, provider)?;
+    let client = build_client(proxy, Some(provider.timeout()))?;
+
+    let response = send_json(
+        &client,
+        "POST",
+        &provider.chat_url(model),
+        provider.headers(api_key.as_deref())?,
+        &provider.chat_body(model, messages, json_schema),
+    )
+    .await?;
+    let text = provider.parse_chat(&response)?;
+
+    let (usage, estimated) = match provider.parse_usage(&response) {
+        Some(usage) => (usage, false),
+        None => {
+            let family = ModelFamily::of(provider, model);
+            let usage = Usage {
+                prompt_tokens: count_message_tokens(family, messages),
+                completion_tokens: count_tokens(family, &text),
+            };
+            (usage, true)
+        }
+    };
+    // 记账失败不影响本次调用的结果
+    if let Err(e) = usage::record_usage(ctx.db, provider, model, ctx.lesson_id, usage, estimated) {
+        warn!("记录AI用量失败：{}", e);
+    }
+
+    Ok(text)
+}
+
+/// AI对话
+///
+/// # Arguments
+///
+/// * `request` - 平台、模型、消息和代理 `AiChatRequest`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.text` 为模型输出，`data.answered_by` 为实际答复的 `{provider, model}`
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(provider = request.provider.name(), model = %request.model))]
+pub async fn ai_chat(
+    secrets: State<'_, SecretStore>,
+    db: State<'_, Database>,
+    request: AiChatRequest,
+) -> Result<CustomResult, CustomResult> {
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: request.lesson_id,
+        limiter: None,
+    };
+    let reply = chat_with_failover(
+        ctx,
+        request.provider,
+        &request.model,
+        &request.messages,
+        None,
+        request.proxy.as_deref(),
+    )
+    .await?;
+    info!("AI对话完成");
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"text": reply.text, "answered_by": reply.route})),
+    ))
+}
+
+/// 设置本地模型服务
+///
+/// 配置保存在 `appSetting` 中，启动时自动加载
+///
+/// # Arguments
+///
+/// * `base_url` - 服务地址，例如 Ollama 的 `http://localhost:11434`、llama.cpp 的 `http://localhost:8080/v1`
+/// * `api` - 接口类型，`open_ai` 或 `ollama`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为生效的配置 `{base_url, api}`
+/// * 地址无效返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn set_local_ai_endpoint(
+    db: State<'_, Database>,
+    base_url: String,
+    api: LocalApi,
+) -> Result<CustomResult, CustomResult> {
+    let url = Url::parse(base_url.trim())
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or_else(|| AppError::InvalidArgument(Msg::new("ai.invalid_local_url").arg("url", &base_url)))?;
+
+    provider::save_local_endpoint(
+        &db,
+        LocalEndpoint {
+            base_url: url.to_string(),
+            api,
+        },
+    )?;
+    info!("已设置本地模型服务");
+
+    Ok(CustomResult::success(None, Some(json!(provider::local_endpoint()))))
+}
+
+/// 获取AI平台的模型列表
+///
+/// 结果会缓存一小时，前端可以传 `refresh` 强制刷新
+///
+/// # Arguments
+///
+/// * `provider` - AI平台
+/// * `proxy` - 可选的代理地址
+/// * `refresh` - 是否忽略缓存
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.models` 为 `[{id, display_name, context_length}]`，`data.cached` 表示是否来自缓存
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(cache, secrets, proxy))]
+pub async fn list_models(
+    cache: State<'_, ModelCache>,
+    secrets: State<'_, SecretStore>,
+    provider: Provider,
+    proxy: Option<String>,
+    refresh: Option<bool>,
+) -> Result<CustomResult, CustomResult> {
+    let (models, cached) = models::list_models(
+        &cache,
+        &secrets,
+        provider,
+        proxy.as_deref(),
+        refresh.unwrap_or(false),
+    )
+    .await?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"models": models, "cached": cached})),
+    ))
+}
+
+/// AI批量标注单词
+///
+/// 要求模型输出JSON并逐项校验，每个单词单独返回成功或失败，不会因为个别单词格式错误而丢失整批数据。
+/// 同一平台、模型和提示词模板下标注过的单词直接从缓存返回
+///
+/// # Arguments
+///
+/// * `request` - 平台、模型、单词列表等 `AnnotateRequest`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.results` 为每个单词的结果（`cached` 表示是否来自缓存），`data.succeeded`、`data.failed` 为数量；
+///   请求中途失败时已经得到的结果照常返回，其余单词记为失败
+/// * 一个单词都没有得到结果时返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(provider = request.provider.name(), model = %request.model, words = request.words.len()))]
+pub async fn annotate_words(
+    secrets: State<'_, SecretStore>,
+    db: State<'_, Database>,
+    request: AnnotateRequest,
+) -> Result<CustomResult, CustomResult> {
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: request.lesson_id,
+        limiter: None,
+    };
+    let results = annotate_cached(
+        ctx,
+        request.provider,
+        &request.model,
+        &request.words,
+        request.proxy.as_deref(),
+        request.max_retries.unwrap_or(1),
+        request.use_cache.unwrap_or(true),
+    )
+    .await?;
+    let succeeded = results.iter().filter(|r| r.ok).count();
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "results": results,
+            "succeeded": succeeded,
+            "failed": results.len() - succeeded,
+        })),
+    ))
+}
+
+/// 解析半自动标注中用户粘贴回来的AI回复
+///
+/// 提示词由 `render_prompt` 生成，要求模型输出JSON，这里按与 `annotate_words` 相同的规则逐项校验
+///
+/// # Arguments
+///
+/// * `text` - 用户粘贴的AI回复
+/// * `words` - 这一批发给AI的单词
+///
+/// # Returns
+///
+/// * `Ok(CustomResult::success)`，`data.results` 为每个单词的结果，格式与 `annotate_words` 相同
+#[tauri::command]
+#[tracing::instrument(skip(text))]
+pub async fn parse_annotation_reply(text: String, words: Vec<String>) -> Result<CustomResult, CustomResult> {
+    let results = annotate::parse_reply(&text, &words);
+    Ok(CustomResult::success(None, Some(json!({"results": results}))))
+}
+
+/// 批量标注整课的单词
+///
+/// 单词在Rust端分批、限流并发执行，进度通过 `annotation-progress` 事件通知前端，`{job_id, total, done, failed}`。
+/// 运行中的任务可以用 `cancel_annotation_job` 取消
+///
+/// # Arguments
+///
+/// * `request` - 任务参数 `AnnotationJobRequest`
+///
+/// # Returns
+///
+/// * `Ok(CustomResult::success)`，`data.job_id` 为任务ID，`data.results` 为每个单词的结果，`data.succeeded`、`data.failed` 为数量
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(provider = request.provider.name(), model = %request.model, words = request.words.len()))]
+pub async fn start_annotation_job(
+    app: AppHandle,
+    secrets: State<'_, SecretStore>,
+    db: State<'_, Database>,
+    limiter: State<'_, RateLimiter>,
+    jobs: State<'_, AnnotationJobs>,
+    request: AnnotationJobRequest,
+) -> Result<CustomResult, CustomResult> {
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: request.lesson_id,
+        limiter: None,
+    };
+    let (job_id, results) = run_annotation_job(&app, ctx, &limiter, &jobs, request).await;
+    let succeeded = results.iter().filter(|r| r.ok).count();
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "job_id": job_id,
+            "results": results,
+            "succeeded": succeeded,
+            "failed": results.len() - succeeded,
+        })),
+    ))
+}
+
+/// 取消批量标注任务
+///
+/// 已经发出的请求会等待其完成，其余单词记为失败，`start_annotation_job` 照常返回已经得到的结果
+///
+/// # Arguments
+///
+/// * `job_id` - 任务ID
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`
+/// * 任务不存在或已经结束时返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(jobs))]
+pub async fn cancel_annotation_job(
+    jobs: State<'_, AnnotationJobs>,
+    job_id: String,
+) -> Result<CustomResult, CustomResult> {
+    if !jobs.cancel(&job_id) {
+        return Err(AppError::InvalidArgument(Msg::new("ai.job_not_found").arg("job_id", &job_id)).into());
+    }
+    info!("已取消标注任务");
+    Ok(CustomResult::success(None, None))
+}
+
+/// 估算AI请求的token数和费用
+///
+/// 对话按 `messages` 估算一次请求；传了 `words` 时按标注任务估算，每批构造实际的提示词。
+/// token数为近似值（见 `usage::count_tokens`），价格来自价格表，找不到价格时 `cost` 为 `null`
+///
+/// # Arguments
+///
+/// * `request` - 平台、模型和请求内容 `EstimateCostRequest`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为 `{family, requests, input_tokens, output_tokens, price, cost}`
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(provider = request.provider.name(), model = %request.model))]
+pub async fn estimate_ai_cost(
+    db: State<'_, Database>,
+    request: EstimateCostRequest,
+) -> Result<CustomResult, CustomResult> {
+    let family = ModelFamily::of(request.provider, &request.model);
+
+    let (requests, input_tokens, default_output) = if request.words.is_empty() {
+        let input_tokens = count_message_tokens(family, &request.messages);
+        (1, input_tokens, input_tokens)
+    } else {
+        let batch_size = request.batch_size.unwrap_or(20).max(1);
+        let batches: Vec<&[String]> = request.words.chunks(batch_size).collect();
+        let prompt = AnnotationPrompt::load(&db, request.lesson_id)?;
+        let input_tokens = batches
+            .iter()
+            .map(|batch| count_message_tokens(family, &[ChatMessage::user(prompt.render(batch))]))
+            .sum();
+        (
+            batches.len() as u64,
+            input_tokens,
+            request.words.len() as u64 * ANNOTATION_TOKENS_PER_WORD,
+        )
+    };
+
+    let estimate = usage::estimate(
+        &db,
+        request.provider,
+        &request.model,
+        requests,
+        input_tokens,
+        request.output_tokens.unwrap_or(default_output),
+    )?;
+
+    Ok(CustomResult::success(None, Some(json!(estimate))))
+}
+
+/// 查询AI用量账本
+///
+/// # Arguments
+///
+/// * `group_by` - 分组方式，`provider`、`model`、`day` 或 `lesson`
+/// * `filter` - 可选的查询条件 `{provider, lesson_id, from, to}`，日期格式 `YYYY-MM-DD`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.groups` 为 `[{key, requests, prompt_tokens, completion_tokens, cost}]`，`data.total_cost` 为总费用
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn get_ai_usage(
+    db: State<'_, Database>,
+    group_by: UsageGroup,
+    filter: Option<UsageFilter>,
+) -> Result<CustomResult, CustomResult> {
+    let groups = usage::query_usage(&db, group_by, &filter.unwrap_or_default())?;
+    let total_cost: f64 = groups.iter().map(|g| g.cost).sum();
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"groups": groups, "total_cost": total_cost})),
+    ))
+}
+
+/// 获取当前生效的价格表
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.prices` 为 `[{provider, model_prefix, input_price, output_price, custom}]`，价格单位为美元每百万token
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+pub async fn get_ai_prices(db: State<'_, Database>) -> Result<CustomResult, CustomResult> {
+    let prices = usage::prices(&db)?;
+    Ok(CustomResult::success(None, Some(json!({"prices": prices}))))
+}
+
+/// 设置自定义价格
+///
+/// 会替换之前的全部自定义价格，与内置价格的平台和前缀相同时覆盖内置价格，传空数组恢复内置价格表
+///
+/// # Arguments
+///
+/// * `prices` - 自定义价格，`custom` 字段可以省略
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.prices` 为新的价格表
+/// * 价格为负数或写入失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(count = prices.len()))]
+pub async fn set_ai_prices(
+    db: State<'_, Database>,
+    prices: Vec<ModelPrice>,
+) -> Result<CustomResult, CustomResult> {
+    usage::set_custom_prices(&db, &prices)?;
+    info!("已更新自定义价格");
+
+    let prices = usage::prices(&db)?;
+    Ok(CustomResult::success(None, Some(json!({"prices": prices}))))
+}
+
+/// 分页查询标注缓存
+///
+/// # Arguments
+///
+/// * `filter` - 可选的查询条件 `{provider, model, word, stale_only}`
+/// * `limit` - 每页数量，默认50
+/// * `offset` - 跳过的数量，默认0
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.entries` 为当前页的缓存，`data.total` 为符合条件的总数，
+///   `data.max_entries` 为数量上限，`data.template_hash` 为当前提示词模板的哈希，
+///   条目的 `prompt_hash` 不以它开头时为旧模板的缓存
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn get_annotation_cache(
+    db: State<'_, Database>,
+    filter: Option<CacheFilter>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+) -> Result<CustomResult, CustomResult> {
+    let (entries, total) = cache::list(
+        &db,
+        &filter.unwrap_or_default(),
+        limit.unwrap_or(50),
+        offset.unwrap_or(0),
+    )?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "entries": entries,
+            "total": total,
+            "max_entries": cache::max_entries(&db)?,
+            "template_hash": cache::template_hash(&db)?,
+        })),
+    ))
+}
+
+/// 清除标注缓存
+///
+/// # Arguments
+///
+/// * `filter` - 可选的条件 `{provider, model, word, stale_only}`，不传时清除全部缓存
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.removed` 为删除的数量
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn clear_annotation_cache(
+    db: State<'_, Database>,
+    filter: Option<CacheFilter>,
+) -> Result<CustomResult, CustomResult> {
+    let removed = cache::invalidate(&db, &filter.unwrap_or_default())?;
+    info!(removed, "已清除标注缓存");
+
+    Ok(CustomResult::success(None, Some(json!({"removed": removed}))))
+}
+
+/// 设置标注缓存的数量上限
+///
+/// 超出上限时删除最久未使用的条目
+///
+/// # Arguments
+///
+/// * `max_entries` - 最多缓存的单词数量，为0时不再缓存
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.max_entries` 为新的上限，`data.removed` 为立即删除的数量
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn set_annotation_cache_limit(
+    db: State<'_, Database>,
+    max_entries: u64,
+) -> Result<CustomResult, CustomResult> {
+    let removed = cache::set_max_entries(&db, max_entries)?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"max_entries": max_entries, "removed": removed})),
+    ))
+}
+
+/// 开始对话练习
+///
+/// 没有自定义系统提示词时按练习语言、场景和课文生成。传了 `opening` 时立即发送学习者的第一句话
+///
+/// # Arguments
+///
+/// * `request` - 对话设置 `StartSessionRequest`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.session` 为新建的对话，传了 `opening` 时 `data.reply` 为模型的回复，
+///   `data.answered_by` 为实际答复的 `{provider, model}`
+/// * 失败返回 `Err(CustomResult)`，发送第一句话失败时对话仍会保留
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(provider = request.provider.name(), model = %request.model))]
+pub async fn start_chat_session(
+    secrets: State<'_, SecretStore>,
+    db: State<'_, Database>,
+    cache: State<'_, ModelCache>,
+    request: StartSessionRequest,
+) -> Result<CustomResult, CustomResult> {
+    let session = conversation::create_session(&db, &request)?;
+    info!(session_id = session.id, "已创建对话");
+
+    let turn = match request.opening.as_deref().filter(|o| !o.trim().is_empty()) {
+        Some(opening) => {
+            let ctx = AiContext {
+                secrets: &secrets,
+                db: &db,
+                lesson_id: session.lesson_id,
+                limiter: None,
+            };
+            let window = conversation::context_window(&cache, session.provider, &session.model);
+            Some(conversation::send_message(ctx, &session, opening, window, request.proxy.as_deref()).await?)
+        }
+        None => None,
+    };
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "session": conversation::get_session(&db, session.id)?,
+            "reply": turn.as_ref().map(|t| &t.reply),
+            "answered_by": turn.as_ref().map(|t| &t.answered_by),
+            "trimmed": turn.as_ref().map_or(0, |t| t.trimmed),
+        })),
+    ))
+}
+
+/// 在对话中发送一条消息
+///
+/// 历史消息超出模型的上下文长度时，从最早的消息开始裁剪，系统提示词总是保留
+///
+/// # Arguments
+///
+/// * `session_id` - 对话ID
+/// * `content` - 学习者的消息
+/// * `proxy` - 可选的代理地址
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.reply` 为模型的回复，`data.answered_by` 为实际答复的 `{provider, model}`，
+///   `data.trimmed` 为本次没有发送的历史消息数量
+/// * 失败返回 `Err(CustomResult)`，此时消息不会保存
+#[tauri::command]
+#[tracing::instrument(skip(secrets, db, cache, content, proxy))]
+pub async fn continue_chat_session(
+    secrets: State<'_, SecretStore>,
+    db: State<'_, Database>,
+    cache: State<'_, ModelCache>,
+    session_id: i64,
+    content: String,
+    proxy: Option<String>,
+) -> Result<CustomResult, CustomResult> {
+    let session = conversation::get_session(&db, session_id)?;
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: session.lesson_id,
+        limiter: None,
+    };
+    let window = conversation::context_window(&cache, session.provider, &session.model);
+    let turn = conversation::send_message(ctx, &session, &content, window, proxy.as_deref()).await?;
+
+    Ok(CustomResult::success(None, Some(json!(turn))))
+}
+
+/// 对话列表
+///
+/// # Arguments
+///
+/// * `lesson_id` - 只列出该课文的对话，不传时列出全部
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.sessions` 为对话列表，按最后更新时间倒序
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn list_chat_sessions(
+    db: State<'_, Database>,
+    lesson_id: Option<i64>,
+) -> Result<CustomResult, CustomResult> {
+    let sessions = conversation::list_sessions(&db, lesson_id)?;
+    Ok(CustomResult::success(None, Some(json!({"sessions": sessions}))))
+}
+
+/// 读取对话和全部消息
+///
+/// # Arguments
+///
+/// * `session_id` - 对话ID
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.session` 为对话，`data.messages` 为按时间顺序的消息
+/// * 对话不存在返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn get_chat_session(
+    db: State<'_, Database>,
+    session_id: i64,
+) -> Result<CustomResult, CustomResult> {
+    let session = conversation::get_session(&db, session_id)?;
+    let messages = conversation::session_messages(&db, session_id)?;
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"session": session, "messages": messages})),
+    ))
+}
+
+/// AI分析句子的语法
+///
+/// 结果按课文和句子序号保存，同一句子再次分析时直接返回保存的结果，除非传了 `refresh`
+///
+/// # Arguments
+///
+/// * `request` - 平台、模型、课文、句子序号和句子 `AnalyzeSentenceRequest`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.analysis` 为 `{sentence, translation, clauses, grammar_points, difficulty}`，
+///   `data.answered_by` 为答复的 `{provider, model}`，`data.cached` 表示是否为保存的结果
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(provider = request.provider.name(), model = %request.model, lesson_id = request.lesson_id, sentence_index = request.sentence_index))]
+pub async fn analyze_sentence(
+    secrets: State<'_, SecretStore>,
+    db: State<'_, Database>,
+    request: AnalyzeSentenceRequest,
+) -> Result<CustomResult, CustomResult> {
+    if !request.refresh {
+        if let Some(stored) = grammar::find(&db, request.lesson_id, request.sentence_index, &request.sentence)? {
+            return Ok(CustomResult::success(
+                None,
+                Some(json!({
+                    "analysis": stored.analysis,
+                    "answered_by": {"provider": stored.provider, "model": stored.model},
+                    "cached": true,
+                })),
+            ));
+        }
+    }
+
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: Some(request.lesson_id),
+        limiter: None,
+    };
+    let (analysis, route) = grammar::analyze(
+        ctx,
+        request.provider,
+        &request.model,
+        &request.sentence,
+        request.proxy.as_deref(),
+    )
+    .await?;
+    grammar::save(
+        &db,
+        request.lesson_id,
+        request.sentence_index,
+        &route,
+        &analysis,
+    )?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"analysis": analysis, "answered_by": route, "cached": false})),
+    ))
+}
+
+/// 获取课文已保存的全部语法分析
+///
+/// # Arguments
+///
+/// * `lesson_id` - 课题ID
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.analyses` 为 `[{lesson_id, sentence_index, provider, model, analysis, create_time}]`，按句子序号排序
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn get_sentence_analyses(
+    db: State<'_, Database>,
+    lesson_id: i64,
+) -> Result<CustomResult, CustomResult> {
+    let analyses = grammar::list(&db, lesson_id)?;
+    Ok(CustomResult::success(None, Some(json!({"analyses": analyses}))))
+}
+
+/// 获取全部提示词模板
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.templates` 为 `[{kind, template, default_template, custom, variables, legacy_template}]`，
+///   `legacy_template` 为没有迁移成模板的旧版本自定义提示词（没有时为 `null`），`data.native_language` 为学习者的母语
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn get_prompt_templates(db: State<'_, Database>) -> Result<CustomResult, CustomResult> {
+    let mut templates = Vec::new();
+    for kind in PromptKind::ALL {
+        let custom = kind.custom_source(&db)?;
+        templates.push(json!({
+            "kind": kind,
+            "custom": custom.is_some(),
+            "template": custom.as_deref().unwrap_or(kind.default_template()),
+            "default_template": kind.default_template(),
+            "variables": kind.variables(),
+            "legacy_template": kind.legacy_source(&db)?,
+        }));
+    }
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "templates": templates,
+            "native_language": template::native_language(&db)?,
+        })),
+    ))
+}
+
+/// 保存自定义的提示词模板
+///
+/// 模板有语法错误或使用了未知变量时不会保存。修改标注模板后，旧的标注缓存不再命中
+///
+/// # Arguments
+///
+/// * `kind` - 模板类型，`annotation`、`translation`、`grammar` 或 `conversation`
+/// * `template` - 模板内容，为空时恢复内置模板
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`
+/// * 失败返回 `Err(CustomResult)`，未知变量会在错误信息中列出
+#[tauri::command]
+#[tracing::instrument(skip(db, template))]
+pub async fn save_prompt_template(
+    db: State<'_, Database>,
+    kind: PromptKind,
+    template: Option<String>,
+) -> Result<CustomResult, CustomResult> {
+    kind.save(&db, template.as_deref())?;
+    info!("已保存提示词模板");
+
+    Ok(CustomResult::success(None, None))
+}
+
+/// 删除没有迁移成模板的旧版本自定义提示词，用户手动改写后不再提示
+///
+/// # Arguments
+///
+/// * `kind` - 模板类型
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn dismiss_legacy_prompt(
+    db: State<'_, Database>,
+    kind: PromptKind,
+) -> Result<CustomResult, CustomResult> {
+    kind.dismiss_legacy(&db)?;
+    Ok(CustomResult::success(None, None))
+}
+
+/// 设置学习者的母语，提示词模板中的 `{{native_language}}`
+///
+/// # Arguments
+///
+/// * `language` - 母语，例如 `简体中文`，为空时恢复默认值
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn set_native_language(
+    db: State<'_, Database>,
+    language: String,
+) -> Result<CustomResult, CustomResult> {
+    template::set_native_language(&db, &language)?;
+    Ok(CustomResult::success(None, None))
+}
+
+/// 用当前的提示词模板生成提示词，供前端自行调用AI的功能（例如翻译）使用
+///
+/// # Arguments
+///
+/// * `kind` - 模板类型
+/// * `variables` - 模板变量，例如翻译的 `{text}`，会覆盖按课文生成的同名变量
+/// * `lesson_id` - 可选的课题ID，提供 `language`、`lesson_title` 等变量
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.prompt` 为生成的提示词
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db, variables))]
+pub async fn render_prompt(
+    db: State<'_, Database>,
+    kind: PromptKind,
+    variables: Option<Map<String, Value>>,
+    lesson_id: Option<i64>,
+) -> Result<CustomResult, CustomResult> {
+    let mut vars = context_vars(&db, lesson_id)?;
+    vars.extend(variables.unwrap_or_default());
+    let prompt = kind.load(&db)?.render(&vars);
+
+    Ok(CustomResult::success(None, Some(json!({"prompt": prompt}))))
+}
+
+/// 获取备用链
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.chain` 为 `[{provider, model}]`
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn get_failover_chain(db: State<'_, Database>) -> Result<CustomResult, CustomResult> {
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"chain": failover::chain(&db)?})),
+    ))
+}
+
+/// 设置备用链
+///
+/// 首选平台遇到密钥被拒绝、额度不足、限流、网络或服务端故障时，按顺序尝试链上的平台，没有配置密钥的平台会被跳过
+///
+/// # Arguments
+///
+/// * `chain` - 按顺序排列的 `[{provider, model}]`，为空时关闭备用
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db))]
+pub async fn set_failover_chain(
+    db: State<'_, Database>,
+    chain: Vec<Route>,
+) -> Result<CustomResult, CustomResult> {
+    failover::set_chain(&db, &chain)?;
+    info!(length = chain.len(), "已保存备用链");
+    Ok(CustomResult::success(None, None))
+}
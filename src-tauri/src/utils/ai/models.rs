@@ -0,0 +1,203 @@
+use crate::utils::ai::provider::{ApiStyle, Provider};
+use crate::utils::ai::provider_key;
+use crate::utils::error::AppError;
+use crate::utils::http::{build_client, send_json};
+use crate::utils::i18n::Msg;
+use crate::utils::secrets::SecretStore;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Url;
+
+/// 模型列表的缓存时间
+const MODEL_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// 统一格式的模型信息
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    /// 上下文长度（token），平台没有返回时为 `None`
+    pub context_length: Option<u64>,
+}
+
+/// 各平台模型列表的缓存，作为 tauri 的托管状态使用
+#[derive(Default)]
+pub struct ModelCache {
+    entries: Mutex<HashMap<Provider, (Instant, Vec<ModelInfo>)>>,
+}
+
+impl ModelCache {
+    fn get(&self, provider: Provider) -> Option<Vec<ModelInfo>> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(&provider)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < MODEL_CACHE_TTL)
+            .map(|(_, models)| models.clone())
+    }
+
+    /// 已缓存的模型上下文长度，没有缓存或平台没有返回时为 `None`
+    pub fn context_length(&self, provider: Provider, model: &str) -> Option<u64> {
+        self.get(provider)?
+            .into_iter()
+            .find(|m| m.id == model)
+            .and_then(|m| m.context_length)
+    }
+
+    fn put(&self, provider: Provider, models: Vec<ModelInfo>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(provider, (Instant::now(), models));
+    }
+}
+
+/// 获取平台的模型列表
+///
+/// # Arguments
+///
+/// * `cache` - 模型列表缓存
+/// * `secrets` - 密钥存储
+/// * `provider` - AI平台
+/// * `proxy` - 可选的代理地址
+/// * `refresh` - 为 `true` 时忽略缓存重新获取
+///
+/// # Returns
+///
+/// * 成功返回模型列表，并且 `bool` 表示是否来自缓存
+/// * 请求失败返回 `Err(AppError)`
+pub async fn list_models(
+    cache: &ModelCache,
+    secrets: &SecretStore,
+    provider: Provider,
+    proxy: Option<&str>,
+    refresh: bool,
+) -> Result<(Vec<ModelInfo>, bool), AppError> {
+    if !refresh {
+        if let Some(models) = cache.get(provider) {
+            return Ok((models, true));
+        }
+    }
+
+    let api_key = provider_key(secrets, provider)?;
+    let api_key = api_key.as_deref();
+    let client = build_client(proxy, None)?;
+    let url = format!("{}/models", provider.base_url());
+
+    let mut models = match provider.style() {
+        ApiStyle::OpenAi => {
+            let response = send_json(&client, "GET", &url, provider.headers(api_key)?, &Value::Null).await?;
+            parse_openai_models(&response)
+        }
+        ApiStyle::Ollama => {
+            let url = format!("{}/api/tags", provider.base_url());
+            let response = send_json(&client, "GET", &url, provider.headers(api_key)?, &Value::Null).await?;
+            parse_ollama_models(&response)
+        }
+        ApiStyle::Gemini => {
+            // Gemini 的模型列表是分页返回的
+            let mut models = Vec::new();
+            let mut page_token: Option<String> = None;
+            loop {
+                // 分页令牌可能包含 `+`、`/`、`=`，需要编码后再放进查询参数
+                let mut page_url = Url::parse(&url)
+                    .map_err(|e| AppError::InvalidArgument(Msg::new("error.invalid_argument").arg("detail", e)))?;
+                page_url.query_pairs_mut().append_pair("pageSize", "1000");
+                if let Some(token) = &page_token {
+                    page_url.query_pairs_mut().append_pair("pageToken", token);
+                }
+                let response =
+                    send_json(&client, "GET", page_url.as_str(), provider.headers(api_key)?, &Value::Null).await?;
+                models.extend(parse_gemini_models(&response));
+
+                page_token = response["nextPageToken"]
+                    .as_str()
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string);
+                if page_token.is_none() {
+                    break;
+                }
+            }
+            models
+        }
+    };
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+
+    cache.put(provider, models.clone());
+    Ok((models, false))
+}
+
+/// 解析 OpenAI 兼容接口 `/models` 的响应
+///
+/// 标准响应只有 `id`，Groq 等平台会额外返回 `context_window` 或 `context_length`
+fn parse_openai_models(response: &Value) -> Vec<ModelInfo> {
+    response["data"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let id = item["id"].as_str()?.to_string();
+                    let context_length = item["context_window"]
+                        .as_u64()
+                        .or_else(|| item["context_length"].as_u64());
+                    Some(ModelInfo {
+                        display_name: id.clone(),
+                        id,
+                        context_length,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 解析 Ollama `/api/tags` 的响应，本地模型没有上下文长度信息
+fn parse_ollama_models(response: &Value) -> Vec<ModelInfo> {
+    response["models"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let id = item["name"].as_str()?.to_string();
+                    Some(ModelInfo {
+                        display_name: id.clone(),
+                        id,
+                        context_length: None,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 解析 Gemini `models` 的响应，只保留支持 `generateContent` 的模型
+fn parse_gemini_models(response: &Value) -> Vec<ModelInfo> {
+    response["models"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter(|item| {
+                    item["supportedGenerationMethods"]
+                        .as_array()
+                        .is_some_and(|methods| methods.iter().any(|m| m == "generateContent"))
+                })
+                .filter_map(|item| {
+                    let name = item["name"].as_str()?;
+                    let id = name.strip_prefix("models/").unwrap_or(name).to_string();
+                    let display_name = item["displayName"]
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| id.clone());
+                    Some(ModelInfo {
+                        id,
+                        display_name,
+                        context_length: item["inputTokenLimit"].as_u64(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,308 @@
+use crate::utils::ai::usage::Usage;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri_plugin_http::reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use tracing::warn;
+
+/// 覆盖所有平台的接口地址，用于把请求转发到本地的模拟AI服务
+static BASE_URL_OVERRIDE: RwLock<Option<String>> = RwLock::new(None);
+
+/// 设置或取消接口地址覆盖，设置后平台的地址变为 `{url}/{平台名称}`
+pub fn set_base_url_override(url: Option<String>) {
+    *BASE_URL_OVERRIDE.write().unwrap_or_else(|e| e.into_inner()) = url;
+}
+
+/// `appSetting` 中保存本地模型服务配置的键
+const LOCAL_ENDPOINT_KEY: &str = "ai.localEndpoint";
+
+/// 本地模型服务的配置，未设置时使用 Ollama 的默认地址
+static LOCAL_ENDPOINT: RwLock<Option<LocalEndpoint>> = RwLock::new(None);
+
+/// 本地模型服务的接口类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalApi {
+    /// OpenAI 兼容接口，例如 llama.cpp server 的 `http://localhost:8080/v1`
+    OpenAi,
+    /// Ollama 的 `/api/chat` 接口
+    Ollama,
+}
+
+/// 本地模型服务的地址和接口类型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalEndpoint {
+    pub base_url: String,
+    pub api: LocalApi,
+}
+
+impl Default for LocalEndpoint {
+    fn default() -> Self {
+        Self {
+            base_url: "http://localhost:11434".to_string(),
+            api: LocalApi::Ollama,
+        }
+    }
+}
+
+/// 当前的本地模型服务配置
+pub fn local_endpoint() -> LocalEndpoint {
+    LOCAL_ENDPOINT
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// 设置本地模型服务，`base_url` 结尾的 `/` 会被去掉
+pub fn set_local_endpoint(mut endpoint: LocalEndpoint) {
+    endpoint.base_url = endpoint.base_url.trim().trim_end_matches('/').to_string();
+    *LOCAL_ENDPOINT.write().unwrap_or_else(|e| e.into_inner()) = Some(endpoint);
+}
+
+/// 读取 `appSetting` 中保存的本地模型服务配置并生效，启动时调用
+///
+/// 没有保存或保存的内容无法解析时使用默认配置
+pub fn load_local_endpoint(db: &Database) -> Result<(), AppError> {
+    let Some(value) = db.get_setting(LOCAL_ENDPOINT_KEY)? else {
+        return Ok(());
+    };
+    match serde_json::from_str(&value) {
+        Ok(endpoint) => set_local_endpoint(endpoint),
+        Err(e) => warn!("本地模型服务配置无法解析，使用默认配置：{}", e),
+    }
+    Ok(())
+}
+
+/// 设置本地模型服务并保存到 `appSetting`，下次启动时自动加载
+pub fn save_local_endpoint(db: &Database, endpoint: LocalEndpoint) -> Result<(), AppError> {
+    set_local_endpoint(endpoint);
+    db.set_setting(LOCAL_ENDPOINT_KEY, &serde_json::to_string(&local_endpoint())?)
+}
+
+/// 支持的AI平台，名称与前端 `nowAiPlatform` 及 `option` 表的列名一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Provider {
+    ChatGLM,
+    DeepSeek,
+    Groq,
+    Google,
+    ChatGPT,
+    /// 本地模型服务，不需要密钥
+    Local,
+}
+
+/// 平台接口的风格
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiStyle {
+    /// OpenAI 兼容的 `chat/completions` 接口
+    OpenAi,
+    /// Google Gemini 的 `generateContent` 接口
+    Gemini,
+    /// Ollama 的 `/api/chat` 接口
+    Ollama,
+}
+
+/// 一条对话消息，`role` 为 `system`、`user` 或 `assistant`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+impl Provider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::ChatGLM => "ChatGLM",
+            Provider::DeepSeek => "DeepSeek",
+            Provider::Groq => "Groq",
+            Provider::Google => "Google",
+            Provider::ChatGPT => "ChatGPT",
+            Provider::Local => "Local",
+        }
+    }
+
+    pub fn style(&self) -> ApiStyle {
+        match self {
+            Provider::Google => ApiStyle::Gemini,
+            Provider::Local => match local_endpoint().api {
+                LocalApi::OpenAi => ApiStyle::OpenAi,
+                LocalApi::Ollama => ApiStyle::Ollama,
+            },
+            _ => ApiStyle::OpenAi,
+        }
+    }
+
+    /// 是否必须配置密钥，本地模型服务的密钥是可选的
+    pub fn requires_key(&self) -> bool {
+        !matches!(self, Provider::Local)
+    }
+
+    /// 对话请求的整体超时时间，本地模型首次加载和生成都比较慢，超时时间更长
+    pub fn timeout(&self) -> Duration {
+        match self {
+            Provider::Local => Duration::from_secs(15 * 60),
+            _ => Duration::from_secs(3 * 60),
+        }
+    }
+
+    /// 接口的基础地址，不带结尾的 `/`
+    pub fn base_url(&self) -> String {
+        if let Some(url) = BASE_URL_OVERRIDE.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+            return format!("{}/{}", url, self.name());
+        }
+
+        match self {
+            Provider::ChatGLM => "https://open.bigmodel.cn/api/paas/v4",
+            Provider::DeepSeek => "https://api.deepseek.com",
+            Provider::Groq => "https://api.groq.com/openai/v1",
+            Provider::Google => "https://generativelanguage.googleapis.com/v1beta",
+            Provider::ChatGPT => "https://api.openai.com/v1",
+            Provider::Local => return local_endpoint().base_url,
+        }
+        .to_string()
+    }
+
+    /// 对话接口地址
+    pub fn chat_url(&self, model: &str) -> String {
+        match self.style() {
+            ApiStyle::OpenAi => format!("{}/chat/completions", self.base_url()),
+            ApiStyle::Gemini => format!("{}/models/{}:generateContent", self.base_url(), model),
+            ApiStyle::Ollama => format!("{}/api/chat", self.base_url()),
+        }
+    }
+
+    /// 带密钥的请求头，Gemini 的密钥放在请求头而不是URL中，避免出现在日志和错误信息里
+    ///
+    /// 没有密钥（本地模型服务）时不添加认证头
+    pub fn headers(&self, api_key: Option<&str>) -> Result<HeaderMap, AppError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let Some(api_key) = api_key else {
+            return Ok(headers);
+        };
+        let (name, value) = match self.style() {
+            ApiStyle::OpenAi | ApiStyle::Ollama => (AUTHORIZATION.as_str(), format!("Bearer {}", api_key)),
+            ApiStyle::Gemini => ("x-goog-api-key", api_key.to_string()),
+        };
+        let mut value = HeaderValue::from_str(&value).map_err(|e| {
+            AppError::InvalidArgument(Msg::new("error.invalid_argument").arg("detail", e))
+        })?;
+        value.set_sensitive(true);
+        headers.insert(name, value);
+
+        Ok(headers)
+    }
+
+    /// 对话接口的请求体
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - 模型名称
+    /// * `messages` - 对话消息
+    /// * `json_schema` - 需要JSON输出时传入结构描述。Gemini 使用 `responseSchema` 约束输出；
+    ///                   OpenAI 兼容接口不一定支持 `json_schema`，统一使用 `json_object` 模式，结构由提示词约束；
+    ///                   Ollama 使用 `format: "json"`
+    pub fn chat_body(&self, model: &str, messages: &[ChatMessage], json_schema: Option<&Value>) -> Value {
+        match self.style() {
+            ApiStyle::OpenAi => {
+                let mut body = json!({
+                    "model": model,
+                    "messages": messages,
+                    "stream": false,
+                });
+                if json_schema.is_some() {
+                    body["response_format"] = json!({"type": "json_object"});
+                }
+                body
+            }
+            ApiStyle::Gemini => {
+                let system: Vec<&str> = messages
+                    .iter()
+                    .filter(|m| m.role == "system")
+                    .map(|m| m.content.as_str())
+                    .collect();
+                let contents: Vec<Value> = messages
+                    .iter()
+                    .filter(|m| m.role != "system")
+                    .map(|m| {
+                        let role = if m.role == "assistant" { "model" } else { "user" };
+                        json!({"role": role, "parts": [{"text": m.content}]})
+                    })
+                    .collect();
+
+                let mut body = json!({ "contents": contents });
+                if !system.is_empty() {
+                    body["systemInstruction"] = json!({"parts": [{"text": system.join("\n")}]});
+                }
+                if let Some(schema) = json_schema {
+                    body["generationConfig"] = json!({
+                        "responseMimeType": "application/json",
+                        "responseSchema": schema,
+                    });
+                }
+                body
+            }
+            ApiStyle::Ollama => {
+                let mut body = json!({
+                    "model": model,
+                    "messages": messages,
+                    "stream": false,
+                });
+                if json_schema.is_some() {
+                    body["format"] = json!("json");
+                }
+                body
+            }
+        }
+    }
+
+    /// 从对话接口的响应中取出模型输出的文本
+    pub fn parse_chat(&self, response: &Value) -> Result<String, AppError> {
+        let text = match self.style() {
+            ApiStyle::OpenAi => response["choices"][0]["message"]["content"].as_str(),
+            ApiStyle::Gemini => response["candidates"][0]["content"]["parts"]
+                .as_array()
+                .and_then(|parts| parts.iter().find_map(|part| part["text"].as_str())),
+            ApiStyle::Ollama => response["message"]["content"].as_str(),
+        };
+
+        text.map(str::to_string)
+            .filter(|t| !t.is_empty())
+            .ok_or_else(|| AppError::Parse(Msg::new("ai.empty_response").arg("provider", self.name())))
+    }
+
+    /// 从对话接口的响应中取出token用量，平台没有返回时为 `None`
+    pub fn parse_usage(&self, response: &Value) -> Option<Usage> {
+        let (prompt, completion) = match self.style() {
+            ApiStyle::OpenAi => (&response["usage"]["prompt_tokens"], &response["usage"]["completion_tokens"]),
+            ApiStyle::Gemini => (
+                &response["usageMetadata"]["promptTokenCount"],
+                &response["usageMetadata"]["candidatesTokenCount"],
+            ),
+            ApiStyle::Ollama => (&response["prompt_eval_count"], &response["eval_count"]),
+        };
+        if prompt.is_null() && completion.is_null() {
+            return None;
+        }
+
+        Some(Usage {
+            prompt_tokens: prompt.as_u64().unwrap_or(0),
+            completion_tokens: completion.as_u64().unwrap_or(0),
+        })
+    }
+}
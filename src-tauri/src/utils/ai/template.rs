@@ -0,0 +1,693 @@
+use crate::utils::ai::annotate::PartOfSpeech;
+use crate::utils::ai::grammar::Difficulty;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use tracing::{info, warn};
+
+/// `appSetting` 中保存母语的键
+const NATIVE_LANGUAGE_KEY: &str = "prompt.nativeLanguage";
+/// 没有设置时使用的母语
+const DEFAULT_NATIVE_LANGUAGE: &str = "简体中文";
+
+/// 模板中的一个节点
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    /// `{{name}}`
+    Var(String),
+    /// `{{#if name}}...{{else}}...{{/if}}`
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    /// `{{#each name}}...{{/each}}`，块内可以使用 `{{this}}` 和 `{{@index}}`
+    Each { name: String, body: Vec<Node> },
+}
+
+/// 解析中还没有闭合的块
+enum Frame {
+    If {
+        name: String,
+        line: usize,
+        then: Option<Vec<Node>>,
+    },
+    Each {
+        name: String,
+        line: usize,
+    },
+}
+
+/// 提示词模板
+///
+/// 支持的语法：
+///
+/// * `{{name}}` - 变量，字符串原样输出，数组和对象输出为JSON，不存在的变量输出空字符串
+/// * `{{#if name}}...{{else}}...{{/if}}` - 条件，空字符串、空数组、`false`、`0` 和不存在的变量为假
+/// * `{{#each name}}...{{/each}}` - 循环数组，块内 `{{this}}` 为当前元素，`{{@index}}` 为从0开始的序号
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    /// 解析模板
+    ///
+    /// # Returns
+    ///
+    /// * 标签没有闭合、块不匹配或标签无法识别时返回 `Err(Msg)`
+    pub fn parse(source: &str) -> Result<Self, Msg> {
+        let mut stack: Vec<(Frame, Vec<Node>)> = Vec::new();
+        let mut nodes = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            let line = source[..source.len() - rest.len() + start].matches('\n').count() + 1;
+            if start > 0 {
+                nodes.push(Node::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| Msg::new("template.unclosed_tag").arg("line", line))?;
+            let tag = after[..end].trim();
+            rest = &after[end + 2..];
+
+            let unexpected = || Msg::new("template.unexpected_tag").arg("tag", tag).arg("line", line);
+            if let Some(name) = tag.strip_prefix("#if ") {
+                let frame = Frame::If {
+                    name: variable_name(name.trim()).ok_or_else(unexpected)?,
+                    line,
+                    then: None,
+                };
+                stack.push((frame, std::mem::take(&mut nodes)));
+            } else if let Some(name) = tag.strip_prefix("#each ") {
+                let frame = Frame::Each {
+                    name: variable_name(name.trim()).ok_or_else(unexpected)?,
+                    line,
+                };
+                stack.push((frame, std::mem::take(&mut nodes)));
+            } else if tag == "else" {
+                match stack.last_mut() {
+                    Some((Frame::If { then: then @ None, .. }, _)) => *then = Some(std::mem::take(&mut nodes)),
+                    _ => return Err(unexpected()),
+                }
+            } else if tag == "/if" {
+                match stack.pop() {
+                    Some((Frame::If { name, then, .. }, parent)) => {
+                        let block = std::mem::replace(&mut nodes, parent);
+                        let (then, otherwise) = match then {
+                            Some(then) => (then, block),
+                            None => (block, Vec::new()),
+                        };
+                        nodes.push(Node::If { name, then, otherwise });
+                    }
+                    _ => return Err(unexpected()),
+                }
+            } else if tag == "/each" {
+                match stack.pop() {
+                    Some((Frame::Each { name, .. }, parent)) => {
+                        let body = std::mem::replace(&mut nodes, parent);
+                        nodes.push(Node::Each { name, body });
+                    }
+                    _ => return Err(unexpected()),
+                }
+            } else {
+                nodes.push(Node::Var(variable_name(tag).ok_or_else(unexpected)?));
+            }
+        }
+        if !rest.is_empty() {
+            nodes.push(Node::Text(rest.to_string()));
+        }
+
+        if let Some((frame, _)) = stack.pop() {
+            let (block, line) = match frame {
+                Frame::If { line, .. } => ("if", line),
+                Frame::Each { line, .. } => ("each", line),
+            };
+            return Err(Msg::new("template.unclosed_block").arg("block", block).arg("line", line));
+        }
+        Ok(Self { nodes })
+    }
+
+    /// 校验模板只使用了 `known` 中的变量
+    ///
+    /// # Returns
+    ///
+    /// * 有未知变量时返回 `Err(Msg)`，列出全部未知变量
+    pub fn validate(&self, known: &[&str]) -> Result<(), Msg> {
+        let mut unknown = BTreeSet::new();
+        collect_unknown(&self.nodes, known, 0, &mut unknown);
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        Err(Msg::new("template.unknown_variables")
+            .arg("names", unknown.into_iter().collect::<Vec<_>>().join(", "))
+            .arg("known", known.join(", ")))
+    }
+
+    /// 模板中引用的变量，不包括循环中的 `this` 和 `@index`
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        collect_names(&self.nodes, &mut names);
+        names.remove("this");
+        names.remove("@index");
+        names
+    }
+
+    /// 用 `vars` 渲染模板
+    pub fn render(&self, vars: &Map<String, Value>) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, vars, &mut Vec::new(), &mut out);
+        out
+    }
+}
+
+/// 变量名只能由字母、数字和下划线组成，另外支持循环中的 `this` 和 `@index`
+fn variable_name(text: &str) -> Option<String> {
+    let valid = text == "@index"
+        || (!text.is_empty()
+            && !text.starts_with(|c: char| c.is_ascii_digit())
+            && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    valid.then(|| text.to_string())
+}
+
+fn collect_unknown(nodes: &[Node], known: &[&str], depth: usize, unknown: &mut BTreeSet<String>) {
+    for node in nodes {
+        let name = match node {
+            Node::Text(_) => continue,
+            Node::Var(name) => name,
+            Node::If { name, then, otherwise } => {
+                collect_unknown(then, known, depth, unknown);
+                collect_unknown(otherwise, known, depth, unknown);
+                name
+            }
+            Node::Each { name, body } => {
+                collect_unknown(body, known, depth + 1, unknown);
+                name
+            }
+        };
+        let in_loop = depth > 0 && (name == "this" || name == "@index");
+        if !in_loop && !known.contains(&name.as_str()) {
+            unknown.insert(name.clone());
+        }
+    }
+}
+
+fn collect_names(nodes: &[Node], names: &mut BTreeSet<String>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Var(name) => {
+                names.insert(name.clone());
+            }
+            Node::If { name, then, otherwise } => {
+                names.insert(name.clone());
+                collect_names(then, names);
+                collect_names(otherwise, names);
+            }
+            Node::Each { name, body } => {
+                names.insert(name.clone());
+                collect_names(body, names);
+            }
+        }
+    }
+}
+
+/// `scopes` 为外层到内层的循环，每项是当前元素和序号
+fn render_nodes(nodes: &[Node], vars: &Map<String, Value>, scopes: &mut Vec<(Value, usize)>, out: &mut String) {
+    let lookup = |name: &str, scopes: &[(Value, usize)]| -> Value {
+        match (name, scopes.last()) {
+            ("this", Some((item, _))) => item.clone(),
+            ("@index", Some((_, index))) => json!(index),
+            _ => vars.get(name).cloned().unwrap_or(Value::Null),
+        }
+    };
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var(name) => match lookup(name, scopes) {
+                Value::Null => {}
+                Value::String(s) => out.push_str(&s),
+                other => out.push_str(&other.to_string()),
+            },
+            Node::If { name, then, otherwise } => {
+                let branch = if truthy(&lookup(name, scopes)) { then } else { otherwise };
+                render_nodes(branch, vars, scopes, out);
+            }
+            Node::Each { name, body } => {
+                if let Value::Array(items) = lookup(name, scopes) {
+                    for (index, item) in items.into_iter().enumerate() {
+                        scopes.push((item, index));
+                        render_nodes(body, vars, scopes, out);
+                        scopes.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.trim().is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// AI功能使用的提示词
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptKind {
+    /// 单词标注
+    Annotation,
+    /// 原文翻译
+    Translation,
+    /// 句子语法分析
+    Grammar,
+    /// 对话练习的系统提示词
+    Conversation,
+}
+
+impl PromptKind {
+    pub const ALL: [PromptKind; 4] = [
+        PromptKind::Annotation,
+        PromptKind::Translation,
+        PromptKind::Grammar,
+        PromptKind::Conversation,
+    ];
+
+    fn setting_key(self) -> String {
+        format!("prompt.{}", serde_json::to_value(self).unwrap().as_str().unwrap())
+    }
+
+    /// 没有迁移成模板的旧版本自定义提示词的备份
+    fn legacy_key(self) -> String {
+        format!("{}.legacy", self.setting_key())
+    }
+
+    /// 模板中可以使用的变量
+    pub fn variables(self) -> &'static [&'static str] {
+        match self {
+            PromptKind::Annotation => &["words", "language", "lesson_title", "native_language", "parts_of_speech"],
+            PromptKind::Translation => &["text", "language", "lesson_title", "native_language"],
+            PromptKind::Grammar => &["sentence", "language", "lesson_title", "native_language", "difficulties"],
+            PromptKind::Conversation => &["language", "native_language", "scenario", "lesson_title", "lesson_content"],
+        }
+    }
+
+    /// 内置的模板
+    pub fn default_template(self) -> &'static str {
+        match self {
+            PromptKind::Annotation => {
+                r#"你是一个语言专家，请用{{native_language}}标注下面数组中的每个{{#if language}}{{language}}{{/if}}单词，解释单词词性、单词读音、单词释义、附加说明，以及其适用性（仅限于本课文输出0，该语言通用输出1）。
+只输出JSON，不要输出多余的话，格式如下：
+{"words": [{"content": "单词内容", "part_of_speech": ["词性"], "pronunciation": "读音", "interpretation": "释义", "notes": "附加说明", "applicability": 1}]}
+part_of_speech 的可选值：{{parts_of_speech}}
+content 必须与给出的单词完全一致，每个单词输出一项。
+
+单词：{{words}}"#
+            }
+            PromptKind::Translation => {
+                r#"你可以把世界各国的语言翻译成{{native_language}}，请翻译下面的{{#if language}}{{language}}{{/if}}文章，只输出译文，不要回答无关的信息
+文章：{{text}}"#
+            }
+            PromptKind::Grammar => {
+                r#"你是一个语言专家，请用{{native_language}}分析下面{{#if language}}{{language}}{{/if}}句子的语法结构。
+只输出JSON，不要输出多余的话，格式如下：
+{"translation": "地道的{{native_language}}翻译", "clauses": [{"text": "分句原文", "role": "分句作用，例如主句、定语从句", "explanation": "分句解释"}], "grammar_points": [{"pattern": "句中使用的语法或句型原文", "explanation": "语法说明"}], "difficulty": "A1"}
+difficulty 为句子的 CEFR 难度等级，可选值：{{difficulties}}
+句子只有一个分句时 clauses 也输出一项。
+
+句子：{{sentence}}"#
+            }
+            PromptKind::Conversation => {
+                r#"你是一位耐心的{{language}}老师，正在和学习者进行口语对话练习。请始终用{{language}}回复，语句简短自然，符合学习者的水平，每次回复后提出一个问题让对话继续。
+如果学习者的表达有错误，先用{{language}}自然地回应，再用{{native_language}}简要指出错误并给出正确的说法。{{#if scenario}}
+
+请扮演场景中的角色，场景：{{scenario}}{{/if}}{{#if lesson_title}}
+
+对话围绕下面这篇课文展开，尽量使用课文中的词汇和句型。
+课文《{{lesson_title}}》：
+{{lesson_content}}{{/if}}"#
+            }
+        }
+    }
+
+    /// 解析并校验模板
+    pub fn compile(self, source: &str) -> Result<Template, AppError> {
+        let template = Template::parse(source).map_err(AppError::InvalidArgument)?;
+        template.validate(self.variables()).map_err(AppError::InvalidArgument)?;
+        Ok(template)
+    }
+
+    /// 用户保存的模板，没有时为 `None`
+    pub fn custom_source(self, db: &Database) -> Result<Option<String>, AppError> {
+        Ok(db.get_setting(&self.setting_key())?.filter(|s| !s.trim().is_empty()))
+    }
+
+    /// 没有迁移成模板的旧版本自定义提示词，用于提示用户手动改写，没有时为 `None`
+    pub fn legacy_source(self, db: &Database) -> Result<Option<String>, AppError> {
+        Ok(db.get_setting(&self.legacy_key())?.filter(|s| !s.trim().is_empty()))
+    }
+
+    /// 用户处理完旧的自定义提示词后删除备份
+    pub fn dismiss_legacy(self, db: &Database) -> Result<(), AppError> {
+        db.set_setting(&self.legacy_key(), "")
+    }
+
+    /// 当前使用的模板源码，优先使用用户保存的模板
+    pub fn source(self, db: &Database) -> Result<String, AppError> {
+        Ok(self
+            .custom_source(db)?
+            .unwrap_or_else(|| self.default_template().to_string()))
+    }
+
+    /// 当前使用的模板
+    pub fn load(self, db: &Database) -> Result<Template, AppError> {
+        self.compile(&self.source(db)?)
+    }
+
+    /// 保存用户的模板，`source` 为空时恢复内置模板
+    ///
+    /// # Returns
+    ///
+    /// * 模板有语法错误或使用了未知变量时返回 `Err(AppError::InvalidArgument)`，不会保存
+    pub fn save(self, db: &Database, source: Option<&str>) -> Result<(), AppError> {
+        let source = source.filter(|s| !s.trim().is_empty());
+        if let Some(source) = source {
+            self.compile(source)?;
+        }
+        db.set_setting(&self.setting_key(), source.unwrap_or(""))
+    }
+}
+
+/// 学习者的母语，提示词中的 `{{native_language}}`
+pub fn native_language(db: &Database) -> Result<String, AppError> {
+    Ok(db
+        .get_setting(NATIVE_LANGUAGE_KEY)?
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_NATIVE_LANGUAGE.to_string()))
+}
+
+/// 设置学习者的母语，为空时恢复默认值
+pub fn set_native_language(db: &Database, language: &str) -> Result<(), AppError> {
+    db.set_setting(NATIVE_LANGUAGE_KEY, language.trim())
+}
+
+/// 旧版本保存在 `option` 表中的提示词列，以及对应的模板和占位符 `${var}` 替换成的变量
+const LEGACY_PROMPT_COLUMNS: [(&str, PromptKind, &str); 2] = [
+    ("annotationPrompt", PromptKind::Annotation, "{{words}}"),
+    ("translationPrompt", PromptKind::Translation, "{{text}}"),
+];
+
+/// 旧版本的内置提示词，旧的设置页面保存时会原样写入，迁移时视为没有自定义
+const LEGACY_DEFAULT_PROMPTS: [&str; 2] = [
+    "你是一个语言专家，用户会把单词以数组形式发给你，请你以简体中文的形式返回给用户，并解释单词词性、单词读音、单词中文释义、附加说明、以及其适用性是仅限于本课文输出0，该语言通用输出1。
+以如下格式返回，每个单词之前用2个换行隔开，不要输出多余的话：
+单词：单词内容
+词性：可选输出 noun,numeral,measure_word,verb,adjective,distinguishing_word,adverb,conjunction,preposition,auxiliary,modal_particle,phrase,sentence_fragment,pronoun,interjection,onomatopoeia,morpheme,other
+读音：
+中文释义：
+附加说明：
+适用性：可选输出 0,1
+
+单词：${var}",
+    "你可以把世界各国的语言翻译成简体中文，请翻译下面的文章，只输出译文，不要回答无关的信息
+文章：${var}",
+];
+
+/// 把旧版本保存在 `option` 表中的自定义提示词迁移为模板，`${var}` 换成 `{{words}}` 或 `{{text}}`
+///
+/// 与旧内置提示词相同的视为没有自定义。以下情况不迁移，原内容保存为备份（见 `PromptKind::legacy_source`），
+/// 设置页面会提示用户手动改写：已经保存过同类模板、转换后无法解析、标注提示词没有要求输出JSON
+/// （旧版本的标注结果是逐行的文本格式，现在只解析JSON）。迁移或备份成功后才清空该列
+///
+/// # Returns
+///
+/// * 成功返回迁移的模板数量
+pub fn migrate_legacy_prompts(db: &Database) -> Result<usize, AppError> {
+    let mut migrated = 0;
+    for (column, kind, variable) in LEGACY_PROMPT_COLUMNS {
+        let legacy: Option<String> = db.with_conn(|conn| {
+            Ok(conn
+                .query_row(&format!("SELECT {} FROM option WHERE id = 1", column), [], |row| row.get(0))
+                .optional()?
+                .flatten())
+        })?;
+        let Some(legacy) = legacy.filter(|s| !s.trim().is_empty()) else {
+            continue;
+        };
+
+        let is_default = LEGACY_DEFAULT_PROMPTS.iter().any(|p| p.trim() == legacy.trim());
+        if !is_default {
+            let source = legacy.replace("${var}", variable);
+            let kept = if kind.custom_source(db)?.is_some() {
+                Some("已经保存过同类模板".to_string())
+            } else if let Err(e) = kind.compile(&source) {
+                Some(e.to_string())
+            } else if kind == PromptKind::Annotation && !source.to_ascii_lowercase().contains("json") {
+                Some("标注提示词没有要求输出JSON".to_string())
+            } else {
+                None
+            };
+            match kept {
+                Some(reason) => {
+                    warn!(column, reason = %reason, "旧的自定义提示词没有迁移，保存为备份");
+                    db.set_setting(&kind.legacy_key(), &legacy)?;
+                }
+                None => {
+                    kind.save(db, Some(&source))?;
+                    migrated += 1;
+                }
+            }
+        }
+        db.with_conn(|conn| {
+            conn.execute(&format!("UPDATE option SET {} = NULL WHERE id = 1", column), [])?;
+            Ok(())
+        })?;
+    }
+    if migrated > 0 {
+        info!(migrated, "已迁移旧的自定义提示词");
+    }
+    Ok(migrated)
+}
+
+/// 各模板共用的变量：母语、词性和难度的可选值，以及课文的标题、正文和语言
+///
+/// 课文不存在时不提供课文相关的变量
+pub fn context_vars(db: &Database, lesson_id: Option<i64>) -> Result<Map<String, Value>, AppError> {
+    let mut vars = Map::new();
+    vars.insert("native_language".into(), json!(native_language(db)?));
+    vars.insert("parts_of_speech".into(), json!(PartOfSpeech::ALL.join(",")));
+    vars.insert("difficulties".into(), json!(Difficulty::ALL.join(",")));
+
+    let Some(lesson_id) = lesson_id else {
+        return Ok(vars);
+    };
+    let lesson = db.with_conn(|conn| {
+        Ok(conn
+            .query_row(
+                "SELECT c.title, c.content, l.title FROM `class` c LEFT JOIN language l ON l.id = c.languageId
+                 WHERE c.id = ?1",
+                [lesson_id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )
+            .optional()?)
+    })?;
+    if let Some((title, content, language)) = lesson {
+        vars.insert("lesson_title".into(), json!(title));
+        vars.insert("lesson_content".into(), json!(content));
+        if let Some(language) = language {
+            vars.insert("language".into(), json!(language));
+        }
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_legacy(db: &Database, annotation: Option<&str>, translation: Option<&str>) {
+        db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE option SET annotationPrompt = ?1, translationPrompt = ?2 WHERE id = 1",
+                rusqlite::params![annotation, translation],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn legacy_prompts_are_converted_to_templates() {
+        let db = Database::memory().unwrap();
+        set_legacy(&db, Some(LEGACY_DEFAULT_PROMPTS[0]), Some("请翻译成日语：${var}"));
+
+        assert_eq!(migrate_legacy_prompts(&db).unwrap(), 1);
+        // 与旧内置提示词相同的标注提示词改用新的内置模板
+        assert_eq!(PromptKind::Annotation.custom_source(&db).unwrap(), None);
+        let template = PromptKind::Translation.load(&db).unwrap();
+        assert_eq!(template.render(json!({"text": "hello"}).as_object().unwrap()), "请翻译成日语：hello");
+
+        // 列已清空，再次执行不会覆盖之后保存的模板
+        PromptKind::Translation.save(&db, Some("{{text}}")).unwrap();
+        assert_eq!(migrate_legacy_prompts(&db).unwrap(), 0);
+        assert_eq!(PromptKind::Translation.custom_source(&db).unwrap().as_deref(), Some("{{text}}"));
+    }
+
+    fn legacy_column(db: &Database) -> Option<String> {
+        db.with_conn(|conn| Ok(conn.query_row("SELECT annotationPrompt FROM option WHERE id = 1", [], |row| row.get(0))?))
+            .unwrap()
+    }
+
+    #[test]
+    fn unparseable_legacy_prompt_is_kept() {
+        let db = Database::memory().unwrap();
+        let legacy = "单词：${var} {{#if}}，输出JSON";
+        set_legacy(&db, Some(legacy), None);
+
+        assert_eq!(migrate_legacy_prompts(&db).unwrap(), 0);
+        assert_eq!(PromptKind::Annotation.custom_source(&db).unwrap(), None);
+        assert_eq!(PromptKind::Annotation.legacy_source(&db).unwrap().as_deref(), Some(legacy));
+        assert_eq!(legacy_column(&db), None);
+
+        PromptKind::Annotation.dismiss_legacy(&db).unwrap();
+        assert_eq!(PromptKind::Annotation.legacy_source(&db).unwrap(), None);
+    }
+
+    #[test]
+    fn legacy_annotation_prompt_is_migrated_only_when_it_asks_for_json() {
+        // 旧版本逐行文本格式的自定义提示词，现在无法解析它的输出
+        let db = Database::memory().unwrap();
+        let text_format = "请标注：${var}\n单词：\n词性：\n读音：";
+        set_legacy(&db, Some(text_format), None);
+        assert_eq!(migrate_legacy_prompts(&db).unwrap(), 0);
+        assert_eq!(PromptKind::Annotation.custom_source(&db).unwrap(), None);
+        assert_eq!(PromptKind::Annotation.legacy_source(&db).unwrap().as_deref(), Some(text_format));
+        assert_eq!(legacy_column(&db), None);
+
+        let db = Database::memory().unwrap();
+        set_legacy(&db, Some("请标注 ${var}，只输出 Json"), None);
+        assert_eq!(migrate_legacy_prompts(&db).unwrap(), 1);
+        assert_eq!(
+            PromptKind::Annotation.custom_source(&db).unwrap().as_deref(),
+            Some("请标注 {{words}}，只输出 Json")
+        );
+        assert_eq!(PromptKind::Annotation.legacy_source(&db).unwrap(), None);
+
+        // 已经有同类模板时不覆盖，旧内容同样保存为备份
+        let db = Database::memory().unwrap();
+        PromptKind::Annotation.save(&db, Some("{{words}} JSON")).unwrap();
+        set_legacy(&db, Some("旧的 JSON 提示词 ${var}"), None);
+        assert_eq!(migrate_legacy_prompts(&db).unwrap(), 0);
+        assert_eq!(PromptKind::Annotation.custom_source(&db).unwrap().as_deref(), Some("{{words}} JSON"));
+        assert_eq!(PromptKind::Annotation.legacy_source(&db).unwrap().as_deref(), Some("旧的 JSON 提示词 ${var}"));
+    }
+
+    fn render(source: &str, vars: Value) -> String {
+        Template::parse(source).unwrap().render(vars.as_object().unwrap())
+    }
+
+    fn parse_error(source: &str) -> Msg {
+        Template::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn renders_conditions_and_loops() {
+        let source = "{{#if language}}{{language}}{{else}}外语{{/if}}单词";
+        assert_eq!(render(source, json!({"language": "日语"})), "日语单词");
+        for falsy in [json!(null), json!(""), json!("  "), json!([]), json!(false), json!(0)] {
+            assert_eq!(render(source, json!({"language": falsy})), "外语单词", "{}", falsy);
+        }
+        assert_eq!(render("{{#if flag}}有{{/if}}", json!({"flag": true})), "有");
+
+        let source = "{{#each words}}{{@index}}.{{this}}{{#if sep}}{{sep}}{{/if}}{{/each}}";
+        assert_eq!(render(source, json!({"words": ["猫", "狗"], "sep": ";"})), "0.猫;1.狗;");
+        assert_eq!(render(source, json!({"words": []})), "");
+        // 不是数组时不输出
+        assert_eq!(render(source, json!({"words": "猫"})), "");
+
+        // 嵌套循环中的 this 是内层元素
+        let source = "{{#each groups}}[{{#each this}}{{this}}{{/each}}]{{/each}}";
+        assert_eq!(render(source, json!({"groups": [["a", "b"], ["c"]]})), "[ab][c]");
+    }
+
+    #[test]
+    fn renders_values_and_missing_variables() {
+        let vars = json!({"text": "hi", "words": ["a", "b"], "count": 2});
+        assert_eq!(render("{{text}}|{{words}}|{{count}}", vars), r#"hi|["a","b"]|2"#);
+        // 没有提供的可选变量输出空字符串
+        assert_eq!(render("课文《{{lesson_title}}》{{ scenario }}", json!({})), "课文《》");
+        // 循环外的 this 和 @index 同样视为不存在
+        assert_eq!(render("{{this}}{{@index}}", json!({})), "");
+        assert_eq!(render("没有标签 } {", json!({})), "没有标签 } {");
+    }
+
+    #[test]
+    fn rejects_unclosed_and_mismatched_blocks() {
+        let cases = [
+            ("第一行\n{{text", "template.unclosed_tag", "2"),
+            ("{{#if text}}没有结束", "template.unclosed_block", "1"),
+            ("\n\n{{#each words}}{{#if this}}{{/if}}", "template.unclosed_block", "3"),
+            ("{{#if text}}{{/each}}", "template.unexpected_tag", "1"),
+            ("{{#each words}}{{/if}}", "template.unexpected_tag", "1"),
+            ("{{/if}}", "template.unexpected_tag", "1"),
+            ("{{#if a}}{{else}}{{else}}{{/if}}", "template.unexpected_tag", "1"),
+            ("{{#each words}}{{else}}{{/each}}", "template.unexpected_tag", "1"),
+            ("{{#if}}", "template.unexpected_tag", "1"),
+            ("{{1st}}", "template.unexpected_tag", "1"),
+            ("{{lesson-title}}", "template.unexpected_tag", "1"),
+        ];
+        for (source, key, line) in cases {
+            let error = parse_error(source);
+            assert_eq!(error.key, key, "{:?}", source);
+            assert!(error.render().contains(line), "{:?}: {}", source, error.render());
+        }
+    }
+
+    #[test]
+    fn saving_rejects_unknown_variables() {
+        let db = Database::memory().unwrap();
+        let error = PromptKind::Translation
+            .save(&db, Some("{{text}} {{#if tone}}{{tone}}{{/if}} {{#each words}}{{this}}{{/each}}"))
+            .unwrap_err();
+        let AppError::InvalidArgument(msg) = error else {
+            panic!("{:?}", error);
+        };
+        assert_eq!(msg.key, "template.unknown_variables");
+        // 列出全部未知变量，循环中的 this 不算
+        assert!(msg.render().contains("tone, words"), "{}", msg.render());
+        assert_eq!(PromptKind::Translation.custom_source(&db).unwrap(), None);
+
+        // 循环外使用 this 也是未知变量
+        assert!(PromptKind::Translation.save(&db, Some("{{this}}")).is_err());
+        let error = PromptKind::Translation.save(&db, Some("{{#if text}}")).unwrap_err();
+        assert!(matches!(error, AppError::InvalidArgument(msg) if msg.key == "template.unclosed_block"));
+
+        PromptKind::Translation.save(&db, Some("{{#if language}}{{language}}{{/if}}{{text}}")).unwrap();
+        assert!(PromptKind::Translation.custom_source(&db).unwrap().is_some());
+        // 保存空内容恢复内置模板
+        PromptKind::Translation.save(&db, Some("  ")).unwrap();
+        assert_eq!(PromptKind::Translation.custom_source(&db).unwrap(), None);
+        for kind in PromptKind::ALL {
+            kind.compile(kind.default_template()).unwrap();
+        }
+    }
+}
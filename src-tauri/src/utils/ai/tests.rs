@@ -0,0 +1,513 @@
+//! 通过模拟AI服务测试单词标注、备用平台切换、缓存、模型列表、对话练习和语法分析
+
+use crate::utils::ai::cache::annotate_cached;
+use crate::utils::ai::conversation::{create_session, send_message, session_messages, StartSessionRequest};
+use crate::utils::ai::failover::{set_chain, Route};
+use crate::utils::ai::grammar::{self, parse_analysis, Difficulty};
+use crate::utils::ai::mock::{start_exclusive, MockResponse};
+use crate::utils::ai::models::{list_models, ModelCache};
+use crate::utils::ai::provider::{ChatMessage, Provider};
+use crate::utils::ai::{chat, AiContext};
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::fixtures;
+use crate::utils::secrets::SecretStore;
+use serde_json::json;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// 模型输出的一项标注
+fn item(word: &str, part_of_speech: &str) -> serde_json::Value {
+    json!({
+        "content": word,
+        "part_of_speech": [part_of_speech],
+        "pronunciation": "",
+        "interpretation": format!("{}的释义", word),
+        "notes": "",
+        "applicability": 1,
+    })
+}
+
+fn reply(items: &[serde_json::Value]) -> String {
+    json!({ "words": items }).to_string()
+}
+
+/// 请求中发给模型的提示词
+fn prompt(body: &serde_json::Value) -> String {
+    body["messages"][0]["content"].as_str().unwrap_or_default().to_string()
+}
+
+#[tokio::test]
+async fn annotate_retries_only_invalid_words_and_caches_results() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("ChatGPT", "sk-test").unwrap();
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: None,
+        limiter: None,
+    };
+
+    server.push_reply(reply(&[item("quokka", "noun"), item("saunter", "not-a-part")]));
+    server.push_reply(reply(&[item("saunter", "verb")]));
+
+    let list = words(&["quokka", "saunter"]);
+    let results = annotate_cached(ctx, Provider::ChatGPT, "gpt-test", &list, None, 1, true)
+        .await
+        .unwrap();
+    assert!(results.iter().all(|r| r.ok && !r.cached));
+    assert_eq!(results[1].annotation.as_ref().unwrap().interpretation, "saunter的释义");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    // 密钥由Rust端注入
+    assert!(requests[0]
+        .headers
+        .iter()
+        .any(|(name, value)| name == "authorization" && value == "Bearer sk-test"));
+    // 重试只包含校验失败的单词
+    assert!(prompt(&requests[1].body).contains("saunter"));
+    assert!(!prompt(&requests[1].body).contains("quokka"));
+
+    let results = annotate_cached(ctx, Provider::ChatGPT, "gpt-test", &list, None, 1, true)
+        .await
+        .unwrap();
+    assert!(results.iter().all(|r| r.ok && r.cached));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn annotate_keeps_partial_results_when_retry_fails() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("ChatGPT", "sk-test").unwrap();
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: None,
+        limiter: None,
+    };
+
+    // 第一次漏掉了一个单词，重试时平台故障
+    server.push_reply(reply(&[item("quokka", "noun")]));
+    server.push("ChatGPT", MockResponse::json(500, json!({"error": {"message": "boom"}})));
+
+    let results = annotate_cached(ctx, Provider::ChatGPT, "gpt-test", &words(&["quokka", "wombat"]), None, 1, false)
+        .await
+        .unwrap();
+    assert!(results[0].ok);
+    assert!(!results[1].ok);
+    assert!(results[1].error.is_some());
+
+    // 第一次请求就失败时返回错误，保留错误种类
+    server.push("ChatGPT", MockResponse::json(401, json!({"error": {"message": "bad key"}})));
+    let error = annotate_cached(ctx, Provider::ChatGPT, "gpt-test", &words(&["numbat"]), None, 1, false)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, AppError::ProviderAuth { status: 401, .. }));
+}
+
+#[tokio::test]
+async fn annotate_fails_over_to_backup_provider() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("DeepSeek", "sk-deepseek").unwrap();
+    secrets.set("Google", "google-key").unwrap();
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: None,
+        limiter: None,
+    };
+    // ChatGPT 没有密钥，会被跳过
+    set_chain(&db, &[Route::new(Provider::ChatGPT, "gpt-test"), Route::new(Provider::Google, "gemini-test")]).unwrap();
+
+    server.push("DeepSeek", MockResponse::rate_limited(30));
+    server.push("Google", MockResponse::reply(reply(&[item("quokka", "noun")])));
+
+    let results = annotate_cached(ctx, Provider::DeepSeek, "deepseek-test", &words(&["quokka"]), None, 0, true)
+        .await
+        .unwrap();
+    assert!(results[0].ok);
+    assert_eq!(results[0].answered_by, Some(Route::new(Provider::Google, "gemini-test")));
+
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths.len(), 2);
+    assert!(paths[0].starts_with("/DeepSeek/"));
+    assert!(paths[1].starts_with("/Google/"));
+}
+
+/// 添加一种语言和一篇课文，返回课文id
+fn add_lesson(db: &Database, language: &str, title: &str) -> i64 {
+    let language_id = fixtures::add_language(db, language, "", "");
+    fixtures::add_lesson_record(db, language_id, title, "")
+}
+
+#[tokio::test]
+async fn annotate_cache_depends_on_language_and_skips_lesson_only_annotations() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("ChatGPT", "sk-test").unwrap();
+    let english = add_lesson(&db, "英语", "Lesson 1");
+    let french = add_lesson(&db, "法语", "Leçon 1");
+    let ctx = |lesson_id| AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: Some(lesson_id),
+        limiter: None,
+    };
+
+    let mut lesson_only = item("Tom", "noun");
+    lesson_only["applicability"] = json!(0);
+    server.push_reply(reply(&[item("chat", "noun"), lesson_only]));
+    let list = words(&["chat", "Tom"]);
+    let results = annotate_cached(ctx(english), Provider::ChatGPT, "gpt-test", &list, None, 0, true)
+        .await
+        .unwrap();
+    assert!(results.iter().all(|r| r.ok && !r.cached));
+    assert!(prompt(&server.requests()[0].body).contains("英语"));
+
+    // 同一种语言的课文复用通用的标注，仅适用于本课文的标注重新请求
+    let other_english = add_lesson(&db, "英语", "Lesson 2");
+    server.push_reply(reply(&[item("Tom", "noun")]));
+    let results = annotate_cached(ctx(other_english), Provider::ChatGPT, "gpt-test", &list, None, 0, true)
+        .await
+        .unwrap();
+    assert!(results[0].cached);
+    assert!(!results[1].cached);
+    assert!(!prompt(&server.requests()[1].body).contains("chat"));
+
+    // 其他语言的课文不命中
+    server.push_reply(reply(&[item("chat", "noun")]));
+    let results = annotate_cached(ctx(french), Provider::ChatGPT, "gpt-test", &words(&["chat"]), None, 0, true)
+        .await
+        .unwrap();
+    assert!(!results[0].cached);
+    assert!(prompt(&server.requests()[2].body).contains("法语"));
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn list_models_parses_openai_and_ollama_responses() {
+    let (_guard, server) = start_exclusive().await;
+    let secrets = SecretStore::memory();
+    secrets.set("Groq", "gsk-test").unwrap();
+    let cache = ModelCache::default();
+
+    server.push(
+        "Groq/models",
+        MockResponse::json(
+            200,
+            json!({"object": "list", "data": [
+                {"id": "llama-3", "context_window": 8192},
+                {"id": "mixtral", "context_length": 32768},
+                {"id": "whisper"},
+                {"object": "model"},
+            ]}),
+        ),
+    );
+    let (models, cached) = list_models(&cache, &secrets, Provider::Groq, None, false).await.unwrap();
+    assert!(!cached);
+    let parsed: Vec<_> = models.iter().map(|m| (m.id.as_str(), m.context_length)).collect();
+    assert_eq!(parsed, [("llama-3", Some(8192)), ("mixtral", Some(32768)), ("whisper", None)]);
+    assert_eq!(cache.context_length(Provider::Groq, "mixtral"), Some(32768));
+
+    // 缓存有效期内不再请求
+    let (_, cached) = list_models(&cache, &secrets, Provider::Groq, None, false).await.unwrap();
+    assert!(cached);
+    assert_eq!(server.requests().len(), 1);
+
+    // 本地模型服务默认是 Ollama 接口，不需要密钥
+    server.push(
+        "Local/api/tags",
+        MockResponse::json(200, json!({"models": [{"name": "qwen2:7b"}, {"name": "llama3:latest"}, {}]})),
+    );
+    let (models, _) = list_models(&cache, &secrets, Provider::Local, None, true).await.unwrap();
+    let ids: Vec<_> = models.iter().map(|m| (m.id.as_str(), m.context_length)).collect();
+    assert_eq!(ids, [("llama3:latest", None), ("qwen2:7b", None)]);
+    assert!(server.requests()[1].path.ends_with("/Local/api/tags"));
+}
+
+#[tokio::test]
+async fn list_models_follows_gemini_pages_with_encoded_tokens() {
+    let (_guard, server) = start_exclusive().await;
+    let secrets = SecretStore::memory();
+    secrets.set("Google", "google-key").unwrap();
+    let cache = ModelCache::default();
+
+    let model = |name: &str, methods: &[&str]| {
+        json!({
+            "name": format!("models/{}", name),
+            "displayName": name.to_uppercase(),
+            "inputTokenLimit": 1048576,
+            "supportedGenerationMethods": methods,
+        })
+    };
+    server.push(
+        "Google/models",
+        MockResponse::json(
+            200,
+            json!({
+                "models": [model("gemini-pro", &["generateContent"]), model("embedding", &["embedContent"])],
+                "nextPageToken": "a+b/c==&x",
+            }),
+        ),
+    );
+    server.push(
+        "Google/models",
+        MockResponse::json(200, json!({"models": [model("gemini-flash", &["countTokens", "generateContent"])]})),
+    );
+
+    let (models, _) = list_models(&cache, &secrets, Provider::Google, None, true).await.unwrap();
+    let parsed: Vec<_> = models
+        .iter()
+        .map(|m| (m.id.as_str(), m.display_name.as_str(), m.context_length))
+        .collect();
+    assert_eq!(
+        parsed,
+        [("gemini-flash", "GEMINI-FLASH", Some(1048576)), ("gemini-pro", "GEMINI-PRO", Some(1048576))]
+    );
+
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths.len(), 2);
+    assert!(!paths[0].contains("pageToken"));
+    assert!(paths[1].ends_with("?pageSize=1000&pageToken=a%2Bb%2Fc%3D%3D%26x"), "{}", paths[1]);
+}
+
+#[tokio::test]
+async fn mock_default_reply_annotates_the_prompted_words() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("Google", "google-key").unwrap();
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: None,
+        limiter: None,
+    };
+
+    let list = words(&["quokka", "wombat"]);
+    let results = annotate_cached(ctx, Provider::Google, "gemini-test", &list, None, 0, false)
+        .await
+        .unwrap();
+    assert!(results.iter().all(|r| r.ok), "{:?}", results);
+    assert_eq!(results[1].annotation.as_ref().unwrap().content, "wombat");
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn mock_responses_can_be_delayed_and_loaded_from_a_script() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("ChatGPT", "sk-test").unwrap();
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: None,
+        limiter: None,
+    };
+    let messages = [ChatMessage::user("hello")];
+
+    server.push("", MockResponse::reply("slow").with_delay(Duration::from_millis(300)));
+    let started = Instant::now();
+    let text = chat(ctx, Provider::ChatGPT, "gpt-test", &messages, None, None).await.unwrap();
+    assert_eq!(text, "slow");
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    let path = std::env::temp_dir().join(format!("learn-language-mock-{}.json", Uuid::new_v4()));
+    std::fs::write(
+        &path,
+        json!([
+            {"path": "/chat/completions", "reply": "scripted"},
+            {"status": 429, "json": {"error": {"message": "slow down"}}, "headers": {"Retry-After": "0"}},
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    assert_eq!(server.load_script(&path).unwrap(), 2);
+    std::fs::remove_file(&path).unwrap();
+
+    let text = chat(ctx, Provider::ChatGPT, "gpt-test", &messages, None, None).await.unwrap();
+    assert_eq!(text, "scripted");
+    let error = chat(ctx, Provider::ChatGPT, "gpt-test", &messages, None, None).await.unwrap_err();
+    assert!(matches!(error, AppError::RateLimited { .. }), "{:?}", error);
+}
+
+#[tokio::test]
+async fn send_message_stores_nothing_when_the_request_fails() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("ChatGPT", "sk-test").unwrap();
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: None,
+        limiter: None,
+    };
+    let request = StartSessionRequest {
+        provider: Provider::ChatGPT,
+        model: "gpt-test".to_string(),
+        target_language: "English".to_string(),
+        lesson_id: None,
+        title: None,
+        scenario: Some("在咖啡店点单".to_string()),
+        system_prompt: None,
+        context_tokens: None,
+        opening: None,
+        proxy: None,
+    };
+    let session = create_session(&db, &request).unwrap();
+    assert_eq!(session.title, "在咖啡店点单");
+
+    server.push("ChatGPT", MockResponse::json(500, json!({"error": {"message": "boom"}})));
+    let error = send_message(ctx, &session, "A latte, please.", 8_192, None).await.unwrap_err();
+    assert!(matches!(error, AppError::HttpStatus { status: 500, .. }), "{:?}", error);
+    assert!(session_messages(&db, session.id).unwrap().is_empty());
+
+    server.push_reply("Sure, anything else?");
+    let turn = send_message(ctx, &session, "A latte, please.", 8_192, None).await.unwrap();
+    assert_eq!(turn.reply, "Sure, anything else?");
+    assert_eq!(turn.trimmed, 0);
+    let stored: Vec<_> = session_messages(&db, session.id)
+        .unwrap()
+        .into_iter()
+        .map(|m| (m.role, m.content))
+        .collect();
+    assert_eq!(
+        stored,
+        [
+            ("user".to_string(), "A latte, please.".to_string()),
+            ("assistant".to_string(), "Sure, anything else?".to_string()),
+        ]
+    );
+    // 失败的那次请求没有进入历史，第二次请求只带系统提示词和这条消息
+    let requests = server.requests();
+    assert_eq!(requests[1].body["messages"].as_array().unwrap().len(), 2);
+}
+
+fn analysis_reply(translation: &str, difficulty: &str) -> String {
+    json!({
+        "translation": translation,
+        "clauses": [
+            {"text": " The cat sat ", "role": "主句", "explanation": "猫坐着"},
+            {"text": "", "role": "空分句", "explanation": "会被去掉"},
+        ],
+        "grammar_points": [
+            {"pattern": "sat", "explanation": "sit 的过去式"},
+            {"pattern": "on", "explanation": ""},
+        ],
+        "difficulty": difficulty,
+    })
+    .to_string()
+}
+
+#[test]
+fn parse_analysis_repairs_model_output() {
+    for (text, expected) in [
+        ("a1", Some(Difficulty::A1)),
+        (" B2 ", Some(Difficulty::B2)),
+        ("c2\n", Some(Difficulty::C2)),
+        ("D1", None),
+        ("", None),
+    ] {
+        assert_eq!(Difficulty::parse(text), expected, "{:?}", text);
+    }
+
+    let reply = format!("```json\n{}\n```", analysis_reply(" 猫坐在垫子上。 ", "b1"));
+    let analysis = parse_analysis(&reply, "The cat sat on the mat.").unwrap();
+    assert_eq!(analysis.sentence, "The cat sat on the mat.");
+    assert_eq!(analysis.translation, "猫坐在垫子上。");
+    assert_eq!(analysis.difficulty, Difficulty::B1);
+    // 空分句和没有说明的语法点被去掉，文本去掉首尾空白
+    assert_eq!(analysis.clauses.len(), 1);
+    assert_eq!(analysis.clauses[0].text, "The cat sat");
+    assert_eq!(analysis.grammar_points.len(), 1);
+
+    let error_key = |text: &str| parse_analysis(text, "s").unwrap_err().key;
+    assert_eq!(error_key("not json"), "grammar.invalid_response");
+    assert_eq!(error_key(&analysis_reply("  ", "A1")), "grammar.missing_translation");
+    assert_eq!(error_key(&analysis_reply("译文", "expert")), "grammar.invalid_difficulty");
+    assert_eq!(error_key(r#"{"translation": "译文"}"#), "grammar.invalid_difficulty");
+}
+
+#[tokio::test]
+async fn analyze_retries_once_when_output_cannot_be_parsed() {
+    let (_guard, server) = start_exclusive().await;
+    let db = Database::memory().unwrap();
+    let secrets = SecretStore::memory();
+    secrets.set("ChatGPT", "sk-test").unwrap();
+    let ctx = AiContext {
+        secrets: &secrets,
+        db: &db,
+        lesson_id: None,
+        limiter: None,
+    };
+
+    server.push_reply("我觉得这句话很简单");
+    server.push_reply(analysis_reply("猫坐在垫子上。", "a2"));
+    let (analysis, route) = grammar::analyze(ctx, Provider::ChatGPT, "gpt-test", " The cat sat on the mat. ", None)
+        .await
+        .unwrap();
+    assert_eq!(analysis.difficulty, Difficulty::A2);
+    assert_eq!(analysis.sentence, "The cat sat on the mat.");
+    assert_eq!(route, Route::new(Provider::ChatGPT, "gpt-test"));
+    assert_eq!(server.requests().len(), 2);
+
+    // 两次都无法解析时返回解析错误，不再继续请求
+    server.push_reply("not json");
+    server.push_reply(analysis_reply("", "A1"));
+    let error = grammar::analyze(ctx, Provider::ChatGPT, "gpt-test", "Hello.", None).await.unwrap_err();
+    assert!(matches!(&error, AppError::Parse(msg) if msg.key == "grammar.missing_translation"), "{:?}", error);
+    assert_eq!(server.requests().len(), 4);
+
+    let error = grammar::analyze(ctx, Provider::ChatGPT, "gpt-test", "  ", None).await.unwrap_err();
+    assert!(matches!(error, AppError::InvalidArgument(msg) if msg.key == "grammar.empty_sentence"));
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn saved_analysis_is_upserted_per_sentence_and_ignored_when_the_sentence_changes() {
+    let db = Database::memory().unwrap();
+    let lesson_id = add_lesson(&db, "English", "Lesson");
+    let parse = |translation: &str, sentence: &str| parse_analysis(&analysis_reply(translation, "A1"), sentence).unwrap();
+
+    let first = parse("旧译文", "The cat sat.");
+    grammar::save(&db, lesson_id, 0, &Route::new(Provider::ChatGPT, "gpt-test"), &first).unwrap();
+    let stored = grammar::find(&db, lesson_id, 0, " The cat sat. ").unwrap().unwrap();
+    assert_eq!(stored.analysis.translation, "旧译文");
+    assert_eq!(stored.provider, "ChatGPT");
+    // 字幕修改后句子变了，旧结果不再使用
+    assert!(grammar::find(&db, lesson_id, 0, "The dog sat.").unwrap().is_none());
+    assert!(grammar::find(&db, lesson_id, 1, "The cat sat.").unwrap().is_none());
+
+    let second = parse("新译文", "The dog sat.");
+    grammar::save(&db, lesson_id, 0, &Route::new(Provider::Google, "gemini-test"), &second).unwrap();
+    grammar::save(&db, lesson_id, 1, &Route::new(Provider::Google, "gemini-test"), &first).unwrap();
+    let stored = grammar::find(&db, lesson_id, 0, "The dog sat.").unwrap().unwrap();
+    assert_eq!(stored.analysis.translation, "新译文");
+    assert_eq!(stored.model, "gemini-test");
+    assert!(grammar::find(&db, lesson_id, 0, "The cat sat.").unwrap().is_none());
+
+    let listed: Vec<_> = grammar::list(&db, lesson_id)
+        .unwrap()
+        .into_iter()
+        .map(|s| (s.sentence_index, s.analysis.translation))
+        .collect();
+    assert_eq!(listed, [(0, "新译文".to_string()), (1, "旧译文".to_string())]);
+    let rows: i64 = db
+        .with_conn(|conn| Ok(conn.query_row("SELECT COUNT(*) FROM sentenceGrammar", [], |row| row.get(0))?))
+        .unwrap();
+    assert_eq!(rows, 2);
+}
@@ -0,0 +1,511 @@
+use crate::utils::ai::provider::{ChatMessage, Provider};
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+/// 每条消息额外的格式开销（角色、分隔符等）
+const TOKENS_PER_MESSAGE: u64 = 4;
+/// 每次请求额外的开销
+const TOKENS_PER_REQUEST: u64 = 3;
+
+/// 模型家族，不同家族的分词器对中日韩文字的切分差别很大
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelFamily {
+    Gpt,
+    Gemini,
+    Glm,
+    DeepSeek,
+    /// Llama、Qwen、Mistral 等开源模型（Groq 和本地模型）
+    Open,
+}
+
+impl ModelFamily {
+    /// 优先按模型名称判断，无法判断时按平台判断
+    pub fn of(provider: Provider, model: &str) -> Self {
+        let model = model.to_ascii_lowercase();
+        if model.starts_with("gpt") || model.starts_with("o1") || model.starts_with("o3") || model.starts_with("o4") {
+            ModelFamily::Gpt
+        } else if model.contains("gemini") || model.contains("gemma") {
+            ModelFamily::Gemini
+        } else if model.contains("glm") {
+            ModelFamily::Glm
+        } else if model.contains("deepseek") {
+            ModelFamily::DeepSeek
+        } else {
+            match provider {
+                Provider::ChatGPT => ModelFamily::Gpt,
+                Provider::Google => ModelFamily::Gemini,
+                Provider::ChatGLM => ModelFamily::Glm,
+                Provider::DeepSeek => ModelFamily::DeepSeek,
+                Provider::Groq | Provider::Local => ModelFamily::Open,
+            }
+        }
+    }
+
+    /// 每个中日韩字符平均占用的token数，以及其他文字平均每个token的字符数
+    fn ratios(self) -> (f64, f64) {
+        match self {
+            ModelFamily::Gpt => (1.0, 4.0),
+            ModelFamily::Gemini => (0.8, 4.0),
+            ModelFamily::Glm => (0.7, 4.0),
+            ModelFamily::DeepSeek => (0.6, 3.3),
+            ModelFamily::Open => (1.3, 4.0),
+        }
+    }
+}
+
+/// 中日韩文字（包括假名、谚文和全角标点）
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3000}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{ff00}'..='\u{ffef}')
+}
+
+/// 估算文本的token数
+///
+/// 没有内置各家的分词器，按字符类型和经验比例估算，误差一般在 ±20% 以内，只用于提前提示费用
+pub fn count_tokens(family: ModelFamily, text: &str) -> u64 {
+    let (cjk_weight, chars_per_token) = family.ratios();
+    let (cjk, other) = text.chars().fold((0u64, 0u64), |(cjk, other), c| {
+        if is_cjk(c) {
+            (cjk + 1, other)
+        } else {
+            (cjk, other + 1)
+        }
+    });
+    (cjk as f64 * cjk_weight + other as f64 / chars_per_token).ceil() as u64
+}
+
+/// 估算一次对话请求的输入token数
+pub fn count_message_tokens(family: ModelFamily, messages: &[ChatMessage]) -> u64 {
+    messages
+        .iter()
+        .map(|m| count_tokens(family, &m.content) + TOKENS_PER_MESSAGE)
+        .sum::<u64>()
+        + TOKENS_PER_REQUEST
+}
+
+/// 模型价格，单位为美元每百万token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub provider: Provider,
+    /// 模型名称前缀，按最长前缀匹配，空字符串匹配该平台全部模型
+    pub model_prefix: String,
+    pub input_price: f64,
+    pub output_price: f64,
+    /// 是否为用户自定义的价格
+    #[serde(default)]
+    pub custom: bool,
+}
+
+/// 内置价格表（美元/百万token），价格会调整，以平台官网为准，用户可以通过 `set_ai_prices` 覆盖
+const DEFAULT_PRICES: &[(Provider, &str, f64, f64)] = &[
+    (Provider::ChatGPT, "", 2.5, 10.0),
+    (Provider::ChatGPT, "gpt-4o", 2.5, 10.0),
+    (Provider::ChatGPT, "gpt-4o-mini", 0.15, 0.6),
+    (Provider::ChatGPT, "gpt-4.1", 2.0, 8.0),
+    (Provider::ChatGPT, "gpt-4.1-mini", 0.4, 1.6),
+    (Provider::ChatGPT, "gpt-4.1-nano", 0.1, 0.4),
+    (Provider::ChatGPT, "gpt-3.5-turbo", 0.5, 1.5),
+    (Provider::Google, "", 0.3, 2.5),
+    (Provider::Google, "gemini-1.5-flash", 0.075, 0.3),
+    (Provider::Google, "gemini-1.5-pro", 1.25, 5.0),
+    (Provider::Google, "gemini-2.0-flash", 0.1, 0.4),
+    (Provider::Google, "gemini-2.0-flash-lite", 0.075, 0.3),
+    (Provider::Google, "gemini-2.5-flash", 0.3, 2.5),
+    (Provider::Google, "gemini-2.5-pro", 1.25, 10.0),
+    (Provider::DeepSeek, "", 0.27, 1.1),
+    (Provider::DeepSeek, "deepseek-reasoner", 0.55, 2.19),
+    (Provider::ChatGLM, "", 0.7, 0.7),
+    (Provider::ChatGLM, "glm-4-flash", 0.0, 0.0),
+    (Provider::ChatGLM, "glm-4-air", 0.07, 0.07),
+    (Provider::Groq, "", 0.59, 0.79),
+    (Provider::Groq, "llama-3.1-8b", 0.05, 0.08),
+    (Provider::Groq, "gemma2-9b", 0.2, 0.2),
+    (Provider::Local, "", 0.0, 0.0),
+];
+
+/// 当前生效的价格表，自定义价格在前
+pub fn prices(db: &Database) -> Result<Vec<ModelPrice>, AppError> {
+    let mut prices = custom_prices(db)?;
+    for &(provider, prefix, input_price, output_price) in DEFAULT_PRICES {
+        if !prices.iter().any(|p| p.provider == provider && p.model_prefix == prefix) {
+            prices.push(ModelPrice {
+                provider,
+                model_prefix: prefix.to_string(),
+                input_price,
+                output_price,
+                custom: false,
+            });
+        }
+    }
+    Ok(prices)
+}
+
+fn custom_prices(db: &Database) -> Result<Vec<ModelPrice>, AppError> {
+    db.with_conn(|conn| {
+        let mut statement =
+            conn.prepare("SELECT provider, modelPrefix, inputPrice, outputPrice FROM aiPrice ORDER BY provider, modelPrefix")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+
+        let mut prices = Vec::new();
+        for row in rows {
+            let (provider, model_prefix, input_price, output_price) = row?;
+            // 平台名称不认识（例如旧版本保存的数据）时跳过
+            if let Ok(provider) = serde_json::from_value(serde_json::json!(provider)) {
+                prices.push(ModelPrice {
+                    provider,
+                    model_prefix,
+                    input_price,
+                    output_price,
+                    custom: true,
+                });
+            }
+        }
+        Ok(prices)
+    })
+}
+
+/// 用新的自定义价格替换全部自定义价格，传空数组恢复内置价格表
+pub fn set_custom_prices(db: &Database, prices: &[ModelPrice]) -> Result<(), AppError> {
+    if let Some(price) = prices.iter().find(|p| p.input_price < 0.0 || p.output_price < 0.0) {
+        return Err(AppError::InvalidArgument(
+            Msg::new("usage.negative_price").arg("model", &price.model_prefix),
+        ));
+    }
+
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM aiPrice", [])?;
+        for price in prices {
+            tx.execute(
+                "INSERT OR REPLACE INTO aiPrice (provider, modelPrefix, inputPrice, outputPrice) VALUES (?1, ?2, ?3, ?4)",
+                params![price.provider.name(), price.model_prefix.trim(), price.input_price, price.output_price],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    })
+}
+
+/// 按平台和模型名称的最长前缀查找价格
+pub fn price_for(db: &Database, provider: Provider, model: &str) -> Result<Option<ModelPrice>, AppError> {
+    Ok(prices(db)?
+        .into_iter()
+        .filter(|p| p.provider == provider && model.starts_with(&p.model_prefix))
+        .max_by_key(|p| p.model_prefix.len()))
+}
+
+impl ModelPrice {
+    /// 计算费用，单位美元
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input_price + completion_tokens as f64 * self.output_price) / 1_000_000.0
+    }
+}
+
+/// 一次请求的实际token用量
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// 把一次请求的用量记入账本
+///
+/// # Arguments
+///
+/// * `db` - 数据库
+/// * `provider` - AI平台
+/// * `model` - 模型名称
+/// * `lesson_id` - 课题ID，与课文无关的调用传 `None`
+/// * `usage` - token用量
+/// * `estimated` - 用量是否为估算值
+pub fn record_usage(
+    db: &Database,
+    provider: Provider,
+    model: &str,
+    lesson_id: Option<i64>,
+    usage: Usage,
+    estimated: bool,
+) -> Result<(), AppError> {
+    let cost = price_for(db, provider, model)?
+        .map(|price| price.cost(usage.prompt_tokens, usage.completion_tokens))
+        .unwrap_or(0.0);
+
+    db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO aiUsage (provider, model, classId, promptTokens, completionTokens, cost, estimated) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                provider.name(),
+                model,
+                lesson_id,
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                cost,
+                estimated
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+/// 用量汇总的分组方式
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGroup {
+    Provider,
+    Model,
+    Day,
+    Lesson,
+}
+
+impl UsageGroup {
+    fn column(self) -> &'static str {
+        match self {
+            UsageGroup::Provider => "provider",
+            UsageGroup::Model => "provider || '/' || model",
+            UsageGroup::Day => "substr(createTime, 1, 10)",
+            UsageGroup::Lesson => "classId",
+        }
+    }
+}
+
+/// 用量查询条件，都为空时查询全部记录
+#[derive(Debug, Default, Deserialize)]
+pub struct UsageFilter {
+    pub provider: Option<Provider>,
+    pub lesson_id: Option<i64>,
+    /// 开始日期（含），格式 `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// 结束日期（含），格式 `YYYY-MM-DD`
+    pub to: Option<String>,
+}
+
+/// 一组用量汇总
+#[derive(Debug, Serialize)]
+pub struct UsageSummary {
+    /// 分组的值，按课文分组时与课文无关的调用为 `null`
+    pub key: Option<String>,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+/// 按分组汇总账本
+pub fn query_usage(db: &Database, group: UsageGroup, filter: &UsageFilter) -> Result<Vec<UsageSummary>, AppError> {
+    let sql = format!(
+        "SELECT CAST({column} AS TEXT), COUNT(*), SUM(promptTokens), SUM(completionTokens), SUM(cost)
+         FROM aiUsage
+         WHERE (?1 IS NULL OR provider = ?1)
+           AND (?2 IS NULL OR classId = ?2)
+           AND (?3 IS NULL OR substr(createTime, 1, 10) >= ?3)
+           AND (?4 IS NULL OR substr(createTime, 1, 10) <= ?4)
+         GROUP BY {column}
+         ORDER BY {column}",
+        column = group.column()
+    );
+
+    db.with_conn(|conn| {
+        let mut statement = conn.prepare(&sql)?;
+        let rows = statement.query_map(
+            params![
+                filter.provider.map(|p| p.name()),
+                filter.lesson_id,
+                filter.from,
+                filter.to
+            ],
+            |row| {
+                Ok(UsageSummary {
+                    key: row.get(0)?,
+                    requests: row.get(1)?,
+                    prompt_tokens: row.get::<_, Option<u64>>(2)?.unwrap_or(0),
+                    completion_tokens: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+                    cost: row.get::<_, Option<f64>>(4)?.unwrap_or(0.0),
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    })
+}
+
+/// 费用估算结果
+#[derive(Debug, Serialize)]
+pub struct CostEstimate {
+    pub family: ModelFamily,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 找不到价格时为 `null`
+    pub price: Option<ModelPrice>,
+    /// 美元，找不到价格时为 `null`
+    pub cost: Option<f64>,
+}
+
+/// 根据token数和价格表估算费用
+pub fn estimate(
+    db: &Database,
+    provider: Provider,
+    model: &str,
+    requests: u64,
+    input_tokens: u64,
+    output_tokens: u64,
+) -> Result<CostEstimate, AppError> {
+    let price = price_for(db, provider, model)?;
+    Ok(CostEstimate {
+        family: ModelFamily::of(provider, model),
+        requests,
+        input_tokens,
+        output_tokens,
+        cost: price.as_ref().map(|p| p.cost(input_tokens, output_tokens)),
+        price,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn counts_tokens_by_model_family() {
+        assert_eq!(ModelFamily::of(Provider::Groq, "gpt-4o"), ModelFamily::Gpt);
+        assert_eq!(ModelFamily::of(Provider::Local, "DeepSeek-R1"), ModelFamily::DeepSeek);
+        assert_eq!(ModelFamily::of(Provider::Groq, "llama-3.1-8b"), ModelFamily::Open);
+        assert_eq!(ModelFamily::of(Provider::ChatGLM, "custom"), ModelFamily::Glm);
+        assert_eq!(ModelFamily::of(Provider::Google, "gemma2-9b"), ModelFamily::Gemini);
+
+        let cases = [
+            (ModelFamily::Gpt, "hello world", 3),
+            (ModelFamily::Gpt, "こんにちは", 5),
+            (ModelFamily::Gemini, "你好", 2),
+            (ModelFamily::Glm, "日本語", 3),
+            (ModelFamily::DeepSeek, "abcdefghij", 4),
+            (ModelFamily::Open, "你好", 3),
+            (ModelFamily::Open, "안녕 hi", 4),
+            (ModelFamily::Gpt, "", 0),
+        ];
+        for (family, text, expected) in cases {
+            assert_eq!(count_tokens(family, text), expected, "{:?} {:?}", family, text);
+        }
+
+        let messages = [ChatMessage::user("hello world"), ChatMessage::user("你好")];
+        assert_eq!(
+            count_message_tokens(ModelFamily::Gpt, &messages),
+            3 + 2 + 2 * TOKENS_PER_MESSAGE + TOKENS_PER_REQUEST
+        );
+    }
+
+    #[test]
+    fn price_for_uses_the_longest_prefix_and_custom_prices() {
+        let db = Database::memory().unwrap();
+        let price = |provider, model| price_for(&db, provider, model).unwrap().unwrap();
+
+        assert!(close(price(Provider::ChatGPT, "gpt-4o-mini-2024-07-18").input_price, 0.15));
+        assert!(close(price(Provider::ChatGPT, "gpt-4o-2024-08-06").input_price, 2.5));
+        assert_eq!(price(Provider::ChatGPT, "o3").model_prefix, "");
+        assert!(close(price(Provider::Local, "qwen2:7b").cost(1_000_000, 1_000_000), 0.0));
+        assert!(close(price(Provider::ChatGPT, "gpt-4o").cost(1_000_000, 500_000), 7.5));
+
+        let custom = |model_prefix: &str, input_price| ModelPrice {
+            provider: Provider::ChatGPT,
+            model_prefix: model_prefix.to_string(),
+            input_price,
+            output_price: 2.0,
+            custom: true,
+        };
+        set_custom_prices(&db, &[custom("gpt-4o", 1.0), custom("gpt-4o-mini-tts", 0.6)]).unwrap();
+        let overridden = price(Provider::ChatGPT, "gpt-4o-2024-08-06");
+        assert!(overridden.custom && close(overridden.input_price, 1.0));
+        assert!(!price(Provider::ChatGPT, "gpt-4o-mini").custom);
+        assert!(close(price(Provider::ChatGPT, "gpt-4o-mini-tts").input_price, 0.6));
+        // 自定义价格覆盖同一前缀的内置价格，不会出现两条
+        assert_eq!(
+            prices(&db).unwrap().iter().filter(|p| p.provider == Provider::ChatGPT && p.model_prefix == "gpt-4o").count(),
+            1
+        );
+
+        let error = set_custom_prices(&db, &[custom("gpt-4.1", -1.0)]).unwrap_err();
+        assert!(matches!(error, AppError::InvalidArgument(msg) if msg.key == "usage.negative_price"));
+        // 校验失败时不改动已有的自定义价格
+        assert!(price(Provider::ChatGPT, "gpt-4o").custom);
+
+        set_custom_prices(&db, &[]).unwrap();
+        assert!(!price(Provider::ChatGPT, "gpt-4o").custom);
+    }
+
+    #[test]
+    fn query_usage_groups_by_day_provider_model_and_lesson() {
+        let db = Database::memory().unwrap();
+        let usage = |prompt_tokens, completion_tokens| Usage {
+            prompt_tokens,
+            completion_tokens,
+        };
+        record_usage(&db, Provider::ChatGPT, "gpt-4o", Some(1), usage(1000, 500), false).unwrap();
+        record_usage(&db, Provider::ChatGPT, "gpt-4o-mini", None, usage(2000, 0), true).unwrap();
+        record_usage(&db, Provider::Google, "gemini-2.0-flash", Some(1), usage(100, 100), false).unwrap();
+        db.with_conn(|conn| {
+            conn.execute("UPDATE aiUsage SET createTime = '2026-01-01 10:00:00' WHERE id IN (1, 2)", [])?;
+            conn.execute("UPDATE aiUsage SET createTime = '2026-01-02 08:30:00' WHERE id = 3", [])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let summary = |group, filter: &UsageFilter| -> Vec<(Option<String>, u64, u64, u64)> {
+            query_usage(&db, group, filter)
+                .unwrap()
+                .into_iter()
+                .map(|s| (s.key, s.requests, s.prompt_tokens, s.completion_tokens))
+                .collect()
+        };
+        let all = UsageFilter::default();
+        let key = |s: &str| Some(s.to_string());
+
+        assert_eq!(
+            summary(UsageGroup::Day, &all),
+            [(key("2026-01-01"), 2, 3000, 500), (key("2026-01-02"), 1, 100, 100)]
+        );
+        assert_eq!(
+            summary(UsageGroup::Provider, &all),
+            [(key("ChatGPT"), 2, 3000, 500), (key("Google"), 1, 100, 100)]
+        );
+        assert_eq!(
+            summary(UsageGroup::Model, &all),
+            [
+                (key("ChatGPT/gpt-4o"), 1, 1000, 500),
+                (key("ChatGPT/gpt-4o-mini"), 1, 2000, 0),
+                (key("Google/gemini-2.0-flash"), 1, 100, 100),
+            ]
+        );
+        assert_eq!(summary(UsageGroup::Lesson, &all), [(None, 1, 2000, 0), (key("1"), 2, 1100, 600)]);
+
+        let filter = UsageFilter {
+            provider: Some(Provider::ChatGPT),
+            from: Some("2026-01-01".into()),
+            to: Some("2026-01-01".into()),
+            ..Default::default()
+        };
+        assert_eq!(summary(UsageGroup::Provider, &filter), [(key("ChatGPT"), 2, 3000, 500)]);
+        let filter = UsageFilter {
+            from: Some("2026-01-02".into()),
+            ..Default::default()
+        };
+        assert_eq!(summary(UsageGroup::Model, &filter), [(key("Google/gemini-2.0-flash"), 1, 100, 100)]);
+
+        // 费用按记录时的价格计算
+        let cost = query_usage(&db, UsageGroup::Model, &all).unwrap()[0].cost;
+        assert!(close(cost, (1000.0 * 2.5 + 500.0 * 10.0) / 1_000_000.0));
+    }
+}
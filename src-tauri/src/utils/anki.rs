@@ -0,0 +1,977 @@
+use crate::utils::audio_cache::{self, AudioKey};
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use crate::utils::storage::{self, DataDir};
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::{info, warn};
+use uuid::Uuid;
+use zip::ZipWriter;
+
+/// 单词笔记类型的ID，固定不变，重复导入时 Anki 会识别为同一个笔记类型
+const WORD_MODEL_ID: i64 = 1_726_000_000_001;
+/// 例句填空笔记类型的ID
+const CLOZE_MODEL_ID: i64 = 1_726_000_000_002;
+/// Anki 字段之间的分隔符
+const FIELD_SEPARATOR: &str = "\u{1f}";
+/// 句末标点，显示页面（Show.vue）还会在逗号、引号等处断开，例句需要完整的句子，只按句末标点分句
+const SENTENCE_END: &[char] = &['。', '？', '！', '…', '.', '?', '!'];
+/// 准备单词音频时通知前端进度的事件
+pub const PROGRESS_EVENT: &str = "vocabulary-audio-progress";
+/// 两次进度事件之间的最短间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+/// 连续配音失败这么多次后不再配音，其余单词只使用已缓存的音频
+const MAX_SYNTHESIS_FAILURES: usize = 3;
+
+/// Anki 旧版集合（schema 11）的表结构，`.apkg` 中的 `collection.anki2` 使用这个结构
+const COLLECTION_SCHEMA: &str = r#"
+CREATE TABLE col (
+    id integer PRIMARY KEY, crt integer NOT NULL, mod integer NOT NULL, scm integer NOT NULL, ver integer NOT NULL,
+    dty integer NOT NULL, usn integer NOT NULL, ls integer NOT NULL, conf text NOT NULL, models text NOT NULL,
+    decks text NOT NULL, dconf text NOT NULL, tags text NOT NULL
+);
+CREATE TABLE notes (
+    id integer PRIMARY KEY, guid text NOT NULL, mid integer NOT NULL, mod integer NOT NULL, usn integer NOT NULL,
+    tags text NOT NULL, flds text NOT NULL, sfld integer NOT NULL, csum integer NOT NULL, flags integer NOT NULL,
+    data text NOT NULL
+);
+CREATE TABLE cards (
+    id integer PRIMARY KEY, nid integer NOT NULL, did integer NOT NULL, ord integer NOT NULL, mod integer NOT NULL,
+    usn integer NOT NULL, type integer NOT NULL, queue integer NOT NULL, due integer NOT NULL, ivl integer NOT NULL,
+    factor integer NOT NULL, reps integer NOT NULL, lapses integer NOT NULL, left integer NOT NULL,
+    odue integer NOT NULL, odid integer NOT NULL, flags integer NOT NULL, data text NOT NULL
+);
+CREATE TABLE revlog (
+    id integer PRIMARY KEY, cid integer NOT NULL, usn integer NOT NULL, ease integer NOT NULL, ivl integer NOT NULL,
+    lastIvl integer NOT NULL, factor integer NOT NULL, time integer NOT NULL, type integer NOT NULL
+);
+CREATE TABLE graves (usn integer NOT NULL, oid integer NOT NULL, type integer NOT NULL);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);
+"#;
+
+/// 导出的文件格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VocabularyFormat {
+    /// Anki 牌组，包含单词音频
+    #[default]
+    Apkg,
+    Csv,
+    Tsv,
+}
+
+/// 导出哪些单词
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VocabularySource {
+    /// 一篇课文的单词
+    Lesson(i64),
+    /// 一种语言下所有课文的单词
+    Language(i64),
+}
+
+/// 导出选项
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VocabularyOptions {
+    pub format: VocabularyFormat,
+    /// 是否附带单词音频，只对 `apkg` 有效，没有缓存的单词会先配音
+    pub audio: bool,
+    /// 是否为有例句的单词生成例句填空卡片
+    pub cloze: bool,
+    /// 只导出有释义的单词
+    pub annotated_only: bool,
+    /// 牌组名称，默认为“语言::课文”或语言名称
+    pub deck_name: Option<String>,
+}
+
+impl Default for VocabularyOptions {
+    fn default() -> Self {
+        Self {
+            format: VocabularyFormat::Apkg,
+            audio: true,
+            cloze: true,
+            annotated_only: true,
+            deck_name: None,
+        }
+    }
+}
+
+/// 导出结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VocabularyReport {
+    pub path: String,
+    pub format: VocabularyFormat,
+    /// 导出的单词数，同一个单词只导出一次
+    pub words: usize,
+    /// 例句填空的数量
+    pub clozes: usize,
+    /// 附带音频的单词数
+    pub audios: usize,
+    /// 配音失败、没有音频的单词数
+    pub missing_audios: usize,
+}
+
+/// 准备单词音频的进度
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AudioProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// 单词所在的例句，`target` 为单词本身
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Example {
+    before: String,
+    target: String,
+    after: String,
+}
+
+impl Example {
+    fn sentence(&self) -> String {
+        format!("{}{}{}", self.before, self.target, self.after)
+    }
+
+    /// Anki 的填空格式，`escape` 用来转义 apkg 中的 HTML
+    fn cloze(&self, escape: fn(&str) -> String) -> String {
+        format!("{}{{{{c1::{}}}}}{}", escape(&self.before), escape(&self.target), escape(&self.after))
+    }
+}
+
+/// 一个导出的单词
+#[derive(Debug)]
+struct VocabularyEntry {
+    content: String,
+    oart_of_speech: String,
+    pronunciation: String,
+    interpretation: String,
+    other: String,
+    example: Option<Example>,
+    /// 音频文件名和内容
+    audio: Option<(String, Vec<u8>)>,
+}
+
+/// 导出单词的来源信息
+struct Collected {
+    language_id: i64,
+    voice: String,
+    deck_name: String,
+    entries: Vec<VocabularyEntry>,
+}
+
+/// 课文字幕中的一个片段
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+    /// 片段在正文中的字节范围
+    range: Range<usize>,
+    /// 所在句子在正文中的字节范围
+    sentence: Range<usize>,
+}
+
+/// 按字幕时间轴把课文切成片段，并按句末标点分句
+///
+/// 与显示页面一样，每个片段从字幕文字在正文中的位置开始，到下一个字幕文字的位置结束，中间的空格和标点属于前一个片段。
+///
+/// # Returns
+///
+/// * 每个字幕片段的位置和所在的句子，字幕与正文对不上时返回 `None`
+fn split_sentences(content: &str, metadata: &[Value]) -> Option<Vec<Segment>> {
+    let texts: Vec<&str> = metadata
+        .iter()
+        .map(|item| item.pointer("/Metadata/0/Data/text/Text").and_then(Value::as_str))
+        .collect::<Option<_>>()?;
+
+    let mut ranges = Vec::with_capacity(texts.len());
+    let mut cursor = 0;
+    for (i, text) in texts.iter().enumerate() {
+        let start = cursor + content.get(cursor..)?.find(text)?;
+        let text_end = start + text.len();
+        let end = match texts.get(i + 1) {
+            Some(next) => content[text_end..].find(next).map_or(text_end, |p| text_end + p),
+            None => content.len(),
+        };
+        ranges.push(start..end);
+        cursor = text_end;
+    }
+
+    let mut segments = Vec::with_capacity(ranges.len());
+    let mut sentence_first = 0;
+    for (i, range) in ranges.iter().enumerate() {
+        if content[range.clone()].contains(SENTENCE_END) || i == ranges.len() - 1 {
+            let sentence = ranges[sentence_first].start..range.end;
+            segments.extend(ranges[sentence_first..=i].iter().map(|r| Segment {
+                range: r.clone(),
+                sentence: sentence.clone(),
+            }));
+            sentence_first = i + 1;
+        }
+    }
+    Some(segments)
+}
+
+/// 单词在课文中的例句
+///
+/// 优先使用单词所在字幕片段的位置，单词经过合并或拆分对不上时，在所在句子中查找
+fn find_example(content: &str, segments: &[Segment], start_index: i64, word: &str) -> Option<Example> {
+    let segment = segments.get(usize::try_from(start_index).ok()?)?;
+    let sentence = &segment.sentence;
+    let text = &content[sentence.clone()];
+    let (start, len) = match match_prefix(&content[segment.range.start..sentence.end], word) {
+        Some(len) => (segment.range.start, len),
+        None => text
+            .char_indices()
+            .find_map(|(i, _)| match_prefix(&text[i..], word).map(|len| (sentence.start + i, len)))?,
+    };
+
+    let end = start + len;
+    Some(Example {
+        before: squash(&content[sentence.start..start]).trim_start().to_string(),
+        target: squash(&content[start..end]),
+        after: squash(&content[end..sentence.end]).trim_end().to_string(),
+    })
+}
+
+/// `word` 出现在 `text` 开头时返回在 `text` 中的长度，短语中的空格可以对应正文中的任意空白（比如换行）
+fn match_prefix(text: &str, word: &str) -> Option<usize> {
+    let mut rest = text;
+    for (i, part) in word.split_whitespace().enumerate() {
+        if i > 0 {
+            let trimmed = rest.trim_start();
+            if trimmed.len() == rest.len() {
+                return None;
+            }
+            rest = trimmed;
+        }
+        rest = rest.strip_prefix(part)?;
+    }
+    Some(text.len() - rest.len())
+}
+
+/// 连续的空白字符（包括换行）合并为一个空格
+fn squash(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                result.push(' ');
+            }
+            in_space = true;
+        } else {
+            result.push(c);
+            in_space = false;
+        }
+    }
+    result
+}
+
+/// 读取需要导出的单词和例句
+fn collect(
+    db: &Database,
+    data_dir: &DataDir,
+    source: VocabularySource,
+    options: &VocabularyOptions,
+) -> Result<Collected, AppError> {
+    type LessonRow = (i64, String, String, String, String);
+    type WordRow = (i64, String, Option<String>, Option<String>, Option<String>, Option<String>, i64);
+    // 课文的字幕文件在数据目录中，读取期间数据目录不能被更换
+    let (language_id, deck_name, voice, words, texts) = data_dir.with_root_and_db(db, |_, db| {
+        let (language_id, deck_name, voice, lessons, words) = db.with_conn(|conn| {
+            let (language_id, lesson_title) = match source {
+                VocabularySource::Language(id) => (id, None),
+                VocabularySource::Lesson(id) => conn
+                    .query_row("SELECT languageId, title FROM `class` WHERE id = ?1", [id], |row| {
+                        Ok((row.get(0)?, Some(row.get::<_, String>(1)?)))
+                    })
+                    .optional()?
+                    .ok_or_else(|| AppError::InvalidArgument(Msg::new("lesson.not_found").arg("id", id)))?,
+            };
+            let (language_title, voice): (String, String) = conn
+                .query_row("SELECT title, voice FROM language WHERE id = ?1", [language_id], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .optional()?
+                .ok_or_else(|| AppError::InvalidArgument(Msg::new("vocabulary.language_not_found").arg("id", language_id)))?;
+
+            let lesson_id = match source {
+                VocabularySource::Lesson(id) => Some(id),
+                VocabularySource::Language(_) => None,
+            };
+            let lessons: Vec<LessonRow> = {
+                let mut stmt = conn.prepare(
+                    "SELECT id, title, content, filePath, audioSrtJsonName FROM `class`
+                     WHERE languageId = ?1 AND (?2 IS NULL OR id = ?2) ORDER BY id",
+                )?;
+                let rows = stmt.query_map(params![language_id, lesson_id], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+                })?;
+                rows.collect::<Result<_, _>>()?
+            };
+            let words: Vec<WordRow> = {
+                let mut stmt = conn.prepare(
+                    "SELECT w.classId, w.content, w.oartOfSpeech, w.pronunciation, w.interpretation, w.other, w.startIndex
+                     FROM word w JOIN `class` c ON c.id = w.classId
+                     WHERE c.languageId = ?1 AND (?2 IS NULL OR c.id = ?2) ORDER BY c.id, w.sort",
+                )?;
+                let rows = stmt.query_map(params![language_id, lesson_id], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get::<_, Option<i64>>(6)?.unwrap_or(0),
+                    ))
+                })?;
+                rows.collect::<Result<_, _>>()?
+            };
+            let deck_name = lesson_title.map_or_else(|| language_title.clone(), |t| format!("{}::{}", language_title, t));
+            Ok((language_id, deck_name, voice, lessons, words))
+        })?;
+
+        // 课文的正文和字幕分段，字幕文件缺失或与正文对不上的课文没有例句
+        let mut texts: HashMap<i64, (String, Vec<Segment>)> = HashMap::new();
+        for (id, title, content, file_path, srt_name) in lessons {
+            let path = Path::new(&file_path).join(&srt_name);
+            let metadata: Option<Vec<Value>> = fs::read(&path).ok().and_then(|b| serde_json::from_slice(&b).ok());
+            match metadata.and_then(|m| split_sentences(&content, &m)) {
+                Some(segments) => {
+                    texts.insert(id, (content, segments));
+                }
+                None => warn!(lesson = %title, path = %path.display(), "课文字幕读取失败或与正文不一致，不生成例句"),
+            }
+        }
+        Ok((language_id, deck_name, voice, words, texts))
+    })?;
+
+    // 同一个单词只导出一次，优先保留有释义的
+    let mut entries: Vec<VocabularyEntry> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (class_id, content, oart_of_speech, pronunciation, interpretation, other, start_index) in words {
+        let content = content.trim().to_string();
+        let interpretation = interpretation.unwrap_or_default().trim().to_string();
+        if content.is_empty() || (options.annotated_only && interpretation.is_empty()) {
+            continue;
+        }
+        let example = texts
+            .get(&class_id)
+            .and_then(|(text, segments)| find_example(text, segments, start_index, &content));
+        let entry = VocabularyEntry {
+            content: content.clone(),
+            oart_of_speech: oart_of_speech.unwrap_or_default(),
+            pronunciation: pronunciation.unwrap_or_default(),
+            interpretation,
+            other: other.unwrap_or_default(),
+            example,
+            audio: None,
+        };
+        match positions.get(&content) {
+            Some(&i) if entries[i].interpretation.is_empty() && !entry.interpretation.is_empty() => entries[i] = entry,
+            Some(_) => {}
+            None => {
+                positions.insert(content, entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+
+    Ok(Collected {
+        language_id,
+        voice,
+        deck_name: options.deck_name.clone().filter(|n| !n.trim().is_empty()).unwrap_or(deck_name),
+        entries,
+    })
+}
+
+/// 导出单词
+///
+/// `apkg` 格式为 Anki 牌组，每个单词一张卡片，附带单词音频和例句；开启例句填空时，有例句的单词另有一张填空卡片。
+/// 笔记的ID由语言和单词决定，重复导入同一个单词时 Anki 会更新已有的笔记。
+/// `csv`、`tsv` 格式带有 Anki 的文件头，可以直接导入 Anki，也可以用表格软件打开。
+///
+/// 没有缓存的单词逐个配音，进度通过 `vocabulary-audio-progress` 事件通知前端，`{done, total}`；
+/// 连续配音失败时其余单词只使用已缓存的音频。读取和写入文件在 `spawn_blocking` 中进行。
+///
+/// # Arguments
+///
+/// * `source` - 导出一篇课文或一种语言的单词
+/// * `path` - 保存路径
+/// * `options` - 导出选项
+///
+/// # Returns
+///
+/// * 成功返回导出的统计
+/// * 课文或语言不存在、没有可导出的单词或写入失败时返回 `Err(AppError)`，不会留下不完整的文件
+pub async fn export(
+    app: &AppHandle,
+    source: VocabularySource,
+    path: PathBuf,
+    options: VocabularyOptions,
+) -> Result<VocabularyReport, AppError> {
+    let task_app = app.clone();
+    let collect_options = options.clone();
+    let Collected {
+        language_id,
+        voice,
+        deck_name,
+        mut entries,
+    } = tauri::async_runtime::spawn_blocking(move || {
+        collect(&task_app.state::<Database>(), &task_app.state::<DataDir>(), source, &collect_options)
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    if entries.is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("vocabulary.no_words")));
+    }
+
+    let mut report = VocabularyReport {
+        path: path.display().to_string(),
+        format: options.format,
+        words: entries.len(),
+        clozes: if options.cloze { entries.iter().filter(|e| e.example.is_some()).count() } else { 0 },
+        audios: 0,
+        missing_audios: 0,
+    };
+    if options.format == VocabularyFormat::Apkg && options.audio {
+        let data_dir = app.state::<DataDir>();
+        let total = entries.len();
+        let mut failures = 0;
+        let mut last_emit: Option<Instant> = None;
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let key = AudioKey::new(&voice, &entry.content);
+            let audio = if failures < MAX_SYNTHESIS_FAILURES {
+                audio_cache::get_or_synthesize(&data_dir, key)
+                    .await
+                    .and_then(|(file, _)| Ok(Some(fs::read(file)?)))
+            } else {
+                data_dir.try_root().and_then(|root| audio_cache::read(&root, &key))
+            };
+            match audio {
+                Ok(Some(bytes)) => {
+                    entry.audio = Some((format!("learn-language-{}.mp3", key.hash()), bytes));
+                    report.audios += 1;
+                    failures = 0;
+                }
+                Ok(None) => report.missing_audios += 1,
+                Err(e) => {
+                    warn!(word = %entry.content, error = %e, "单词配音失败，导出时不附带音频");
+                    report.missing_audios += 1;
+                    failures += 1;
+                    if failures == MAX_SYNTHESIS_FAILURES {
+                        warn!("连续配音失败，其余单词只使用已缓存的音频");
+                    }
+                }
+            }
+
+            let progress = AudioProgress { done: i + 1, total };
+            if progress.done == total || last_emit.is_none_or(|time| time.elapsed() >= PROGRESS_INTERVAL) {
+                last_emit = Some(Instant::now());
+                if let Err(e) = app.emit(PROGRESS_EVENT, progress) {
+                    warn!("发送进度事件失败：{}", e);
+                }
+            }
+        }
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        storage::write_atomic(&path, |temp| match options.format {
+            VocabularyFormat::Apkg => write_apkg(temp, language_id, &deck_name, &entries, options.cloze),
+            VocabularyFormat::Csv => write_delimited(temp, ',', &entries, options.cloze),
+            VocabularyFormat::Tsv => write_delimited(temp, '\t', &entries, options.cloze),
+        })
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    Ok(report)
+}
+
+/// 写入带 Anki 文件头的 CSV 或 TSV
+fn write_delimited(path: &Path, separator: char, entries: &[VocabularyEntry], cloze: bool) -> Result<(), AppError> {
+    let columns = ["Word", "PartOfSpeech", "Pronunciation", "Meaning", "Notes", "Sentence", "Cloze"];
+    let mut file = io::BufWriter::new(File::create(path)?);
+    let separator_name = if separator == '\t' { "Tab" } else { "Comma" };
+    writeln!(file, "#separator:{}", separator_name)?;
+    writeln!(file, "#html:false")?;
+    writeln!(file, "#columns:{}", columns.join(&separator.to_string()))?;
+    for entry in entries {
+        let sentence = entry.example.as_ref().map(Example::sentence).unwrap_or_default();
+        let cloze = entry
+            .example
+            .as_ref()
+            .filter(|_| cloze)
+            .map(|e| e.cloze(str::to_string))
+            .unwrap_or_default();
+        let fields = [
+            entry.content.as_str(),
+            &entry.oart_of_speech,
+            &entry.pronunciation,
+            &entry.interpretation,
+            &entry.other,
+            &sentence,
+            &cloze,
+        ];
+        let line: Vec<String> = fields.iter().map(|f| quote_field(f, separator)).collect();
+        writeln!(file, "{}", line.join(&separator.to_string()))?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// 字段中有分隔符、引号或换行时加引号，引号写两次
+fn quote_field(field: &str, separator: char) -> String {
+    if field.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// 转义 HTML 特殊字符，Anki 的字段内容是 HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "<br>")
+}
+
+/// 笔记的 `guid`，由语言、笔记类型和单词决定
+fn note_guid(language_id: i64, model_id: i64, word: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\0{}\0{}", language_id, model_id, word).as_bytes());
+    hex::encode(&hasher.finalize()[..8])
+}
+
+/// Anki 用来查重的校验和：第一个字段的 SHA-1 的前8位十六进制
+fn field_checksum(text: &str) -> i64 {
+    let digest = Sha1::digest(text.as_bytes());
+    i64::from(u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]))
+}
+
+/// 笔记类型的JSON
+///
+/// `template` 为卡片正面和背面的模板
+fn model_json(id: i64, name: &str, cloze: bool, fields: &[&str], template: (&str, &str), deck_id: i64, now: i64) -> Value {
+    let (qfmt, afmt) = template;
+    json!({
+        "id": id,
+        "name": name,
+        "type": if cloze { 1 } else { 0 },
+        "mod": now,
+        "usn": 0,
+        "sortf": 0,
+        "did": deck_id,
+        "tmpls": [{
+            "name": if cloze { "Cloze" } else { "Card 1" },
+            "ord": 0,
+            "qfmt": qfmt,
+            "afmt": afmt,
+            "bqfmt": "",
+            "bafmt": "",
+            "did": null,
+            "bfont": "",
+            "bsize": 0,
+        }],
+        "flds": fields.iter().enumerate().map(|(ord, name)| json!({
+            "name": name,
+            "ord": ord,
+            "sticky": false,
+            "rtl": false,
+            "font": "Arial",
+            "size": 20,
+            "media": [],
+        })).collect::<Vec<_>>(),
+        "css": ".card { font-family: arial; font-size: 20px; text-align: center; color: black; background-color: white; }\n\
+                .word { font-size: 32px; }\n.pos, .notes, .sentence { color: #888; font-size: 16px; }\n\
+                .cloze { font-weight: bold; color: blue; }",
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false,
+        "tags": [],
+        "vers": [],
+        "req": [[0, "any", [0]]],
+    })
+}
+
+/// 写入 Anki 牌组
+///
+/// `.apkg` 是zip文件，包含旧版格式的集合数据库 `collection.anki2`、音频文件名的映射 `media` 和以序号命名的音频文件
+fn write_apkg(
+    path: &Path,
+    language_id: i64,
+    deck_name: &str,
+    entries: &[VocabularyEntry],
+    cloze: bool,
+) -> Result<(), AppError> {
+    let collection = path.with_extension(format!("{}.anki2", Uuid::new_v4()));
+    let result = write_collection(&collection, language_id, deck_name, entries, cloze)
+        .and_then(|_| write_apkg_archive(path, &collection, entries));
+    let _ = fs::remove_file(&collection);
+    result
+}
+
+fn write_collection(
+    path: &Path,
+    language_id: i64,
+    deck_name: &str,
+    entries: &[VocabularyEntry],
+    cloze: bool,
+) -> Result<(), AppError> {
+    let now = Local::now();
+    let now_secs = now.timestamp();
+    let now_ms = now.timestamp_millis();
+    let deck_id = now_ms;
+
+    let word_model = model_json(
+        WORD_MODEL_ID,
+        "Learn Language Word",
+        false,
+        &["Word", "PartOfSpeech", "Pronunciation", "Meaning", "Notes", "Audio", "Sentence"],
+        (
+            "<div class=word>{{Word}}</div>{{Audio}}",
+            "{{FrontSide}}<hr id=answer>{{#PartOfSpeech}}<div class=pos>{{PartOfSpeech}}</div>{{/PartOfSpeech}}\
+         {{#Pronunciation}}<div>{{Pronunciation}}</div>{{/Pronunciation}}<div>{{Meaning}}</div>\
+         {{#Notes}}<div class=notes>{{Notes}}</div>{{/Notes}}{{#Sentence}}<div class=sentence>{{Sentence}}</div>{{/Sentence}}",
+        ),
+        deck_id,
+        now_secs,
+    );
+    let cloze_model = model_json(
+        CLOZE_MODEL_ID,
+        "Learn Language Cloze",
+        true,
+        &["Text", "Word", "Meaning", "Audio"],
+        (
+            "{{cloze:Text}}",
+            "{{cloze:Text}}<hr id=answer><div class=word>{{Word}}</div>{{Audio}}<div>{{Meaning}}</div>",
+        ),
+        deck_id,
+        now_secs,
+    );
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id, "name": name, "mod": now_secs, "usn": 0, "desc": "", "dyn": 0, "conf": 1, "collapsed": false,
+            "browserCollapsed": false, "extendNew": 0, "extendRev": 0, "lrnToday": [0, 0], "revToday": [0, 0],
+            "newToday": [0, 0], "timeToday": [0, 0],
+        })
+    };
+    let dconf = json!({"1": {
+        "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true, "timer": 0, "replayq": true,
+        "dyn": false,
+        "new": {"bury": false, "delays": [1.0, 10.0], "initialFactor": 2500, "ints": [1, 4, 0], "order": 1, "perDay": 20},
+        "rev": {"bury": false, "ease4": 1.3, "ivlFct": 1.0, "maxIvl": 36500, "perDay": 200, "hardFactor": 1.2},
+        "lapse": {"delays": [10.0], "leechAction": 1, "leechFails": 8, "minInt": 1, "mult": 0.0},
+    }});
+    let conf = json!({
+        "nextPos": 1, "estTimes": true, "activeDecks": [1], "sortType": "noteFld", "timeLim": 0,
+        "sortBackwards": false, "addToCur": true, "curDeck": 1, "newSpread": 0, "dueCounts": true,
+        "curModel": WORD_MODEL_ID, "collapseTime": 1200,
+    });
+
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(COLLECTION_SCHEMA)?;
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            now_secs - now_secs % 86400,
+            now_ms,
+            conf.to_string(),
+            json!({WORD_MODEL_ID.to_string(): word_model, CLOZE_MODEL_ID.to_string(): cloze_model}).to_string(),
+            json!({"1": deck(1, "Default"), deck_id.to_string(): deck(deck_id, deck_name)}).to_string(),
+            dconf.to_string()
+        ],
+    )?;
+
+    {
+        let mut insert_note = tx.prepare(
+            "INSERT INTO notes (id, guid, mid, mod, usn, tags, flds, sfld, csum, flags, data)
+             VALUES (?1, ?2, ?3, ?4, 0, ' learn-language ', ?5, ?6, ?7, 0, '')",
+        )?;
+        let mut insert_card = tx.prepare(
+            "INSERT INTO cards (id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps, lapses, left, odue,
+                odid, flags, data)
+             VALUES (?1, ?2, ?3, 0, ?4, 0, 0, 0, ?5, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+        )?;
+        // 笔记和卡片的ID是毫秒时间戳，按导出顺序递增，新卡片按这个顺序学习
+        let mut position = 0;
+        let mut add = |model_id: i64, word: &str, fields: &[String], sort_field: &str| -> Result<(), AppError> {
+            position += 1;
+            let note_id = now_ms + position;
+            insert_note.execute(params![
+                note_id,
+                note_guid(language_id, model_id, word),
+                model_id,
+                now_secs,
+                fields.join(FIELD_SEPARATOR),
+                sort_field,
+                field_checksum(sort_field)
+            ])?;
+            insert_card.execute(params![note_id, note_id, deck_id, now_secs, position])?;
+            Ok(())
+        };
+
+        for entry in entries {
+            let audio = entry
+                .audio
+                .as_ref()
+                .map(|(name, _)| format!("[sound:{}]", name))
+                .unwrap_or_default();
+            let sentence = entry.example.as_ref().map(|e| escape_html(&e.sentence())).unwrap_or_default();
+            add(
+                WORD_MODEL_ID,
+                &entry.content,
+                &[
+                    escape_html(&entry.content),
+                    escape_html(&entry.oart_of_speech),
+                    escape_html(&entry.pronunciation),
+                    escape_html(&entry.interpretation),
+                    escape_html(&entry.other),
+                    audio.clone(),
+                    sentence,
+                ],
+                &entry.content,
+            )?;
+            if let Some(example) = entry.example.as_ref().filter(|_| cloze) {
+                add(
+                    CLOZE_MODEL_ID,
+                    &entry.content,
+                    &[
+                        example.cloze(escape_html),
+                        escape_html(&entry.content),
+                        escape_html(&entry.interpretation),
+                        audio,
+                    ],
+                    &example.cloze(str::to_string),
+                )?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn write_apkg_archive(path: &Path, collection: &Path, entries: &[VocabularyEntry]) -> Result<(), AppError> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let (deflated, stored) = storage::zip_options(false);
+
+    zip.start_file("collection.anki2", deflated)?;
+    io::copy(&mut File::open(collection)?, &mut zip)?;
+
+    let mut media = serde_json::Map::new();
+    for (name, bytes) in entries.iter().filter_map(|e| e.audio.as_ref()) {
+        let index = media.len().to_string();
+        zip.start_file(index.as_str(), stored)?;
+        zip.write_all(bytes)?;
+        media.insert(index, json!(name));
+    }
+    zip.start_file("media", deflated)?;
+    zip.write_all(&serde_json::to_vec(&media)?)?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// 导出单词到 Anki 牌组或表格
+///
+/// # Arguments
+///
+/// * `source` - `{"lesson": 课文ID}` 或 `{"language": 语言ID}`
+/// * `path` - 保存路径，一般以 `.apkg`、`.csv` 或 `.tsv` 结尾
+/// * `options` - 导出选项，不传时导出带音频和例句填空的 `apkg`，只包含有释义的单词
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为导出的统计
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+pub async fn export_vocabulary(
+    app: AppHandle,
+    source: VocabularySource,
+    path: String,
+    options: Option<VocabularyOptions>,
+) -> Result<CustomResult, CustomResult> {
+    let report = export(&app, source, PathBuf::from(path), options.unwrap_or_default()).await?;
+    info!(
+        words = report.words,
+        clozes = report.clozes,
+        audios = report.audios,
+        missing_audios = report.missing_audios,
+        "已导出单词"
+    );
+
+    Ok(CustomResult::success(None, Some(json!(report))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    /// 字幕文件的内容，每个片段只有文字
+    fn metadata(texts: &[&str]) -> Vec<Value> {
+        texts
+            .iter()
+            .map(|text| json!({"Metadata": [{"Data": {"text": {"Text": text}}}]}))
+            .collect()
+    }
+
+    fn example(before: &str, target: &str, after: &str) -> Example {
+        Example {
+            before: before.to_string(),
+            target: target.to_string(),
+            after: after.to_string(),
+        }
+    }
+
+    fn entry(content: &str, example: Option<Example>, audio: Option<&str>) -> VocabularyEntry {
+        VocabularyEntry {
+            content: content.to_string(),
+            oart_of_speech: "noun".to_string(),
+            pronunciation: String::new(),
+            interpretation: format!("{} 的释义", content),
+            other: String::new(),
+            example,
+            audio: audio.map(|name| (name.to_string(), name.as_bytes().to_vec())),
+        }
+    }
+
+    #[test]
+    fn split_sentences_follows_subtitles_and_sentence_ends() {
+        let content = "Hello world. How are you?";
+        let segments = split_sentences(content, &metadata(&["Hello", "world.", "How", "are", "you?"])).unwrap();
+        let ranges: Vec<_> = segments.iter().map(|s| (s.range.clone(), s.sentence.clone())).collect();
+        assert_eq!(
+            ranges,
+            vec![(0..6, 0..13), (6..13, 0..13), (13..17, 13..25), (17..21, 13..25), (21..25, 13..25)]
+        );
+
+        // 字幕与正文对不上或缺少文字时没有分段
+        assert_eq!(split_sentences(content, &metadata(&["Hello", "planet"])), None);
+        assert_eq!(split_sentences(content, &[json!({"Metadata": []})]), None);
+    }
+
+    #[test]
+    fn find_example_uses_start_index_then_searches_the_sentence() {
+        let content = "Hello world. How are you?";
+        let segments = split_sentences(content, &metadata(&["Hello", "world.", "How", "are", "you?"])).unwrap();
+        let expected = example("Hello ", "world", ".");
+        assert_eq!(find_example(content, &segments, 1, "world"), Some(expected.clone()));
+        assert_eq!(expected.cloze(str::to_string), "Hello {{c1::world}}.");
+
+        // 位置对不上时在所在句子中查找，不会找到其他句子
+        assert_eq!(find_example(content, &segments, 0, "world"), Some(expected));
+        assert_eq!(find_example(content, &segments, 2, "world"), None);
+        assert_eq!(find_example(content, &segments, 9, "world"), None);
+        assert_eq!(find_example(content, &segments, -1, "world"), None);
+
+        // 短语中的空格对应正文中的换行，例句中合并为一个空格
+        let content = "I will pick\nup the kids.";
+        let segments = split_sentences(content, &metadata(&["I", "will", "pick", "up", "the", "kids."])).unwrap();
+        assert_eq!(find_example(content, &segments, 2, "pick up"), Some(example("I will ", "pick up", " the kids.")));
+        assert_eq!(find_example(content, &segments, 0, "pick up"), Some(example("I will ", "pick up", " the kids.")));
+        assert_eq!(find_example(content, &segments, 2, "pickup"), None);
+    }
+
+    #[test]
+    fn quote_field_and_checksum() {
+        assert_eq!(quote_field("plain", ','), "plain");
+        assert_eq!(quote_field("a,b", ','), "\"a,b\"");
+        assert_eq!(quote_field("a,b", '\t'), "a,b");
+        assert_eq!(quote_field("a\tb", '\t'), "\"a\tb\"");
+        assert_eq!(quote_field("say \"hi\"", ','), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote_field("line\nbreak", '\t'), "\"line\nbreak\"");
+
+        // SHA-1("hello") = aaf4c61d...
+        assert_eq!(field_checksum("hello"), 0xaaf4c61d);
+        assert!((0..=u32::MAX as i64).contains(&field_checksum("任意的单词")));
+    }
+
+    #[test]
+    fn apkg_contains_notes_cards_and_media() {
+        let dir = fixtures::temp_dir("anki");
+        let path = dir.join("words.apkg");
+        let entries = [
+            entry("world", Some(example("Hello ", "world", ".")), Some("learn-language-a.mp3")),
+            entry("<b>", None, None),
+            entry("you", Some(example("How are ", "you", "?")), Some("learn-language-b.mp3")),
+        ];
+        write_apkg(&path, 7, "English::Lesson", &entries, true).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, ["0", "1", "collection.anki2", "media"]);
+        let mut media = String::new();
+        archive.by_name("media").unwrap().read_to_string(&mut media).unwrap();
+        let media: Value = serde_json::from_str(&media).unwrap();
+        assert_eq!(media, json!({"0": "learn-language-a.mp3", "1": "learn-language-b.mp3"}));
+        let mut audio = Vec::new();
+        archive.by_name("1").unwrap().read_to_end(&mut audio).unwrap();
+        assert_eq!(audio, b"learn-language-b.mp3");
+
+        let collection = dir.join("collection.anki2");
+        io::copy(&mut archive.by_name("collection.anki2").unwrap(), &mut File::create(&collection).unwrap()).unwrap();
+        let conn = Connection::open(&collection).unwrap();
+        let decks: String = conn.query_row("SELECT decks FROM col", [], |row| row.get(0)).unwrap();
+        let decks: Value = serde_json::from_str(&decks).unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT n.guid, n.mid, n.flds, n.sfld, n.csum, c.did FROM notes n JOIN cards c ON c.nid = n.id ORDER BY n.id",
+            )
+            .unwrap();
+        let notes: Vec<(String, i64, String, String, i64, i64)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let cards: i64 = conn.query_row("SELECT COUNT(*) FROM cards", [], |row| row.get(0)).unwrap();
+        assert_eq!(cards, 5);
+        // 每个单词一张卡片，有例句的单词另有一张填空卡片
+        let models: Vec<i64> = notes.iter().map(|n| n.1).collect();
+        assert_eq!(models, [WORD_MODEL_ID, CLOZE_MODEL_ID, WORD_MODEL_ID, WORD_MODEL_ID, CLOZE_MODEL_ID]);
+        for (guid, model_id, flds, sfld, csum, deck_id) in &notes {
+            assert_eq!(*csum, field_checksum(sfld));
+            assert_eq!(decks[deck_id.to_string()]["name"], "English::Lesson");
+            let word = if *model_id == WORD_MODEL_ID { sfld.as_str() } else { flds.split(FIELD_SEPARATOR).nth(1).unwrap() };
+            let word = word.replace("&lt;", "<").replace("&gt;", ">");
+            assert_eq!(*guid, note_guid(7, *model_id, &word));
+        }
+
+        let fields: Vec<&str> = notes[0].2.split(FIELD_SEPARATOR).collect();
+        assert_eq!(fields.len(), 7);
+        assert_eq!((fields[0], fields[5], fields[6]), ("world", "[sound:learn-language-a.mp3]", "Hello world."));
+        assert_eq!(notes[1].2.split(FIELD_SEPARATOR).next(), Some("Hello {{c1::world}}."));
+        // 字段内容按 HTML 转义，没有音频的单词音频字段为空
+        let fields: Vec<&str> = notes[2].2.split(FIELD_SEPARATOR).collect();
+        assert_eq!((fields[0], fields[5]), ("&lt;b&gt;", ""));
+
+        drop(stmt);
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,22 +1,26 @@
 use crate::utils::custom_result::CustomResult;
+use crate::utils::error::AppError;
+use crate::utils::http::{build_client, send_json};
+use crate::utils::i18n::Msg;
+use crate::utils::storage::DataDir;
 use crate::utils::tts::TTS;
 use base64::{engine::general_purpose, Engine as _};
 use futures_util::{sink::SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use tauri::Url;
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
 use tokio_tungstenite::{
     connect_async,
     tungstenite::{client::IntoClientRequest, Message},
 };
 use uuid::Uuid;
+use tracing::warn;
+use tauri::State;
 use tauri_plugin_http::reqwest;
-use reqwest::{ClientBuilder, Proxy, header::{HeaderMap, HeaderValue, HeaderName}};
+use reqwest::header::{HeaderMap, HeaderValue, HeaderName};
 
 #[derive(Deserialize)]
 pub struct TTSData {
@@ -25,7 +29,6 @@ pub struct TTSData {
     pitch: i32,
     rate: i32,
     volume: i32,
-    root_path: String,
     save_file: bool,
 }
 
@@ -39,33 +42,29 @@ pub struct ApiRequest {
 }
 
 
-/// 配音
-///
-/// 根据传入数据调用微软Edge浏览器的大声朗读接口，实现配音功能
+/// 配音结果
+pub struct Speech {
+    /// mp3音频
+    pub audio: Vec<u8>,
+    /// 字幕时间轴，即接口返回的 `audio.metadata`
+    pub metadata: Vec<Value>,
+    /// 本次请求的ID
+    pub request_id: String,
+}
+
+/// 调用微软Edge浏览器的大声朗读接口配音
 ///
 /// # Arguments
 ///
-/// * `data` - 配音所需的数据 `TTSData`。
+/// * `voice` - 配音员
+/// * `text` - 需要配音的文本
+/// * `pitch`、`rate`、`volume` - 音调、语速、音量的调整百分比，0为不调整
 ///
 /// # Returns
 ///
-/// * 如果配音，则返回 `Ok(CustomResult::success)`
-/// * 如果发生错误，则返回 `Err(CustomResult:error)`
-#[tauri::command]
-pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
-    let voice = data.voice;
-    let text = data.text;
-    let pitch = data.pitch;
-    let rate = data.rate;
-    let volume = data.volume;
-    let mut root_path = data.root_path;
-    let save_file = data.save_file;
-
-    // 检查参数
-    if voice.is_empty() || text.is_empty() {
-        return Err(CustomResult::error(Some("参数错误".to_string()), None));
-    }
-
+/// * 成功返回音频和字幕时间轴
+/// * 连接或接收失败时返回 `Err(AppError)`
+pub async fn synthesize(voice: &str, text: &str, pitch: i32, rate: i32, volume: i32) -> Result<Speech, AppError> {
     // 格式化数据
     let pitch_str = if pitch >= 0 {
         format!("+{}", pitch)
@@ -87,10 +86,14 @@ pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
 
     let tts_client = TTS {};
     let send_request_id = Uuid::new_v4().to_string().replace('-', "");
-    let sec_ms_gec_value = tts_client.generate_sec_ms_gec()?.data["hax"].clone();
+    let sec_ms_gec_value = tts_client
+        .generate_sec_ms_gec()
+        .map_err(|_| AppError::Parse(Msg::new("tts.token_failed")))?
+        .data["hax"]
+        .clone();
     let sec_ms_gec = sec_ms_gec_value
         .as_str()
-        .ok_or_else(|| CustomResult::error(Some("生成令牌字符串失败".to_string()), None))?;
+        .ok_or_else(|| AppError::Parse(Msg::new("tts.token_failed")))?;
     let audio_output_format = "audio-24khz-48kbitrate-mono-mp3";
     let binary_delim = "Path:audio\r\n";
 
@@ -102,7 +105,7 @@ pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
 
     let mut request = url_str
         .into_client_request()
-        .map_err(|e| CustomResult::error(Some(format!("编码URL失败：{}", e.to_string())), None))?;
+        .map_err(|e| AppError::InvalidArgument(Msg::new("tts.encode_url_failed").arg("error", e)))?;
 
     // 添加请求头
     request
@@ -127,20 +130,20 @@ pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
 
     let (mut socket, _) = connect_async(request)
         .await
-        .map_err(|e| CustomResult::error(Some(format!("连接wss失败：{}", e.to_string())), None))?;
+        .map_err(AppError::from)?;
 
     // 发送音频格式设定
     let audio_config = tts_client.convert_to_audio_format_websocket_string(audio_output_format);
     socket
         .send(Message::Text(audio_config.into()))
         .await
-        .map_err(|e| CustomResult::error(Some(format!("发送音频格式失败：{}", e)), None))?;
+        .map_err(AppError::from)?;
 
     // 发送 SSML 文本
     let ssml = tts_client.convert_to_ssml_websocket_string(
         &send_request_id,
-        &voice,
-        &text,
+        voice,
+        text,
         &pitch_str,
         &rate_str,
         &volumn_str,
@@ -148,15 +151,14 @@ pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
     socket
         .send(Message::Text(ssml.into()))
         .await
-        .map_err(|e| CustomResult::error(Some(format!("发送SSML文本失败：{}", e)), None))?;
+        .map_err(AppError::from)?;
 
     // 接收数据
     let mut audio_data: Vec<u8> = Vec::new();
     let mut messages: Vec<Value> = vec![];
 
     while let Some(msg) = socket.next().await {
-        let msg =
-            msg.map_err(|e| CustomResult::error(Some(format!("读取WS输出失败：{}", e)), None))?;
+        let msg = msg.map_err(AppError::from)?;
 
         match msg {
             Message::Text(txt) => {
@@ -171,7 +173,7 @@ pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
                         if let Ok(json) = serde_json::from_str(json_part) {
                             messages.push(json);
                         } else {
-                            eprintln!("JSON 解析失败: {}", json_part);
+                            warn!("JSON 解析失败: {}", json_part);
                         }
                     }
                 }
@@ -188,22 +190,57 @@ pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
         }
     }
 
+    Ok(Speech {
+        audio: audio_data,
+        metadata: messages,
+        request_id: send_request_id,
+    })
+}
+
+/// 配音
+///
+/// 根据传入数据调用微软Edge浏览器的大声朗读接口，实现配音功能
+///
+/// # Arguments
+///
+/// * `data_dir` - 数据目录，保存文件时音频和字幕放在其中以请求ID命名的文件夹下
+/// * `data` - 配音所需的数据 `TTSData`。
+///
+/// # Returns
+///
+/// * 如果配音，则返回 `Ok(CustomResult::success)`
+/// * 如果发生错误，则返回 `Err(CustomResult:error)`
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(voice = %data.voice, text_len = data.text.len()))]
+pub async fn start_tts(data_dir: State<'_, DataDir>, data: TTSData) -> Result<CustomResult, CustomResult> {
+    let voice = data.voice;
+    let text = data.text;
+    let save_file = data.save_file;
+
+    // 检查参数
+    if voice.is_empty() || text.is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("tts.empty_voice_or_text")).into());
+    }
+
+    let Speech {
+        audio: audio_data,
+        metadata: messages,
+        request_id: send_request_id,
+    } = synthesize(&voice, &text, data.pitch, data.rate, data.volume).await?;
+
     let mut path_str = String::from("");
     let json_name = format!("output_{}.json", send_request_id);
     let output_name = format!("output_{}.mp3", send_request_id);
     if save_file {
-        // 如果保存文件
-        if root_path.is_empty() {
-            root_path = ".".to_string();
-        }
-
-        let path_buf = PathBuf::from(root_path).join("datas").join(send_request_id.clone());
+        // 如果保存文件，写入期间持有数据目录，避免与更换数据目录同时进行
+        let root = data_dir.try_root()?;
+        let path_buf = root.join(send_request_id.clone());
         path_str = path_buf.display().to_string();
 
         let folder_path = Path::new(&path_str);
         if !folder_path.exists() {
             fs::create_dir_all(folder_path)
-                .map_err(|e| CustomResult::error(Some(format!("创建文件夹失败：{}", e)), None))?;
+                .map_err(|e| AppError::Io(Msg::new("tts.create_dir_failed").arg("error", e)))?;
         }
 
         // 保存音频数据
@@ -211,21 +248,21 @@ pub async fn start_tts(data: TTSData) -> Result<CustomResult, CustomResult> {
             PathBuf::from(path_str.clone()).join(output_name.clone());
         let output_path = path_buf.display().to_string();
         let mut file = File::create(&output_path)
-            .map_err(|e| CustomResult::error(Some(format!("创建音频文件失败：{}", e)), None))?;
+            .map_err(|e| AppError::Io(Msg::new("tts.create_audio_failed").arg("error", e)))?;
         file.write_all(&audio_data)
-            .map_err(|e| CustomResult::error(Some(format!("写入音频文件失败：{}", e)), None))?;
+            .map_err(|e| AppError::Io(Msg::new("tts.write_audio_failed").arg("error", e)))?;
 
         // 写入JSON数据
         let path_buf =
             PathBuf::from(path_str.clone()).join(json_name.clone());
         let json_path = path_buf.display().to_string();
         let mut json_file = File::create(&json_path)
-            .map_err(|e| CustomResult::error(Some(format!("创建JSON文件失败：{}", e)), None))?;
+            .map_err(|e| AppError::Io(Msg::new("tts.create_json_failed").arg("error", e)))?;
         let json_str = serde_json::to_string(&messages)
-            .map_err(|e| CustomResult::error(Some(format!("序列化JSON失败：{}", e)), None))?;
+            .map_err(AppError::from)?;
         json_file
             .write_all(json_str.as_bytes())
-            .map_err(|e| CustomResult::error(Some(format!("写入JSON文件失败：{}", e)), None))?;
+            .map_err(|e| AppError::Io(Msg::new("tts.write_json_failed").arg("error", e)))?;
     }
 
     // 编码成base64
@@ -259,55 +296,6 @@ pub async fn get_app_version(app_handle: tauri::AppHandle) -> Result<CustomResul
     ))
 }
 
-/// 删除文件或空目录，或递归删除目录及其内容。
-///
-/// 如果路径指向文件，则删除文件。
-/// 如果路径指向目录，则删除其所有内容，然后删除目录本身。
-///
-/// # Arguments
-///
-/// * `path_str` - 表示文件或目录路径的 `String`。
-///
-/// # Returns
-///
-/// * 如果删除成功，则返回 `Ok(CustomResult::success)`。
-/// * 如果路径无效、不存在或删除过程中发生错误，则返回 `Err(CustomResult:error)`
-#[tauri::command]
-pub async fn delete_path_contents(path_str: String) -> Result<CustomResult, CustomResult> {
-    let path = Path::new(&path_str);
-
-    // 1. 验证路径是否合法 (简单的存在性检查，更复杂的合法性可能需要regex或更深入的OS检查)
-    if !path.exists() {
-        return Err(CustomResult::error(
-            Some(format!("错误: 路径 '{}' 不存在.", path_str)),
-            None,
-        ));
-    }
-
-    if path.is_file() {
-        match fs::remove_file(path) {
-            Ok(_) => Ok(CustomResult::success(None, None)),
-            Err(e) => Err(CustomResult::error(
-                Some(format!("错误: 删除文件 '{}' 失败: {}", path_str, e)),
-                None,
-            )),
-        }
-    } else if path.is_dir() {
-        match fs::remove_dir_all(path) {
-            Ok(_) => Ok(CustomResult::success(None, None)),
-            Err(e) => Err(CustomResult::error(
-                Some(format!("错误: 删除目录 '{}' 失败: {}", path_str, e)),
-                None,
-            )),
-        }
-    } else {
-        Err(CustomResult::error(
-            Some(format!("错误: 路径 '{}' 不是文件也不是目录.", path_str)),
-            None,
-        ))
-    }
-}
-
 /// 将音频数据（来自文件或内存）转成 Base64 编码字符串。
 ///
 /// # Arguments
@@ -319,27 +307,29 @@ pub async fn delete_path_contents(path_str: String) -> Result<CustomResult, Cust
 /// # Returns
 ///
 /// * 如果成功编码，则返回 Base64 字符串 `String`。
-/// * 如果两个输入都为空，或读取文件失败，则返回 `Err(CustomResult::error)`。
+/// * 如果两个输入都为空，或读取文件失败，则返回 `Err(AppError)`。
 pub fn encode_audio_to_base64(
     file_path: Option<&str>,
     audio_data_bytes: Option<Vec<u8>>,
-) -> Result<String, CustomResult> {
+) -> Result<String, AppError> {
     let bytes_to_encode: Vec<u8>;
 
     if let Some(path) = file_path {
         // 如果提供了文件路径，则尝试读取文件
-        bytes_to_encode = fs::read(path).map_err(|e| {
-            CustomResult::error(Some(format!("读取音频文件 '{}' 失败：{}", path, e)), None)
-        })?;
+        bytes_to_encode = fs::read(path)
+            .map_err(|e| {
+                AppError::Io(
+                    Msg::new("audio.read_failed")
+                        .arg("path", path)
+                        .arg("error", e),
+                )
+            })?;
     } else if let Some(data) = audio_data_bytes {
         // 如果文件路径为空，但提供了 Vec<u8>，则直接使用它
         bytes_to_encode = data;
     } else {
         // 如果两个输入都为空，返回错误
-        return Err(CustomResult::error(
-            Some("未提供文件路径或音频数据，无法进行 Base64 编码".to_string()),
-            None,
-        ));
+        return Err(AppError::InvalidArgument(Msg::new("audio.no_input")));
     }
 
     // 执行 Base64 编码
@@ -348,41 +338,22 @@ pub fn encode_audio_to_base64(
     Ok(encoded)
 }
 
+/// 发送HTTP请求
+///
+/// 供前端调用第三方接口使用，请求在Rust端发出，可以绕过浏览器的跨域限制，并支持代理
+///
+/// # Arguments
+///
+/// * `request` - 请求内容 `ApiRequest`
+///
+/// # Returns
+///
+/// * 请求成功返回 `Ok(CustomResult::success)`，`data.data` 为响应JSON
+/// * 请求失败返回 `Err(CustomResult)`，`data.kind` 为错误种类
 #[tauri::command]
+#[tracing::instrument(skip_all, fields(method = %request.method, url = %request.url))]
 pub async fn send_api_request(request: ApiRequest) -> Result<CustomResult, CustomResult> {
-    println!("创建客户端...");
-    let client_builder = ClientBuilder::new().connect_timeout(Duration::from_secs(5));
-
-    let client : reqwest::Client;
-
-    println!("客户端创建完成");
-    if let Some(proxy_url_str) = request.proxy {
-        println!("匹配到了代理");
-        match Url::parse(&proxy_url_str) {
-            Ok(proxy_url) => {
-                if proxy_url.scheme() == "http" || proxy_url.scheme() == "https" || proxy_url.scheme() == "socks5" {
-                    let proxy = Proxy::all(proxy_url_str).map_err(|e| 
-                        CustomResult::error(Some(format!("添加代理失败：{}", e)), None)
-                    )?;
-                    client = client_builder.proxy(proxy).build().map_err(|e| 
-                        CustomResult::error(Some(format!("创建客户端失败：{}", e)), None)
-                    )?;
-                    println!("代理添加成功");
-                } else {
-                    return Err(CustomResult::error(Some("不支持的代理协议".to_string()),  None));
-                }
-            }
-            Err(e) => {
-                return Err(CustomResult::error(Some(format!("解析代理URL失败: {}", e)), None))
-            }
-        }
-    }else{
-        client = client_builder.build().map_err(|e| 
-            CustomResult::error(Some(format!("创建客户端失败：{}", e)), None)
-        )?;
-    }
-
-    println!("添加请求头...");
+    let client = build_client(request.proxy.as_deref(), None)?;
 
     let mut headers = HeaderMap::new();
     for (key, value) in request.headers { // key 是 String 类型
@@ -396,64 +367,21 @@ pub async fn send_api_request(request: ApiRequest) -> Result<CustomResult, Custo
                 } else {
                     // 处理 header_value 解析失败的情况
                     // 打印警告或记录日志，或者选择在这里返回一个 CustomResult 错误
-                    eprintln!("Warning: Invalid HeaderValue for key '{}': '{}'", key, value);
+                    // 值可能是密钥，只记录名称
+                    warn!("Invalid HeaderValue for key '{}'", key);
                 }
             }
             Err(e) => {
                 // 处理 key 无法解析为有效 HeaderName 的情况
                 // 打印警告或记录日志，或者选择在这里返回一个 CustomResult 错误
-                eprintln!("Warning: Invalid HeaderName '{}': {}", key, e);
+                warn!("Invalid HeaderName '{}': {}", key, e);
                 // 示例：如果你希望遇到无效头名称就停止并返回错误，可以这样做：
                 // return Err(CustomResult::error(Some(format!("无效的请求头名称 '{}': {}", key, e)), None));
             }
         }
     }
 
-    println!("请求头添加成功");
+    let json_data = send_json(&client, &request.method, &request.url, headers, &request.body).await?;
 
-    let mut request_builder = match request.method.as_str() {
-        "GET" => client.get(&request.url),
-        "POST" => client.post(&request.url),
-        "PUT" => client.put(&request.url),
-        "DELETE" => client.delete(&request.url),
-        _ => {
-            return Err(CustomResult::error(Some(format!("不支持的 HTTP 方法: {}", request.method)), None));
-        }
-    };
-
-    if !request.body.is_null() {
-        request_builder = request_builder.json(&request.body);
-    }
-
-    request_builder = request_builder.headers(headers);
-
-    // 超时时间 - 可能会影响正常输出
-    // if let Some(timeout_s) = request.timeout_seconds {
-    //     request_builder = request_builder.timeout(Duration::from_secs(timeout_s));
-    // }
-
-    println!("发送数据");
-    match request_builder.send().await {
-        Ok(response) => {
-            println!("成功接收到数据");
-            let status = response.status();
-            println!("获取text");
-            let text = response.text().await.unwrap_or_else(|_| "".to_string());
-
-            if status.is_success() {
-                println!("成功状态");
-                match serde_json::from_str::<serde_json::Value>(&text) {
-                    Ok(json_data) => Ok(CustomResult::success(None, Some(json!({"data": json_data})))),
-                    Err(e) => Err(CustomResult::error(Some(format!("解析 JSON 响应失败: {} - 响应内容: {}", e, text)), None)),
-                }
-            } else {
-                println!("失败状态");
-                Err(CustomResult::error(Some(format!("API 请求失败，状态码: {}，响应: {}", status, text)), None))
-            }
-        }
-        Err(e) =>{ 
-            println!("失败接收到数据");
-            Err(CustomResult::error(Some(format!("发送 HTTP 请求失败: {}", e)), None))
-        } ,
-    }
-}
\ No newline at end of file
+    Ok(CustomResult::success(None, Some(json!({"data": json_data}))))
+}
@@ -0,0 +1,316 @@
+use crate::utils::api::synthesize;
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use crate::utils::storage::{self, DataDir};
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::params;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, State};
+use tracing::{error, info, warn};
+
+/// 数据目录下保存单词音频的文件夹
+pub const CACHE_DIR_NAME: &str = "word_audio";
+/// 每次从旧表移出的条数
+const LEGACY_BATCH: i64 = 100;
+
+/// 单词音频的配音参数，参数相同的音频只保存一份
+#[derive(Debug, Clone, Copy)]
+pub struct AudioKey<'a> {
+    pub voice: &'a str,
+    pub text: &'a str,
+    pub rate: i32,
+    pub pitch: i32,
+}
+
+impl<'a> AudioKey<'a> {
+    /// 单词朗读使用的默认语速和音调
+    pub fn new(voice: &'a str, text: &'a str) -> Self {
+        Self {
+            voice,
+            text,
+            rate: 0,
+            pitch: 0,
+        }
+    }
+
+    /// 配音参数的SHA-256，十六进制
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        // 各字段之间用 NUL 分隔，避免拼接后不同的参数得到相同的内容
+        for field in [self.voice, &self.rate.to_string(), &self.pitch.to_string(), self.text] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// 缓存文件的路径，按哈希的前两位分文件夹
+    pub fn path(&self, root: &Path) -> PathBuf {
+        let hash = self.hash();
+        root.join(CACHE_DIR_NAME).join(&hash[..2]).join(format!("{}.mp3", hash))
+    }
+}
+
+/// 读取缓存的音频，没有缓存时返回 `None`
+pub fn read(root: &Path, key: &AudioKey) -> Result<Option<Vec<u8>>, AppError> {
+    match fs::read(key.path(root)) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 保存音频到缓存，已有缓存时不覆盖
+///
+/// # Returns
+///
+/// * 成功返回缓存文件的路径和是否新写入
+pub fn write(root: &Path, key: &AudioKey, audio: &[u8]) -> Result<(PathBuf, bool), AppError> {
+    let path = key.path(root);
+    if path.exists() {
+        return Ok((path, false));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    storage::write_atomic(&path, |temp| Ok(fs::write(temp, audio)?))?;
+    Ok((path, true))
+}
+
+/// 获取单词音频，没有缓存时配音并保存
+///
+/// # Returns
+///
+/// * 成功返回缓存文件的路径和是否来自缓存
+/// * 配音或保存失败时返回 `Err(AppError)`
+pub async fn get_or_synthesize(data_dir: &DataDir, key: AudioKey<'_>) -> Result<(PathBuf, bool), AppError> {
+    let path = key.path(&data_dir.try_root()?);
+    if path.exists() {
+        return Ok((path, true));
+    }
+
+    let speech = synthesize(key.voice, key.text, key.pitch, key.rate, 0).await?;
+    // 配音期间数据目录可能被更换，重新取一次
+    let (path, _) = write(&data_dir.try_root()?, &key, &speech.audio)?;
+    Ok((path, false))
+}
+
+/// 把 `wordAudioLegacy` 中的旧音频移到缓存文件夹，全部移完后删除这张表
+///
+/// 每批先写文件再删除数据库中的行，中途退出时下次启动继续。课文已删除、没有配音员的音频直接丢弃。
+///
+/// # Returns
+///
+/// * 成功返回移出的音频数，旧表不存在时为0
+pub fn migrate_legacy(db: &Database, data_dir: &DataDir) -> Result<usize, AppError> {
+    let exists: bool = db.with_conn(|conn| {
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'wordAudioLegacy')",
+            [],
+            |row| row.get(0),
+        )?)
+    })?;
+    if !exists {
+        return Ok(0);
+    }
+
+    let mut moved = 0;
+    loop {
+        let batch = data_dir.with_root_and_db(db, |root, db| {
+            let rows: Vec<(i64, Option<String>, String, String)> = db.with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT id, voice, word, base64 FROM wordAudioLegacy LIMIT ?1")?;
+                let rows = stmt.query_map([LEGACY_BATCH], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+                Ok(rows.collect::<Result<_, _>>()?)
+            })?;
+
+            for (id, voice, word, base64) in &rows {
+                let Some(voice) = voice.as_deref().filter(|v| !v.is_empty()) else {
+                    continue;
+                };
+                match general_purpose::STANDARD.decode(base64) {
+                    Ok(audio) => {
+                        write(root, &AudioKey::new(voice, word), &audio)?;
+                        moved += 1;
+                    }
+                    Err(e) => warn!(id, word = %word, error = %e, "旧的单词音频无法解码，已丢弃"),
+                }
+            }
+            db.with_conn(|conn| {
+                let tx = conn.transaction()?;
+                {
+                    let mut delete = tx.prepare("DELETE FROM wordAudioLegacy WHERE id = ?1")?;
+                    for (id, ..) in &rows {
+                        delete.execute(params![id])?;
+                    }
+                }
+                tx.commit()?;
+                Ok(())
+            })?;
+            Ok(rows.len())
+        })?;
+        if batch == 0 {
+            break;
+        }
+    }
+
+    // 不执行 VACUUM：重写整个数据库会长时间占用共享的连接，空出的页由之后的写入复用
+    db.with_conn(|conn| Ok(conn.execute_batch("DROP TABLE wordAudioLegacy")?))?;
+    info!(moved, "旧的单词音频已移到缓存文件夹");
+    Ok(moved)
+}
+
+/// 在后台把旧的单词音频移到缓存文件夹
+pub fn spawn_legacy_migration(app: AppHandle) {
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = migrate_legacy(&app.state::<Database>(), &app.state::<DataDir>()) {
+            error!(error = %e, "移动旧的单词音频失败，下次启动时继续");
+        }
+    });
+}
+
+/// 获取单词音频
+///
+/// 音频按配音员、语速、音调和文本缓存在数据目录的 `word_audio` 文件夹下，没有缓存时先配音
+///
+/// # Arguments
+///
+/// * `voice` - 配音员
+/// * `text` - 单词或短语
+/// * `rate` - 语速调整百分比，默认为0
+/// * `pitch` - 音调调整百分比，默认为0
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.path` 为mp3文件的路径，`data.cached` 为是否来自缓存
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(data_dir))]
+pub async fn get_word_audio(
+    data_dir: State<'_, DataDir>,
+    voice: String,
+    text: String,
+    rate: Option<i32>,
+    pitch: Option<i32>,
+) -> Result<CustomResult, CustomResult> {
+    if voice.is_empty() || text.is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("tts.empty_voice_or_text")).into());
+    }
+    let key = AudioKey {
+        voice: &voice,
+        text: &text,
+        rate: rate.unwrap_or(0),
+        pitch: pitch.unwrap_or(0),
+    };
+    let (path, cached) = get_or_synthesize(&data_dir, key).await?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"path": path.display().to_string(), "hash": key.hash(), "cached": cached})),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures;
+
+    #[test]
+    fn key_hash_separates_fields_and_path_uses_prefix_folder() {
+        let key = AudioKey::new("en-US-AriaNeural", "hello");
+        let hash = key.hash();
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(hash, AudioKey::new("en-US-AriaNeural", "hello").hash());
+
+        // 语速、音调不同或字段边界不同时哈希不同
+        assert_ne!(hash, AudioKey { rate: 10, ..key }.hash());
+        assert_ne!(hash, AudioKey { pitch: -5, ..key }.hash());
+        assert_ne!(AudioKey::new("ab", "c").hash(), AudioKey::new("a", "bc").hash());
+
+        let root = Path::new("datas");
+        assert_eq!(
+            key.path(root),
+            root.join(CACHE_DIR_NAME).join(&hash[..2]).join(format!("{}.mp3", hash))
+        );
+    }
+
+    #[test]
+    fn write_keeps_existing_file() {
+        let root = fixtures::temp_dir("audio-cache");
+        let key = AudioKey::new("en-US-AriaNeural", "hello");
+        assert_eq!(read(&root, &key).unwrap(), None);
+
+        let (path, written) = write(&root, &key, b"first").unwrap();
+        assert!(written);
+        assert_eq!(path, key.path(&root));
+        let (_, written) = write(&root, &key, b"second").unwrap();
+        assert!(!written);
+        assert_eq!(read(&root, &key).unwrap().unwrap(), b"first");
+        // 只留下正式文件，没有临时文件
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn add_legacy(db: &Database, voice: Option<&str>, word: &str, base64: &str) {
+        db.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO wordAudioLegacy (classId, word, base64, voice) VALUES (1, ?1, ?2, ?3)",
+                params![word, base64, voice],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    fn legacy_rows(db: &Database) -> Option<i64> {
+        db.with_conn(|conn| Ok(conn.query_row("SELECT COUNT(*) FROM wordAudioLegacy", [], |row| row.get(0)).ok()))
+            .unwrap()
+    }
+
+    #[test]
+    fn migrate_legacy_resumes_and_drops_the_table() {
+        let base = fixtures::temp_dir("audio-legacy");
+        let root = base.join("datas");
+        fs::create_dir_all(&root).unwrap();
+        let db = Database::memory().unwrap();
+        let data_dir = DataDir::open(root.clone()).unwrap();
+
+        // 超过两批的音频，另有课文已删除没有配音员的和无法解码的
+        let count = LEGACY_BATCH as usize * 2 + 5;
+        for i in 0..count {
+            let word = format!("word{}", i);
+            add_legacy(&db, Some("en-US-AriaNeural"), &word, &general_purpose::STANDARD.encode(&word));
+        }
+        add_legacy(&db, None, "orphan", &general_purpose::STANDARD.encode("orphan"));
+        add_legacy(&db, Some(""), "empty", &general_purpose::STANDARD.encode("empty"));
+        add_legacy(&db, Some("en-US-AriaNeural"), "broken", "not base64!");
+        let total = count as i64 + 3;
+
+        // 缓存文件夹无法创建时中断，旧表保持不变
+        fs::write(root.join(CACHE_DIR_NAME), b"").unwrap();
+        assert!(migrate_legacy(&db, &data_dir).is_err());
+        assert_eq!(legacy_rows(&db), Some(total));
+        fs::remove_file(root.join(CACHE_DIR_NAME)).unwrap();
+
+        // 上次中断前已写入文件、还没删除行的音频不覆盖
+        write(&root, &AudioKey::new("en-US-AriaNeural", "word0"), b"earlier").unwrap();
+        assert_eq!(migrate_legacy(&db, &data_dir).unwrap(), count);
+        assert_eq!(legacy_rows(&db), None);
+        assert_eq!(read(&root, &AudioKey::new("en-US-AriaNeural", "word0")).unwrap().unwrap(), b"earlier");
+        assert_eq!(read(&root, &AudioKey::new("en-US-AriaNeural", "word7")).unwrap().unwrap(), b"word7");
+        assert_eq!(read(&root, &AudioKey::new("en-US-AriaNeural", "broken")).unwrap(), None);
+        assert_eq!(read(&root, &AudioKey::new("", "empty")).unwrap(), None);
+
+        // 旧表已删除，再次执行不做任何事
+        assert_eq!(migrate_legacy(&db, &data_dir).unwrap(), 0);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,788 @@
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::{self, Database};
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use crate::utils::storage::{self, DataDir};
+use chrono::{Local, NaiveDateTime};
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use zip::{ZipArchive, ZipWriter};
+
+/// 备份的格式版本，结构变化时加一
+pub const FORMAT_VERSION: u32 = 1;
+const INFO_NAME: &str = "backup.json";
+const DATABASE_NAME: &str = "database.db";
+/// 备份中数据目录的内容都在这个文件夹下
+const DATAS_DIR: &str = "datas";
+/// 手动备份和自动备份的文件名前缀，后面是创建时间
+const MANUAL_PREFIX: &str = "backup-";
+const AUTO_PREFIX: &str = "auto-backup-";
+const EXTENSION: &str = "zip";
+const TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// `appSetting` 中自动备份间隔（小时）的键，为0时不自动备份
+const INTERVAL_KEY: &str = "backup.autoIntervalHours";
+/// `appSetting` 中保留的自动备份数量的键
+const KEEP_KEY: &str = "backup.keep";
+const DEFAULT_KEEP: u32 = 5;
+/// 检查是否需要自动备份的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// 备份文件夹，作为 tauri 的托管状态使用
+///
+/// 备份和恢复不能同时进行，后开始的会等待前一个完成
+pub struct Backups {
+    dir: PathBuf,
+    running: Mutex<()>,
+}
+
+/// 备份中的 `backup.json`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupInfo {
+    format_version: u32,
+    create_time: String,
+    /// 备份时数据库的版本
+    schema_version: u32,
+    /// 备份时的数据目录，恢复时用来改写课文的 `filePath`
+    data_root: String,
+}
+
+/// 备份文件
+#[derive(Debug, Serialize)]
+pub struct BackupFile {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    /// 创建时间，格式为 `%Y-%m-%d %H:%M:%S`
+    pub create_time: String,
+    /// 是否为自动备份，只有自动备份会被清理
+    pub auto: bool,
+}
+
+/// 自动备份的设置
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSettings {
+    /// 自动备份的间隔（小时），为0时不自动备份
+    pub interval_hours: u32,
+    /// 保留最近的几个自动备份
+    pub keep: u32,
+}
+
+/// 恢复备份的结果
+#[derive(Debug, Serialize)]
+pub struct RestoreReport {
+    pub create_time: String,
+    /// 恢复的数据目录文件数
+    pub files: usize,
+    /// 改写了 `filePath` 的课文数
+    pub lessons: usize,
+    /// 回收站不在备份中，恢复时清空，这是清空前回收站中的条目数
+    pub trash_cleared: usize,
+}
+
+impl Backups {
+    /// 打开备份文件夹，不存在时创建
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - 备份文件夹，一般为 app 数据目录下的 `backups`
+    pub fn open(dir: PathBuf) -> Result<Self, AppError> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            running: Mutex::new(()),
+        })
+    }
+
+    /// 备份数据库和数据目录
+    ///
+    /// 数据库用 `VACUUM INTO` 生成一致的快照，数据目录中由程序创建的条目（见 `storage::owned_entries`）除回收站外原样打包，
+    /// 其他文件不在备份中。
+    /// 先写入临时文件，完成后才改为正式的文件名，失败时不会留下不完整的备份。
+    ///
+    /// 会等待正在进行的更换数据目录并读写大量文件，需要在 `spawn_blocking` 中调用
+    ///
+    /// # Arguments
+    ///
+    /// * `auto` - 是否为自动备份
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回备份文件
+    /// * 快照或写入失败时返回 `Err(AppError)`
+    pub fn create(&self, db: &Database, data_dir: &DataDir, auto: bool) -> Result<BackupFile, AppError> {
+        let _running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        data_dir.with_root_and_db(db, |root, db| self.write_backup(db, root, auto))
+    }
+
+    fn write_backup(&self, db: &Database, root: &Path, auto: bool) -> Result<BackupFile, AppError> {
+        let now = Local::now();
+        let prefix = if auto { AUTO_PREFIX } else { MANUAL_PREFIX };
+        let stem = format!("{}{}", prefix, now.format(TIME_FORMAT));
+        let mut path = self.dir.join(format!("{}.{}", stem, EXTENSION));
+        for n in 1.. {
+            if !path.exists() {
+                break;
+            }
+            path = self.dir.join(format!("{}-{}.{}", stem, n, EXTENSION));
+        }
+
+        let snapshot = self.dir.join(format!(".{}.db", Uuid::new_v4()));
+        let result = db
+            .with_conn(|conn| {
+                conn.execute("VACUUM INTO ?1", [snapshot.display().to_string()])?;
+                Ok((db::schema_version(conn)?, storage::owned_entries(conn, root)?))
+            })
+            .and_then(|(schema_version, entries)| {
+                let info = BackupInfo {
+                    format_version: FORMAT_VERSION,
+                    create_time: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    schema_version,
+                    data_root: root.display().to_string(),
+                };
+                storage::write_atomic(&path, |temp| write_archive(temp, &info, &snapshot, root, &entries))
+            });
+        let _ = fs::remove_file(&snapshot);
+        result?;
+        backup_file(&path)?.ok_or_else(|| AppError::Io(Msg::new("backup.invalid")))
+    }
+
+    /// 备份文件夹中的备份，按创建时间从新到旧排列
+    pub fn list(&self) -> Result<Vec<BackupFile>, AppError> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            if let Some(file) = backup_file(&entry?.path())? {
+                files.push(file);
+            }
+        }
+        files.sort_by(|a, b| b.create_time.cmp(&a.create_time).then_with(|| b.name.cmp(&a.name)));
+        Ok(files)
+    }
+
+    /// 删除最近 `keep` 个以外的自动备份，手动备份不受影响
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回删除的备份数
+    pub fn prune(&self, keep: u32) -> Result<usize, AppError> {
+        let mut removed = 0;
+        for file in self.list()?.into_iter().filter(|f| f.auto).skip(keep as usize) {
+            fs::remove_file(&file.path)?;
+            info!(path = %file.path, "已删除旧的自动备份");
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// 从备份恢复数据库和数据目录
+    ///
+    /// 先完整校验备份：文件名、格式版本、数据库完整性和版本，再把数据库升级到最新结构、改写课文的 `filePath`，
+    /// 备份中由程序创建的条目解压到数据目录内的临时文件夹。全部成功后把数据目录中由程序创建的条目移开、换入解压的条目，
+    /// 再用 SQLite 的在线备份把数据库整体替换；任何一步失败时按相反顺序移回。数据目录中的其他文件保持不动。
+    ///
+    /// 回收站不在备份中，恢复后原来回收站中的内容一起删除，删除的条目数见 `RestoreReport::trash_cleared`。
+    ///
+    /// 整个过程持有数据目录的写锁并读写大量文件，需要在 `spawn_blocking` 中调用
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - 备份文件的路径
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回恢复的统计
+    /// * 备份不合法或恢复失败时返回 `Err(AppError)`
+    pub fn restore(&self, db: &Database, data_dir: &DataDir, path: &Path) -> Result<RestoreReport, AppError> {
+        let _running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let info = read_info(&mut archive)?;
+
+        // 整个过程持有写锁，其他读写数据目录的操作等恢复完成后再继续
+        let root = data_dir.lock();
+        let id = Uuid::new_v4();
+        // 临时文件夹放在数据目录内，换入换出都是同一文件系统内的改名
+        let work = root.join(format!(".restore-{}", id));
+        let staging = work.join("new");
+        let old = work.join("old");
+        let staged_db = self.dir.join(format!(".{}.db", id));
+        let result = stage(&mut archive, &info, &root, &staging, &staged_db);
+        drop(archive);
+        let result = result.and_then(|(files, lessons)| {
+            let current = db.with_conn(|conn| storage::owned_entries(conn, &root))?;
+            let trash_cleared = match fs::read_dir(root.join(storage::TRASH_DIR_NAME)) {
+                Ok(entries) => entries.count(),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+                Err(e) => return Err(e.into()),
+            };
+            swap_in(db, &root, &current, &staging, &old, &staged_db)?;
+            Ok(RestoreReport {
+                create_time: info.create_time,
+                files,
+                lessons,
+                trash_cleared,
+            })
+        });
+        let _ = fs::remove_file(&staged_db);
+
+        if result.is_ok() {
+            if let Err(e) = fs::remove_dir_all(&work) {
+                warn!(path = %work.display(), error = %e, "恢复前的文件删除失败，需要手动清理");
+            }
+        } else {
+            // 没能移回的旧文件留在 `old` 中，不能删除
+            let _ = fs::remove_dir_all(&staging);
+            let _ = fs::remove_dir(&old);
+            let _ = fs::remove_dir(&work);
+        }
+        result
+    }
+
+    /// 到了间隔时间时创建自动备份，并删除多余的自动备份
+    ///
+    /// # Returns
+    ///
+    /// * 创建了备份时返回备份文件，未开启或未到时间时返回 `None`
+    pub fn run_due(&self, db: &Database, data_dir: &DataDir) -> Result<Option<BackupFile>, AppError> {
+        let settings = settings(db)?;
+        if settings.interval_hours == 0 {
+            return Ok(None);
+        }
+        let last = self.list()?.into_iter().find(|f| f.auto);
+        if let Some(last) = last {
+            let last = NaiveDateTime::parse_from_str(&last.create_time, "%Y-%m-%d %H:%M:%S")
+                .map_err(|e| AppError::Parse(Msg::new("error.parse").arg("detail", e)))?;
+            let elapsed = Local::now().naive_local() - last;
+            if elapsed < chrono::Duration::hours(settings.interval_hours as i64) {
+                return Ok(None);
+            }
+        }
+
+        let file = self.create(db, data_dir, true)?;
+        self.prune(settings.keep)?;
+        Ok(Some(file))
+    }
+}
+
+/// 读取自动备份的设置，没有设置过时不自动备份、保留5个
+pub fn settings(db: &Database) -> Result<BackupSettings, AppError> {
+    let read = |key: &str, default: u32| -> Result<u32, AppError> {
+        Ok(db.get_setting(key)?.and_then(|v| v.trim().parse().ok()).unwrap_or(default))
+    };
+    Ok(BackupSettings {
+        interval_hours: read(INTERVAL_KEY, 0)?,
+        keep: read(KEEP_KEY, DEFAULT_KEEP)?,
+    })
+}
+
+/// 备份文件夹中的文件是备份时返回其信息，其他文件返回 `None`
+fn backup_file(path: &Path) -> Result<Option<BackupFile>, AppError> {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(None);
+    };
+    let Some(stem) = name.strip_suffix(&format!(".{}", EXTENSION)) else {
+        return Ok(None);
+    };
+    let (auto, time) = if let Some(time) = stem.strip_prefix(AUTO_PREFIX) {
+        (true, time)
+    } else if let Some(time) = stem.strip_prefix(MANUAL_PREFIX) {
+        (false, time)
+    } else {
+        return Ok(None);
+    };
+    // 同一秒内的备份在时间后加了序号
+    let time = time.get(..15).unwrap_or(time);
+    let Ok(create_time) = NaiveDateTime::parse_from_str(time, TIME_FORMAT) else {
+        return Ok(None);
+    };
+
+    Ok(Some(BackupFile {
+        name: name.to_string(),
+        path: path.display().to_string(),
+        size: fs::metadata(path)?.len(),
+        create_time: create_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        auto,
+    }))
+}
+
+fn write_archive(
+    path: &Path,
+    info: &BackupInfo,
+    snapshot: &Path,
+    root: &Path,
+    entries: &[PathBuf],
+) -> Result<(), AppError> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let (deflated, stored) = storage::zip_options(true);
+
+    zip.start_file(INFO_NAME, deflated)?;
+    zip.write_all(&serde_json::to_vec_pretty(info)?)?;
+    zip.start_file(DATABASE_NAME, deflated)?;
+    io::copy(&mut File::open(snapshot)?, &mut zip)?;
+
+    let mut paths: Vec<PathBuf> = entries
+        .iter()
+        .filter(|path| path.file_name() != Some(storage::TRASH_DIR_NAME.as_ref()))
+        .cloned()
+        .collect();
+    while let Some(path) = paths.pop() {
+        let file_type = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata.file_type(),
+            // 备份期间课文被删除
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if file_type.is_symlink() {
+            continue;
+        }
+        let relative = path.strip_prefix(root).map_err(|_| AppError::Io(Msg::new("backup.invalid")))?;
+        let name = Path::new(DATAS_DIR)
+            .join(relative)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if file_type.is_dir() {
+            zip.add_directory(name, stored)?;
+            for entry in fs::read_dir(&path)? {
+                paths.push(entry?.path());
+            }
+            continue;
+        }
+
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let options = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") | Some("srt") | Some("txt") => deflated,
+            _ => stored,
+        };
+        zip.start_file(name, options)?;
+        io::copy(&mut file, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// 读取并校验备份的 `backup.json`，同时检查所有文件名
+fn read_info<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<BackupInfo, AppError> {
+    let invalid = || AppError::InvalidArgument(Msg::new("backup.invalid"));
+    let info: BackupInfo = {
+        let mut entry = archive.by_name(INFO_NAME).map_err(|_| invalid())?;
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        serde_json::from_slice(&bytes).map_err(|_| invalid())?
+    };
+    if info.format_version > FORMAT_VERSION {
+        return Err(AppError::InvalidArgument(
+            Msg::new("backup.newer_version")
+                .arg("version", info.format_version)
+                .arg("latest", FORMAT_VERSION),
+        ));
+    }
+    if archive.index_for_name(DATABASE_NAME).is_none() {
+        return Err(invalid());
+    }
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        let name = entry.name();
+        let valid = name == INFO_NAME
+            || name == DATABASE_NAME
+            || entry.enclosed_name().is_some_and(|path| path.starts_with(DATAS_DIR));
+        if !valid {
+            return Err(AppError::InvalidArgument(Msg::new("backup.invalid_name").arg("name", name)));
+        }
+    }
+    Ok(info)
+}
+
+/// 把备份的数据库解压到 `staged_db` 并准备好，数据目录中由程序创建的条目解压到 `staging`
+///
+/// 旧版本的备份包含数据目录中的所有文件，按名称和备份中课文的 `filePath` 只解压由程序创建的条目
+///
+/// # Returns
+///
+/// * 成功返回解压的文件数和改写了 `filePath` 的课文数
+fn stage<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    info: &BackupInfo,
+    root: &Path,
+    staging: &Path,
+    staged_db: &Path,
+) -> Result<(usize, usize), AppError> {
+    io::copy(&mut archive.by_name(DATABASE_NAME)?, &mut File::create(staged_db)?)?;
+    let mut conn = Connection::open_with_flags(staged_db, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    let check: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| AppError::InvalidArgument(Msg::new("backup.corrupt").arg("detail", e)))?;
+    if check != "ok" {
+        return Err(AppError::InvalidArgument(Msg::new("backup.corrupt").arg("detail", check)));
+    }
+    if db::schema_version(&conn)? == 0 {
+        return Err(AppError::InvalidArgument(Msg::new("backup.invalid")));
+    }
+    db::migrate(&mut conn)?;
+
+    let old = PathBuf::from(&info.data_root);
+    let old_canonical = old.canonicalize().unwrap_or_else(|_| old.clone());
+    let tx = conn.transaction()?;
+    let lessons = storage::rewrite_lesson_paths(&tx, &old, &old_canonical, root)?;
+    tx.commit()?;
+    let lesson_dirs: HashSet<OsString> = {
+        let mut stmt = conn.prepare("SELECT filePath FROM `class`")?;
+        let paths = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut dirs = HashSet::new();
+        for path in paths {
+            let path = PathBuf::from(path?);
+            if let Some(Component::Normal(name)) = path.strip_prefix(root).ok().and_then(|r| r.components().next()) {
+                dirs.insert(name.to_os_string());
+            }
+        }
+        dirs
+    };
+    drop(conn);
+
+    fs::create_dir_all(staging)?;
+    let mut files = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(relative) = entry
+            .enclosed_name()
+            .and_then(|path| path.strip_prefix(DATAS_DIR).ok().map(Path::to_path_buf))
+        else {
+            continue;
+        };
+        let owned = match relative.components().next() {
+            Some(Component::Normal(name)) => {
+                name != storage::TRASH_DIR_NAME && (storage::is_owned_name(name) || lesson_dirs.contains(name))
+            }
+            _ => false,
+        };
+        if !owned {
+            continue;
+        }
+        let target = staging.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&target)?)?;
+        files += 1;
+    }
+    Ok((files, lessons))
+}
+
+/// 把数据目录中由程序创建的条目 `current` 移到 `old`，换入 `staging` 中的条目，再用 `staged_db` 替换数据库
+///
+/// 任何一步失败时按相反顺序移回，数据目录中的其他文件不受影响
+fn swap_in(
+    db: &Database,
+    root: &Path,
+    current: &[PathBuf],
+    staging: &Path,
+    old: &Path,
+    staged_db: &Path,
+) -> Result<(), AppError> {
+    let incoming = fs::read_dir(staging)?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // 备份中的课文文件夹与数据目录中的其他文件重名时不覆盖
+    for name in &incoming {
+        let target = root.join(name);
+        if target.symlink_metadata().is_ok() && !current.contains(&target) {
+            return Err(AppError::InvalidArgument(
+                Msg::new("backup.conflict").arg("path", target.display()),
+            ));
+        }
+    }
+
+    fs::create_dir_all(old)?;
+    let mut moves = Vec::new();
+    // 在线备份期间数据库被独占，前端的连接会等待替换完成；一直被占用时放弃
+    let result = swap_entries(root, current, staging, old, &incoming, &mut moves).and_then(|()| {
+        db.with_conn(|conn| {
+            conn.restore(DatabaseName::Main, staged_db, None::<fn(Progress)>)?;
+            Ok(())
+        })
+    });
+    if let Err(e) = result {
+        error!(error = %e, "恢复备份失败，换回原来的文件");
+        for (from, to) in moves.iter().rev() {
+            if let Err(e) = fs::rename(to, from) {
+                error!(from = %to.display(), to = %from.display(), error = %e, "换回文件失败");
+            }
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// 逐个改名，把完成的改名记录到 `moves` 中，失败时由调用方移回
+fn swap_entries(
+    root: &Path,
+    current: &[PathBuf],
+    staging: &Path,
+    old: &Path,
+    incoming: &[OsString],
+    moves: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), AppError> {
+    for path in current {
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let to = old.join(name);
+        fs::rename(path, &to)?;
+        moves.push((path.clone(), to));
+    }
+    for name in incoming {
+        let (from, to) = (staging.join(name), root.join(name));
+        fs::rename(&from, &to)?;
+        moves.push((from, to));
+    }
+    Ok(())
+}
+
+/// 启动自动备份，定期检查是否到了备份时间
+pub fn spawn_scheduler(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let app = app.clone();
+            let result = tauri::async_runtime::spawn_blocking(move || {
+                let backups = app.state::<Backups>();
+                backups.run_due(&app.state::<Database>(), &app.state::<DataDir>())
+            })
+            .await;
+            match result {
+                Ok(Ok(Some(file))) => info!(path = %file.path, "已自动备份"),
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => error!(error = %e, "自动备份失败"),
+                Err(e) => error!(error = %e, "自动备份任务异常退出"),
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+/// 立即备份数据库和数据目录
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为备份文件
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+pub async fn create_backup(app: AppHandle) -> Result<CustomResult, CustomResult> {
+    let file = tauri::async_runtime::spawn_blocking(move || {
+        let backups = app.state::<Backups>();
+        backups.create(&app.state::<Database>(), &app.state::<DataDir>(), false)
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    info!(path = %file.path, size = file.size, "已备份");
+
+    Ok(CustomResult::success(None, Some(json!(file))))
+}
+
+/// 获取全部备份
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为备份文件列表，从新到旧排列
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+pub async fn list_backups(backups: State<'_, Backups>) -> Result<CustomResult, CustomResult> {
+    let files = backups.list()?;
+    Ok(CustomResult::success(None, Some(json!(files))))
+}
+
+/// 从备份恢复
+///
+/// 恢复成功后前端需要重新加载页面
+///
+/// # Arguments
+///
+/// * `path` - 备份文件的路径，可以是 `list_backups` 返回的路径或其他位置的备份
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为恢复的统计，`trash_cleared` 为清空的回收站条目数
+/// * 失败返回 `Err(CustomResult)`，此时数据不做修改
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+pub async fn restore_backup(app: AppHandle, path: String) -> Result<CustomResult, CustomResult> {
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let backups = app.state::<Backups>();
+        backups.restore(&app.state::<Database>(), &app.state::<DataDir>(), Path::new(&path))
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    info!(?report, "已从备份恢复");
+
+    Ok(CustomResult::success(None, Some(json!(report))))
+}
+
+/// 获取自动备份的设置
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为设置
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+pub async fn get_backup_settings(db: State<'_, Database>) -> Result<CustomResult, CustomResult> {
+    Ok(CustomResult::success(None, Some(json!(settings(&db)?))))
+}
+
+/// 保存自动备份的设置
+///
+/// # Arguments
+///
+/// * `settings` - 自动备份的间隔（小时，0为不自动备份）和保留的数量（至少为1）
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(backups, db))]
+pub async fn set_backup_settings(
+    backups: State<'_, Backups>,
+    db: State<'_, Database>,
+    settings: BackupSettings,
+) -> Result<CustomResult, CustomResult> {
+    if settings.keep == 0 {
+        return Err(AppError::InvalidArgument(Msg::new("backup.invalid_keep")).into());
+    }
+    db.set_setting(INTERVAL_KEY, &settings.interval_hours.to_string())?;
+    db.set_setting(KEEP_KEY, &settings.keep.to_string())?;
+    backups.prune(settings.keep)?;
+
+    Ok(CustomResult::success(None, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures;
+
+    #[test]
+    fn restore_replaces_only_owned_entries() {
+        let base = fixtures::temp_dir("backup");
+        let root = base.join("datas");
+        fs::create_dir_all(&root).unwrap();
+        let db = Database::memory().unwrap();
+        let data_dir = DataDir::open(root.clone()).unwrap();
+        let backups = Backups::open(base.join("backups")).unwrap();
+
+        let lesson = fixtures::add_lesson(&db, &root, "Lesson", b"before").1;
+        fs::write(root.join("notes.txt"), b"mine").unwrap();
+        let deleted = fixtures::add_lesson(&db, &root, "Lesson", b"deleted").1;
+        data_dir.delete(&deleted, true).unwrap();
+        let backup = backups.create(&db, &data_dir, false).unwrap();
+
+        // 备份后的修改：改了课文音频、新增课文、改了其他文件
+        fs::write(lesson.join("audio.mp3"), b"after").unwrap();
+        let added = fixtures::add_lesson(&db, &root, "Lesson", b"added").1;
+        fs::write(root.join("notes.txt"), b"changed").unwrap();
+
+        let report = backups.restore(&db, &data_dir, Path::new(&backup.path)).unwrap();
+        assert_eq!(report.files, 2);
+        assert_eq!(report.trash_cleared, 1);
+        assert_eq!(fs::read(lesson.join("audio.mp3")).unwrap(), b"before");
+        assert!(!added.exists());
+        assert!(!root.join(storage::TRASH_DIR_NAME).exists());
+        assert_eq!(fs::read(root.join("notes.txt")).unwrap(), b"changed");
+        let names: HashSet<_> = fs::read_dir(&root).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, HashSet::from([OsString::from("notes.txt"), lesson.file_name().unwrap().into()]));
+        let lessons: i64 = db
+            .with_conn(|conn| Ok(conn.query_row("SELECT COUNT(*) FROM `class`", [], |row| row.get(0))?))
+            .unwrap();
+        assert_eq!(lessons, 2);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    /// 在备份文件夹中放一个指定时间的空备份文件
+    fn add_backup_file(backups: &Backups, auto: bool, time: NaiveDateTime) -> PathBuf {
+        let prefix = if auto { AUTO_PREFIX } else { MANUAL_PREFIX };
+        let path = backups.dir.join(format!("{}{}.{}", prefix, time.format(TIME_FORMAT), EXTENSION));
+        fs::write(&path, b"").unwrap();
+        path
+    }
+
+    #[test]
+    fn prune_keeps_the_latest_auto_backups_and_all_manual_ones() {
+        let base = fixtures::temp_dir("backup-prune");
+        let backups = Backups::open(base.join("backups")).unwrap();
+        let now = Local::now().naive_local();
+        let auto: Vec<_> = (0..4).map(|days| add_backup_file(&backups, true, now - chrono::Duration::days(days))).collect();
+        let manual: Vec<_> = (0..3).map(|days| add_backup_file(&backups, false, now - chrono::Duration::days(days + 10))).collect();
+        fs::write(backups.dir.join("notes.txt"), b"mine").unwrap();
+
+        assert_eq!(backups.prune(2).unwrap(), 2);
+        assert!(auto[..2].iter().all(|p| p.exists()));
+        assert!(auto[2..].iter().all(|p| !p.exists()));
+        assert!(manual.iter().all(|p| p.exists()));
+        assert!(backups.dir.join("notes.txt").exists());
+
+        // 保留数大于自动备份数时不删除
+        assert_eq!(backups.prune(5).unwrap(), 0);
+        assert_eq!(backups.list().unwrap().len(), 5);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn run_due_backs_up_after_the_interval_and_prunes() {
+        let base = fixtures::temp_dir("backup-due");
+        let root = base.join("datas");
+        fs::create_dir_all(&root).unwrap();
+        let db = Database::memory().unwrap();
+        let data_dir = DataDir::open(root.clone()).unwrap();
+        let backups = Backups::open(base.join("backups")).unwrap();
+        fixtures::add_lesson(&db, &root, "Lesson", b"audio");
+
+        // 没有开启自动备份
+        assert!(backups.run_due(&db, &data_dir).unwrap().is_none());
+
+        db.set_setting(INTERVAL_KEY, "24").unwrap();
+        db.set_setting(KEEP_KEY, "2").unwrap();
+        let now = Local::now().naive_local();
+        let old: Vec<_> = (2..5).map(|days| add_backup_file(&backups, true, now - chrono::Duration::days(days))).collect();
+        let manual = add_backup_file(&backups, false, now - chrono::Duration::days(30));
+
+        // 最近的自动备份已超过间隔，新建一个并只保留最近2个自动备份
+        let file = backups.run_due(&db, &data_dir).unwrap().unwrap();
+        assert!(file.auto);
+        assert!(old[0].exists());
+        assert!(!old[1].exists() && !old[2].exists());
+        assert!(manual.exists());
+        let names: Vec<_> = backups.list().unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0], file.name);
+
+        // 刚备份过，未到间隔
+        assert!(backups.run_due(&db, &data_dir).unwrap().is_none());
+        assert_eq!(backups.list().unwrap().len(), 3);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -19,16 +19,4 @@ impl CustomResult {
             data.unwrap_or(json!(null)),
         )
     }
-
-    pub fn error(msg: Option<String>, data: Option<Value>) -> Self {
-        Self::new(
-            500,
-            msg.unwrap_or("error".to_string()),
-            data.unwrap_or(json!(null)),
-        )
-    }
-
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
-    }
 }
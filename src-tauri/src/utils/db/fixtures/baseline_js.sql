@@ -0,0 +1,67 @@
+-- 引入版本号之前前端 sqlite.js 创建的数据库，用于测试从旧版本升级
+
+CREATE TABLE language(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    title TEXT NOT NULL,
+    languageText TEXT NOT NULL,
+    voice TEXT NOT NULL,
+    lastViewId INTEGER DEFAULT 0,
+    createTime TEXT DEFAULT (datetime('now', 'localtime'))
+);
+
+CREATE TABLE `class`(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    languageId INTEGER NOT NULL,
+    isFinish INTEGER DEFAULT 0,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    translation TEXT,
+    filePath TEXT NOT NULL,
+    audioFileName TEXT NOT NULL,
+    audioSrtJsonName TEXT NOT NULL,
+    createTime TEXT DEFAULT (datetime('now', 'localtime'))
+);
+
+CREATE TABLE word(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    classId INTEGER NOT NULL,
+    languageId INTEGER NOT NULL,
+    inlineId INTEGER,
+    content TEXT,
+    oartOfSpeech TEXT,
+    pronunciation TEXT,
+    interpretation TEXT,
+    other TEXT,
+    applicable INTEGER DEFAULT 1,
+    spell INTEGER DEFAULT 1,
+    startIndex INTEGER NOT NULL,
+    sort INTEGER DEFAULT 0,
+    createTime TEXT DEFAULT (datetime('now', 'localtime'))
+);
+CREATE INDEX idx_word_languageId_applicable ON word (languageId, applicable);
+CREATE INDEX idx_word_classId ON word (classId);
+
+CREATE TABLE option(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    nowAiPlatform TEXT DEFAULT 'ChatGLM',
+    ChatGLM TEXT,
+    DeepSeek TEXT,
+    Groq TEXT,
+    Google TEXT,
+    ChatGPT TEXT,
+    annotationPrompt TEXT,
+    translationPrompt TEXT,
+    annotationRule TEXT DEFAULT 'skip',
+    annotationNumber INTEGER DEFAULT 20,
+    showOartOfSpeech INTEGER DEFAULT 1,
+    playSpeed TEXT DEFAULT '1.0'
+);
+INSERT INTO option (nowAiPlatform) VALUES ('ChatGLM');
+
+CREATE TABLE wordBase64(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    classId INTEGER NOT NULL,
+    word TEXT NOT NULL UNIQUE,
+    base64 TEXT NOT NULL
+);
+CREATE INDEX idx_wordBase64_word ON wordBase64 (word);
@@ -0,0 +1,32 @@
+-- 由Rust端创建的表在引入版本号之前使用 CREATE TABLE IF NOT EXISTS 创建，这里同样兼容已经存在的表
+
+CREATE TABLE IF NOT EXISTS aiUsage(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    classId INTEGER,
+    promptTokens INTEGER NOT NULL DEFAULT 0,
+    completionTokens INTEGER NOT NULL DEFAULT 0,
+    cost REAL NOT NULL DEFAULT 0,
+    estimated INTEGER NOT NULL DEFAULT 0,
+    createTime TEXT DEFAULT (datetime('now', 'localtime'))
+);
+CREATE INDEX IF NOT EXISTS idx_aiUsage_createTime ON aiUsage (createTime);
+CREATE INDEX IF NOT EXISTS idx_aiUsage_classId ON aiUsage (classId);
+-- COMMENT ON TABLE aiUsage IS 'AI调用的token用量账本';
+-- COMMENT ON COLUMN aiUsage.classId IS '课题ID，与课文无关的调用为空';
+-- COMMENT ON COLUMN aiUsage.cost IS '按记录时的价格表计算的费用，美元';
+-- COMMENT ON COLUMN aiUsage.estimated IS '平台没有返回用量时为1，token数为估算值';
+
+CREATE TABLE IF NOT EXISTS aiPrice(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    provider TEXT NOT NULL,
+    modelPrefix TEXT NOT NULL,
+    inputPrice REAL NOT NULL,
+    outputPrice REAL NOT NULL,
+    UNIQUE (provider, modelPrefix)
+);
+-- COMMENT ON TABLE aiPrice IS '用户自定义的模型价格，覆盖内置价格表';
+-- COMMENT ON COLUMN aiPrice.modelPrefix IS '模型名称前缀，按最长前缀匹配，空字符串匹配该平台全部模型';
+-- COMMENT ON COLUMN aiPrice.inputPrice IS '每百万输入token的价格，美元';
+-- COMMENT ON COLUMN aiPrice.outputPrice IS '每百万输出token的价格，美元';
@@ -0,0 +1,26 @@
+-- 由Rust端创建的表在引入版本号之前使用 CREATE TABLE IF NOT EXISTS 创建，这里同样兼容已经存在的表
+
+CREATE TABLE IF NOT EXISTS aiCache(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    promptHash TEXT NOT NULL,
+    word TEXT NOT NULL,
+    annotation TEXT NOT NULL,
+    hits INTEGER NOT NULL DEFAULT 0,
+    lastUsedTime TEXT DEFAULT (datetime('now', 'localtime')),
+    createTime TEXT DEFAULT (datetime('now', 'localtime')),
+    UNIQUE (provider, model, promptHash, word)
+);
+CREATE INDEX IF NOT EXISTS idx_aiCache_lastUsedTime ON aiCache (lastUsedTime);
+-- COMMENT ON TABLE aiCache IS 'AI单词标注结果的缓存';
+-- COMMENT ON COLUMN aiCache.promptHash IS '提示词模板的哈希，模板变化后旧缓存不再命中';
+-- COMMENT ON COLUMN aiCache.annotation IS '标注结果JSON';
+-- COMMENT ON COLUMN aiCache.hits IS '命中次数';
+-- COMMENT ON COLUMN aiCache.lastUsedTime IS '最后写入或命中的时间，超出数量上限时先删除最久未使用的';
+
+CREATE TABLE IF NOT EXISTS appSetting(
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
+-- COMMENT ON TABLE appSetting IS 'Rust端的设置，键值对';
@@ -0,0 +1,34 @@
+-- 由Rust端创建的表在引入版本号之前使用 CREATE TABLE IF NOT EXISTS 创建，这里同样兼容已经存在的表
+
+CREATE TABLE IF NOT EXISTS chatSession(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    classId INTEGER,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    title TEXT NOT NULL,
+    targetLanguage TEXT NOT NULL,
+    scenario TEXT,
+    systemPrompt TEXT NOT NULL,
+    contextTokens INTEGER,
+    updateTime TEXT DEFAULT (datetime('now', 'localtime')),
+    createTime TEXT DEFAULT (datetime('now', 'localtime'))
+);
+CREATE INDEX IF NOT EXISTS idx_chatSession_classId ON chatSession (classId);
+-- COMMENT ON TABLE chatSession IS 'AI对话练习';
+-- COMMENT ON COLUMN chatSession.classId IS '围绕的课题ID，可以为空';
+-- COMMENT ON COLUMN chatSession.targetLanguage IS '练习的语言';
+-- COMMENT ON COLUMN chatSession.scenario IS '角色扮演场景';
+-- COMMENT ON COLUMN chatSession.contextTokens IS '自定义的上下文长度，为空时按模型决定';
+
+CREATE TABLE IF NOT EXISTS chatMessage(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    sessionId INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    tokens INTEGER NOT NULL DEFAULT 0,
+    createTime TEXT DEFAULT (datetime('now', 'localtime'))
+);
+CREATE INDEX IF NOT EXISTS idx_chatMessage_sessionId ON chatMessage (sessionId);
+-- COMMENT ON TABLE chatMessage IS 'AI对话练习的消息';
+-- COMMENT ON COLUMN chatMessage.role IS 'user 或 assistant';
+-- COMMENT ON COLUMN chatMessage.tokens IS '估算的token数';
@@ -0,0 +1,19 @@
+-- 由Rust端创建的表在引入版本号之前使用 CREATE TABLE IF NOT EXISTS 创建，这里同样兼容已经存在的表
+
+CREATE TABLE IF NOT EXISTS sentenceGrammar(
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    classId INTEGER NOT NULL,
+    sentenceIndex INTEGER NOT NULL,
+    sentence TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    analysis TEXT NOT NULL,
+    difficulty TEXT,
+    createTime TEXT DEFAULT (datetime('now', 'localtime')),
+    UNIQUE (classId, sentenceIndex)
+);
+-- COMMENT ON TABLE sentenceGrammar IS '句子的AI语法分析';
+-- COMMENT ON COLUMN sentenceGrammar.classId IS '课题ID';
+-- COMMENT ON COLUMN sentenceGrammar.sentenceIndex IS '句子在课文字幕时间轴中的序号';
+-- COMMENT ON COLUMN sentenceGrammar.analysis IS '分析结果JSON：翻译、分句、语法点和难度';
+-- COMMENT ON COLUMN sentenceGrammar.difficulty IS 'CEFR 难度等级，A1-C2';
@@ -0,0 +1,11 @@
+-- 单词音频改为按配音参数的哈希保存在数据目录的 word_audio 文件夹下（见 audio_cache.rs），不再存入数据库
+-- 旧表改名为 wordAudioLegacy 并补上配音员，程序启动后把其中的音频移到文件夹中，全部移完后删除这张表
+
+ALTER TABLE wordBase64 RENAME TO wordAudioLegacy;
+DROP INDEX IF EXISTS idx_wordBase64_word;
+ALTER TABLE wordAudioLegacy ADD COLUMN voice TEXT;
+UPDATE wordAudioLegacy SET voice = (
+    SELECT l.voice FROM `class` c JOIN language l ON l.id = c.languageId WHERE c.id = wordAudioLegacy.classId
+);
+-- COMMENT ON TABLE wordAudioLegacy IS '等待移到单词音频缓存的旧数据';
+-- COMMENT ON COLUMN wordAudioLegacy.voice IS '课文所属语言的配音员，课文已删除时为空，这些音频直接丢弃';
@@ -0,0 +1,279 @@
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::info;
+
+/// 数据库文件名，与前端 `Database.load('sqlite:database.db')` 使用同一个文件（位于应用配置目录）
+pub const DATABASE_FILE_NAME: &str = "database.db";
+/// 前端也在读写同一个文件，遇到锁时最多等待的时间
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 一次数据库结构升级
+struct Migration {
+    version: u32,
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// 全部升级，按版本号递增排列，已经发布的升级不能再修改，结构变化只能追加新的升级
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "基础表",
+        sql: include_str!("migrations/0001_base.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "AI用量账本和价格表",
+        sql: include_str!("migrations/0002_ai_usage.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "AI标注缓存和设置",
+        sql: include_str!("migrations/0003_ai_cache.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "AI对话练习",
+        sql: include_str!("migrations/0004_chat.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "句子语法分析",
+        sql: include_str!("migrations/0005_sentence_grammar.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "单词音频移到文件缓存",
+        sql: include_str!("migrations/0006_word_audio_cache.sql"),
+    },
+];
+
+/// 记录已执行升级的表
+const VERSION_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS schema_version(
+    version INTEGER PRIMARY KEY NOT NULL,
+    description TEXT NOT NULL,
+    applyTime TEXT DEFAULT (datetime('now', 'localtime'))
+);
+-- COMMENT ON TABLE schema_version IS '已执行的数据库升级，当前版本为最大的 version';
+"#;
+
+/// 程序支持的最新版本
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// 数据库当前的版本，没有执行过任何升级时为0
+pub fn schema_version(conn: &Connection) -> Result<u32, AppError> {
+    conn.execute_batch(VERSION_TABLE)?;
+    Ok(conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?)
+}
+
+/// 依次执行尚未执行的升级，每次升级在单独的事务中完成
+///
+/// # Returns
+///
+/// * 成功返回升级后的版本
+/// * 数据库版本高于程序支持的版本或升级失败时返回 `Err(AppError)`，失败的升级会整体回滚
+pub fn migrate(conn: &mut Connection) -> Result<u32, AppError> {
+    let current = schema_version(conn)?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(AppError::Database(
+            Msg::new("db.newer_version").arg("version", current).arg("latest", latest),
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+            params![migration.version, migration.description],
+        )?;
+        tx.commit()?;
+        info!(version = migration.version, description = migration.description, "已升级数据库");
+    }
+    Ok(latest)
+}
+
+/// Rust端的数据库连接，作为 tauri 的托管状态使用
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    /// 打开 `dir` 下的数据库文件，不存在时创建，并升级到最新的结构
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - 应用配置目录
+    pub fn open(dir: PathBuf) -> Result<Self, AppError> {
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(DATABASE_FILE_NAME);
+        let db = Self::init(Connection::open(&path)?)?;
+        info!(path = %path.display(), "已打开数据库");
+        Ok(db)
+    }
+
+    /// 内存数据库，不落盘，供测试使用
+    #[cfg(test)]
+    pub fn memory() -> Result<Self, AppError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, AppError> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// 在数据库连接上执行操作
+    ///
+    /// 连接由所有命令共享，`f` 中不要执行耗时的非数据库操作
+    pub fn with_conn<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut conn)
+    }
+
+    /// 读取 `appSetting` 中的设置
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>, AppError> {
+        self.with_conn(|conn| {
+            Ok(conn
+                .query_row("SELECT value FROM appSetting WHERE key = ?1", [key], |row| row.get(0))
+                .optional()?)
+        })
+    }
+
+    /// 写入 `appSetting` 中的设置
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<(), AppError> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO appSetting (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 引入版本号之前前端创建的数据库
+    const BASELINE_JS: &str = include_str!("fixtures/baseline_js.sql");
+
+    /// 处于 `version` 的数据库：0为前端创建的旧数据库，其余为依次执行到该版本的升级，并写入一些数据
+    fn fixture(version: u32) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        if version == 0 {
+            conn.execute_batch(BASELINE_JS).unwrap();
+        } else {
+            conn.execute_batch(VERSION_TABLE).unwrap();
+            for migration in MIGRATIONS.iter().filter(|m| m.version <= version) {
+                conn.execute_batch(migration.sql).unwrap();
+                conn.execute(
+                    "INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+                    params![migration.version, migration.description],
+                )
+                .unwrap();
+            }
+        }
+        conn.execute_batch(
+            "INSERT INTO language (title, languageText, voice) VALUES ('英语', 'en-US', 'en-US-AriaNeural');
+             INSERT INTO `class` (languageId, title, content, filePath, audioFileName, audioSrtJsonName)
+                 VALUES (1, 'Lesson 1', 'Hello world', '1', 'audio.mp3', 'audio.json');
+             INSERT INTO word (classId, languageId, content, startIndex) VALUES (1, 1, 'hello', 0);
+             UPDATE option SET annotationNumber = 30 WHERE id = 1;",
+        )
+        .unwrap();
+        if version < 6 {
+            conn.execute("INSERT INTO wordBase64 (classId, word, base64) VALUES (1, 'hello', 'AAAA')", [])
+                .unwrap();
+        }
+        conn
+    }
+
+    /// 全部表和索引的名称
+    fn objects(conn: &Connection) -> Vec<(String, String)> {
+        let mut statement = conn
+            .prepare("SELECT type, name FROM sqlite_master WHERE name NOT LIKE 'sqlite_%' ORDER BY type, name")
+            .unwrap();
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    /// 表的列名和类型
+    fn columns(conn: &Connection, table: &str) -> Vec<(String, String)> {
+        let mut statement = conn.prepare(&format!("PRAGMA table_info(`{}`)", table)).unwrap();
+        let rows = statement.query_map([], |row| Ok((row.get(1)?, row.get(2)?))).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn every_version_migrates_to_latest_schema() {
+        let mut expected = Connection::open_in_memory().unwrap();
+        migrate(&mut expected).unwrap();
+
+        for version in 0..latest_version() {
+            let mut conn = fixture(version);
+            assert_eq!(schema_version(&conn).unwrap(), version);
+            assert_eq!(migrate(&mut conn).unwrap(), latest_version(), "版本 {}", version);
+            assert_eq!(schema_version(&conn).unwrap(), latest_version(), "版本 {}", version);
+
+            // 表、索引和每张表的列都与新建的数据库一致
+            assert_eq!(objects(&conn), objects(&expected), "版本 {}", version);
+            for (kind, name) in objects(&expected) {
+                if kind == "table" {
+                    assert_eq!(columns(&conn, &name), columns(&expected, &name), "版本 {} 的表 {}", version, name);
+                }
+            }
+
+            // 已有数据保留，设置表仍然只有一条数据
+            let (options, number): (u32, u32) = conn
+                .query_row("SELECT COUNT(*), MAX(annotationNumber) FROM option", [], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap();
+            assert_eq!((options, number), (1, 30), "版本 {}", version);
+            let word: String = conn.query_row("SELECT content FROM word", [], |row| row.get(0)).unwrap();
+            assert_eq!(word, "hello");
+            // 旧的单词音频移到 wordAudioLegacy 并补上课文语言的配音员
+            let voice: String = conn
+                .query_row("SELECT voice FROM wordAudioLegacy WHERE word = 'hello'", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(voice, "en-US-AriaNeural", "版本 {}", version);
+        }
+    }
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let mut conn = fixture(0);
+        migrate(&mut conn).unwrap();
+        let before = objects(&conn);
+        assert_eq!(migrate(&mut conn).unwrap(), latest_version());
+        assert_eq!(objects(&conn), before);
+        let applied: u32 = conn.query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(applied, latest_version());
+    }
+
+    #[test]
+    fn newer_database_is_rejected() {
+        let mut conn = fixture(latest_version());
+        conn.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, 'future')",
+            [latest_version() + 1],
+        )
+        .unwrap();
+        assert!(matches!(migrate(&mut conn), Err(AppError::Database(_))));
+    }
+}
@@ -0,0 +1,288 @@
+use crate::utils::custom_result::CustomResult;
+use crate::utils::i18n::Msg;
+use serde_json::json;
+use std::fmt;
+use std::io;
+use tauri_plugin_http::reqwest;
+use tokio_tungstenite::tungstenite;
+
+/// 后端统一的错误类型
+///
+/// 每个变体对应一个固定的错误码（见 `code`）和一个机器可读的种类（见 `kind`），
+/// 转换成 `CustomResult` 后，前端可以直接根据 `data.kind` 判断错误，不需要解析 `msg`。
+/// 错误信息以消息键加参数的形式保存（见 `Msg`），`msg` 按当前语言渲染。
+#[derive(Debug)]
+pub enum AppError {
+    /// 网络连接失败（DNS、TLS、连接被重置等）
+    Network(Msg),
+    /// 连接或读取超时
+    Timeout(Msg),
+    /// 代理地址无效或代理不可用
+    Proxy(Msg),
+    /// 服务端返回了非成功的状态码
+    HttpStatus { status: u16, body: String },
+    /// 服务端拒绝了密钥（401/403）
+    ProviderAuth { status: u16, body: String },
+    /// 触发了服务端的频率限制（429），`retry_after` 为服务端建议的等待秒数
+    RateLimited { retry_after: Option<u64>, body: String },
+    /// 响应内容无法解析
+    Parse(Msg),
+    /// 文件读写失败
+    Io(Msg),
+    /// 参数错误
+    InvalidArgument(Msg),
+    /// 操作被取消
+    Cancelled,
+    /// 数据库读写失败
+    Database(Msg),
+}
+
+impl AppError {
+    /// 稳定的数字错误码，前端可以依赖这些值，新增变体只能追加新的错误码
+    pub fn code(&self) -> i32 {
+        match self {
+            AppError::Network(_) => 1001,
+            AppError::Timeout(_) => 1002,
+            AppError::Proxy(_) => 1003,
+            AppError::HttpStatus { .. } => 1004,
+            AppError::ProviderAuth { .. } => 1005,
+            AppError::RateLimited { .. } => 1006,
+            AppError::Parse(_) => 1007,
+            AppError::Io(_) => 1008,
+            AppError::InvalidArgument(_) => 1009,
+            AppError::Cancelled => 1010,
+            AppError::Database(_) => 1011,
+        }
+    }
+
+    /// 机器可读的错误种类，序列化到 `CustomResult.data.kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::Network(_) => "network",
+            AppError::Timeout(_) => "timeout",
+            AppError::Proxy(_) => "proxy",
+            AppError::HttpStatus { .. } => "http_status",
+            AppError::ProviderAuth { .. } => "provider_auth",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::Parse(_) => "parse",
+            AppError::Io(_) => "io",
+            AppError::InvalidArgument(_) => "invalid_argument",
+            AppError::Cancelled => "cancelled",
+            AppError::Database(_) => "database",
+        }
+    }
+
+    /// 根据HTTP状态码生成对应的错误
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - HTTP状态码
+    /// * `body` - 响应内容
+    /// * `retry_after` - 响应头 `Retry-After` 中的秒数（如果有）
+    pub fn from_status(status: u16, body: String, retry_after: Option<u64>) -> Self {
+        match status {
+            401 | 403 => AppError::ProviderAuth { status, body },
+            429 => AppError::RateLimited { retry_after, body },
+            _ => AppError::HttpStatus { status, body },
+        }
+    }
+
+    /// 错误对应的消息键和参数
+    pub fn message(&self) -> Msg {
+        match self {
+            AppError::Network(message)
+            | AppError::Timeout(message)
+            | AppError::Proxy(message)
+            | AppError::Parse(message)
+            | AppError::Io(message)
+            | AppError::InvalidArgument(message)
+            | AppError::Database(message) => message.clone(),
+            AppError::HttpStatus { status, body } => Msg::new("error.http_status")
+                .arg("status", status)
+                .arg("body", body),
+            AppError::ProviderAuth { status, body } => Msg::new("error.provider_auth")
+                .arg("status", status)
+                .arg("body", body),
+            AppError::RateLimited { body, .. } => {
+                Msg::new("error.rate_limited").arg("body", body)
+            }
+            AppError::Cancelled => Msg::new("error.cancelled"),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message().render())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<AppError> for CustomResult {
+    fn from(error: AppError) -> Self {
+        let message = error.message();
+        let mut data = json!({
+            "kind": error.kind(),
+            "key": message.key,
+            "params": message.to_json()["params"],
+        });
+        match &error {
+            AppError::HttpStatus { status, body } | AppError::ProviderAuth { status, body } => {
+                data["status"] = json!(status);
+                data["body"] = json!(body);
+            }
+            AppError::RateLimited { retry_after, body } => {
+                data["status"] = json!(429);
+                data["retry_after"] = json!(retry_after);
+                data["body"] = json!(body);
+            }
+            _ => {}
+        }
+
+        CustomResult::new(error.code(), message.render(), data)
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            AppError::Timeout(detail("error.timeout", error))
+        } else if error.is_decode() {
+            AppError::Parse(detail("error.parse", error))
+        } else if error.is_builder() {
+            AppError::InvalidArgument(detail("error.invalid_argument", error))
+        } else if let Some(status) = error.status() {
+            AppError::from_status(status.as_u16(), error.to_string(), None)
+        } else {
+            AppError::Network(detail("error.network", error))
+        }
+    }
+}
+
+impl From<tungstenite::Error> for AppError {
+    fn from(error: tungstenite::Error) -> Self {
+        match error {
+            tungstenite::Error::Io(e) => AppError::from(e),
+            tungstenite::Error::Url(e) => {
+                AppError::InvalidArgument(detail("error.invalid_argument", e))
+            }
+            tungstenite::Error::Http(response) => {
+                let status = response.status().as_u16();
+                let body = response
+                    .body()
+                    .as_ref()
+                    .map(|b| String::from_utf8_lossy(b).to_string())
+                    .unwrap_or_default();
+                AppError::from_status(status, body, None)
+            }
+            e => AppError::Network(detail("error.network", e)),
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::TimedOut => AppError::Timeout(detail("error.timeout", error)),
+            _ => AppError::Io(detail("error.io", error)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(error: serde_json::Error) -> Self {
+        AppError::Parse(detail("error.parse", error))
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(error: rusqlite::Error) -> Self {
+        AppError::Database(detail("error.database", error))
+    }
+}
+
+impl From<zip::result::ZipError> for AppError {
+    fn from(error: zip::result::ZipError) -> Self {
+        match error {
+            zip::result::ZipError::Io(e) => AppError::from(e),
+            e => AppError::Parse(detail("error.parse", e)),
+        }
+    }
+}
+
+impl From<quick_xml::Error> for AppError {
+    fn from(error: quick_xml::Error) -> Self {
+        AppError::Parse(detail("error.parse", error))
+    }
+}
+
+/// 通用错误消息，只带一个 `detail` 参数
+fn detail(key: &'static str, error: impl ToString) -> Msg {
+    Msg::new(key).arg("detail", error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn msg() -> Msg {
+        Msg::new("error.io").arg("detail", "disk full")
+    }
+
+    #[test]
+    fn codes_and_kinds_are_stable() {
+        let cases = [
+            (AppError::Network(msg()), 1001, "network"),
+            (AppError::Timeout(msg()), 1002, "timeout"),
+            (AppError::Proxy(msg()), 1003, "proxy"),
+            (AppError::from_status(500, String::new(), None), 1004, "http_status"),
+            (AppError::from_status(401, String::new(), None), 1005, "provider_auth"),
+            (AppError::from_status(403, String::new(), None), 1005, "provider_auth"),
+            (AppError::from_status(429, String::new(), Some(3)), 1006, "rate_limited"),
+            (AppError::Parse(msg()), 1007, "parse"),
+            (AppError::Io(msg()), 1008, "io"),
+            (AppError::InvalidArgument(msg()), 1009, "invalid_argument"),
+            (AppError::Cancelled, 1010, "cancelled"),
+            (AppError::Database(msg()), 1011, "database"),
+        ];
+        for (error, code, kind) in cases {
+            assert_eq!((error.code(), error.kind()), (code, kind), "{:?}", error);
+            let result: Value = serde_json::to_value(CustomResult::from(error)).unwrap();
+            assert_eq!(result["code"], code);
+            assert_eq!(result["data"]["kind"], kind);
+            assert!(result["msg"].as_str().is_some_and(|m| !m.is_empty()));
+        }
+    }
+
+    #[test]
+    fn custom_result_carries_key_params_and_status() {
+        let result = serde_json::to_value(CustomResult::from(AppError::Io(msg()))).unwrap();
+        assert_eq!(result["data"]["key"], "error.io");
+        assert_eq!(result["data"]["params"]["detail"], "disk full");
+        assert!(result["data"].get("status").is_none());
+
+        let result = serde_json::to_value(CustomResult::from(AppError::from_status(
+            429,
+            "slow down".to_string(),
+            Some(7),
+        )))
+        .unwrap();
+        assert_eq!(result["data"]["status"], 429);
+        assert_eq!(result["data"]["retry_after"], 7);
+        assert_eq!(result["data"]["body"], "slow down");
+
+        let result = serde_json::to_value(CustomResult::from(AppError::from_status(
+            502,
+            "bad gateway".to_string(),
+            None,
+        )))
+        .unwrap();
+        assert_eq!(result["data"]["status"], 502);
+        assert_eq!(result["data"]["body"], "bad gateway");
+
+        let success = serde_json::to_value(CustomResult::success(None, None)).unwrap();
+        assert_eq!(success, serde_json::json!({"code": 200, "msg": "Success", "data": null}));
+    }
+}
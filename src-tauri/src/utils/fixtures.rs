@@ -0,0 +1,54 @@
+//! 单元测试共用的临时目录、语言和课文数据
+
+use crate::utils::db::Database;
+use rusqlite::params;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// 新建临时目录 `learn-language-{name}-{UUID}`，测试结束后由调用方删除
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("learn-language-{}-{}", name, Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 新建语言，返回语言ID
+pub fn add_language(db: &Database, title: &str, language_text: &str, voice: &str) -> i64 {
+    db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO language (title, languageText, voice) VALUES (?1, ?2, ?3)",
+            params![title, language_text, voice],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })
+    .unwrap()
+}
+
+/// 新建没有文件的课文，返回课题ID
+pub fn add_lesson_record(db: &Database, language_id: i64, title: &str, file_path: &str) -> i64 {
+    db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO `class` (languageId, title, content, filePath, audioFileName, audioSrtJsonName)
+             VALUES (?1, ?2, '', ?3, 'audio.mp3', 'audio.json')",
+            params![language_id, title, file_path],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })
+    .unwrap()
+}
+
+/// 在 `root` 下新建以UUID命名的课文文件夹，写入 `audio.mp3`（内容为 `audio`）和 `audio.json`，
+/// 并在语言1下新建引用它的课文
+///
+/// # Returns
+///
+/// * 课题ID和课文文件夹
+pub fn add_lesson(db: &Database, root: &Path, title: &str, audio: &[u8]) -> (i64, PathBuf) {
+    let folder = root.join(Uuid::new_v4().to_string());
+    fs::create_dir_all(&folder).unwrap();
+    fs::write(folder.join("audio.mp3"), audio).unwrap();
+    fs::write(folder.join("audio.json"), b"[]").unwrap();
+    let lesson_id = add_lesson_record(db, 1, title, &folder.display().to_string());
+    (lesson_id, folder)
+}
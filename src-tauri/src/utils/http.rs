@@ -0,0 +1,182 @@
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use serde_json::Value;
+use std::time::Duration;
+use tauri::Url;
+use tauri_plugin_http::reqwest;
+use reqwest::{header::HeaderMap, Client, ClientBuilder, NoProxy, Proxy};
+use tracing::{debug, info, warn};
+
+/// 连接超时时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 不经过代理的本机地址
+const NO_PROXY_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+/// HTTP客户端，记录是否配置了代理
+///
+/// 连接代理失败时 reqwest 的错误与连接目标失败相同，只能根据是否配置了代理区分
+pub struct HttpClient {
+    client: Client,
+    proxied: bool,
+}
+
+/// 创建HTTP客户端
+///
+/// # Arguments
+///
+/// * `proxy` - 可选的代理地址，支持 `http://`、`https://`、`socks5://`，可以带 `user:pass@`。
+///             访问本机地址（本地模型服务等）时不经过代理
+/// * `timeout` - 可选的整体请求超时时间，为 `None` 时不限制（AI生成较慢，限制过短会影响正常输出）
+///
+/// # Returns
+///
+/// * 成功返回 `HttpClient`
+/// * 代理地址无效或创建客户端失败返回 `Err(AppError)`
+pub fn build_client(proxy: Option<&str>, timeout: Option<Duration>) -> Result<HttpClient, AppError> {
+    let mut client_builder = ClientBuilder::new().connect_timeout(CONNECT_TIMEOUT);
+    if let Some(timeout) = timeout {
+        client_builder = client_builder.timeout(timeout);
+    }
+
+    if let Some(proxy_url_str) = proxy {
+        debug!("使用代理");
+        let proxy_url = Url::parse(proxy_url_str)
+            .map_err(|e| AppError::Proxy(Msg::new("http.proxy_invalid_url").arg("error", e)))?;
+        if !matches!(proxy_url.scheme(), "http" | "https" | "socks5") {
+            return Err(AppError::Proxy(Msg::new("http.proxy_unsupported_scheme")));
+        }
+        let proxy = Proxy::all(proxy_url_str)
+            .map_err(|e| AppError::Proxy(Msg::new("http.proxy_add_failed").arg("error", e)))?
+            .no_proxy(NoProxy::from_string(&NO_PROXY_HOSTS.join(",")));
+        client_builder = client_builder.proxy(proxy);
+    }
+
+    Ok(HttpClient {
+        client: client_builder.build()?,
+        proxied: proxy.is_some(),
+    })
+}
+
+/// 把请求错误转换为 `AppError`，经过代理的请求连接失败时为代理错误
+fn request_error(client: &HttpClient, error: reqwest::Error) -> AppError {
+    let bypassed = error
+        .url()
+        .and_then(|url| url.host_str())
+        .is_some_and(|host| NO_PROXY_HOSTS.contains(&host.trim_start_matches('[').trim_end_matches(']')));
+    if client.proxied && error.is_connect() && !bypassed {
+        return AppError::Proxy(Msg::new("http.proxy_connect_failed").arg("error", error));
+    }
+    error.into()
+}
+
+/// 发送请求并把响应解析为JSON
+///
+/// # Arguments
+///
+/// * `client` - HTTP客户端，见 `build_client`
+/// * `method` - 请求方法，支持 `GET`、`POST`、`PUT`、`DELETE`
+/// * `url` - 请求地址
+/// * `headers` - 请求头
+/// * `body` - 请求体，为 `null` 时不发送请求体
+///
+/// # Returns
+///
+/// * 成功返回响应JSON
+/// * 请求失败、状态码非 2xx 或响应不是JSON时返回 `Err(AppError)`
+pub async fn send_json(
+    client: &HttpClient,
+    method: &str,
+    url: &str,
+    headers: HeaderMap,
+    body: &Value,
+) -> Result<Value, AppError> {
+    let mut request_builder = match method {
+        "GET" => client.client.get(url),
+        "POST" => client.client.post(url),
+        "PUT" => client.client.put(url),
+        "DELETE" => client.client.delete(url),
+        _ => {
+            return Err(AppError::InvalidArgument(
+                Msg::new("http.unsupported_method").arg("method", method),
+            ));
+        }
+    };
+
+    if !body.is_null() {
+        request_builder = request_builder.json(body);
+    }
+
+    // 请求头的值可能是密钥，只记录名称
+    debug!(headers = ?headers.keys().collect::<Vec<_>>(), "发送请求");
+    request_builder = request_builder.headers(headers);
+
+    let response = request_builder.send().await.map_err(|e| request_error(client, e))?;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let text = response.text().await.unwrap_or_else(|_| "".to_string());
+
+    if status.is_success() {
+        info!(status = status.as_u16(), "请求成功");
+        serde_json::from_str::<Value>(&text).map_err(|e| {
+            AppError::Parse(
+                Msg::new("http.invalid_json")
+                    .arg("error", e)
+                    .arg("body", &text),
+            )
+        })
+    } else {
+        warn!(status = status.as_u16(), body = %text, "请求失败");
+        Err(AppError::from_status(status.as_u16(), text, retry_after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 一个没有监听的本机端口
+    async fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port()
+    }
+
+    /// 对所有 `CONNECT` 请求返回407的代理
+    async fn rejecting_proxy() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").await;
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn connect_failures_through_a_proxy_are_proxy_errors() {
+        let closed = closed_port().await;
+        let rejecting = rejecting_proxy().await;
+        let cases = [
+            // 目标地址由代理解析，连不上代理时不会访问目标
+            (Some(format!("http://127.0.0.1:{}", closed)), "http://example.invalid/".to_string(), "proxy"),
+            (Some(format!("socks5://127.0.0.1:{}", closed)), "http://example.invalid/".to_string(), "proxy"),
+            (Some(format!("http://127.0.0.1:{}", rejecting)), "https://example.invalid/".to_string(), "proxy"),
+            (None, format!("http://127.0.0.1:{}/", closed), "network"),
+            // 本机地址不经过代理
+            (Some(format!("http://127.0.0.1:{}", rejecting)), format!("http://127.0.0.1:{}/", closed), "network"),
+            (Some(format!("http://127.0.0.1:{}", rejecting)), format!("http://localhost:{}/", closed), "network"),
+        ];
+        for (proxy, url, kind) in cases {
+            let client = build_client(proxy.as_deref(), None).unwrap();
+            let error = send_json(&client, "GET", &url, HeaderMap::new(), &Value::Null).await.unwrap_err();
+            assert_eq!(error.kind(), kind, "{:?} {} {:?}", proxy, url, error);
+        }
+    }
+}
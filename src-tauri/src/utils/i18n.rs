@@ -0,0 +1,382 @@
+use crate::utils::custom_result::CustomResult;
+use crate::utils::error::AppError;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::sync::RwLock;
+
+/// 后端支持的界面语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    ZhCn,
+    EnUs,
+}
+
+impl Locale {
+    /// 解析前端传入的语言代码，只比较主语言部分，例如 `en`、`en-GB` 都会匹配到 `en-US`
+    pub fn parse(code: &str) -> Option<Self> {
+        let primary = code
+            .split(['-', '_'])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match primary.as_str() {
+            "zh" => Some(Locale::ZhCn),
+            "en" => Some(Locale::EnUs),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::EnUs => "en-US",
+        }
+    }
+}
+
+/// 当前生效的语言，默认简体中文
+static LOCALE: RwLock<Locale> = RwLock::new(Locale::ZhCn);
+
+pub fn current_locale() -> Locale {
+    *LOCALE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 带参数的消息，`key` 对应消息目录中的条目，`params` 用于替换模板中的 `{name}`
+#[derive(Debug, Clone, Serialize)]
+pub struct Msg {
+    pub key: &'static str,
+    #[serde(serialize_with = "serialize_params")]
+    pub params: Vec<(&'static str, String)>,
+}
+
+impl Msg {
+    pub fn new(key: &'static str) -> Self {
+        Self {
+            key,
+            params: Vec::new(),
+        }
+    }
+
+    /// 追加一个模板参数
+    pub fn arg(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.params.push((name, value.to_string()));
+        self
+    }
+
+    /// 按当前语言渲染消息
+    pub fn render(&self) -> String {
+        self.render_in(current_locale())
+    }
+
+    /// 按指定语言渲染消息，目录中没有的条目回退到简体中文，再回退到 `key` 本身
+    pub fn render_in(&self, locale: Locale) -> String {
+        let template = catalog(locale, self.key)
+            .or_else(|| catalog(Locale::ZhCn, self.key))
+            .unwrap_or(self.key);
+
+        let mut text = template.to_string();
+        for (name, value) in &self.params {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+
+    /// 序列化为 `{ key, params }`，放入 `CustomResult.data`，方便前端自行翻译
+    pub fn to_json(&self) -> Value {
+        json!(self)
+    }
+}
+
+fn serialize_params<S: serde::Serializer>(
+    params: &[(&'static str, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let map: Map<String, Value> = params
+        .iter()
+        .map(|(name, value)| (name.to_string(), json!(value)))
+        .collect();
+    map.serialize(serializer)
+}
+
+fn catalog(locale: Locale, key: &str) -> Option<&'static str> {
+    match locale {
+        Locale::ZhCn => zh_cn(key),
+        Locale::EnUs => en_us(key),
+    }
+}
+
+fn zh_cn(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "error.network" => "网络错误：{detail}",
+        "error.timeout" => "请求超时：{detail}",
+        "error.parse" => "解析失败：{detail}",
+        "error.io" => "文件操作失败：{detail}",
+        "error.invalid_argument" => "参数错误：{detail}",
+        "error.http_status" => "API 请求失败，状态码: {status}，响应: {body}",
+        "error.provider_auth" => "API 密钥无效或无权限，状态码: {status}，响应: {body}",
+        "error.rate_limited" => "请求过于频繁，响应: {body}",
+        "error.cancelled" => "操作已取消",
+        "error.database" => "数据库操作失败：{detail}",
+
+        "locale.unsupported" => "不支持的语言：{locale}",
+
+        "tts.empty_voice_or_text" => "参数错误：voice 或 text 为空",
+        "tts.token_failed" => "生成令牌字符串失败",
+        "tts.encode_url_failed" => "编码URL失败：{error}",
+        "tts.create_dir_failed" => "创建文件夹失败：{error}",
+        "tts.create_audio_failed" => "创建音频文件失败：{error}",
+        "tts.write_audio_failed" => "写入音频文件失败：{error}",
+        "tts.create_json_failed" => "创建JSON文件失败：{error}",
+        "tts.write_json_failed" => "写入JSON文件失败：{error}",
+
+        "fs.path_not_found" => "错误: 路径 '{path}' 不存在.",
+        "fs.remove_file_failed" => "错误: 删除文件 '{path}' 失败: {error}",
+        "fs.remove_dir_failed" => "错误: 删除目录 '{path}' 失败: {error}",
+        "fs.outside_data_dir" => "路径 {path} 不在数据目录内，不能删除",
+        "fs.protected_path" => "不能删除数据目录或回收站本身：{path}",
+        "fs.symlink_escape" => "路径 {path} 是指向数据目录之外的链接（{target}），不能删除",
+        "fs.trash_failed" => "移动 {path} 到回收站失败：{error}",
+        "fs.trash_not_found" => "回收站中没有 {id}",
+        "fs.restore_conflict" => "原位置 {path} 已存在，无法恢复",
+
+        "audio.read_failed" => "读取音频文件 '{path}' 失败：{error}",
+        "audio.no_input" => "未提供文件路径或音频数据，无法进行 Base64 编码",
+
+        "http.proxy_add_failed" => "添加代理失败：{error}",
+        "http.proxy_unsupported_scheme" => "不支持的代理协议",
+        "http.proxy_invalid_url" => "解析代理URL失败: {error}",
+        "http.proxy_connect_failed" => "连接代理失败，请检查代理设置：{error}",
+        "http.unsupported_method" => "不支持的 HTTP 方法: {method}",
+        "http.invalid_json" => "解析 JSON 响应失败: {error} - 响应内容: {body}",
+
+        "log.init_failed" => "初始化日志失败：{error}",
+        "log.not_initialized" => "日志系统尚未初始化",
+
+        "secret.locked" => "密钥存储已加密，请先输入口令解锁",
+        "secret.wrong_passphrase" => "口令错误，无法解锁密钥存储",
+        "secret.missing_key" => "未设置 {provider} 的API密钥",
+        "secret.empty_key" => "平台名称和密钥不能为空",
+        "secret.invalid_key_file" => "密钥文件已损坏",
+        "secret.crypto_failed" => "加解密失败：{error}",
+        "secret.open_failed" => "无法打开密钥存储 {path}：{error}",
+
+        "db.newer_version" => "数据库版本 {version} 高于程序支持的版本 {latest}，请升级程序",
+
+        "storage.relative_path" => "数据目录必须是绝对路径：{path}",
+        "storage.busy" => "正在更换数据目录或恢复备份，请稍后再试",
+        "storage.inside_old_root" => "新的数据目录不能是当前数据目录或其中的文件夹：{path}",
+        "storage.target_conflict" => "新的数据目录中已有同名的文件或文件夹：{path}",
+        "storage.target_not_empty" => "新的数据目录 {path} 中有其他文件，请选择空文件夹",
+        "storage.checksum_mismatch" => "复制后的文件与原文件不一致：{path}",
+        "storage.scope_failed" => "无法授权访问数据目录：{error}",
+
+        "package.no_lessons" => "请选择要导出的课文",
+        "package.missing_file" => "课文文件 {path} 读取失败：{error}",
+        "package.invalid" => "不是有效的课程包",
+        "package.newer_version" => "课程包格式版本 {version} 高于程序支持的版本 {latest}，请升级程序",
+        "package.unknown_language" => "课程包中课文 {lesson} 的语言不存在",
+        "package.invalid_name" => "课程包中的文件名 {name} 不合法",
+        "package.missing_entry" => "课程包缺少文件 {name}",
+        "package.entry_too_large" => "课程包中的文件 {name} 过大",
+
+        "backup.invalid" => "不是有效的备份文件",
+        "backup.newer_version" => "备份格式版本 {version} 高于程序支持的版本 {latest}，请升级程序",
+        "backup.invalid_name" => "备份中的文件名 {name} 不合法",
+        "backup.corrupt" => "备份中的数据库已损坏：{detail}",
+        "backup.invalid_keep" => "保留的自动备份数量至少为1",
+        "backup.conflict" => "数据目录中已有与备份同名的文件 {path}，请移走后再恢复",
+
+        "ai.empty_response" => "{provider} 响应格式不正确或无内容",
+        "ai.invalid_local_url" => "本地模型服务地址无效：{url}",
+        "ai.job_not_found" => "标注任务 {job_id} 不存在或已经结束",
+
+        "usage.negative_price" => "模型 {model} 的价格不能为负数",
+
+        "conversation.not_found" => "对话 {id} 不存在",
+        "conversation.lesson_not_found" => "课文 {id} 不存在",
+        "conversation.empty_message" => "消息不能为空",
+
+        "grammar.empty_sentence" => "句子不能为空",
+        "grammar.invalid_response" => "语法分析结果不是有效的JSON：{error}",
+        "grammar.missing_translation" => "语法分析结果缺少翻译",
+        "grammar.invalid_difficulty" => "难度只能是 A1、A2、B1、B2、C1、C2 之一",
+
+        "failover.empty_model" => "备用链中的模型名称不能为空",
+
+        "lesson.not_found" => "课文 {id} 不存在",
+        "lesson.word_not_found" => "单词 {id} 不属于课文 {lesson}，本次修改未保存",
+
+        "vocabulary.language_not_found" => "语言 {id} 不存在",
+        "vocabulary.no_words" => "没有可以导出的单词",
+
+        "document.unsupported" => "不支持的文件格式：{path}，请选择 txt、md、epub、srt 或 vtt 文件",
+        "document.too_large" => "文件 {name} 过大",
+        "document.invalid_epub" => "不是有效的 EPUB 文件",
+        "document.epub_missing_entry" => "EPUB 文件缺少 {name}",
+        "document.empty" => "文件 {name} 中没有可以导入的文字",
+
+        "template.unclosed_tag" => "第 {line} 行的标签缺少 }}",
+        "template.unclosed_block" => "第 {line} 行的 {block} 块没有结束标签",
+        "template.unexpected_tag" => "第 {line} 行的标签 {tag} 无法识别或不匹配",
+        "template.unknown_variables" => "提示词中有未知变量：{names}，可用的变量：{known}",
+
+        "annotate.missing_word" => "模型未返回该单词",
+        "annotate.unknown_part_of_speech" => "未知的词性：{value}",
+        "annotate.missing_part_of_speech" => "缺少词性",
+        "annotate.invalid_applicability" => "适用性只能是0或1",
+        "annotate.missing_interpretation" => "缺少释义",
+        _ => return None,
+    })
+}
+
+fn en_us(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "error.network" => "Network error: {detail}",
+        "error.timeout" => "Request timed out: {detail}",
+        "error.parse" => "Failed to parse: {detail}",
+        "error.io" => "File operation failed: {detail}",
+        "error.invalid_argument" => "Invalid argument: {detail}",
+        "error.http_status" => "API request failed with status {status}, response: {body}",
+        "error.provider_auth" => {
+            "API key is invalid or lacks permission, status {status}, response: {body}"
+        }
+        "error.rate_limited" => "Too many requests, response: {body}",
+        "error.cancelled" => "Operation cancelled",
+        "error.database" => "Database operation failed: {detail}",
+
+        "locale.unsupported" => "Unsupported locale: {locale}",
+
+        "tts.empty_voice_or_text" => "Invalid argument: voice or text is empty",
+        "tts.token_failed" => "Failed to generate the token string",
+        "tts.encode_url_failed" => "Failed to encode URL: {error}",
+        "tts.create_dir_failed" => "Failed to create folder: {error}",
+        "tts.create_audio_failed" => "Failed to create audio file: {error}",
+        "tts.write_audio_failed" => "Failed to write audio file: {error}",
+        "tts.create_json_failed" => "Failed to create JSON file: {error}",
+        "tts.write_json_failed" => "Failed to write JSON file: {error}",
+
+        "fs.path_not_found" => "Error: path '{path}' does not exist.",
+        "fs.remove_file_failed" => "Error: failed to delete file '{path}': {error}",
+        "fs.remove_dir_failed" => "Error: failed to delete folder '{path}': {error}",
+        "fs.outside_data_dir" => "The path {path} is outside the data folder and cannot be deleted",
+        "fs.protected_path" => "The data folder and the trash themselves cannot be deleted: {path}",
+        "fs.symlink_escape" => "The path {path} is a link to {target} outside the data folder and cannot be deleted",
+        "fs.trash_failed" => "Failed to move {path} to the trash: {error}",
+        "fs.trash_not_found" => "There is no {id} in the trash",
+        "fs.restore_conflict" => "The original location {path} already exists, unable to restore",
+
+        "audio.read_failed" => "Failed to read audio file '{path}': {error}",
+        "audio.no_input" => "No file path or audio data given, nothing to Base64 encode",
+
+        "http.proxy_add_failed" => "Failed to add proxy: {error}",
+        "http.proxy_unsupported_scheme" => "Unsupported proxy scheme",
+        "http.proxy_invalid_url" => "Failed to parse proxy URL: {error}",
+        "http.proxy_connect_failed" => "Failed to connect through the proxy, check the proxy settings: {error}",
+        "http.unsupported_method" => "Unsupported HTTP method: {method}",
+        "http.invalid_json" => "Failed to parse JSON response: {error} - response: {body}",
+
+        "log.init_failed" => "Failed to initialize logging: {error}",
+        "log.not_initialized" => "Logging has not been initialized",
+
+        "secret.locked" => "The key store is encrypted, unlock it with your passphrase first",
+        "secret.wrong_passphrase" => "Wrong passphrase, unable to unlock the key store",
+        "secret.missing_key" => "No API key set for {provider}",
+        "secret.empty_key" => "Provider name and key must not be empty",
+        "secret.invalid_key_file" => "The key file is corrupted",
+        "secret.crypto_failed" => "Encryption failed: {error}",
+        "secret.open_failed" => "Unable to open the key store {path}: {error}",
+
+        "db.newer_version" => "The database version {version} is newer than the supported version {latest}, please update the app",
+
+        "storage.relative_path" => "The data folder must be an absolute path: {path}",
+        "storage.busy" => "The data folder is being moved or restored from a backup, please try again later",
+        "storage.inside_old_root" => "The new data folder must not be the current data folder or inside it: {path}",
+        "storage.target_conflict" => "The new data folder already contains a file or folder with the same name: {path}",
+        "storage.target_not_empty" => "The new data folder {path} contains other files, please choose an empty folder",
+        "storage.checksum_mismatch" => "The copied file does not match the original: {path}",
+        "storage.scope_failed" => "Failed to grant access to the data folder: {error}",
+
+        "package.no_lessons" => "Select the lessons to export",
+        "package.missing_file" => "Failed to read the lesson file {path}: {error}",
+        "package.invalid" => "Not a valid lesson package",
+        "package.newer_version" => "The package format version {version} is newer than the supported version {latest}, please update the app",
+        "package.unknown_language" => "The language of lesson {lesson} is missing from the package",
+        "package.invalid_name" => "Invalid file name in the package: {name}",
+        "package.missing_entry" => "The package is missing {name}",
+        "package.entry_too_large" => "The file {name} in the package is too large",
+
+        "backup.invalid" => "Not a valid backup file",
+        "backup.newer_version" => "The backup format version {version} is newer than the supported version {latest}, please update the app",
+        "backup.invalid_name" => "Invalid file name in the backup: {name}",
+        "backup.corrupt" => "The database in the backup is corrupted: {detail}",
+        "backup.invalid_keep" => "Keep at least one automatic backup",
+        "backup.conflict" => "{path} in the data folder has the same name as an entry in the backup, move it away and try again",
+
+        "ai.empty_response" => "{provider} returned an unexpected or empty response",
+        "ai.invalid_local_url" => "Invalid local model server URL: {url}",
+        "ai.job_not_found" => "Annotation job {job_id} does not exist or has finished",
+
+        "usage.negative_price" => "The price of model {model} must not be negative",
+
+        "conversation.not_found" => "Conversation {id} does not exist",
+        "conversation.lesson_not_found" => "Lesson {id} does not exist",
+        "conversation.empty_message" => "The message must not be empty",
+
+        "grammar.empty_sentence" => "The sentence must not be empty",
+        "grammar.invalid_response" => "The grammar analysis is not valid JSON: {error}",
+        "grammar.missing_translation" => "The grammar analysis has no translation",
+        "grammar.invalid_difficulty" => "Difficulty must be one of A1, A2, B1, B2, C1, C2",
+
+        "failover.empty_model" => "Model names in the failover chain must not be empty",
+
+        "lesson.not_found" => "Lesson {id} does not exist",
+        "lesson.word_not_found" => "Word {id} does not belong to lesson {lesson}, no changes were saved",
+
+        "vocabulary.language_not_found" => "Language {id} does not exist",
+        "vocabulary.no_words" => "There are no words to export",
+
+        "document.unsupported" => "Unsupported file format: {path}, choose a txt, md, epub, srt or vtt file",
+        "document.too_large" => "The file {name} is too large",
+        "document.invalid_epub" => "Not a valid EPUB file",
+        "document.epub_missing_entry" => "The EPUB file is missing {name}",
+        "document.empty" => "There is no text to import in {name}",
+
+        "template.unclosed_tag" => "The tag on line {line} is missing }}",
+        "template.unclosed_block" => "The {block} block on line {line} is never closed",
+        "template.unexpected_tag" => "The tag {tag} on line {line} is invalid or unmatched",
+        "template.unknown_variables" => "Unknown variables in the prompt: {names}. Available variables: {known}",
+
+        "annotate.missing_word" => "The model did not return this word",
+        "annotate.unknown_part_of_speech" => "Unknown part of speech: {value}",
+        "annotate.missing_part_of_speech" => "Missing part of speech",
+        "annotate.invalid_applicability" => "Applicability must be 0 or 1",
+        "annotate.missing_interpretation" => "Missing interpretation",
+        _ => return None,
+    })
+}
+
+/// 设置后端消息使用的语言
+///
+/// 设置后，所有 `CustomResult.msg` 都会按该语言输出
+///
+/// # Arguments
+///
+/// * `locale` - 语言代码，例如 `zh-CN`、`en-US`
+///
+/// # Returns
+///
+/// * 设置成功返回 `Ok(CustomResult::success)`，`data.locale` 为实际生效的语言
+/// * 不支持的语言返回 `Err(CustomResult)`
+#[tauri::command]
+pub async fn set_locale(locale: String) -> Result<CustomResult, CustomResult> {
+    let parsed = Locale::parse(&locale).ok_or_else(|| {
+        AppError::InvalidArgument(Msg::new("locale.unsupported").arg("locale", &locale))
+    })?;
+
+    *LOCALE.write().unwrap_or_else(|e| e.into_inner()) = parsed;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"locale": parsed.code()})),
+    ))
+}
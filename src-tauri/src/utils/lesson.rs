@@ -0,0 +1,468 @@
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use crate::utils::storage::{self, DataDir};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager, State};
+use tracing::{info, warn};
+
+/// 前端单词编辑页的一个单词，字段与 `useWord.js` 中的对象一致
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LessonWord {
+    /// 已有单词的ID，为0或不传时新增
+    #[serde(default, deserialize_with = "loose_int")]
+    pub id: i64,
+    #[serde(default, deserialize_with = "loose_int")]
+    pub inline_id: i64,
+    /// 单词，对应 `word.content`
+    pub word: String,
+    pub oart_of_speech: Option<String>,
+    pub pronunciation: Option<String>,
+    pub interpretation: Option<String>,
+    pub other: Option<String>,
+    #[serde(default = "default_flag", deserialize_with = "loose_int")]
+    pub applicable: i64,
+    #[serde(default = "default_flag", deserialize_with = "loose_int")]
+    pub spell: i64,
+    /// 单词在字幕文件中的数组的下标
+    #[serde(default, deserialize_with = "loose_int")]
+    pub start_index: i64,
+}
+
+fn default_flag() -> i64 {
+    1
+}
+
+/// 前端传来的数字可能是数字、布尔值、数字字符串或空字符串，统一转换为整数，无法转换时为0
+fn loose_int<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_i64().unwrap_or_else(|| n.as_f64().unwrap_or(0.0) as i64),
+        Value::Bool(b) => b as i64,
+        Value::String(s) => s.trim().parse().unwrap_or(0),
+        _ => 0,
+    })
+}
+
+/// 课文所属的语言ID
+fn lesson_language(conn: &Connection, lesson_id: i64) -> Result<i64, AppError> {
+    conn.query_row("SELECT languageId FROM `class` WHERE id = ?1", [lesson_id], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| AppError::InvalidArgument(Msg::new("lesson.not_found").arg("id", lesson_id)))
+}
+
+/// 在一个事务中保存课文的全部单词
+///
+/// `words` 的顺序即单词的排序。ID为0的单词新增，其余按ID更新，`deleted_ids` 中的单词删除；
+/// 更新和删除只作用于本课文的单词，任何一步失败都会整体回滚。
+///
+/// # Returns
+///
+/// * 成功返回每个单词的ID，顺序与 `words` 一致
+/// * 课文不存在、单词不属于本课文或写入失败时返回 `Err(AppError)`
+pub fn save_words(
+    db: &Database,
+    lesson_id: i64,
+    words: &[LessonWord],
+    deleted_ids: &[i64],
+) -> Result<Vec<i64>, AppError> {
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        let language_id = lesson_language(&tx, lesson_id)?;
+        let mut ids = Vec::with_capacity(words.len());
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO word (classId, languageId, inlineId, content, oartOfSpeech, pronunciation, interpretation,
+                    other, applicable, spell, startIndex, sort)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            let mut update = tx.prepare(
+                "UPDATE word SET content = ?3, oartOfSpeech = ?4, pronunciation = ?5, interpretation = ?6, other = ?7,
+                    applicable = ?8, spell = ?9, startIndex = ?10, sort = ?11
+                 WHERE id = ?1 AND classId = ?2",
+            )?;
+            let mut delete = tx.prepare("DELETE FROM word WHERE id = ?1 AND classId = ?2")?;
+
+            for (sort, word) in words.iter().enumerate() {
+                if word.id == 0 {
+                    insert.execute(params![
+                        lesson_id,
+                        language_id,
+                        word.inline_id,
+                        word.word,
+                        word.oart_of_speech,
+                        word.pronunciation,
+                        word.interpretation,
+                        word.other,
+                        word.applicable,
+                        word.spell,
+                        word.start_index,
+                        sort as i64
+                    ])?;
+                    ids.push(tx.last_insert_rowid());
+                } else {
+                    let changed = update.execute(params![
+                        word.id,
+                        lesson_id,
+                        word.word,
+                        word.oart_of_speech,
+                        word.pronunciation,
+                        word.interpretation,
+                        word.other,
+                        word.applicable,
+                        word.spell,
+                        word.start_index,
+                        sort as i64
+                    ])?;
+                    if changed == 0 {
+                        return Err(AppError::InvalidArgument(
+                            Msg::new("lesson.word_not_found").arg("id", word.id).arg("lesson", lesson_id),
+                        ));
+                    }
+                    ids.push(word.id);
+                }
+            }
+            for id in deleted_ids {
+                delete.execute(params![id, lesson_id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(ids)
+    })
+}
+
+/// 删除课文时实际删除的内容
+#[derive(Debug, Serialize)]
+pub struct DeletedLesson {
+    /// 课文记录是否存在并被删除
+    pub lesson: bool,
+    pub words: usize,
+    /// 句子语法分析的条数
+    pub sentence_analyses: usize,
+    /// 围绕课文的AI对话练习数，对话的消息一起删除
+    pub chat_sessions: usize,
+    /// 删除的音频和字幕文件夹，文件夹不存在时为空
+    pub folder: Option<String>,
+}
+
+/// 删除课文及其单词、语法分析、AI对话练习和音频字幕文件夹
+///
+/// 先把文件夹移到回收站，再在一个事务中删除数据库记录，提交后才从回收站中彻底删除；
+/// 移动文件夹失败（包括不在数据目录内）或数据库删除失败时文件夹移回原处、数据库不做修改，可以重试。
+/// 已经删除的部分（课文记录、文件夹等）直接跳过，所以重复删除或删除只剩一部分的课文也会成功。
+/// 单词音频按内容缓存，可能被其他课文使用，不随课文删除。
+///
+/// # Returns
+///
+/// * 成功返回实际删除的内容
+/// * 数据库写入或文件夹删除失败时返回 `Err(AppError)`
+pub fn delete(db: &Database, data_dir: &DataDir, lesson_id: i64) -> Result<DeletedLesson, AppError> {
+    data_dir.with_root_and_db(db, |root, db| {
+        let file_path: Option<String> = db.with_conn(|conn| {
+            Ok(conn
+                .query_row("SELECT filePath FROM `class` WHERE id = ?1", [lesson_id], |row| row.get(0))
+                .optional()?)
+        })?;
+        // 相对路径相对数据目录，与 `storage::delete_in` 的解析一致
+        let folder = match file_path.filter(|path| !path.trim().is_empty()) {
+            Some(path) if fs::symlink_metadata(root.join(&path)).is_ok() => {
+                let (_, trash_id) = storage::delete_in(root, Path::new(&path), true)?;
+                Some((path, trash_id))
+            }
+            _ => None,
+        };
+
+        let result = db.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM chatMessage WHERE sessionId IN (SELECT id FROM chatSession WHERE classId = ?1)",
+                [lesson_id],
+            )?;
+            let deleted = DeletedLesson {
+                lesson: tx.execute("DELETE FROM `class` WHERE id = ?1", [lesson_id])? > 0,
+                words: tx.execute("DELETE FROM word WHERE classId = ?1", [lesson_id])?,
+                sentence_analyses: tx.execute("DELETE FROM sentenceGrammar WHERE classId = ?1", [lesson_id])?,
+                chat_sessions: tx.execute("DELETE FROM chatSession WHERE classId = ?1", [lesson_id])?,
+                folder: None,
+            };
+            tx.commit()?;
+            Ok(deleted)
+        });
+
+        let mut deleted = match result {
+            Ok(deleted) => deleted,
+            Err(e) => {
+                if let Some((_, Some(id))) = &folder {
+                    if let Err(e) = storage::restore_in(root, id) {
+                        warn!(lesson_id, trash_id = %id, error = %e, "删除课文失败后文件夹移回失败，可以从回收站恢复");
+                    }
+                }
+                return Err(e);
+            }
+        };
+        if let Some((path, trash_id)) = folder {
+            if let Some(id) = trash_id {
+                if let Err(e) = storage::delete_in(root, &Path::new(storage::TRASH_DIR_NAME).join(&id), false) {
+                    warn!(lesson_id, trash_id = %id, error = %e, "课文文件夹已移到回收站，从回收站删除失败");
+                }
+            }
+            deleted.folder = Some(path);
+        }
+        Ok(deleted)
+    })
+}
+
+/// 保存课文的全部单词
+///
+/// 新增、修改和删除在同一个事务中完成，任何一个单词失败时整课不做修改
+///
+/// # Arguments
+///
+/// * `lesson_id` - 课题ID
+/// * `words` - 课文的完整单词列表，顺序即排序，`id` 为0的单词为新增
+/// * `deleted_ids` - 需要删除的单词ID
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.ids` 为每个单词的ID，顺序与 `words` 一致
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(db, words, deleted_ids), fields(words = words.len()))]
+pub async fn save_lesson_words(
+    db: State<'_, Database>,
+    lesson_id: i64,
+    words: Vec<LessonWord>,
+    deleted_ids: Option<Vec<i64>>,
+) -> Result<CustomResult, CustomResult> {
+    let deleted_ids = deleted_ids.unwrap_or_default();
+    let ids = save_words(&db, lesson_id, &words, &deleted_ids)?;
+    info!(deleted = deleted_ids.len(), "已保存课文单词");
+
+    Ok(CustomResult::success(None, Some(json!({"ids": ids}))))
+}
+
+/// 删除课文
+///
+/// 课文记录、单词、语法分析、AI对话练习和音频字幕文件夹一起删除，任何一步失败时数据库和文件夹不做修改。
+/// 课文已经全部或部分删除时同样返回成功。
+///
+/// # Arguments
+///
+/// * `lesson_id` - 课题ID
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为实际删除的内容
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+pub async fn delete_lesson(app: AppHandle, lesson_id: i64) -> Result<CustomResult, CustomResult> {
+    let deleted = tauri::async_runtime::spawn_blocking(move || {
+        delete(&app.state::<Database>(), &app.state::<DataDir>(), lesson_id)
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    info!(?deleted, "已删除课文");
+
+    Ok(CustomResult::success(None, Some(json!(deleted))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures;
+    use std::path::PathBuf;
+
+    fn word(id: i64, content: &str) -> LessonWord {
+        serde_json::from_value(json!({"id": id, "word": content, "interpretation": format!("{} 的释义", content)})).unwrap()
+    }
+
+    /// 课文的单词，按 `sort` 排列
+    fn lesson_words(db: &Database, lesson_id: i64) -> Vec<(i64, String, i64)> {
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, content, sort FROM word WHERE classId = ?1 ORDER BY sort")?;
+            let rows = stmt.query_map([lesson_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            Ok(rows.collect::<Result<_, _>>()?)
+        })
+        .unwrap()
+    }
+
+    /// 两篇课文，各自已有单词，返回课文ID
+    fn two_lessons(db: &Database) -> (i64, i64) {
+        let language_id = fixtures::add_language(db, "English", "en-US", "en-US-AriaNeural");
+        let lesson = fixtures::add_lesson_record(db, language_id, "Lesson", "");
+        let other = fixtures::add_lesson_record(db, language_id, "Other", "");
+        save_words(db, lesson, &[word(0, "a"), word(0, "b")], &[]).unwrap();
+        save_words(db, other, &[word(0, "x")], &[]).unwrap();
+        (lesson, other)
+    }
+
+    #[test]
+    fn save_words_returns_ids_in_input_order_and_sorts_by_list() {
+        let db = Database::memory().unwrap();
+        let (lesson, _) = two_lessons(&db);
+        let before = lesson_words(&db, lesson);
+        let (a, b) = (before[0].0, before[1].0);
+
+        let ids = save_words(&db, lesson, &[word(0, "c"), word(b, "B"), word(a, "a")], &[]).unwrap();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[1..], [b, a]);
+        assert_eq!(
+            lesson_words(&db, lesson),
+            vec![(ids[0], "c".to_string(), 0), (b, "B".to_string(), 1), (a, "a".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn word_from_another_lesson_rolls_back_the_batch() {
+        let db = Database::memory().unwrap();
+        let (lesson, other) = two_lessons(&db);
+        let before = lesson_words(&db, lesson);
+        let x = lesson_words(&db, other)[0].0;
+
+        let error = save_words(&db, lesson, &[word(0, "new"), word(before[0].0, "changed"), word(x, "stolen")], &[])
+            .unwrap_err();
+        assert!(matches!(error, AppError::InvalidArgument(msg) if msg.key == "lesson.word_not_found"));
+        assert_eq!(lesson_words(&db, lesson), before);
+        assert_eq!(lesson_words(&db, other), vec![(x, "x".to_string(), 0)]);
+
+        assert!(save_words(&db, 999, &[word(0, "a")], &[]).is_err());
+    }
+
+    #[test]
+    fn deleted_ids_only_remove_words_of_the_lesson() {
+        let db = Database::memory().unwrap();
+        let (lesson, other) = two_lessons(&db);
+        let before = lesson_words(&db, lesson);
+        let x = lesson_words(&db, other)[0].0;
+
+        let ids = save_words(&db, lesson, &[word(before[1].0, "b")], &[before[0].0, x]).unwrap();
+        assert_eq!(ids, [before[1].0]);
+        assert_eq!(lesson_words(&db, lesson), vec![(before[1].0, "b".to_string(), 0)]);
+        assert_eq!(lesson_words(&db, other), vec![(x, "x".to_string(), 0)]);
+    }
+
+    fn count(db: &Database, sql: &str) -> i64 {
+        db.with_conn(|conn| Ok(conn.query_row(sql, [], |row| row.get(0))?)).unwrap()
+    }
+
+    /// 数据目录中的一篇课文，有两个单词和一个带消息的对话练习，`filePath` 为相对数据目录的路径
+    fn add_full_lesson(db: &Database, root: &Path) -> (i64, PathBuf) {
+        let (lesson_id, folder) = fixtures::add_lesson(db, root, "Lesson", b"audio");
+        let relative = format!("./{}", folder.file_name().unwrap().to_string_lossy());
+        save_words(db, lesson_id, &[word(0, "a"), word(0, "b")], &[]).unwrap();
+        db.with_conn(|conn| {
+            conn.execute("UPDATE `class` SET filePath = ?1 WHERE id = ?2", params![relative, lesson_id])?;
+            conn.execute(
+                "INSERT INTO chatSession (classId, provider, model, title, targetLanguage, systemPrompt)
+                 VALUES (?1, 'ChatGPT', 'gpt-test', 'Chat', 'English', '')",
+                [lesson_id],
+            )?;
+            conn.execute("INSERT INTO chatMessage (sessionId, role, content) VALUES (?1, 'user', 'hi')", [conn.last_insert_rowid()])?;
+            Ok(())
+        })
+        .unwrap();
+        (lesson_id, folder)
+    }
+
+    #[test]
+    fn delete_removes_records_chats_and_relative_folder() {
+        let base = fixtures::temp_dir("lesson-delete");
+        let db = Database::memory().unwrap();
+        let data_dir = DataDir::open(base.clone()).unwrap();
+        let (lesson_id, folder) = add_full_lesson(&db, &base);
+        let (other_id, other_folder) = add_full_lesson(&db, &base);
+
+        let deleted = delete(&db, &data_dir, lesson_id).unwrap();
+        assert!(deleted.lesson);
+        assert_eq!((deleted.words, deleted.sentence_analyses, deleted.chat_sessions), (2, 0, 1));
+        assert!(deleted.folder.is_some());
+        assert!(!folder.exists());
+        // 回收站只是中转，删除后不留条目
+        assert_eq!(fs::read_dir(base.join(storage::TRASH_DIR_NAME)).unwrap().count(), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM chatSession"), 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM chatMessage"), 1);
+
+        // 其他课文不受影响
+        assert!(other_folder.exists());
+        assert_eq!(lesson_words(&db, other_id).len(), 2);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn delete_finishes_partially_deleted_lessons() {
+        let base = fixtures::temp_dir("lesson-partial");
+        let db = Database::memory().unwrap();
+        let data_dir = DataDir::open(base.clone()).unwrap();
+
+        // 课文记录已删除，单词、对话和文件夹还在
+        let (lesson_id, folder) = add_full_lesson(&db, &base);
+        db.with_conn(|conn| Ok(conn.execute("DELETE FROM `class` WHERE id = ?1", [lesson_id])?)).unwrap();
+        let deleted = delete(&db, &data_dir, lesson_id).unwrap();
+        assert!(!deleted.lesson);
+        assert_eq!((deleted.words, deleted.chat_sessions, deleted.folder), (2, 1, None));
+        // 没有课文记录时不知道文件夹的位置，留在原处
+        assert!(folder.exists());
+
+        // 文件夹已删除，数据库记录还在
+        let (lesson_id, folder) = add_full_lesson(&db, &base);
+        fs::remove_dir_all(&folder).unwrap();
+        let deleted = delete(&db, &data_dir, lesson_id).unwrap();
+        assert!(deleted.lesson);
+        assert_eq!((deleted.words, deleted.chat_sessions, deleted.folder), (2, 1, None));
+
+        // 全部删除后再删除也成功，什么都不做
+        let deleted = delete(&db, &data_dir, lesson_id).unwrap();
+        assert!(!deleted.lesson);
+        assert_eq!((deleted.words, deleted.sentence_analyses, deleted.chat_sessions, deleted.folder), (0, 0, 0, None));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn failed_delete_moves_the_folder_back() {
+        let base = fixtures::temp_dir("lesson-rollback");
+        let db = Database::memory().unwrap();
+        let data_dir = DataDir::open(base.clone()).unwrap();
+        let (lesson_id, folder) = add_full_lesson(&db, &base);
+        db.with_conn(|conn| Ok(conn.execute_batch("DROP TABLE sentenceGrammar")?)).unwrap();
+
+        assert!(delete(&db, &data_dir, lesson_id).is_err());
+        assert!(folder.join("audio.mp3").exists());
+        assert_eq!(fs::read_dir(base.join(storage::TRASH_DIR_NAME)).unwrap().count(), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM `class`"), 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM chatMessage"), 1);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn delete_outside_data_dir_changes_nothing() {
+        let base = fixtures::temp_dir("lesson-outside");
+        let root = base.join("datas");
+        fs::create_dir_all(&root).unwrap();
+        let db = Database::memory().unwrap();
+        let data_dir = DataDir::open(root.clone()).unwrap();
+        let (lesson_id, _) = add_full_lesson(&db, &root);
+        let outside = base.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        db.with_conn(|conn| {
+            Ok(conn.execute("UPDATE `class` SET filePath = ?1 WHERE id = ?2", params![outside.display().to_string(), lesson_id])?)
+        })
+        .unwrap();
+
+        assert!(matches!(delete(&db, &data_dir, lesson_id), Err(AppError::InvalidArgument(_))));
+        assert!(outside.exists());
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM `class`"), 1);
+        assert_eq!(lesson_words(&db, lesson_id).len(), 2);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM chatMessage"), 1);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,324 @@
+use crate::utils::custom_result::CustomResult;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use serde_json::json;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// 日志文件名前缀，滚动后的文件名形如 `learn-language.2025-06-10.log`
+const LOG_FILE_PREFIX: &str = "learn-language";
+const LOG_FILE_SUFFIX: &str = "log";
+/// 最多保留的日志文件数量（按天滚动，即保留一周）
+const MAX_LOG_FILES: usize = 7;
+/// `get_recent_logs` 默认返回的行数
+const DEFAULT_RECENT_LINES: usize = 500;
+
+/// 日志目录，初始化后才有值
+static LOG_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// 后台写日志线程的守卫，程序退出前不能被释放，否则会丢失尚未写入的日志
+static LOG_GUARD: OnceLock<WorkerGuard> = OnceLock::new();
+
+/// 初始化日志系统
+///
+/// 日志按天滚动写入 `log_dir`，写入前会对密钥类信息脱敏。调试模式下同时输出到控制台。
+/// 默认级别为 `info`，本程序自身为 `debug`，可通过环境变量 `RUST_LOG` 覆盖。
+///
+/// # Arguments
+///
+/// * `log_dir` - 日志目录，一般为 app 的日志目录
+///
+/// # Returns
+///
+/// * 初始化成功返回 `Ok(())`
+/// * 创建日志目录或日志文件失败返回 `Err(AppError)`
+pub fn init(log_dir: PathBuf) -> Result<(), AppError> {
+    fs::create_dir_all(&log_dir)?;
+
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(&log_dir)
+        .map_err(|e| AppError::Io(Msg::new("log.init_failed").arg("error", e)))?;
+    let (file_writer, guard) = tracing_appender::non_blocking(appender);
+
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info,learn_language_lib=debug"));
+
+    let file_layer = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_target(true)
+        .with_writer(Redacted(file_writer));
+
+    let console_layer = cfg!(debug_assertions)
+        .then(|| tracing_subscriber::fmt::layer().with_writer(Redacted(io::stdout)));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+        .map_err(|e| AppError::Io(Msg::new("log.init_failed").arg("error", e)))?;
+
+    let _ = LOG_GUARD.set(guard);
+    let _ = LOG_DIR.set(log_dir);
+
+    Ok(())
+}
+
+/// 日志文件不可用时的后备日志，只输出到标准错误
+///
+/// 同样会脱敏，级别规则与 `init` 相同。已经初始化过日志时不做任何事。
+pub fn init_stderr() {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info,learn_language_lib=debug"));
+
+    let _ = tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(Redacted(io::stderr)),
+        )
+        .try_init();
+}
+
+/// 包装任意 `MakeWriter`，写入前对每条日志脱敏
+struct Redacted<M>(M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacted<M> {
+    type Writer = RedactedWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactedWriter(self.0.make_writer())
+    }
+}
+
+struct RedactedWriter<W>(W);
+
+impl<W: Write> Write for RedactedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // fmt 层每条日志只调用一次 write，所以按缓冲区整体脱敏即可
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// 对日志文本脱敏
+///
+/// 会隐藏 `Authorization`、`x-api-key`、`x-goog-api-key` 请求头的值，以及URL中 `key=` 查询参数的值。
+///
+/// # Arguments
+///
+/// * `text` - 原始文本
+///
+/// # Returns
+///
+/// * 脱敏后的文本
+pub fn redact(text: &str) -> String {
+    let mut text = text.to_string();
+    for header in ["authorization", "x-api-key", "x-goog-api-key"] {
+        text = redact_header(&text, header);
+    }
+    redact_query_key(&text)
+}
+
+/// 隐藏 `name: value`、`"name": "value"`、`name=value` 形式中的值，保留 `Bearer` 等认证方案
+fn redact_header(text: &str, name: &str) -> String {
+    let lower = text.to_ascii_lowercase();
+    let bytes = text.as_bytes();
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut search_from = 0;
+
+    while let Some(found) = lower[search_from..].find(name) {
+        let mut cursor = search_from + found + name.len();
+        search_from = cursor;
+
+        // 跳过分隔符，必须至少有一个 `:` 或 `=` 才算是键值对
+        let mut has_separator = false;
+        while cursor < bytes.len() && b"\"' :=".contains(&bytes[cursor]) {
+            has_separator |= bytes[cursor] == b':' || bytes[cursor] == b'=';
+            cursor += 1;
+        }
+        if !has_separator {
+            continue;
+        }
+
+        // 保留认证方案，只隐藏凭证本身
+        for scheme in ["bearer ", "basic "] {
+            if lower[cursor..].starts_with(scheme) {
+                cursor += scheme.len();
+                break;
+            }
+        }
+
+        let end = value_end(bytes, cursor);
+        if end > cursor {
+            result.push_str(&text[copied..cursor]);
+            result.push_str("***");
+            copied = end;
+            search_from = end;
+        }
+    }
+
+    result.push_str(&text[copied..]);
+    result
+}
+
+/// 隐藏URL中 `key=` 查询参数的值
+fn redact_query_key(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut search_from = 0;
+
+    while let Some(found) = text[search_from..].find("key=") {
+        let start = search_from + found;
+        let cursor = start + "key=".len();
+        search_from = cursor;
+
+        // 只处理 `?key=`、`&key=`、`api_key=` 这类参数，避免误伤 `monkey=` 之类的普通文本
+        let preceded_by_separator = start == 0 || matches!(bytes[start - 1], b'?' | b'&' | b'_');
+        if !preceded_by_separator {
+            continue;
+        }
+
+        let end = value_end(bytes, cursor);
+        if end > cursor {
+            result.push_str(&text[copied..cursor]);
+            result.push_str("***");
+            copied = end;
+            search_from = end;
+        }
+    }
+
+    result.push_str(&text[copied..]);
+    result
+}
+
+/// 凭证的结束位置：遇到空白、引号或URL/JSON分隔符即结束
+fn value_end(bytes: &[u8], start: usize) -> usize {
+    let mut end = start;
+    while end < bytes.len() && !b" \t\r\n\"'&,;}#".contains(&bytes[end]) {
+        end += 1;
+    }
+    end
+}
+
+/// 读取最近的日志
+///
+/// 按文件名（即日期）从新到旧读取日志文件，直到凑够需要的行数，方便用户在反馈问题时附上日志。
+///
+/// # Arguments
+///
+/// * `lines` - 需要的行数，默认 500 行
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.logs` 为日志文本，`data.dir` 为日志目录
+/// * 日志未初始化或读取失败返回 `Err(CustomResult)`
+#[tauri::command]
+pub async fn get_recent_logs(lines: Option<usize>) -> Result<CustomResult, CustomResult> {
+    let wanted = lines.unwrap_or(DEFAULT_RECENT_LINES);
+    let log_dir = LOG_DIR
+        .get()
+        .ok_or_else(|| AppError::Io(Msg::new("log.not_initialized")))?;
+
+    let logs = read_recent_lines(log_dir, wanted)?;
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({
+            "dir": log_dir.display().to_string(),
+            "logs": logs.join("\n"),
+        })),
+    ))
+}
+
+fn read_recent_lines(log_dir: &Path, wanted: usize) -> Result<Vec<String>, AppError> {
+    let mut files: Vec<PathBuf> = fs::read_dir(log_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(LOG_FILE_PREFIX))
+        })
+        .collect();
+    files.sort();
+
+    let mut collected: Vec<String> = Vec::new();
+    for file in files.iter().rev() {
+        if collected.len() >= wanted {
+            break;
+        }
+        let content = fs::read_to_string(file)?;
+        let mut file_lines: Vec<String> = content.lines().map(str::to_string).collect();
+        let keep = wanted - collected.len();
+        if file_lines.len() > keep {
+            file_lines.drain(..file_lines.len() - keep);
+        }
+        file_lines.append(&mut collected);
+        collected = file_lines;
+    }
+
+    Ok(collected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri_plugin_http::reqwest::header::{HeaderMap, HeaderValue};
+
+    #[test]
+    fn redacts_headers_in_debug_output() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer sk-secret-1"));
+        headers.insert("x-api-key", HeaderValue::from_static("sk-ant-secret-2"));
+        headers.insert("x-goog-api-key", HeaderValue::from_static("AIza-secret-3"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+
+        let text = redact(&format!("headers={:?}", headers));
+        for secret in ["sk-secret-1", "sk-ant-secret-2", "AIza-secret-3"] {
+            assert!(!text.contains(secret), "{}", text);
+        }
+        assert!(text.contains(r#""authorization": "Bearer ***""#), "{}", text);
+        assert!(text.contains(r#""x-api-key": "***""#), "{}", text);
+        assert!(text.contains(r#""x-goog-api-key": "***""#), "{}", text);
+        assert!(text.contains("application/json"), "{}", text);
+    }
+
+    #[test]
+    fn redacts_headers_and_query_keys_in_plain_text() {
+        let cases = [
+            ("Authorization: Bearer sk-abc done", "Authorization: Bearer *** done"),
+            ("authorization=Basic Zm9vOmJhcg==", "authorization=Basic ***"),
+            ("X-Api-Key: sk-ant-1\nnext", "X-Api-Key: ***\nnext"),
+            ("x-goog-api-key = AIza123", "x-goog-api-key = ***"),
+            ("GET https://host/v1/models?key=AIza123", "GET https://host/v1/models?key=***"),
+            ("https://host/m:generate?alt=sse&key=AIza123#top", "https://host/m:generate?alt=sse&key=***#top"),
+            ("https://host/?key=AIza123&alt=json", "https://host/?key=***&alt=json"),
+            ("api_key=zzz, ok", "api_key=***, ok"),
+            // 不是键值对或不是查询参数时保持不变
+            ("monkey=banana", "monkey=banana"),
+            ("the authorization header is missing", "the authorization header is missing"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(redact(input), expected, "{}", input);
+        }
+    }
+}
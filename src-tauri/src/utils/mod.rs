@@ -1,3 +1,19 @@
+pub mod ai;
+pub mod anki;
 pub mod api;
+pub mod audio_cache;
+pub mod backup;
 pub mod custom_result;
+pub mod db;
+pub mod error;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod http;
+pub mod i18n;
+pub mod lesson;
+pub mod logging;
+pub mod package;
+pub mod secrets;
+pub mod storage;
+pub mod text_import;
 pub mod tts;
@@ -0,0 +1,1001 @@
+use crate::utils::audio_cache::{self, AudioKey};
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use crate::utils::storage::{self, DataDir};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+use tracing::{info, warn};
+use uuid::Uuid;
+use zip::{ZipArchive, ZipWriter};
+
+/// 课程包的格式版本，结构变化时加一
+pub const FORMAT_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest.json";
+const LESSON_NAME: &str = "lesson.json";
+const WORD_AUDIO_NAME: &str = "word_audio.json";
+/// 包内单个文件的大小上限，防止解压出异常大的文件
+const MAX_ENTRY_SIZE: u64 = 1 << 30;
+
+/// 课程包清单，描述包内的语言和课文
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub format_version: u32,
+    pub create_time: String,
+    pub languages: Vec<PackLanguage>,
+    pub lessons: Vec<PackLessonEntry>,
+}
+
+/// 包内的语言，`id` 只在包内有效
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackLanguage {
+    pub id: i64,
+    pub title: String,
+    pub language_text: String,
+    pub voice: String,
+}
+
+/// 清单中的课文，文件在 `dir` 文件夹下
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackLessonEntry {
+    pub id: i64,
+    pub language_id: i64,
+    pub title: String,
+    pub dir: String,
+    pub audio_file_name: String,
+    pub audio_srt_json_name: String,
+}
+
+/// 课文正文、翻译和单词
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackLesson {
+    content: String,
+    translation: Option<String>,
+    is_finish: i64,
+    words: Vec<PackWord>,
+}
+
+/// 包内的单词，`id` 和 `inline_id` 只在包内有效
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackWord {
+    id: i64,
+    inline_id: i64,
+    content: Option<String>,
+    oart_of_speech: Option<String>,
+    pronunciation: Option<String>,
+    interpretation: Option<String>,
+    other: Option<String>,
+    applicable: i64,
+    spell: i64,
+    start_index: i64,
+    sort: i64,
+}
+
+/// 单词音频缓存
+#[derive(Debug, Serialize, Deserialize)]
+struct PackWordAudio {
+    word: String,
+    base64: String,
+}
+
+/// 导入时遇到同名课文的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// 跳过同一语言下标题相同的课文
+    #[default]
+    Skip,
+    /// 同名课文也导入
+    KeepBoth,
+}
+
+/// 导入过程中与已有数据的冲突
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportConflict {
+    /// 已有相同 `languageText` 的语言，课文导入到已有语言下，配音员不同时保留已有的
+    LanguageMerged {
+        title: String,
+        language_text: String,
+        voice: String,
+        existing_voice: String,
+    },
+    /// 同一语言下已有同名课文
+    LessonExists { title: String, skipped: bool },
+    /// 已有该单词的音频缓存，保留已有的
+    WordAudioExists { word: String },
+}
+
+/// 导入结果
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    /// 新建的语言ID
+    pub languages: Vec<i64>,
+    /// 导入的课文ID，顺序与清单一致，跳过的课文不在其中
+    pub lessons: Vec<i64>,
+    pub words: usize,
+    pub word_audios: usize,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+/// 导出结果
+#[derive(Debug, Serialize)]
+pub struct ExportReport {
+    pub path: String,
+    pub languages: usize,
+    pub lessons: usize,
+    pub words: usize,
+    pub word_audios: usize,
+}
+
+/// 把课文导出为课程包
+///
+/// 课程包是zip文件，包含清单、每篇课文的正文、翻译、单词、音频、字幕JSON和单词音频缓存
+///
+/// # Arguments
+///
+/// * `db` - 数据库
+/// * `data_dir` - 数据目录，课文文件必须在其中
+/// * `lesson_ids` - 需要导出的课文ID
+/// * `path` - 课程包的保存路径
+///
+/// # Returns
+///
+/// * 成功返回导出的统计
+/// * 课文不存在、课文文件缺失或写入失败时返回 `Err(AppError)`，不会留下不完整的课程包
+pub fn export(db: &Database, data_dir: &DataDir, lesson_ids: &[i64], path: &Path) -> Result<ExportReport, AppError> {
+    if lesson_ids.is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("package.no_lessons")));
+    }
+
+    // 数据库只用于读出课文，写包时不再占用
+    let report = data_dir.with_root_and_db(db, |root, db| {
+        let lessons = db.with_conn(|conn| {
+            lesson_ids
+                .iter()
+                .enumerate()
+                .map(|(index, &lesson_id)| read_lesson(conn, index, lesson_id))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        storage::write_atomic(path, |temp| write_package(root, lessons, temp))
+    })?;
+
+    Ok(ExportReport {
+        path: path.display().to_string(),
+        ..report
+    })
+}
+
+/// 从数据库读出的一篇待导出课文
+struct ExportLesson {
+    entry: PackLessonEntry,
+    lesson: PackLesson,
+    file_path: String,
+    language: PackLanguage,
+}
+
+fn read_lesson(conn: &Connection, index: usize, lesson_id: i64) -> Result<ExportLesson, AppError> {
+    let lesson = conn
+        .query_row(
+            "SELECT c.languageId, c.isFinish, c.title, c.content, c.translation, c.filePath, c.audioFileName,
+                c.audioSrtJsonName, l.title, l.languageText, l.voice
+             FROM `class` c JOIN language l ON l.id = c.languageId WHERE c.id = ?1",
+            [lesson_id],
+            |row| {
+                Ok(ExportLesson {
+                    entry: PackLessonEntry {
+                        id: lesson_id,
+                        language_id: row.get(0)?,
+                        title: row.get(2)?,
+                        dir: format!("lessons/{}", index + 1),
+                        audio_file_name: row.get(6)?,
+                        audio_srt_json_name: row.get(7)?,
+                    },
+                    lesson: PackLesson {
+                        content: row.get(3)?,
+                        translation: row.get(4)?,
+                        is_finish: row.get(1)?,
+                        words: Vec::new(),
+                    },
+                    file_path: row.get(5)?,
+                    language: PackLanguage {
+                        id: row.get(0)?,
+                        title: row.get(8)?,
+                        language_text: row.get(9)?,
+                        voice: row.get(10)?,
+                    },
+                })
+            },
+        )
+        .optional()?
+        .ok_or_else(|| AppError::InvalidArgument(Msg::new("lesson.not_found").arg("id", lesson_id)))?;
+    Ok(ExportLesson {
+        lesson: PackLesson {
+            words: lesson_words(conn, lesson_id)?,
+            ..lesson.lesson
+        },
+        ..lesson
+    })
+}
+
+fn write_package(root: &Path, lessons: Vec<ExportLesson>, path: &Path) -> Result<ExportReport, AppError> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let (deflated, stored) = storage::zip_options(false);
+
+    let mut languages: Vec<PackLanguage> = Vec::new();
+    let mut entries = Vec::new();
+    let mut report = ExportReport {
+        path: String::new(),
+        languages: 0,
+        lessons: 0,
+        words: 0,
+        word_audios: 0,
+    };
+
+    for ExportLesson {
+        entry,
+        lesson,
+        file_path,
+        language,
+    } in lessons
+    {
+        let word_audios = lesson_word_audios(root, &language.voice, &lesson.words)?;
+        if !languages.iter().any(|l| l.id == language.id) {
+            languages.push(language);
+        }
+        report.words += lesson.words.len();
+        report.word_audios += word_audios.len();
+
+        zip.start_file(format!("{}/{}", entry.dir, LESSON_NAME), deflated)?;
+        zip.write_all(&serde_json::to_vec(&lesson)?)?;
+        zip.start_file(format!("{}/{}", entry.dir, WORD_AUDIO_NAME), deflated)?;
+        zip.write_all(&serde_json::to_vec(&word_audios)?)?;
+        for (name, options) in [(&entry.audio_file_name, stored), (&entry.audio_srt_json_name, deflated)] {
+            let file = Path::new(&file_path).join(name);
+            let bytes = fs::read(&file).map_err(|e| {
+                AppError::Io(Msg::new("package.missing_file").arg("path", file.display()).arg("error", e))
+            })?;
+            zip.start_file(format!("{}/{}", entry.dir, name), options)?;
+            zip.write_all(&bytes)?;
+        }
+        entries.push(entry);
+    }
+
+    report.languages = languages.len();
+    report.lessons = entries.len();
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        create_time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        languages,
+        lessons: entries,
+    };
+    zip.start_file(MANIFEST_NAME, deflated)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.finish()?;
+    Ok(report)
+}
+
+fn lesson_words(conn: &Connection, lesson_id: i64) -> Result<Vec<PackWord>, AppError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, inlineId, content, oartOfSpeech, pronunciation, interpretation, other, applicable, spell,
+            startIndex, sort
+         FROM word WHERE classId = ?1 ORDER BY sort ASC",
+    )?;
+    let rows = stmt.query_map([lesson_id], |row| {
+        Ok(PackWord {
+            id: row.get(0)?,
+            inline_id: row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+            content: row.get(2)?,
+            oart_of_speech: row.get(3)?,
+            pronunciation: row.get(4)?,
+            interpretation: row.get(5)?,
+            other: row.get(6)?,
+            applicable: row.get::<_, Option<i64>>(7)?.unwrap_or(1),
+            spell: row.get::<_, Option<i64>>(8)?.unwrap_or(1),
+            start_index: row.get(9)?,
+            sort: row.get::<_, Option<i64>>(10)?.unwrap_or(0),
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// 课文单词在缓存中的音频，没有缓存的单词跳过
+fn lesson_word_audios(root: &Path, voice: &str, words: &[PackWord]) -> Result<Vec<PackWordAudio>, AppError> {
+    let mut audios: Vec<PackWordAudio> = Vec::new();
+    for word in words.iter().filter_map(|w| w.content.as_deref()) {
+        if audios.iter().any(|a| a.word == word) {
+            continue;
+        }
+        if let Some(audio) = audio_cache::read(root, &AudioKey::new(voice, word))? {
+            audios.push(PackWordAudio {
+                word: word.to_string(),
+                base64: general_purpose::STANDARD.encode(audio),
+            });
+        }
+    }
+    Ok(audios)
+}
+
+/// 读取并校验课程包的清单
+///
+/// # Arguments
+///
+/// * `archive` - 课程包
+/// * `max_entry_size` - 包内单个文件的大小上限，一般为 `MAX_ENTRY_SIZE`
+///
+/// # Returns
+///
+/// * 成功返回清单
+/// * 不是课程包、格式版本高于程序支持的版本、清单引用了包内不存在或过大的文件时返回 `Err(AppError)`
+pub fn read_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>, max_entry_size: u64) -> Result<Manifest, AppError> {
+    if archive.index_for_name(MANIFEST_NAME).is_none() {
+        return Err(AppError::InvalidArgument(Msg::new("package.invalid")));
+    }
+    let manifest: Manifest = serde_json::from_slice(&read_entry(archive, MANIFEST_NAME, max_entry_size)?)?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(AppError::InvalidArgument(
+            Msg::new("package.newer_version")
+                .arg("version", manifest.format_version)
+                .arg("latest", FORMAT_VERSION),
+        ));
+    }
+
+    for lesson in &manifest.lessons {
+        if !manifest.languages.iter().any(|l| l.id == lesson.language_id) {
+            return Err(AppError::InvalidArgument(
+                Msg::new("package.unknown_language").arg("lesson", &lesson.title),
+            ));
+        }
+        // 文件名会用来在数据目录下创建文件，只能是单独的文件名
+        for name in [&lesson.audio_file_name, &lesson.audio_srt_json_name] {
+            if name.is_empty() || Path::new(name).file_name() != Some(name.as_ref()) {
+                return Err(AppError::InvalidArgument(Msg::new("package.invalid_name").arg("name", name)));
+            }
+        }
+        // 导入前检查全部文件，避免导入到一半才发现包不完整
+        for name in [LESSON_NAME, WORD_AUDIO_NAME, &lesson.audio_file_name, &lesson.audio_srt_json_name] {
+            let entry = format!("{}/{}", lesson.dir, name);
+            let size = match archive.by_name(&entry) {
+                Ok(file) => file.size(),
+                Err(_) => {
+                    return Err(AppError::InvalidArgument(Msg::new("package.missing_entry").arg("name", entry)));
+                }
+            };
+            if size > max_entry_size {
+                return Err(AppError::InvalidArgument(Msg::new("package.entry_too_large").arg("name", entry)));
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str, max_size: u64) -> Result<Vec<u8>, AppError> {
+    let entry = archive.by_name(name)?;
+    if entry.size() > max_size {
+        return Err(AppError::InvalidArgument(Msg::new("package.entry_too_large").arg("name", name)));
+    }
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry.take(max_size).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// 导入课程包
+///
+/// 语言按 `languageText` 去重，课文、单词的ID全部重新分配，单词的 `inlineId` 指向包内单词时改为新ID，否则置0。
+/// 先读出已有的语言和课文决定如何处理冲突，再把课文文件写入数据目录下新建的文件夹、单词音频写入缓存，
+/// 最后在一个事务中写入数据库。任何一步失败时删除已写入的文件夹，数据库不做修改；
+/// 缓存按内容保存，导入失败时不需要删除。
+///
+/// 整个过程持有数据目录的读锁并读写大量文件，需要在 `spawn_blocking` 中调用
+///
+/// # Arguments
+///
+/// * `db` - 数据库
+/// * `data_dir` - 数据目录
+/// * `path` - 课程包路径
+/// * `policy` - 同一语言下已有同名课文时的处理方式
+///
+/// # Returns
+///
+/// * 成功返回导入的统计和冲突
+/// * 课程包不合法或写入失败时返回 `Err(AppError)`
+pub fn import(db: &Database, data_dir: &DataDir, path: &Path, policy: ConflictPolicy) -> Result<ImportReport, AppError> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let manifest = read_manifest(&mut archive, MAX_ENTRY_SIZE)?;
+    let mut report = ImportReport {
+        languages: Vec::new(),
+        lessons: Vec::new(),
+        words: 0,
+        word_audios: 0,
+        conflicts: Vec::new(),
+    };
+
+    data_dir.with_root_and_db(db, |root, db| {
+        let plan = db.with_conn(|conn| plan_import(conn, &manifest, policy, &mut report.conflicts))?;
+        let mut created = Vec::new();
+        let result = stage_lessons(root, &mut archive, &manifest, &plan, &mut report, &mut created).and_then(|staged| {
+            db.with_conn(|conn| {
+                let tx = conn.transaction()?;
+                insert_lessons(&tx, &manifest, &plan, staged, &mut report)?;
+                tx.commit()?;
+                Ok(())
+            })
+        });
+        if let Err(e) = result {
+            for dir in &created {
+                if let Err(e) = fs::remove_dir_all(dir) {
+                    warn!(path = %dir.display(), error = %e, "导入失败后清理文件夹失败");
+                }
+            }
+            return Err(e);
+        }
+        Ok(())
+    })?;
+    Ok(report)
+}
+
+/// 根据已有数据决定的导入方式
+struct ImportPlan<'a> {
+    /// 包内语言ID到已有语言ID，不在其中的语言导入时新建
+    languages: HashMap<i64, i64>,
+    /// 需要导入的课文，跳过的同名课文不在其中
+    lessons: Vec<&'a PackLessonEntry>,
+}
+
+/// 已写好文件、等待写入数据库的课文
+struct StagedLesson<'a> {
+    entry: &'a PackLessonEntry,
+    lesson: PackLesson,
+    folder: PathBuf,
+}
+
+fn pack_language<'a>(manifest: &'a Manifest, entry: &PackLessonEntry) -> &'a PackLanguage {
+    manifest
+        .languages
+        .iter()
+        .find(|l| l.id == entry.language_id)
+        .expect("read_manifest 已校验课文的语言都在包内")
+}
+
+fn plan_import<'a>(
+    conn: &Connection,
+    manifest: &'a Manifest,
+    policy: ConflictPolicy,
+    conflicts: &mut Vec<ImportConflict>,
+) -> Result<ImportPlan<'a>, AppError> {
+    let mut languages = HashMap::new();
+    // 包内 `languageText` 相同的新语言只新建一个
+    let mut new_languages: HashMap<&str, &PackLanguage> = HashMap::new();
+    for language in &manifest.languages {
+        let existing: Option<(i64, String)> = conn
+            .query_row(
+                "SELECT id, voice FROM language WHERE languageText = ?1 ORDER BY id LIMIT 1",
+                [&language.language_text],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let existing_voice = match existing {
+            Some((id, voice)) => {
+                languages.insert(language.id, id);
+                voice
+            }
+            None => match new_languages.get(language.language_text.as_str()) {
+                Some(first) => first.voice.clone(),
+                None => {
+                    new_languages.insert(&language.language_text, language);
+                    continue;
+                }
+            },
+        };
+        conflicts.push(ImportConflict::LanguageMerged {
+            title: language.title.clone(),
+            language_text: language.language_text.clone(),
+            voice: language.voice.clone(),
+            existing_voice,
+        });
+    }
+
+    let mut lessons = Vec::new();
+    // 包内同一语言下的同名课文也算冲突
+    let mut titles = HashSet::new();
+    for entry in &manifest.lessons {
+        let language_text = &pack_language(manifest, entry).language_text;
+        let mut exists = !titles.insert((language_text, &entry.title));
+        if let (false, Some(language_id)) = (exists, languages.get(&entry.language_id)) {
+            exists = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM `class` WHERE languageId = ?1 AND title = ?2)",
+                params![language_id, entry.title],
+                |row| row.get(0),
+            )?;
+        }
+        if exists {
+            let skipped = policy == ConflictPolicy::Skip;
+            conflicts.push(ImportConflict::LessonExists {
+                title: entry.title.clone(),
+                skipped,
+            });
+            if skipped {
+                continue;
+            }
+        }
+        lessons.push(entry);
+    }
+    Ok(ImportPlan { languages, lessons })
+}
+
+fn stage_lessons<'a, R: Read + Seek>(
+    root: &Path,
+    archive: &mut ZipArchive<R>,
+    manifest: &Manifest,
+    plan: &ImportPlan<'a>,
+    report: &mut ImportReport,
+    created: &mut Vec<PathBuf>,
+) -> Result<Vec<StagedLesson<'a>>, AppError> {
+    let mut staged = Vec::new();
+    for &entry in &plan.lessons {
+        let lesson: PackLesson =
+            serde_json::from_slice(&read_entry(archive, &format!("{}/{}", entry.dir, LESSON_NAME), MAX_ENTRY_SIZE)?)?;
+        let word_audios: Vec<PackWordAudio> = serde_json::from_slice(&read_entry(
+            archive,
+            &format!("{}/{}", entry.dir, WORD_AUDIO_NAME),
+            MAX_ENTRY_SIZE,
+        )?)?;
+
+        // 与 start_tts 一样，每篇课文的文件放在数据目录下以随机ID命名的文件夹中
+        let folder = root.join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&folder)?;
+        created.push(folder.clone());
+        for name in [&entry.audio_file_name, &entry.audio_srt_json_name] {
+            let bytes = read_entry(archive, &format!("{}/{}", entry.dir, name), MAX_ENTRY_SIZE)?;
+            fs::write(folder.join(name), bytes)?;
+        }
+
+        // 单词音频按包内的配音员保存到缓存
+        let voice = &pack_language(manifest, entry).voice;
+        for audio in word_audios {
+            let bytes = general_purpose::STANDARD
+                .decode(&audio.base64)
+                .map_err(|e| AppError::Parse(Msg::new("error.parse").arg("detail", e)))?;
+            if audio_cache::write(root, &AudioKey::new(voice, &audio.word), &bytes)?.1 {
+                report.word_audios += 1;
+            } else {
+                report.conflicts.push(ImportConflict::WordAudioExists { word: audio.word });
+            }
+        }
+        staged.push(StagedLesson { entry, lesson, folder });
+    }
+    Ok(staged)
+}
+
+fn insert_lessons(
+    tx: &Transaction,
+    manifest: &Manifest,
+    plan: &ImportPlan,
+    staged: Vec<StagedLesson>,
+    report: &mut ImportReport,
+) -> Result<(), AppError> {
+    // 包内语言ID到本地语言ID
+    let mut language_ids = plan.languages.clone();
+    let mut inserted: HashMap<&str, i64> = HashMap::new();
+    for language in &manifest.languages {
+        if language_ids.contains_key(&language.id) {
+            continue;
+        }
+        let id = match inserted.get(language.language_text.as_str()) {
+            Some(&id) => id,
+            None => {
+                tx.execute(
+                    "INSERT INTO language (title, languageText, voice) VALUES (?1, ?2, ?3)",
+                    params![language.title, language.language_text, language.voice],
+                )?;
+                let id = tx.last_insert_rowid();
+                report.languages.push(id);
+                inserted.insert(&language.language_text, id);
+                id
+            }
+        };
+        language_ids.insert(language.id, id);
+    }
+
+    let mut insert_word = tx.prepare(
+        "INSERT INTO word (classId, languageId, inlineId, content, oartOfSpeech, pronunciation, interpretation,
+            other, applicable, spell, startIndex, sort)
+         VALUES (?1, ?2, 0, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )?;
+    // 包内单词ID到本地单词ID，所有课文共用，`inlineId` 可以指向其他课文的单词
+    let mut word_ids = HashMap::new();
+    let mut inline_ids = Vec::new();
+
+    for StagedLesson { entry, lesson, folder } in staged {
+        let language_id = language_ids[&entry.language_id];
+        tx.execute(
+            "INSERT INTO `class` (languageId, isFinish, title, content, translation, filePath, audioFileName, audioSrtJsonName)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                language_id,
+                lesson.is_finish,
+                entry.title,
+                lesson.content,
+                lesson.translation,
+                folder.display().to_string(),
+                entry.audio_file_name,
+                entry.audio_srt_json_name
+            ],
+        )?;
+        let lesson_id = tx.last_insert_rowid();
+        report.lessons.push(lesson_id);
+
+        for word in &lesson.words {
+            insert_word.execute(params![
+                lesson_id,
+                language_id,
+                word.content,
+                word.oart_of_speech,
+                word.pronunciation,
+                word.interpretation,
+                word.other,
+                word.applicable,
+                word.spell,
+                word.start_index,
+                word.sort
+            ])?;
+            let id = tx.last_insert_rowid();
+            word_ids.insert(word.id, id);
+            if word.inline_id != 0 {
+                inline_ids.push((id, word.inline_id));
+            }
+        }
+        report.words += lesson.words.len();
+    }
+
+    // 所有单词都有了新ID后再改写 `inlineId`
+    let mut update_inline = tx.prepare("UPDATE word SET inlineId = ?1 WHERE id = ?2")?;
+    for (id, inline_id) in inline_ids {
+        if let Some(new_inline_id) = word_ids.get(&inline_id) {
+            update_inline.execute(params![new_inline_id, id])?;
+        }
+    }
+    Ok(())
+}
+
+/// 导出课程包
+///
+/// # Arguments
+///
+/// * `lesson_ids` - 需要导出的课文ID
+/// * `path` - 课程包的保存路径，一般以 `.llpkg` 结尾
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为导出的统计
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+pub async fn export_lessons(app: AppHandle, lesson_ids: Vec<i64>, path: String) -> Result<CustomResult, CustomResult> {
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        export(&app.state::<Database>(), &app.state::<DataDir>(), &lesson_ids, Path::new(&path))
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    info!(lessons = report.lessons, words = report.words, "已导出课程包");
+
+    Ok(CustomResult::success(None, Some(json!(report))))
+}
+
+/// 导入课程包
+///
+/// # Arguments
+///
+/// * `path` - 课程包路径
+/// * `policy` - 已有同名课文时的处理方式，`skip`（默认）或 `keep_both`
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为导入的统计和冲突
+/// * 失败返回 `Err(CustomResult)`，此时不会导入任何内容
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+pub async fn import_lessons(
+    app: AppHandle,
+    path: String,
+    policy: Option<ConflictPolicy>,
+) -> Result<CustomResult, CustomResult> {
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        import(
+            &app.state::<Database>(),
+            &app.state::<DataDir>(),
+            Path::new(&path),
+            policy.unwrap_or_default(),
+        )
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    info!(
+        lessons = report.lessons.len(),
+        words = report.words,
+        conflicts = report.conflicts.len(),
+        "已导入课程包"
+    );
+
+    Ok(CustomResult::success(None, Some(json!(report))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures;
+    use std::io::Cursor;
+    use zip::write::SimpleFileOptions;
+
+    /// 只有一篇课文的清单
+    fn manifest(audio_file_name: &str) -> Manifest {
+        Manifest {
+            format_version: FORMAT_VERSION,
+            create_time: String::new(),
+            languages: vec![PackLanguage {
+                id: 1,
+                title: "English".to_string(),
+                language_text: "en-US".to_string(),
+                voice: "en-US-AriaNeural".to_string(),
+            }],
+            lessons: vec![PackLessonEntry {
+                id: 1,
+                language_id: 1,
+                title: "Lesson".to_string(),
+                dir: "lessons/1".to_string(),
+                audio_file_name: audio_file_name.to_string(),
+                audio_srt_json_name: "audio.json".to_string(),
+            }],
+        }
+    }
+
+    fn archive(manifest: Option<&Manifest>, files: &[(&str, &[u8])]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        if let Some(manifest) = manifest {
+            zip.start_file(MANIFEST_NAME, SimpleFileOptions::default()).unwrap();
+            zip.write_all(&serde_json::to_vec(manifest).unwrap()).unwrap();
+        }
+        for (name, bytes) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(bytes).unwrap();
+        }
+        ZipArchive::new(Cursor::new(zip.finish().unwrap().into_inner())).unwrap()
+    }
+
+    fn lesson_files(audio: &[u8]) -> Vec<(&'static str, &[u8])> {
+        vec![
+            ("lessons/1/lesson.json", b"{}"),
+            ("lessons/1/word_audio.json", b"[]"),
+            ("lessons/1/audio.mp3", audio),
+            ("lessons/1/audio.json", b"[]"),
+        ]
+    }
+
+    fn error_key(result: Result<Manifest, AppError>) -> (&'static str, Vec<(&'static str, String)>) {
+        match result {
+            Err(AppError::InvalidArgument(msg)) => (msg.key, msg.params),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn read_manifest_checks_names_entries_and_sizes() {
+        let files = lesson_files(b"audio");
+        assert!(read_manifest(&mut archive(Some(&manifest("audio.mp3")), &files), MAX_ENTRY_SIZE).is_ok());
+
+        let (key, _) = error_key(read_manifest(&mut archive(None, &files), MAX_ENTRY_SIZE));
+        assert_eq!(key, "package.invalid");
+
+        for name in ["", "../audio.mp3", "sub/audio.mp3", "/tmp/audio.mp3"] {
+            let (key, _) = error_key(read_manifest(&mut archive(Some(&manifest(name)), &files), MAX_ENTRY_SIZE));
+            assert_eq!(key, "package.invalid_name", "{name}");
+        }
+
+        let missing: Vec<_> = files.iter().copied().filter(|(name, _)| !name.ends_with(WORD_AUDIO_NAME)).collect();
+        let (key, params) = error_key(read_manifest(&mut archive(Some(&manifest("audio.mp3")), &missing), MAX_ENTRY_SIZE));
+        assert_eq!(key, "package.missing_entry");
+        assert_eq!(params, vec![("name", "lessons/1/word_audio.json".to_string())]);
+
+        // 上限大于清单本身，只有音频超出
+        let audio = vec![0; 4096];
+        let (key, params) =
+            error_key(read_manifest(&mut archive(Some(&manifest("audio.mp3")), &lesson_files(&audio)), 1024));
+        assert_eq!(key, "package.entry_too_large");
+        assert_eq!(params, vec![("name", "lessons/1/audio.mp3".to_string())]);
+    }
+
+    /// 在 `root` 下新建课文文件夹并写入课文和单词，单词为 (内容, `inlineId` 指向的单词在 `ids` 中的下标)
+    fn add_lesson(db: &Database, root: &Path, title: &str, words: &[(&str, Option<usize>)], ids: &mut Vec<i64>) -> i64 {
+        let (lesson_id, _) = fixtures::add_lesson(db, root, title, b"audio");
+        db.with_conn(|conn| {
+            for (index, (content, inline)) in words.iter().enumerate() {
+                let inline_id = inline.map_or(0, |i| ids[i]);
+                conn.execute(
+                    "INSERT INTO word (classId, languageId, inlineId, content, startIndex, sort)
+                     VALUES (?1, 1, ?2, ?3, 0, ?4)",
+                    params![lesson_id, inline_id, content, index as i64],
+                )?;
+                ids.push(conn.last_insert_rowid());
+            }
+            Ok(())
+        })
+        .unwrap();
+        lesson_id
+    }
+
+    #[test]
+    fn import_remaps_inline_ids() {
+        let base = fixtures::temp_dir("package-inline");
+        let source = Database::memory().unwrap();
+        let source_root = base.join("source");
+        fixtures::add_language(&source, "English", "en-US", "en-US-AriaNeural");
+        let mut ids = Vec::new();
+        // "b" 指向同一课文的 "a"，"c" 指向前一篇课文的 "b"，"d" 指向包外的单词
+        let first = add_lesson(&source, &source_root, "First", &[("a", None), ("b", Some(0))], &mut ids);
+        let second = add_lesson(&source, &source_root, "Second", &[("c", Some(1)), ("d", None)], &mut ids);
+        source
+            .with_conn(|conn| Ok(conn.execute("UPDATE word SET inlineId = 9999 WHERE content = 'd'", [])?))
+            .unwrap();
+        let path = base.join("lessons.llpkg");
+        let exported = export(&source, &DataDir::open(source_root).unwrap(), &[first, second], &path).unwrap();
+        assert_eq!((exported.lessons, exported.words), (2, 4));
+
+        // 目标库已有单词，新ID与包内ID错开
+        let target = Database::memory().unwrap();
+        let target_root = base.join("target");
+        add_lesson(&target, &target_root, "Existing", &[("x", None), ("y", None), ("z", None)], &mut Vec::new());
+        let report = import(&target, &DataDir::open(target_root.clone()).unwrap(), &path, ConflictPolicy::Skip).unwrap();
+        assert_eq!((report.lessons.len(), report.words), (2, 4));
+
+        let words: HashMap<String, (i64, i64)> = target
+            .with_conn(|conn| {
+                let mut stmt = conn.prepare("SELECT content, id, inlineId FROM word WHERE classId IN (?1, ?2)")?;
+                let rows = stmt.query_map(params![report.lessons[0], report.lessons[1]], |row| {
+                    Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+                })?;
+                Ok(rows.collect::<Result<_, _>>()?)
+            })
+            .unwrap();
+        assert_eq!(words["a"].1, 0);
+        assert_eq!(words["b"].1, words["a"].0);
+        assert_eq!(words["c"].1, words["b"].0);
+        assert_eq!(words["d"].1, 0);
+        assert_ne!(words["a"].0, ids[0]);
+
+        let folder: String = target
+            .with_conn(|conn| {
+                Ok(conn.query_row("SELECT filePath FROM `class` WHERE id = ?1", [report.lessons[0]], |row| row.get(0))?)
+            })
+            .unwrap();
+        assert!(Path::new(&folder).starts_with(&target_root));
+        assert_eq!(fs::read(Path::new(&folder).join("audio.mp3")).unwrap(), b"audio");
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    /// 导出两种 `languageText` 相同、配音员不同的语言，每种语言一篇课文，返回课程包路径
+    fn export_same_language_text(base: &Path) -> PathBuf {
+        let source = Database::memory().unwrap();
+        let source_root = base.join("source");
+        fixtures::add_language(&source, "English", "en-US", "en-US-AriaNeural");
+        let british = fixtures::add_language(&source, "英语", "en-US", "en-US-GuyNeural");
+        let first = add_lesson(&source, &source_root, "First", &[("a", None)], &mut Vec::new());
+        let second = add_lesson(&source, &source_root, "Second", &[("b", None)], &mut Vec::new());
+        source
+            .with_conn(|conn| Ok(conn.execute("UPDATE `class` SET languageId = ?1 WHERE id = ?2", [british, second])?))
+            .unwrap();
+        let path = base.join("lessons.llpkg");
+        export(&source, &DataDir::open(source_root).unwrap(), &[first, second], &path).unwrap();
+        path
+    }
+
+    /// 导入后每篇课文的 (标题, 语言ID)
+    fn lesson_languages(db: &Database, lesson_ids: &[i64]) -> Vec<(String, i64)> {
+        lesson_ids
+            .iter()
+            .map(|id| {
+                db.with_conn(|conn| {
+                    Ok(conn.query_row("SELECT title, languageId FROM `class` WHERE id = ?1", [id], |row| {
+                        Ok((row.get(0)?, row.get(1)?))
+                    })?)
+                })
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn import_merges_languages_by_language_text() {
+        let base = fixtures::temp_dir("package-languages");
+        let path = export_same_language_text(&base);
+
+        // 包内两种语言的 `languageText` 相同，只新建一种，第二种并入第一种
+        let target = Database::memory().unwrap();
+        let target_root = base.join("target");
+        let report = import(&target, &DataDir::open(target_root.clone()).unwrap(), &path, ConflictPolicy::Skip).unwrap();
+        assert_eq!(report.languages.len(), 1);
+        assert_eq!(report.conflicts.len(), 1);
+        assert!(matches!(
+            &report.conflicts[0],
+            ImportConflict::LanguageMerged { title, voice, existing_voice, .. }
+                if title == "英语" && voice == "en-US-GuyNeural" && existing_voice == "en-US-AriaNeural"
+        ));
+        let language_id = report.languages[0];
+        assert_eq!(
+            lesson_languages(&target, &report.lessons),
+            vec![("First".to_string(), language_id), ("Second".to_string(), language_id)]
+        );
+
+        // 已有相同 `languageText` 的语言时两种都并入已有语言，保留已有的配音员
+        let target = Database::memory().unwrap();
+        let target_root = base.join("existing");
+        let existing = fixtures::add_language(&target, "美式英语", "en-US", "en-US-JennyNeural");
+        let report = import(&target, &DataDir::open(target_root.clone()).unwrap(), &path, ConflictPolicy::Skip).unwrap();
+        assert!(report.languages.is_empty());
+        assert_eq!(report.conflicts.len(), 2);
+        assert!(report.conflicts.iter().all(|c| matches!(
+            c,
+            ImportConflict::LanguageMerged { existing_voice, .. } if existing_voice == "en-US-JennyNeural"
+        )));
+        assert!(lesson_languages(&target, &report.lessons).iter().all(|(_, id)| *id == existing));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn existing_lessons_are_skipped_or_kept_by_policy() {
+        let base = fixtures::temp_dir("package-policy");
+        let path = export_same_language_text(&base);
+        let existing_target = |name: &str| {
+            let db = Database::memory().unwrap();
+            let root = base.join(name);
+            fixtures::add_language(&db, "English", "en-US", "en-US-AriaNeural");
+            add_lesson(&db, &root, "First", &[("x", None)], &mut Vec::new());
+            (db, DataDir::open(root).unwrap())
+        };
+        let lesson_exists = |report: &ImportReport| -> Vec<(String, bool)> {
+            report
+                .conflicts
+                .iter()
+                .filter_map(|c| match c {
+                    ImportConflict::LessonExists { title, skipped } => Some((title.clone(), *skipped)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let (db, data_dir) = existing_target("skip");
+        let report = import(&db, &data_dir, &path, ConflictPolicy::Skip).unwrap();
+        assert_eq!(lesson_exists(&report), vec![("First".to_string(), true)]);
+        assert_eq!(lesson_languages(&db, &report.lessons), vec![("Second".to_string(), 1)]);
+        assert_eq!(report.words, 1);
+
+        let (db, data_dir) = existing_target("keep-both");
+        let report = import(&db, &data_dir, &path, ConflictPolicy::KeepBoth).unwrap();
+        assert_eq!(lesson_exists(&report), vec![("First".to_string(), false)]);
+        assert_eq!(
+            lesson_languages(&db, &report.lessons),
+            vec![("First".to_string(), 1), ("Second".to_string(), 1)]
+        );
+        let titles: i64 = db
+            .with_conn(|conn| Ok(conn.query_row("SELECT COUNT(*) FROM `class` WHERE title = 'First'", [], |row| row.get(0))?))
+            .unwrap();
+        assert_eq!(titles, 2);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
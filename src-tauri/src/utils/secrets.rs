@@ -0,0 +1,819 @@
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use crate::utils::storage;
+use argon2::Argon2;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Local;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tauri::State;
+use tracing::{info, warn};
+
+/// 加密文件名
+const VAULT_FILE_NAME: &str = "secrets.json";
+/// 本地密钥文件名，未设置口令时用它加密
+const KEY_FILE_NAME: &str = "secrets.key";
+/// 设为 `memory` 时使用内存后端，密钥不落盘，供无桌面环境的CI测试使用
+const BACKEND_ENV: &str = "LEARN_LANGUAGE_SECRETS";
+/// 用于校验口令是否正确的明文
+const CHECK_PLAINTEXT: &[u8] = b"learn-language";
+const VAULT_VERSION: u32 = 1;
+/// 旧版本在 `option` 表中明文保存密钥的列，列名即平台名称
+const LEGACY_KEY_COLUMNS: &[&str] = &["ChatGLM", "DeepSeek", "Groq", "Google", "ChatGPT"];
+
+/// 密钥存储后端
+pub trait SecretBackend: Send + Sync {
+    fn get(&self, provider: &str) -> Result<Option<String>, AppError>;
+    fn set(&mut self, provider: &str, secret: &str) -> Result<(), AppError>;
+    fn delete(&mut self, provider: &str) -> Result<(), AppError>;
+    /// 已保存密钥的平台名称，不返回密钥本身
+    fn providers(&self) -> Vec<String>;
+}
+
+/// 内存后端，程序退出后密钥即丢失
+#[derive(Default)]
+pub struct MemoryBackend {
+    secrets: HashMap<String, String>,
+}
+
+impl SecretBackend for MemoryBackend {
+    fn get(&self, provider: &str) -> Result<Option<String>, AppError> {
+        Ok(self.secrets.get(provider).cloned())
+    }
+
+    fn set(&mut self, provider: &str, secret: &str) -> Result<(), AppError> {
+        self.secrets.insert(provider.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&mut self, provider: &str) -> Result<(), AppError> {
+        self.secrets.remove(provider);
+        Ok(())
+    }
+
+    fn providers(&self) -> Vec<String> {
+        let mut providers: Vec<String> = self.secrets.keys().cloned().collect();
+        providers.sort();
+        providers
+    }
+}
+
+/// 加密密钥的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum KdfKind {
+    /// 随机生成并保存在本地的密钥文件
+    KeyFile,
+    /// 由用户口令通过 Argon2 派生
+    Passphrase,
+}
+
+/// 一条加密数据，`nonce` 和 `data` 均为 base64
+#[derive(Clone, Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    kdf: KdfKind,
+    /// 口令派生时使用的盐，base64
+    salt: Option<String>,
+    /// 加密后的 `CHECK_PLAINTEXT`，用于校验口令
+    check: Sealed,
+    entries: BTreeMap<String, Sealed>,
+}
+
+/// 加密文件后端
+///
+/// 每个平台的密钥用 ChaCha20-Poly1305 单独加密，平台名称作为附加数据，防止密文被挪用到其他平台。
+pub struct VaultBackend {
+    path: PathBuf,
+    cipher: ChaCha20Poly1305,
+    file: VaultFile,
+}
+
+impl VaultBackend {
+    /// 使用本地密钥文件打开加密文件，文件不存在时创建
+    fn open_with_key_file(dir: &Path) -> Result<Self, AppError> {
+        let path = dir.join(VAULT_FILE_NAME);
+        let key = load_or_create_key_file(&dir.join(KEY_FILE_NAME))?;
+        match read_vault_file(&path)? {
+            Some(file) => Self::open(path, file, KdfKind::KeyFile, key),
+            None => {
+                let vault = Self::create(path, KdfKind::KeyFile, key, None)?;
+                write_vault_file(&vault.path, &vault.file)?;
+                Ok(vault)
+            }
+        }
+    }
+
+    /// 使用口令打开加密文件，文件不存在时创建
+    fn open_with_passphrase(dir: &Path, passphrase: &str) -> Result<Self, AppError> {
+        let path = dir.join(VAULT_FILE_NAME);
+        match read_vault_file(&path)? {
+            Some(file) => {
+                let salt = decode(file.salt.as_deref().unwrap_or_default())?;
+                let key = derive_key(passphrase, &salt)?;
+                Self::open(path, file, KdfKind::Passphrase, key)
+            }
+            None => {
+                let vault = Self::create_with_passphrase(path, passphrase)?;
+                write_vault_file(&vault.path, &vault.file)?;
+                Ok(vault)
+            }
+        }
+    }
+
+    /// 校验密钥能否解开已有的加密文件
+    fn open(path: PathBuf, file: VaultFile, kdf: KdfKind, key: [u8; 32]) -> Result<Self, AppError> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        if file.kdf != kdf || open_sealed(&cipher, "", &file.check).is_err() {
+            return Err(AppError::InvalidArgument(Msg::new("secret.wrong_passphrase")));
+        }
+        Ok(Self { path, cipher, file })
+    }
+
+    /// 在内存中创建一个空的加密文件，调用方负责写入磁盘
+    fn create(
+        path: PathBuf,
+        kdf: KdfKind,
+        key: [u8; 32],
+        salt: Option<Vec<u8>>,
+    ) -> Result<Self, AppError> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            kdf,
+            salt: salt.map(|s| general_purpose::STANDARD.encode(s)),
+            check: seal(&cipher, "", CHECK_PLAINTEXT)?,
+            entries: BTreeMap::new(),
+        };
+        Ok(Self { path, cipher, file })
+    }
+
+    fn create_with_passphrase(path: PathBuf, passphrase: &str) -> Result<Self, AppError> {
+        let salt = random_bytes::<16>().to_vec();
+        let key = derive_key(passphrase, &salt)?;
+        Self::create(path, KdfKind::Passphrase, key, Some(salt))
+    }
+}
+
+impl SecretBackend for VaultBackend {
+    fn get(&self, provider: &str) -> Result<Option<String>, AppError> {
+        match self.file.entries.get(provider) {
+            Some(sealed) => {
+                let plain = open_sealed(&self.cipher, provider, sealed)?;
+                let secret = String::from_utf8(plain)
+                    .map_err(|e| AppError::Parse(Msg::new("error.parse").arg("detail", e)))?;
+                Ok(Some(secret))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn set(&mut self, provider: &str, secret: &str) -> Result<(), AppError> {
+        let sealed = seal(&self.cipher, provider, secret.as_bytes())?;
+        self.file.entries.insert(provider.to_string(), sealed);
+        write_vault_file(&self.path, &self.file)
+    }
+
+    fn delete(&mut self, provider: &str) -> Result<(), AppError> {
+        if self.file.entries.remove(provider).is_some() {
+            write_vault_file(&self.path, &self.file)?;
+        }
+        Ok(())
+    }
+
+    fn providers(&self) -> Vec<String> {
+        self.file.entries.keys().cloned().collect()
+    }
+}
+
+/// AI平台密钥的存储，作为 tauri 的托管状态使用
+///
+/// 密钥只在Rust端读取，前端只能写入、删除和查看哪些平台已保存密钥。
+pub struct SecretStore {
+    /// 加密文件所在目录，内存后端时为 `None`
+    dir: Option<PathBuf>,
+    /// 使用口令加密且尚未解锁时为 `None`
+    backend: RwLock<Option<Box<dyn SecretBackend>>>,
+    /// 启动时打开加密文件失败的原因，解锁成功后清除
+    open_error: RwLock<Option<Msg>>,
+}
+
+impl SecretStore {
+    /// 打开密钥存储
+    ///
+    /// 环境变量 `LEARN_LANGUAGE_SECRETS=memory` 时使用内存后端；
+    /// 否则使用 `dir` 下的加密文件，如果加密文件设置了口令，需要先调用 `unlock` 解锁。
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - 加密文件和密钥文件所在目录，一般为 app 的数据目录
+    pub fn open(dir: PathBuf) -> Result<Self, AppError> {
+        if std::env::var(BACKEND_ENV).is_ok_and(|v| v == "memory") {
+            info!("使用内存密钥存储");
+            return Ok(Self::memory());
+        }
+
+        fs::create_dir_all(&dir)?;
+        let backend: Option<Box<dyn SecretBackend>> =
+            match read_vault_file(&dir.join(VAULT_FILE_NAME))? {
+                Some(file) if file.kdf == KdfKind::Passphrase => None,
+                _ => Some(Box::new(VaultBackend::open_with_key_file(&dir)?)),
+            };
+
+        Ok(Self {
+            dir: Some(dir),
+            backend: RwLock::new(backend),
+            open_error: RwLock::new(None),
+        })
+    }
+
+    /// 打开加密文件失败时使用的存储，处于锁定状态，失败原因通过 `open_error` 告知前端
+    ///
+    /// 加密文件损坏不应导致程序无法启动，用户仍然可以使用不需要密钥的功能。
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - 加密文件所在目录
+    /// * `error` - 打开失败的原因
+    pub fn locked(dir: PathBuf, error: &AppError) -> Self {
+        Self {
+            dir: Some(dir.clone()),
+            backend: RwLock::new(None),
+            open_error: RwLock::new(Some(
+                Msg::new("secret.open_failed")
+                    .arg("path", dir.join(VAULT_FILE_NAME).display())
+                    .arg("error", error),
+            )),
+        }
+    }
+
+    /// 仅在内存中保存密钥的存储
+    pub fn memory() -> Self {
+        Self {
+            dir: None,
+            backend: RwLock::new(Some(Box::new(MemoryBackend::default()))),
+            open_error: RwLock::new(None),
+        }
+    }
+
+    /// 启动时打开加密文件失败的原因
+    pub fn open_error(&self) -> Option<Msg> {
+        self.open_error.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.backend
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none()
+    }
+
+    /// 读取平台密钥
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回密钥
+    /// * 存储被锁定或该平台没有保存密钥时返回 `Err(AppError)`
+    pub fn get(&self, provider: &str) -> Result<String, AppError> {
+        let backend = self.backend.read().unwrap_or_else(|e| e.into_inner());
+        let backend = backend
+            .as_ref()
+            .ok_or_else(|| AppError::InvalidArgument(Msg::new("secret.locked")))?;
+        backend.get(provider)?.ok_or_else(|| {
+            AppError::InvalidArgument(Msg::new("secret.missing_key").arg("provider", provider))
+        })
+    }
+
+    pub fn set(&self, provider: &str, secret: &str) -> Result<(), AppError> {
+        self.with_backend(|backend| backend.set(provider, secret))
+    }
+
+    pub fn delete(&self, provider: &str) -> Result<(), AppError> {
+        self.with_backend(|backend| backend.delete(provider))
+    }
+
+    pub fn providers(&self) -> Result<Vec<String>, AppError> {
+        self.with_backend(|backend| Ok(backend.providers()))
+    }
+
+    /// 用口令解锁加密文件
+    pub fn unlock(&self, passphrase: &str) -> Result<(), AppError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let mut backend = self.backend.write().unwrap_or_else(|e| e.into_inner());
+        *backend = Some(Box::new(VaultBackend::open_with_passphrase(dir, passphrase)?));
+        *self.open_error.write().unwrap_or_else(|e| e.into_inner()) = None;
+        Ok(())
+    }
+
+    /// 设置或取消口令，`None` 表示改回本地密钥文件加密
+    ///
+    /// 新的加密文件先在内存中生成，最后一次性替换磁盘上的文件，中途失败不会丢失已有密钥。
+    pub fn change_passphrase(&self, passphrase: Option<&str>) -> Result<(), AppError> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let mut backend = self.backend.write().unwrap_or_else(|e| e.into_inner());
+        let current = backend
+            .as_ref()
+            .ok_or_else(|| AppError::InvalidArgument(Msg::new("secret.locked")))?;
+
+        let path = dir.join(VAULT_FILE_NAME);
+        let mut vault = match passphrase {
+            Some(passphrase) => VaultBackend::create_with_passphrase(path, passphrase)?,
+            None => {
+                let key = load_or_create_key_file(&dir.join(KEY_FILE_NAME))?;
+                VaultBackend::create(path, KdfKind::KeyFile, key, None)?
+            }
+        };
+        for provider in current.providers() {
+            if let Some(secret) = current.get(&provider)? {
+                let sealed = seal(&vault.cipher, &provider, secret.as_bytes())?;
+                vault.file.entries.insert(provider, sealed);
+            }
+        }
+        write_vault_file(&vault.path, &vault.file)?;
+
+        *backend = Some(Box::new(vault));
+        Ok(())
+    }
+
+    /// 重置密钥存储，改用新的本地密钥文件和空的加密文件
+    ///
+    /// 用于加密文件损坏或忘记口令、无法解锁时恢复，已保存的密钥需要重新输入。
+    /// 原来的加密文件和密钥文件改名为 `{文件名}.bak-{时间}` 保留在原目录，不会删除。
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回备份文件的路径
+    /// * 备份或创建失败返回 `Err(AppError)`
+    pub fn reset(&self) -> Result<Vec<PathBuf>, AppError> {
+        let mut backend = self.backend.write().unwrap_or_else(|e| e.into_inner());
+        let Some(dir) = &self.dir else {
+            *backend = Some(Box::new(MemoryBackend::default()));
+            return Ok(Vec::new());
+        };
+
+        let suffix = format!("bak-{}", Local::now().format("%Y%m%d-%H%M%S"));
+        let mut backups = Vec::new();
+        for name in [VAULT_FILE_NAME, KEY_FILE_NAME] {
+            let path = dir.join(name);
+            if path.exists() {
+                let backup = dir.join(format!("{}.{}", name, suffix));
+                fs::rename(&path, &backup)?;
+                backups.push(backup);
+            }
+        }
+        *backend = Some(Box::new(VaultBackend::open_with_key_file(dir)?));
+        *self.open_error.write().unwrap_or_else(|e| e.into_inner()) = None;
+        warn!(?backups, "已重置密钥存储");
+        Ok(backups)
+    }
+
+    /// 把旧版本明文保存在 `option` 表中的密钥移入加密存储，并删除明文
+    ///
+    /// 旧版本每个平台的设置以 `{model, apiKey, proxy}` 的JSON保存在 `option` 表的同名列中。
+    /// 先写入加密存储再删除明文，中途失败下次启动时会重新执行；加密存储中已有密钥的平台不会被旧密钥覆盖。
+    /// 存储被锁定时不做任何事，解锁后再执行。
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - 数据库
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回移入的密钥数量
+    /// * 读写失败返回 `Err(AppError)`
+    pub fn migrate_legacy_keys(&self, db: &Database) -> Result<usize, AppError> {
+        if self.is_locked() {
+            return Ok(0);
+        }
+
+        let existing = self.providers()?;
+        let mut migrated = 0;
+        for column in LEGACY_KEY_COLUMNS {
+            let rows: Vec<(i64, String)> = db.with_conn(|conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT id, {column} FROM option WHERE {column} LIKE '%apiKey%'"
+                ))?;
+                let rows = stmt
+                    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<Result<_, _>>()?;
+                Ok(rows)
+            })?;
+
+            for (id, text) in rows {
+                let Ok(serde_json::Value::Object(mut settings)) = serde_json::from_str(&text) else {
+                    warn!(provider = column, "旧的平台设置无法解析，跳过");
+                    continue;
+                };
+                let key = settings.remove("apiKey");
+                if let Some(key) = key.as_ref().and_then(|k| k.as_str()).filter(|k| !k.is_empty()) {
+                    if !existing.iter().any(|p| p == column) {
+                        self.set(column, key)?;
+                        migrated += 1;
+                    }
+                }
+                let text = serde_json::to_string(&settings)?;
+                db.with_conn(|conn| {
+                    conn.execute(
+                        &format!("UPDATE option SET {column} = ?1 WHERE id = ?2"),
+                        rusqlite::params![text, id],
+                    )?;
+                    Ok(())
+                })?;
+            }
+        }
+
+        if migrated > 0 {
+            info!(migrated, "已将旧的明文密钥移入加密存储");
+        }
+        Ok(migrated)
+    }
+
+    fn with_backend<T>(
+        &self,
+        f: impl FnOnce(&mut dyn SecretBackend) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let mut backend = self.backend.write().unwrap_or_else(|e| e.into_inner());
+        let backend = backend
+            .as_mut()
+            .ok_or_else(|| AppError::InvalidArgument(Msg::new("secret.locked")))?;
+        f(backend.as_mut())
+    }
+}
+
+fn read_vault_file(path: &Path) -> Result<Option<VaultFile>, AppError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&text)?))
+}
+
+/// 先写临时文件再重命名，避免写入一半时程序退出导致文件损坏
+fn write_vault_file(path: &Path, file: &VaultFile) -> Result<(), AppError> {
+    let text = serde_json::to_string_pretty(file)?;
+    storage::write_atomic(path, |temp| Ok(fs::write(temp, text)?))
+}
+
+fn load_or_create_key_file(path: &Path) -> Result<[u8; 32], AppError> {
+    if path.exists() {
+        let bytes = fs::read(path)?;
+        return bytes
+            .try_into()
+            .map_err(|_| AppError::Parse(Msg::new("secret.invalid_key_file")));
+    }
+
+    let key = random_bytes::<32>();
+    // 创建时就限制权限，避免写入后、修改权限前被其他用户读取
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(&key)?;
+    Ok(key)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], AppError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| AppError::InvalidArgument(Msg::new("secret.crypto_failed").arg("error", e)))?;
+    Ok(key)
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn seal(cipher: &ChaCha20Poly1305, aad: &str, plain: &[u8]) -> Result<Sealed, AppError> {
+    let nonce = random_bytes::<12>();
+    let data = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plain,
+                aad: aad.as_bytes(),
+            },
+        )
+        .map_err(|e| AppError::InvalidArgument(Msg::new("secret.crypto_failed").arg("error", e)))?;
+    Ok(Sealed {
+        nonce: general_purpose::STANDARD.encode(nonce),
+        data: general_purpose::STANDARD.encode(data),
+    })
+}
+
+fn open_sealed(cipher: &ChaCha20Poly1305, aad: &str, sealed: &Sealed) -> Result<Vec<u8>, AppError> {
+    let nonce = decode(&sealed.nonce)?;
+    let data = decode(&sealed.data)?;
+    if nonce.len() != 12 {
+        return Err(AppError::Parse(Msg::new("secret.crypto_failed").arg("error", "nonce")));
+    }
+    cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &data,
+                aad: aad.as_bytes(),
+            },
+        )
+        .map_err(|e| AppError::InvalidArgument(Msg::new("secret.crypto_failed").arg("error", e)))
+}
+
+fn decode(text: &str) -> Result<Vec<u8>, AppError> {
+    general_purpose::STANDARD
+        .decode(text)
+        .map_err(|e| AppError::Parse(Msg::new("error.parse").arg("detail", e)))
+}
+
+/// 保存AI平台的密钥
+///
+/// # Arguments
+///
+/// * `provider` - 平台名称，例如 `ChatGLM`、`Google`
+/// * `api_key` - 密钥
+///
+/// # Returns
+///
+/// * 保存成功返回 `Ok(CustomResult::success)`
+/// * 存储被锁定或写入失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(store, api_key))]
+pub async fn set_provider_key(
+    store: State<'_, SecretStore>,
+    provider: String,
+    api_key: String,
+) -> Result<CustomResult, CustomResult> {
+    if provider.is_empty() || api_key.is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("secret.empty_key")).into());
+    }
+    store.set(&provider, &api_key)?;
+    Ok(CustomResult::success(None, None))
+}
+
+/// 删除AI平台的密钥
+#[tauri::command]
+#[tracing::instrument(skip(store))]
+pub async fn delete_provider_key(
+    store: State<'_, SecretStore>,
+    provider: String,
+) -> Result<CustomResult, CustomResult> {
+    store.delete(&provider)?;
+    Ok(CustomResult::success(None, None))
+}
+
+/// 查询密钥存储状态
+///
+/// # Returns
+///
+/// * `data.locked` - 是否需要先输入口令解锁
+/// * `data.providers` - 已保存密钥的平台名称（不包含密钥本身）
+/// * `data.error` - 启动时打开加密文件失败的原因，没有失败时为 `null`
+#[tauri::command]
+pub async fn get_secret_status(store: State<'_, SecretStore>) -> Result<CustomResult, CustomResult> {
+    let locked = store.is_locked();
+    let providers = if locked { Vec::new() } else { store.providers()? };
+    let error = store.open_error().map(|msg| msg.render());
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"locked": locked, "providers": providers, "error": error})),
+    ))
+}
+
+/// 使用口令解锁密钥存储
+///
+/// 解锁后会把旧版本明文保存的密钥移入加密存储
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn unlock_secrets(
+    store: State<'_, SecretStore>,
+    db: State<'_, Database>,
+    passphrase: String,
+) -> Result<CustomResult, CustomResult> {
+    store.unlock(&passphrase)?;
+    store.migrate_legacy_keys(&db)?;
+    Ok(CustomResult::success(None, None))
+}
+
+/// 设置或取消密钥存储的口令
+///
+/// # Arguments
+///
+/// * `passphrase` - 新口令，为空时改回本地密钥文件加密
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn set_secret_passphrase(
+    store: State<'_, SecretStore>,
+    passphrase: Option<String>,
+) -> Result<CustomResult, CustomResult> {
+    let passphrase = passphrase.filter(|p| !p.is_empty());
+    store.change_passphrase(passphrase.as_deref())?;
+    Ok(CustomResult::success(None, None))
+}
+
+/// 重置密钥存储
+///
+/// 加密文件损坏或忘记口令时使用，原来的文件改名备份，已保存的密钥需要重新输入
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.backups` 为备份文件的路径
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip_all)]
+pub async fn reset_secrets(store: State<'_, SecretStore>) -> Result<CustomResult, CustomResult> {
+    let backups: Vec<String> = store.reset()?.iter().map(|p| p.display().to_string()).collect();
+    Ok(CustomResult::success(None, Some(json!({"backups": backups}))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_legacy_keys_moves_keys_into_store() {
+        let db = Database::memory().unwrap();
+        db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE option SET ChatGLM = ?1, Google = ?2, Groq = 'null' WHERE id = 1",
+                [
+                    r#"{"model":"glm-4-plus","apiKey":"glm-secret","proxy":""}"#,
+                    r#"{"model":"gemini-2.0-flash","apiKey":"old-google","proxy":""}"#,
+                ],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let store = SecretStore::memory();
+        store.set("Google", "new-google").unwrap();
+
+        assert_eq!(store.migrate_legacy_keys(&db).unwrap(), 1);
+        assert_eq!(store.get("ChatGLM").unwrap(), "glm-secret");
+        // 加密存储中已有的密钥不会被旧密钥覆盖
+        assert_eq!(store.get("Google").unwrap(), "new-google");
+
+        let (glm, google): (String, String) = db
+            .with_conn(|conn| {
+                Ok(conn.query_row("SELECT ChatGLM, Google FROM option WHERE id = 1", [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?)
+            })
+            .unwrap();
+        assert_eq!(glm, r#"{"model":"glm-4-plus","proxy":""}"#);
+        assert!(!google.contains("apiKey"));
+
+        // 再次执行没有需要移动的密钥
+        assert_eq!(store.migrate_legacy_keys(&db).unwrap(), 0);
+    }
+
+    #[test]
+    fn locked_store_reports_open_error() {
+        let dir = std::env::temp_dir().join(format!("learn-language-secrets-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(VAULT_FILE_NAME), "not json").unwrap();
+
+        let error = SecretStore::open(dir.clone()).err().unwrap();
+        let store = SecretStore::locked(dir.clone(), &error);
+        assert!(store.is_locked());
+        assert!(store.open_error().is_some());
+        assert!(store.get("ChatGLM").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("learn-language-secrets-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn error_key<T: std::fmt::Debug>(result: Result<T, AppError>) -> &'static str {
+        result.unwrap_err().message().key
+    }
+
+    #[test]
+    fn sealed_data_opens_only_with_the_same_key_and_provider() {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&random_bytes::<32>()));
+        let sealed = seal(&cipher, "ChatGPT", b"sk-test").unwrap();
+        assert_eq!(open_sealed(&cipher, "ChatGPT", &sealed).unwrap(), b"sk-test");
+        // 相同明文每次加密的结果不同
+        assert_ne!(seal(&cipher, "ChatGPT", b"sk-test").unwrap().data, sealed.data);
+
+        assert!(open_sealed(&cipher, "Google", &sealed).is_err());
+        let other = ChaCha20Poly1305::new(Key::from_slice(&random_bytes::<32>()));
+        assert!(open_sealed(&other, "ChatGPT", &sealed).is_err());
+    }
+
+    #[test]
+    fn swapped_ciphertext_fails_to_open() {
+        let dir = temp_dir();
+        let store = SecretStore::open(dir.clone()).unwrap();
+        store.set("ChatGPT", "sk-openai").unwrap();
+        store.set("Google", "AIza-google").unwrap();
+
+        let path = dir.join(VAULT_FILE_NAME);
+        let mut file = read_vault_file(&path).unwrap().unwrap();
+        let chatgpt = file.entries["ChatGPT"].clone();
+        let google = file.entries["Google"].clone();
+        file.entries.insert("ChatGPT".to_string(), google);
+        file.entries.insert("Google".to_string(), chatgpt);
+        write_vault_file(&path, &file).unwrap();
+
+        let store = SecretStore::open(dir.clone()).unwrap();
+        assert_eq!(error_key(store.get("ChatGPT")), "secret.crypto_failed");
+        assert_eq!(error_key(store.get("Google")), "secret.crypto_failed");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn change_passphrase_re_encrypts_and_requires_unlock() {
+        let dir = temp_dir();
+        let path = dir.join(VAULT_FILE_NAME);
+        let store = SecretStore::open(dir.clone()).unwrap();
+        store.set("ChatGPT", "sk-openai").unwrap();
+        let before = read_vault_file(&path).unwrap().unwrap();
+
+        store.change_passphrase(Some("correct horse")).unwrap();
+        let after = read_vault_file(&path).unwrap().unwrap();
+        assert_eq!(after.kdf, KdfKind::Passphrase);
+        assert!(after.salt.is_some());
+        assert_ne!(after.entries["ChatGPT"].data, before.entries["ChatGPT"].data);
+        assert_eq!(store.get("ChatGPT").unwrap(), "sk-openai");
+
+        let reopened = SecretStore::open(dir.clone()).unwrap();
+        assert!(reopened.is_locked());
+        assert_eq!(error_key(reopened.get("ChatGPT")), "secret.locked");
+        assert_eq!(error_key(reopened.unlock("wrong")), "secret.wrong_passphrase");
+        assert!(reopened.is_locked());
+        reopened.unlock("correct horse").unwrap();
+        assert_eq!(reopened.get("ChatGPT").unwrap(), "sk-openai");
+
+        // 取消口令后改回密钥文件，不需要解锁
+        reopened.change_passphrase(None).unwrap();
+        assert_eq!(read_vault_file(&path).unwrap().unwrap().kdf, KdfKind::KeyFile);
+        let reopened = SecretStore::open(dir.clone()).unwrap();
+        assert_eq!(reopened.get("ChatGPT").unwrap(), "sk-openai");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reset_backs_up_corrupt_vault_and_unlocks() {
+        let dir = temp_dir();
+        fs::write(dir.join(VAULT_FILE_NAME), "not json").unwrap();
+        let error = SecretStore::open(dir.clone()).err().unwrap();
+        let store = SecretStore::locked(dir.clone(), &error);
+
+        let backups = store.reset().unwrap();
+        assert!(!store.is_locked());
+        assert!(store.open_error().is_none());
+        assert!(store.providers().unwrap().is_empty());
+        let vault_backup = backups.iter().find(|p| p.to_string_lossy().contains(VAULT_FILE_NAME)).unwrap();
+        assert_eq!(fs::read_to_string(vault_backup).unwrap(), "not json");
+
+        store.set("ChatGPT", "sk-new").unwrap();
+        assert_eq!(SecretStore::open(dir.clone()).unwrap().get("ChatGPT").unwrap(), "sk-new");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("learn-language-key-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(KEY_FILE_NAME);
+        let key = load_or_create_key_file(&path).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(load_or_create_key_file(&path).unwrap(), key);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,833 @@
+use crate::utils::audio_cache;
+use crate::utils::custom_result::CustomResult;
+use crate::utils::db::Database;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use chrono::Local;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tauri_plugin_fs::FsExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+/// `appSetting` 中保存数据目录的键
+const ROOT_KEY: &str = "storage.dataRoot";
+
+/// 更换数据目录时通知前端进度的事件
+pub const PROGRESS_EVENT: &str = "data-root-progress";
+/// 两次进度事件之间的最短间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 数据目录下存放回收站的文件夹
+pub(crate) const TRASH_DIR_NAME: &str = ".trash";
+/// 回收站条目中被删除的文件或文件夹
+const TRASH_CONTENT_NAME: &str = "content";
+/// 回收站条目中记录原路径的文件
+const TRASH_INFO_NAME: &str = "info.json";
+
+/// 课文音频、字幕等文件所在的数据目录
+///
+/// 前端只能删除这个目录下的内容，删除的内容可以先放进目录下的回收站。
+/// 目录可以通过 `move_data_root` 更换，更换期间其他读写数据目录的操作会等待。
+pub struct DataDir {
+    root: RwLock<PathBuf>,
+}
+
+/// 更换数据目录的结果
+#[derive(Debug, Serialize)]
+pub struct MoveReport {
+    pub from: String,
+    pub to: String,
+    /// 复制并校验过的文件数
+    pub files: usize,
+    pub bytes: u64,
+    /// 改写了 `filePath` 的课文数
+    pub lessons: usize,
+    /// 旧目录中复制过的条目是否已删除，删除失败时数据已经在新目录中，旧目录需要手动清理
+    pub old_removed: bool,
+    /// 旧目录中不属于程序、保留在原处的条目数，为0时旧目录已一起删除
+    pub kept: usize,
+}
+
+/// 更换数据目录的进度，通过 `data-root-progress` 事件通知前端
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MoveProgress {
+    /// 已复制并校验的文件数
+    pub files: usize,
+    pub bytes: u64,
+    pub total_files: usize,
+    pub total_bytes: u64,
+}
+
+/// 回收站中一个条目的信息
+#[derive(Debug, Serialize, Deserialize)]
+struct TrashInfo {
+    /// 相对数据目录的原路径
+    original: PathBuf,
+    delete_time: String,
+}
+
+impl DataDir {
+    /// 打开数据目录，不存在时创建
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - 数据目录的绝对路径
+    pub fn open(root: PathBuf) -> Result<Self, AppError> {
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root: RwLock::new(root),
+        })
+    }
+
+    /// 按设置打开数据目录
+    ///
+    /// 没有设置过时，旧版本保存在资源目录下的数据仍在原处使用，否则使用 `default`
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - 数据库
+    /// * `default` - 默认的数据目录，一般为 app 数据目录下的 `datas`
+    /// * `legacy` - 旧版本使用的数据目录，即资源目录下的 `datas`
+    pub fn load(db: &Database, default: PathBuf, legacy: PathBuf) -> Result<Self, AppError> {
+        if let Some(root) = db.get_setting(ROOT_KEY)? {
+            return Self::open(PathBuf::from(root));
+        }
+        let has_legacy_data = fs::read_dir(&legacy).is_ok_and(|mut entries| entries.next().is_some());
+        if has_legacy_data {
+            info!(root = %legacy.display(), "沿用旧版本的数据目录");
+            return Self::open(legacy);
+        }
+        Self::open(default)
+    }
+
+    /// 当前的数据目录，持有期间不会被更换
+    pub fn root(&self) -> RwLockReadGuard<'_, PathBuf> {
+        self.root.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 当前的数据目录，正在更换数据目录或恢复备份时不等待，直接返回错误
+    ///
+    /// 异步命令中使用，等待写锁会占住异步运行时的线程
+    pub fn try_root(&self) -> Result<RwLockReadGuard<'_, PathBuf>, AppError> {
+        match self.root.try_read() {
+            Ok(root) => Ok(root),
+            Err(TryLockError::Poisoned(e)) => Ok(e.into_inner()),
+            Err(TryLockError::WouldBlock) => Err(AppError::Io(Msg::new("storage.busy"))),
+        }
+    }
+
+    /// 持有数据目录期间读写数据库，`f` 中可以多次使用数据库，数据目录不会被更换
+    ///
+    /// 先锁数据目录再锁数据库，与更换数据目录和恢复备份的顺序一致，同时读写两者的操作都通过这里，避免死锁。
+    /// 会等待正在进行的更换，需要在 `spawn_blocking` 中调用
+    pub fn with_root_and_db<T>(
+        &self,
+        db: &Database,
+        f: impl FnOnce(&Path, &Database) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let root = self.root();
+        f(&root, db)
+    }
+
+    /// 独占数据目录，持有期间其他读写数据目录的操作会等待，用于更换数据目录和恢复备份
+    pub fn lock(&self) -> RwLockWriteGuard<'_, PathBuf> {
+        self.root.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 删除数据目录内的文件或文件夹，参数和返回值见 `delete_in`，正在更换数据目录时返回错误
+    pub fn delete(&self, path: &Path, trash: bool) -> Result<(PathBuf, Option<String>), AppError> {
+        delete_in(&self.try_root()?, path, trash)
+    }
+
+    /// 把回收站中的条目恢复到原位置，参数和返回值见 `restore_in`，正在更换数据目录时返回错误
+    pub fn restore(&self, id: &str) -> Result<PathBuf, AppError> {
+        restore_in(&self.try_root()?, id)
+    }
+
+    /// 更换数据目录
+    ///
+    /// 只处理程序创建的条目（见 `owned_entries`），数据目录中的其他文件保留在原处。先把这些条目复制到新目录，
+    /// 逐个文件比较SHA-256；全部一致后在一个事务中改写课文的 `filePath` 并保存设置，最后才从旧目录中删除它们，
+    /// 旧目录删空后一起删除。复制、校验或改写失败时删除已复制的内容，旧目录和数据库保持不变。
+    ///
+    /// 整个过程持有数据目录的写锁并读写大量文件，需要在 `spawn_blocking` 中调用
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - 数据库
+    /// * `target` - 新的数据目录，必须是绝对路径，不能在旧目录内；已有内容时只能是程序创建的条目，且不能与要复制的重名
+    /// * `on_progress` - 每复制并校验完一个文件调用一次
+    ///
+    /// # Returns
+    ///
+    /// * 成功返回复制和改写的统计
+    /// * 参数不合法或复制、校验、改写失败时返回 `Err(AppError)`
+    pub fn move_to(
+        &self,
+        db: &Database,
+        target: &Path,
+        mut on_progress: impl FnMut(&MoveProgress),
+    ) -> Result<MoveReport, AppError> {
+        if !target.is_absolute() {
+            return Err(AppError::InvalidArgument(
+                Msg::new("storage.relative_path").arg("path", target.display()),
+            ));
+        }
+
+        // 整个过程持有写锁，配音和删除等操作等更换完成后再使用新目录
+        let mut root = self.lock();
+        let old = root.clone();
+        let old_canonical = old.canonicalize()?;
+        fs::create_dir_all(target)?;
+        let target_canonical = target.canonicalize()?;
+        if target_canonical.starts_with(&old_canonical) {
+            return Err(AppError::InvalidArgument(
+                Msg::new("storage.inside_old_root").arg("path", target.display()),
+            ));
+        }
+        // 已有内容的文件夹只能是之前使用过的数据目录，避免复制到其他程序的文件夹中
+        for entry in fs::read_dir(target)? {
+            let entry = entry?;
+            if !is_owned_name(&entry.file_name()) {
+                return Err(AppError::InvalidArgument(
+                    Msg::new("storage.target_not_empty").arg("path", target.display()),
+                ));
+            }
+        }
+
+        let entries = db.with_conn(|conn| owned_entries(conn, &old))?;
+        if let Some(name) = entries.iter().filter_map(|path| path.file_name()).find(|name| target.join(name).exists()) {
+            return Err(AppError::InvalidArgument(
+                Msg::new("storage.target_conflict").arg("path", target.join(name).display()),
+            ));
+        }
+
+        let mut progress = MoveProgress::default();
+        for entry in &entries {
+            count_files(entry, &mut progress)?;
+        }
+        let mut report = MoveReport {
+            from: old.display().to_string(),
+            to: target.display().to_string(),
+            files: 0,
+            bytes: 0,
+            lessons: 0,
+            old_removed: false,
+            kept: 0,
+        };
+        let mut copied = Vec::new();
+        let result = entries
+            .iter()
+            .try_for_each(|entry| {
+                let destination = target.join(entry.file_name().unwrap_or_default());
+                copied.push(destination.clone());
+                copy_verified(entry, &destination, &mut progress, &mut on_progress)
+            })
+            .and_then(|_| {
+                db.with_conn(|conn| {
+                    let tx = conn.transaction()?;
+                    let lessons = rewrite_lesson_paths(&tx, &old, &old_canonical, target)?;
+                    tx.commit()?;
+                    Ok(lessons)
+                })
+            });
+        match result {
+            Ok(lessons) => report.lessons = lessons,
+            Err(e) => {
+                for path in &copied {
+                    let _ = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+                }
+                return Err(e);
+            }
+        }
+        report.files = progress.files;
+        report.bytes = progress.bytes;
+
+        *root = target.to_path_buf();
+        drop(root);
+        info!(from = %report.from, to = %report.to, files = report.files, lessons = report.lessons, "数据目录已更换");
+
+        // 旧目录中只删除已经复制过去的条目，其他文件留在原处
+        report.old_removed = true;
+        for entry in &entries {
+            let removed = if entry.is_dir() { fs::remove_dir_all(entry) } else { fs::remove_file(entry) };
+            if let Err(e) = removed {
+                warn!(path = %entry.display(), error = %e, "旧数据目录中的文件删除失败，需要手动清理");
+                report.old_removed = false;
+            }
+        }
+        report.kept = fs::read_dir(&old).map_or(0, |entries| entries.count());
+        if report.kept == 0 {
+            let _ = fs::remove_dir(&old);
+        }
+        Ok(report)
+    }
+}
+
+/// 数据目录下的一级条目是否由程序创建：以UUID命名的课文文件夹（见 `start_tts` 和课文包导入）、单词音频缓存和回收站
+pub(crate) fn is_owned_name(name: &OsStr) -> bool {
+    name == TRASH_DIR_NAME
+        || name == audio_cache::CACHE_DIR_NAME
+        || name.to_str().is_some_and(|name| Uuid::parse_str(name).is_ok())
+}
+
+/// 数据目录下由程序创建的一级条目
+///
+/// 除了按名称判断的条目（见 `is_owned_name`），课文 `filePath` 引用的文件夹也算在内。
+/// 更换数据目录和恢复备份时只处理这些条目，其他文件保持不动
+pub(crate) fn owned_entries(conn: &Connection, root: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let root_canonical = root.canonicalize()?;
+    let mut names: BTreeSet<OsString> = BTreeSet::new();
+    let mut stmt = conn.prepare("SELECT filePath FROM `class`")?;
+    let paths = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    for path in paths {
+        let path = PathBuf::from(path?);
+        let relative = path.strip_prefix(root).map(Path::to_path_buf).ok().or_else(|| {
+            let canonical = path.canonicalize().ok()?;
+            canonical.strip_prefix(&root_canonical).map(Path::to_path_buf).ok()
+        });
+        if let Some(Component::Normal(name)) = relative.as_deref().and_then(|r| r.components().next()) {
+            names.insert(name.to_os_string());
+        }
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if is_owned_name(&name) || names.contains(&name) {
+            entries.push(entry.path());
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// 先写入同一文件夹下的临时文件，成功后再改名为 `path`，读取方不会读到写了一半的文件
+///
+/// # Arguments
+///
+/// * `path` - 目标文件，已存在时被替换
+/// * `write` - 把内容写入传入的临时文件
+///
+/// # Returns
+///
+/// * 成功返回 `write` 的结果
+/// * 写入或改名失败时返回 `Err(AppError)`，临时文件已删除
+pub(crate) fn write_atomic<T>(path: &Path, write: impl FnOnce(&Path) -> Result<T, AppError>) -> Result<T, AppError> {
+    let temp = path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+    let result = write(&temp).and_then(|value| {
+        fs::rename(&temp, path)?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// 打包数据文件（课程包、备份和 Anki 牌组）使用的压缩选项，返回 (压缩, 不压缩)
+///
+/// 音频本身已经压缩过，使用不压缩的选项。`large_file` 为 `true` 时支持超过4GB的条目
+pub(crate) fn zip_options(large_file: bool) -> (SimpleFileOptions, SimpleFileOptions) {
+    let options = SimpleFileOptions::default().large_file(large_file);
+    (
+        options.compression_method(CompressionMethod::Deflated),
+        options.compression_method(CompressionMethod::Stored),
+    )
+}
+
+/// 删除数据目录内的文件或文件夹
+///
+/// 已经持有数据目录（`DataDir::root`）时使用，避免重复加锁
+///
+/// # Arguments
+///
+/// * `root` - 数据目录
+/// * `path` - 需要删除的路径，绝对路径或相对数据目录的路径
+/// * `trash` - 为 `true` 时移到回收站，回收站内的内容总是直接删除
+///
+/// # Returns
+///
+/// * 成功返回删除的路径和回收站条目ID，直接删除时ID为空
+/// * 路径不合法或删除失败时返回 `Err(AppError)`
+pub fn delete_in(root: &Path, path: &Path, trash: bool) -> Result<(PathBuf, Option<String>), AppError> {
+    let root = root.canonicalize()?;
+    let location = resolve(&root, path)?;
+    if trash && !location.starts_with(root.join(TRASH_DIR_NAME)) {
+        let id = move_to_trash(&root, &location)?;
+        return Ok((location, Some(id)));
+    }
+
+    let is_dir = fs::symlink_metadata(&location)?.is_dir();
+    let result = if is_dir {
+        fs::remove_dir_all(&location)
+    } else {
+        fs::remove_file(&location)
+    };
+    result.map_err(|e| {
+        let key = if is_dir { "fs.remove_dir_failed" } else { "fs.remove_file_failed" };
+        AppError::Io(Msg::new(key).arg("path", location.display()).arg("error", e))
+    })?;
+    Ok((location, None))
+}
+
+/// 把回收站中的条目恢复到原位置
+///
+/// 已经持有数据目录（`DataDir::root`）时使用，避免重复加锁
+///
+/// # Returns
+///
+/// * 成功返回恢复后的路径
+/// * 条目不存在或原位置已有同名文件时返回 `Err(AppError)`
+pub(crate) fn restore_in(root: &Path, id: &str) -> Result<PathBuf, AppError> {
+    let not_found = || AppError::InvalidArgument(Msg::new("fs.trash_not_found").arg("id", id));
+    // ID只能是回收站下的一级名称
+    if id.is_empty() || Path::new(id).file_name() != Some(id.as_ref()) {
+        return Err(not_found());
+    }
+    let entry = root.join(TRASH_DIR_NAME).join(id);
+    let info: TrashInfo = match fs::read(entry.join(TRASH_INFO_NAME)) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(not_found()),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !info.original.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(not_found());
+    }
+    let target = root.join(&info.original);
+    if fs::symlink_metadata(&target).is_ok() {
+        return Err(AppError::InvalidArgument(
+            Msg::new("fs.restore_conflict").arg("path", target.display()),
+        ));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(entry.join(TRASH_CONTENT_NAME), &target)?;
+    fs::remove_dir_all(&entry)?;
+    Ok(target)
+}
+
+/// 把前端传来的路径解析为数据目录内的实际路径
+///
+/// `root` 为解析过符号链接的数据目录，相对路径相对数据目录。除最后一级以外的路径都先解析（`..` 和符号链接），解析后必须在数据目录内，
+/// 且不能是数据目录或回收站本身；最后一级是符号链接时，链接指向的位置也必须在数据目录内。
+///
+/// # Returns
+///
+/// * 成功返回解析后的路径，最后一级是符号链接时为链接本身
+/// * 路径不存在、在数据目录之外或通过符号链接指向数据目录之外时返回 `Err(AppError)`
+fn resolve(root: &Path, path: &Path) -> Result<PathBuf, AppError> {
+    let path = root.join(path);
+    let not_found = || AppError::InvalidArgument(Msg::new("fs.path_not_found").arg("path", path.display()));
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(not_found()),
+        Err(e) => return Err(e.into()),
+    };
+
+    // 路径以 `..` 或 `.` 结尾时没有最后一级的名称，整体解析
+    let location = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent.canonicalize().map_err(|_| not_found())?.join(name),
+        _ => path.canonicalize().map_err(|_| not_found())?,
+    };
+    if !location.starts_with(root) {
+        return Err(AppError::InvalidArgument(
+            Msg::new("fs.outside_data_dir").arg("path", path.display()),
+        ));
+    }
+    if location == root || location == root.join(TRASH_DIR_NAME) {
+        return Err(AppError::InvalidArgument(
+            Msg::new("fs.protected_path").arg("path", path.display()),
+        ));
+    }
+
+    // 指向不存在位置的符号链接删除链接本身不会影响其他文件
+    if metadata.file_type().is_symlink() {
+        if let Ok(target) = location.canonicalize() {
+            if !target.starts_with(root) {
+                return Err(AppError::InvalidArgument(
+                    Msg::new("fs.symlink_escape")
+                        .arg("path", path.display())
+                        .arg("target", target.display()),
+                ));
+            }
+        }
+    }
+    Ok(location)
+}
+
+fn move_to_trash(root: &Path, location: &Path) -> Result<String, AppError> {
+    let id = Uuid::new_v4().to_string();
+    let entry = root.join(TRASH_DIR_NAME).join(&id);
+    fs::create_dir_all(&entry)?;
+
+    let info = TrashInfo {
+        original: location.strip_prefix(root).unwrap_or(location).to_path_buf(),
+        delete_time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    };
+    fs::write(entry.join(TRASH_INFO_NAME), serde_json::to_vec(&info)?)?;
+    if let Err(e) = fs::rename(location, entry.join(TRASH_CONTENT_NAME)) {
+        let _ = fs::remove_dir_all(&entry);
+        return Err(AppError::Io(
+            Msg::new("fs.trash_failed").arg("path", location.display()).arg("error", e),
+        ));
+    }
+    Ok(id)
+}
+
+/// 统计需要复制的文件数和大小
+fn count_files(path: &Path, progress: &mut MoveProgress) -> Result<(), AppError> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            count_files(&entry?.path(), progress)?;
+        }
+    } else if metadata.is_file() {
+        progress.total_files += 1;
+        progress.total_bytes += metadata.len();
+    }
+    Ok(())
+}
+
+/// 复制文件或文件夹，每个文件复制后比较SHA-256
+///
+/// 数据目录中不会有符号链接，遇到时跳过
+fn copy_verified(
+    from: &Path,
+    to: &Path,
+    progress: &mut MoveProgress,
+    on_progress: &mut impl FnMut(&MoveProgress),
+) -> Result<(), AppError> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_verified(&entry.path(), &to.join(entry.file_name()), progress, on_progress)?;
+        }
+    } else if metadata.is_file() {
+        fs::copy(from, to)?;
+        if file_hash(from)? != file_hash(to)? {
+            return Err(AppError::Io(Msg::new("storage.checksum_mismatch").arg("path", from.display())));
+        }
+        progress.files += 1;
+        progress.bytes += metadata.len();
+        on_progress(progress);
+    } else {
+        warn!(path = %from.display(), "跳过符号链接");
+    }
+    Ok(())
+}
+
+fn file_hash(path: &Path) -> Result<Vec<u8>, AppError> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// 把旧目录下课文的 `filePath` 改为新目录下的路径，并保存新目录的设置
+///
+/// 不开启事务，由调用方决定
+///
+/// # Returns
+///
+/// * 成功返回改写的课文数，不在旧目录下的课文保持不变
+pub(crate) fn rewrite_lesson_paths(
+    conn: &Connection,
+    old: &Path,
+    old_canonical: &Path,
+    target: &Path,
+) -> Result<usize, AppError> {
+    let lessons: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare("SELECT id, filePath FROM `class`")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let mut count = 0;
+    for (id, file_path) in lessons {
+        let path = Path::new(&file_path);
+        // 保存的路径可能没有解析过符号链接，先按原样比较，再按解析后的路径比较
+        let relative = path.strip_prefix(old).map(Path::to_path_buf).ok().or_else(|| {
+            let canonical = path.canonicalize().ok()?;
+            canonical.strip_prefix(old_canonical).map(Path::to_path_buf).ok()
+        });
+        let Some(relative) = relative else {
+            continue;
+        };
+        let new_path = target.join(relative).display().to_string();
+        conn.execute("UPDATE `class` SET filePath = ?1 WHERE id = ?2", params![new_path, id])?;
+        count += 1;
+    }
+
+    conn.execute(
+        "INSERT INTO appSetting (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![ROOT_KEY, target.display().to_string()],
+    )?;
+    Ok(count)
+}
+
+/// 删除数据目录内的文件或文件夹
+///
+/// 只能删除数据目录（`datas`）内的内容，路径中的 `..` 和符号链接解析后不能离开数据目录。
+///
+/// # Arguments
+///
+/// * `path_str` - 需要删除的路径，绝对路径或相对数据目录的路径
+/// * `trash` - 为 `true` 时移到回收站，可以用 `restore_trash_item` 恢复
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.path` 为删除的路径，`data.trash_id` 为回收站条目ID
+/// * 路径不存在、不在数据目录内或删除失败时返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(data_dir))]
+pub async fn delete_path_contents(
+    data_dir: State<'_, DataDir>,
+    path_str: String,
+    trash: Option<bool>,
+) -> Result<CustomResult, CustomResult> {
+    let (path, trash_id) = data_dir.delete(Path::new(&path_str), trash.unwrap_or(false))?;
+    info!(path = %path.display(), ?trash_id, "已删除");
+
+    Ok(CustomResult::success(
+        None,
+        Some(json!({"path": path.display().to_string(), "trash_id": trash_id})),
+    ))
+}
+
+/// 恢复回收站中的条目
+///
+/// # Arguments
+///
+/// * `id` - `delete_path_contents` 返回的回收站条目ID
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data.path` 为恢复后的路径
+/// * 条目不存在或原位置已被占用时返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(data_dir))]
+pub async fn restore_trash_item(data_dir: State<'_, DataDir>, id: String) -> Result<CustomResult, CustomResult> {
+    let path = data_dir.restore(&id)?;
+    info!(path = %path.display(), "已从回收站恢复");
+
+    Ok(CustomResult::success(None, Some(json!({"path": path.display().to_string()}))))
+}
+
+/// 允许前端通过 fs 插件读取数据目录
+///
+/// 配置的权限只包含资源目录下的 `datas`，数据目录可以更换，所以在运行时授权。
+/// 授权的范围对 fs 插件的所有命令生效，前端只开放了读取类的命令（见 `capabilities/default.json`），
+/// 删除必须通过 `delete_path_contents`
+pub fn allow_frontend_access<R: Runtime>(app: &AppHandle<R>, root: &Path) -> Result<(), AppError> {
+    app.fs_scope()
+        .allow_directory(root, true)
+        .map_err(|e| AppError::Io(Msg::new("storage.scope_failed").arg("error", e)))
+}
+
+/// 获取当前的数据目录
+///
+/// # Returns
+///
+/// * `Ok(CustomResult::success)`，`data.path` 为数据目录
+#[tauri::command]
+pub async fn get_data_root(data_dir: State<'_, DataDir>) -> Result<CustomResult, CustomResult> {
+    let path = data_dir.try_root()?.display().to_string();
+    Ok(CustomResult::success(None, Some(json!({"path": path}))))
+}
+
+/// 更换数据目录
+///
+/// 复制课文文件、单词音频和回收站并校验后改写课文路径，最后从旧目录中删除它们，任何一步失败时仍使用旧目录。
+/// 旧目录中的其他文件保留在原处。复制进度通过 `data-root-progress` 事件通知前端，`{files, bytes, total_files, total_bytes}`
+///
+/// # Arguments
+///
+/// * `path` - 新的数据目录，必须是绝对路径，为空或之前使用过的数据目录
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为复制和改写的统计
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument(skip(app))]
+pub async fn move_data_root(app: AppHandle, path: String) -> Result<CustomResult, CustomResult> {
+    let task_app = app.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let mut last_emit: Option<Instant> = None;
+        let on_progress = |progress: &MoveProgress| {
+            let finished = progress.files == progress.total_files;
+            if finished || last_emit.is_none_or(|time| time.elapsed() >= PROGRESS_INTERVAL) {
+                last_emit = Some(Instant::now());
+                if let Err(e) = task_app.emit(PROGRESS_EVENT, progress) {
+                    warn!("发送进度事件失败：{}", e);
+                }
+            }
+        };
+        let data_dir = task_app.state::<DataDir>();
+        data_dir.move_to(&task_app.state::<Database>(), Path::new(&path), on_progress)
+    })
+    .await
+    .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    allow_frontend_access(&app, Path::new(&report.to))?;
+    Ok(CustomResult::success(None, Some(json!(report))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures;
+
+    /// 临时目录下的数据目录 `datas` 和数据目录之外的 `outside/victim.txt`
+    struct Fixture {
+        base: PathBuf,
+        root: PathBuf,
+        victim: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let base = fixtures::temp_dir("storage");
+            let root = base.join("datas");
+            fs::create_dir_all(root.join("1")).unwrap();
+            fs::write(root.join("1").join("audio.mp3"), b"audio").unwrap();
+            fs::create_dir_all(base.join("outside")).unwrap();
+            let victim = base.join("outside").join("victim.txt");
+            fs::write(&victim, b"victim").unwrap();
+            Self { base, root, victim }
+        }
+
+        fn delete(&self, path: impl AsRef<Path>) -> Result<(PathBuf, Option<String>), AppError> {
+            delete_in(&self.root, path.as_ref(), false)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.base);
+        }
+    }
+
+    #[test]
+    fn relative_traversal_is_rejected() {
+        let fixture = Fixture::new();
+        for path in ["../outside/victim.txt", "1/../../outside/victim.txt", "../outside", "../..", ".."] {
+            assert!(
+                matches!(fixture.delete(path), Err(AppError::InvalidArgument(_))),
+                "{} 应被拒绝",
+                path
+            );
+        }
+        assert!(fixture.victim.exists());
+        assert!(fixture.root.exists());
+    }
+
+    #[test]
+    fn absolute_paths_must_be_inside_root() {
+        let fixture = Fixture::new();
+        assert!(matches!(fixture.delete(&fixture.victim), Err(AppError::InvalidArgument(_))));
+        assert!(matches!(fixture.delete(&fixture.root), Err(AppError::InvalidArgument(_))));
+        assert!(fixture.victim.exists());
+
+        let audio = fixture.root.join("1").join("audio.mp3");
+        fixture.delete(&audio).unwrap();
+        assert!(!audio.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_to_outside_is_rejected() {
+        let fixture = Fixture::new();
+        std::os::unix::fs::symlink(fixture.base.join("outside"), fixture.root.join("link")).unwrap();
+
+        // 通过链接访问链接指向的内容
+        assert!(matches!(fixture.delete("link/victim.txt"), Err(AppError::InvalidArgument(_))));
+        // 删除指向数据目录之外的链接本身
+        assert!(matches!(fixture.delete("link"), Err(AppError::InvalidArgument(_))));
+        assert!(matches!(delete_in(&fixture.root, Path::new("link"), true), Err(AppError::InvalidArgument(_))));
+        assert!(fixture.victim.exists());
+    }
+
+    #[test]
+    fn trash_is_protected_and_items_can_be_restored() {
+        let fixture = Fixture::new();
+        let data_dir = DataDir::open(fixture.root.clone()).unwrap();
+        let (_, id) = data_dir.delete(Path::new("1"), true).unwrap();
+        assert!(!fixture.root.join("1").exists());
+        assert!(matches!(data_dir.delete(Path::new(TRASH_DIR_NAME), false), Err(AppError::InvalidArgument(_))));
+
+        // 回收站条目ID不能用来访问回收站之外
+        assert!(data_dir.restore("../1").is_err());
+        data_dir.restore(&id.unwrap()).unwrap();
+        assert!(fixture.root.join("1").join("audio.mp3").exists());
+    }
+
+    #[test]
+    fn move_to_moves_only_owned_entries() {
+        let fixture = Fixture::new();
+        let db = Database::memory().unwrap();
+        let (_, lesson) = fixtures::add_lesson(&db, &fixture.root, "Lesson", b"audio");
+        fs::create_dir_all(fixture.root.join(audio_cache::CACHE_DIR_NAME)).unwrap();
+        fs::write(fixture.root.join(audio_cache::CACHE_DIR_NAME).join("a.mp3"), b"word").unwrap();
+        fs::write(fixture.root.join("notes.txt"), b"mine").unwrap();
+
+        let data_dir = DataDir::open(fixture.root.clone()).unwrap();
+        let target = fixture.base.join("moved");
+        let mut events = 0;
+        let report = data_dir.move_to(&db, &target, |_| events += 1).unwrap();
+        assert_eq!((report.files, events, report.lessons), (3, 3, 1));
+        assert!(report.old_removed);
+        // 没有课文引用的文件夹 `1` 和 `notes.txt`
+        assert_eq!(report.kept, 2);
+
+        // 程序的条目已移走，其他文件留在原处
+        assert!(!lesson.exists());
+        assert!(fixture.root.join("1").join("audio.mp3").exists());
+        assert!(fixture.root.join("notes.txt").exists());
+        assert!(!target.join("notes.txt").exists());
+        let moved = target.join(lesson.file_name().unwrap());
+        assert!(moved.join("audio.mp3").exists());
+        assert!(target.join(audio_cache::CACHE_DIR_NAME).join("a.mp3").exists());
+        assert_eq!(*data_dir.root(), target);
+        let file_path: String = db
+            .with_conn(|conn| Ok(conn.query_row("SELECT filePath FROM `class`", [], |row| row.get(0))?))
+            .unwrap();
+        assert_eq!(file_path, moved.display().to_string());
+
+        // 旧目录只剩程序的条目时整体删除
+        let report = data_dir.move_to(&db, &fixture.base.join("again"), |_| {}).unwrap();
+        assert_eq!(report.kept, 0);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn move_to_refuses_foreign_target() {
+        let fixture = Fixture::new();
+        let db = Database::memory().unwrap();
+        let (_, lesson) = fixtures::add_lesson(&db, &fixture.root, "Lesson", b"audio");
+        let data_dir = DataDir::open(fixture.root.clone()).unwrap();
+
+        let target = fixture.base.join("outside");
+        assert!(matches!(data_dir.move_to(&db, &target, |_| {}), Err(AppError::InvalidArgument(_))));
+        assert!(lesson.join("audio.mp3").exists());
+        assert_eq!(*data_dir.root(), fixture.root);
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 1);
+    }
+}
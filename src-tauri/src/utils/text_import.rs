@@ -0,0 +1,1039 @@
+use crate::utils::custom_result::CustomResult;
+use crate::utils::error::AppError;
+use crate::utils::i18n::Msg;
+use encoding_rs::{Encoding, GBK, SHIFT_JIS, UTF_8};
+use quick_xml::escape::{resolve_html5_entity, unescape_with};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+use zip::ZipArchive;
+
+/// 导入文件和 EPUB 中单个文件的大小上限
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+/// 每篇课文默认的最大字数，过长的章节按行拆成多篇，避免单次配音过长
+pub const DEFAULT_MAX_CHARS: usize = 3000;
+/// 超过这个字数的行不当作章节标题
+const MAX_HEADING_CHARS: usize = 50;
+/// 句末标点，拆分过长的段落时使用
+const SENTENCE_END: &[char] = &['。', '？', '！', '…', '.', '?', '!'];
+/// 转成文本时换行的 HTML 元素
+const BLOCK_ELEMENTS: &[&[u8]] = &[
+    b"p", b"div", b"section", b"article", b"blockquote", b"li", b"ul", b"ol", b"dt", b"dd", b"tr", b"table",
+    b"h1", b"h2", b"h3", b"h4", b"h5", b"h6", b"pre", b"hr", b"br", b"figure", b"figcaption", b"aside",
+];
+/// 内容不属于正文的 HTML 元素，`rt`、`rp` 为注音
+const SKIPPED_ELEMENTS: &[&[u8]] = &[b"head", b"script", b"style", b"rt", b"rp", b"svg", b"math"];
+
+/// 可以导入的文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentFormat {
+    Txt,
+    Markdown,
+    Epub,
+    Srt,
+    Vtt,
+}
+
+impl DocumentFormat {
+    /// 按扩展名判断格式
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "txt" | "text" => Some(Self::Txt),
+            "md" | "markdown" => Some(Self::Markdown),
+            "epub" => Some(Self::Epub),
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::Vtt),
+            _ => None,
+        }
+    }
+}
+
+/// 一篇待添加的课文
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LessonDraft {
+    pub title: String,
+    pub content: String,
+}
+
+/// 文件的解析结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedDocument {
+    pub format: DocumentFormat,
+    /// 识别出的文本编码
+    pub encoding: String,
+    /// 书名或文件名
+    pub title: String,
+    pub lessons: Vec<LessonDraft>,
+}
+
+/// 识别文本编码并解码
+///
+/// 有 BOM 时按 BOM 解码；否则依次尝试 UTF-8、GBK 和 Shift-JIS。
+/// 日文按 GBK 解码往往也不出错，GBK 和 Shift-JIS 都能解码时，按 Shift-JIS 解码后假名的比例判断是否为日文。
+///
+/// # Returns
+///
+/// * 解码后的文本和编码名称
+pub fn decode_text(bytes: &[u8]) -> (String, &'static str) {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        return (text.into_owned(), encoding.name());
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return (text.to_string(), UTF_8.name());
+    }
+
+    let gbk = GBK.decode_without_bom_handling_and_without_replacement(bytes);
+    let shift_jis = SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes);
+    let (encoding, text) = match (gbk, shift_jis) {
+        (Some(gbk), Some(shift_jis)) if !looks_japanese(&shift_jis) => (GBK, gbk),
+        (_, Some(shift_jis)) => (SHIFT_JIS, shift_jis),
+        (Some(gbk), None) => (GBK, gbk),
+        // 都有无法解码的字节时，选替换字符较少的
+        (None, None) => {
+            let (gbk, _) = GBK.decode_without_bom_handling(bytes);
+            let (shift_jis, _) = SHIFT_JIS.decode_without_bom_handling(bytes);
+            let replaced = |text: &str| text.matches('\u{fffd}').count();
+            if replaced(&shift_jis) < replaced(&gbk) {
+                (SHIFT_JIS, shift_jis)
+            } else {
+                (GBK, gbk)
+            }
+        }
+    };
+    (text.into_owned(), encoding.name())
+}
+
+/// 日文中平假名、片假名很常见，而中文按 Shift-JIS 解码后多是半角片假名
+fn looks_japanese(text: &str) -> bool {
+    let (mut non_ascii, mut kana, mut half_width) = (0usize, 0usize, 0usize);
+    for c in text.chars().filter(|c| !c.is_ascii()) {
+        non_ascii += 1;
+        match c {
+            '\u{3040}'..='\u{30ff}' => kana += 1,
+            '\u{ff61}'..='\u{ff9f}' => half_width += 1,
+            _ => {}
+        }
+    }
+    kana * 10 >= non_ascii && half_width * 20 < non_ascii
+}
+
+/// 中日文字符，与这些字符相邻时拼接不加空格
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3000}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}'
+        | '\u{ff00}'..='\u{ffef}')
+}
+
+/// 拼接两段文字，英文等按空格分词的文字之间加空格
+fn join_text(current: &mut String, piece: &str) {
+    let (Some(last), Some(first)) = (current.chars().last(), piece.chars().next()) else {
+        current.push_str(piece);
+        return;
+    };
+    if !last.is_whitespace() && !is_cjk(last) && !is_cjk(first) {
+        current.push(' ');
+    }
+    current.push_str(piece);
+}
+
+/// 整理正文：统一换行，去掉控制字符和零宽字符，合并连续的空格和空行，每行去掉首尾空白
+fn clean_text(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.replace("\r\n", "\n").replace('\r', "\n").split('\n') {
+        let mut cleaned = String::with_capacity(line.len());
+        for c in line.chars() {
+            match c {
+                '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => {}
+                '\u{3000}' => cleaned.push(c),
+                c if c.is_whitespace() => {
+                    if !cleaned.ends_with(' ') {
+                        cleaned.push(' ');
+                    }
+                }
+                c if c.is_control() => {}
+                c => cleaned.push(c),
+            }
+        }
+        let cleaned = cleaned.trim().to_string();
+        // 只保留一个空行作为段落之间的分隔
+        if cleaned.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(cleaned);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+/// 生成课文，整理正文后超过 `max_chars` 的按行拆成多篇，标题加上序号
+///
+/// 单独一行过长时按句末标点拆开，仍然过长的句子按字数截断
+fn make_lessons(title: &str, content: &str, max_chars: usize) -> Vec<LessonDraft> {
+    let content = clean_text(content);
+    if content.is_empty() {
+        return Vec::new();
+    }
+    let title = clean_text(title).replace('\n', " ");
+    if max_chars == 0 || content.chars().count() <= max_chars {
+        return vec![LessonDraft { title, content }];
+    }
+
+    let mut units: Vec<&str> = Vec::new();
+    for line in content.split_inclusive('\n') {
+        if line.chars().count() <= max_chars {
+            units.push(line);
+            continue;
+        }
+        for sentence in line.split_inclusive(SENTENCE_END) {
+            let mut rest = sentence;
+            while let Some((i, _)) = rest.char_indices().nth(max_chars) {
+                units.push(&rest[..i]);
+                rest = &rest[i..];
+            }
+            units.push(rest);
+        }
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut current_chars = 0;
+    for unit in units {
+        let chars = unit.chars().count();
+        if current_chars + chars > max_chars && !current.trim().is_empty() {
+            parts.push(std::mem::take(&mut current));
+            current_chars = 0;
+        }
+        current.push_str(unit);
+        current_chars += chars;
+    }
+    if !current.trim().is_empty() {
+        parts.push(current);
+    }
+
+    let total = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| LessonDraft {
+            title: format!("{} ({}/{})", title, i + 1, total),
+            content: part.trim().to_string(),
+        })
+        .collect()
+}
+
+/// 纯文本中的章节标题，比如“第一章”“第3話”“Chapter 12”
+fn chapter_heading() -> &'static Regex {
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    HEADING.get_or_init(|| {
+        Regex::new(
+            r"^(第[0-9０-９零〇一二三四五六七八九十百千两]+[章节節回卷部篇话話课課幕]|(?i:(chapter|part|lesson|section)\s+([0-9]+|[ivxlcdm]+)\b)|序章|序言|前言|楔子|尾声|後記|后记|终章|終章|番外)",
+        )
+        .unwrap()
+    })
+}
+
+/// 按章节标题拆分纯文本，第一个标题之前的内容以文件名为标题，没有章节标题时整篇作为一节
+fn split_txt(text: &str, file_title: &str) -> Vec<(String, String)> {
+    let mut sections = vec![(file_title.to_string(), String::new())];
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.chars().count() <= MAX_HEADING_CHARS && chapter_heading().is_match(trimmed) {
+            sections.push((trimmed.to_string(), String::new()));
+        } else if let Some((_, body)) = sections.last_mut() {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    sections
+}
+
+/// Markdown 转纯文本用到的正则
+struct MarkdownPatterns {
+    heading: Regex,
+    rule: Regex,
+    table_separator: Regex,
+    reference: Regex,
+    prefix: Regex,
+    image: Regex,
+    link: Regex,
+    html: Regex,
+    emphasis: Vec<Regex>,
+    escape: Regex,
+}
+
+fn markdown_patterns() -> &'static MarkdownPatterns {
+    static PATTERNS: OnceLock<MarkdownPatterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| MarkdownPatterns {
+        heading: Regex::new(r"^ {0,3}(#{1,6})\s+(.*?)(\s+#+)?\s*$").unwrap(),
+        rule: Regex::new(r"^ {0,3}([-*_]\s*){3,}$").unwrap(),
+        table_separator: Regex::new(r"^\s*\|?\s*:?-{3,}:?\s*(\|\s*:?-{3,}:?\s*)*\|?\s*$").unwrap(),
+        reference: Regex::new(r"^ {0,3}\[[^\]]+\]:\s*\S+").unwrap(),
+        prefix: Regex::new(r"^\s*(>\s*)*(([-*+]|\d+[.)])\s+(\[[ xX]\]\s+)?)?").unwrap(),
+        image: Regex::new(r"!\[[^\]]*\]\([^)]*\)|!\[[^\]]*\]\[[^\]]*\]").unwrap(),
+        link: Regex::new(r"\[([^\]]*)\]\([^)]*\)|\[([^\]]*)\]\[[^\]]*\]").unwrap(),
+        html: Regex::new(r"<!--.*?-->|</?[A-Za-z][^>]*>").unwrap(),
+        emphasis: [r"\*\*(.+?)\*\*", r"__(.+?)__", r"~~(.+?)~~", r"\*([^*\s][^*]*?)\*", r"\b_([^_]+?)_\b", r"`([^`]*)`"]
+            .iter()
+            .map(|p| Regex::new(p).unwrap())
+            .collect(),
+        escape: Regex::new(r"\\([\\`*_{}\[\]()#+\-.!|~<>])").unwrap(),
+    })
+}
+
+/// 按标题拆分 Markdown
+///
+/// 在最高一级的标题处拆分，最高一级的标题只有一个且还有下级标题时，把它当作文档标题，在下一级标题处拆分
+///
+/// # Returns
+///
+/// * 文档标题和各节的标题、正文（仍为 Markdown）
+fn split_markdown(text: &str, file_title: &str) -> (String, Vec<(String, String)>) {
+    let patterns = markdown_patterns();
+    let lines: Vec<&str> = text.lines().collect();
+
+    // 代码块中的 # 不是标题
+    let mut headings: Vec<(usize, usize, String)> = Vec::new();
+    let mut fence: Option<&str> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        if let Some(captures) = patterns.heading.captures(line) {
+            headings.push((i, captures[1].len(), inline_markdown(&captures[2])));
+        }
+    }
+
+    let mut title = file_title.to_string();
+    let mut skip_line = None;
+    let mut split_level = headings.iter().map(|h| h.1).min().unwrap_or(0);
+    let top: Vec<_> = headings.iter().filter(|h| h.1 == split_level).collect();
+    if let [(line, _, text)] = top.as_slice() {
+        if let Some(next) = headings.iter().map(|h| h.1).filter(|&level| level > split_level).min() {
+            title = text.clone();
+            skip_line = Some(*line);
+            split_level = next;
+        }
+    }
+
+    let mut sections = vec![(title.clone(), String::new())];
+    let mut heading_iter = headings.iter().peekable();
+    for (i, line) in lines.iter().enumerate() {
+        let heading = heading_iter.next_if(|h| h.0 == i);
+        if Some(i) == skip_line {
+            continue;
+        }
+        match heading {
+            Some((_, level, text)) if *level <= split_level => sections.push((text.clone(), String::new())),
+            _ => {
+                if let Some((_, body)) = sections.last_mut() {
+                    body.push_str(line);
+                    body.push('\n');
+                }
+            }
+        }
+    }
+    (title, sections)
+}
+
+/// 去掉行内的 Markdown 标记，链接保留文字，图片和 HTML 标签去掉
+///
+/// 转义的字符先换成私用区的字符，去掉标记后再换回来，避免 `\*` 被当作强调
+fn inline_markdown(text: &str) -> String {
+    let patterns = markdown_patterns();
+    let mut text = patterns
+        .escape
+        .replace_all(text, |captures: &regex::Captures| {
+            let c = captures[1].chars().next().unwrap_or_default();
+            char::from_u32(0xe000 + c as u32).unwrap_or(c).to_string()
+        })
+        .into_owned();
+    text = patterns.image.replace_all(&text, "").into_owned();
+    text = patterns.link.replace_all(&text, "$1$2").into_owned();
+    text = patterns.html.replace_all(&text, "").into_owned();
+    for emphasis in &patterns.emphasis {
+        text = emphasis.replace_all(&text, "$1").into_owned();
+    }
+    let text: String = text
+        .chars()
+        .map(|c| match c {
+            '\u{e000}'..='\u{e07f}' => char::from_u32(c as u32 - 0xe000).unwrap_or(c),
+            c => c,
+        })
+        .collect();
+    unescape_with(&text, resolve_html5_entity).map_or(text.clone(), |t| t.into_owned())
+}
+
+/// Markdown 转纯文本，代码块、分隔线、表格分隔行和链接定义去掉，列表和引用只保留文字
+fn markdown_to_text(markdown: &str) -> String {
+    let patterns = markdown_patterns();
+    let mut result = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        if patterns.rule.is_match(line) || patterns.table_separator.is_match(line) || patterns.reference.is_match(line) {
+            continue;
+        }
+        let line = match patterns.heading.captures(line) {
+            Some(captures) => captures[2].to_string(),
+            None => patterns.prefix.replace(line, "").into_owned(),
+        };
+        let line = if line.trim_start().starts_with('|') {
+            line.split('|').map(str::trim).filter(|cell| !cell.is_empty()).collect::<Vec<_>>().join(" ")
+        } else {
+            line
+        };
+        result.push_str(&inline_markdown(&line));
+        result.push('\n');
+    }
+    result
+}
+
+/// 字幕中的标签，比如 `<i>`、`<c.red>`、`<00:00:01.000>`、`{\an8}`
+fn subtitle_tag() -> &'static Regex {
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    TAG.get_or_init(|| Regex::new(r"<[^>]*>|\{\\[^}]*\}").unwrap())
+}
+
+/// 提取 SRT、VTT 字幕的文字
+///
+/// 字幕块以空行分隔，只有空格的行也算空行。序号、时间轴、`NOTE`、`STYLE` 等块去掉，
+/// 与上一行相同的字幕只保留一次（滚动字幕每条都会重复上一行）。
+/// 字幕常在句子中间换行，同一句的字幕拼成一行，遇到句末标点才换行。
+fn subtitle_to_text(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut blocks: Vec<Vec<&str>> = Vec::new();
+    let mut block = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if !line.is_empty() {
+            block.push(line);
+        } else if !block.is_empty() {
+            blocks.push(std::mem::take(&mut block));
+        }
+    }
+    blocks.push(block);
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut last_line = String::new();
+    for block_lines in &blocks {
+        let Some(timing) = block_lines.iter().position(|l| l.contains("-->")) else {
+            continue;
+        };
+        for line in &block_lines[timing + 1..] {
+            let stripped = subtitle_tag().replace_all(line, "");
+            let stripped = unescape_with(&stripped, resolve_html5_entity).map_or(stripped.to_string(), |t| t.into_owned());
+            // 对话中表示换人说话的横线
+            let stripped = stripped.trim().trim_start_matches(['-', '–']).trim().to_string();
+            if stripped.is_empty() || stripped == last_line {
+                continue;
+            }
+            join_text(&mut current, &stripped);
+            if stripped.ends_with(SENTENCE_END) {
+                lines.push(std::mem::take(&mut current));
+            }
+            last_line = stripped;
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines.join("\n")
+}
+
+/// 读取 EPUB 中的一个文件
+fn read_epub_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String, AppError> {
+    let entry = archive
+        .by_name(name)
+        .map_err(|_| AppError::Parse(Msg::new("document.epub_missing_entry").arg("name", name)))?;
+    if entry.size() > MAX_FILE_SIZE {
+        return Err(AppError::InvalidArgument(Msg::new("document.too_large").arg("name", name)));
+    }
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry.take(MAX_FILE_SIZE).read_to_end(&mut bytes)?;
+    Ok(decode_text(&bytes).0)
+}
+
+/// 解析 XML，不检查结束标签是否匹配，EPUB 中的 XHTML 常有小错误
+fn xml_reader(xml: &str) -> Reader<&[u8]> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().check_end_names = false;
+    reader
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+/// EPUB 中的相对路径转为 zip 中的路径，`href` 中的 `#` 之后的部分去掉
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut decoded = Vec::with_capacity(href.len());
+    let bytes = href.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok())) {
+            (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    let href = String::from_utf8_lossy(&decoded);
+
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// XHTML 转纯文本
+///
+/// # Returns
+///
+/// * 正文和第一个 `h1`～`h3` 标题，没有时用 `<title>`
+fn xhtml_to_text(xhtml: &str) -> (String, Option<String>) {
+    let mut reader = xml_reader(xhtml);
+    let mut text = String::new();
+    let mut skip_depth = 0usize;
+    let mut heading: Option<String> = None;
+    let mut in_heading = false;
+    let mut current_heading = String::new();
+    let mut title = String::new();
+    let mut in_title = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = e.local_name();
+                let name = name.as_ref();
+                if name == b"title" {
+                    in_title = true;
+                }
+                if SKIPPED_ELEMENTS.contains(&name) {
+                    skip_depth += 1;
+                } else if BLOCK_ELEMENTS.contains(&name) {
+                    text.push('\n');
+                }
+                if matches!(name, b"h1" | b"h2" | b"h3") && heading.is_none() {
+                    in_heading = true;
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                if BLOCK_ELEMENTS.contains(&e.local_name().as_ref()) {
+                    text.push('\n');
+                }
+            }
+            Ok(Event::End(e)) => {
+                let name = e.local_name();
+                let name = name.as_ref();
+                if name == b"title" {
+                    in_title = false;
+                }
+                if SKIPPED_ELEMENTS.contains(&name) {
+                    skip_depth = skip_depth.saturating_sub(1);
+                } else if BLOCK_ELEMENTS.contains(&name) {
+                    text.push('\n');
+                }
+                if in_heading && matches!(name, b"h1" | b"h2" | b"h3") {
+                    in_heading = false;
+                    let cleaned = clean_text(&current_heading).replace('\n', " ");
+                    if !cleaned.is_empty() {
+                        heading = Some(cleaned);
+                    }
+                    current_heading.clear();
+                }
+            }
+            Ok(Event::Text(e)) => {
+                let content = e
+                    .unescape_with(resolve_html5_entity)
+                    .map_or_else(|_| String::from_utf8_lossy(&e).into_owned(), |t| t.into_owned());
+                if in_title {
+                    title.push_str(&content);
+                }
+                if skip_depth == 0 {
+                    // 源文件中的换行和缩进只是排版，按 HTML 的规则合并为一个空格
+                    let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+                    let content = if e.first().is_some_and(u8::is_ascii_whitespace) && !content.is_empty() {
+                        format!(" {}", content)
+                    } else {
+                        content
+                    };
+                    let content = if e.last().is_some_and(u8::is_ascii_whitespace) && !content.trim().is_empty() {
+                        format!("{} ", content)
+                    } else {
+                        content
+                    };
+                    if in_heading {
+                        current_heading.push_str(&content);
+                    }
+                    text.push_str(&content);
+                }
+            }
+            Ok(Event::CData(e)) if skip_depth == 0 => text.push_str(&String::from_utf8_lossy(&e)),
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                warn!(error = %e, "XHTML 解析失败，只保留已读取的内容");
+                break;
+            }
+        }
+    }
+    let title = clean_text(&title).replace('\n', " ");
+    (text, heading.or(Some(title).filter(|t| !t.is_empty())))
+}
+
+/// 目录中各文件的标题，EPUB 3 的导航文档和 EPUB 2 的 NCX 都从中读取链接和文字
+fn read_toc(xml: &str, base: &str) -> HashMap<String, String> {
+    let mut titles = HashMap::new();
+    let mut reader = xml_reader(xml);
+    let mut href: Option<String> = None;
+    let mut label = String::new();
+    let mut in_label = false;
+    let mut add = |href: &str, label: &str| {
+        let label = clean_text(label).replace('\n', " ");
+        if !label.is_empty() {
+            titles.entry(resolve_href(base, href)).or_insert(label);
+        }
+    };
+    loop {
+        match reader.read_event() {
+            // 导航文档：<a href="...">标题</a>
+            Ok(Event::Start(e)) if e.local_name().as_ref() == b"a" => {
+                href = attribute(&e, b"href");
+                label.clear();
+                in_label = true;
+            }
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"a" => {
+                if let Some(href) = href.take() {
+                    add(&href, &label);
+                }
+                in_label = false;
+            }
+            // NCX：<navLabel><text>标题</text></navLabel><content src="..."/>
+            Ok(Event::Start(e)) if e.local_name().as_ref() == b"navLabel" => {
+                label.clear();
+                in_label = true;
+            }
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"navLabel" => in_label = false,
+            Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.local_name().as_ref() == b"content" => {
+                if let Some(src) = attribute(&e, b"src") {
+                    add(&src, &label);
+                }
+            }
+            Ok(Event::Text(e)) if in_label => {
+                label.push_str(&e.unescape_with(resolve_html5_entity).unwrap_or_default());
+                label.push(' ');
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                warn!(error = %e, "EPUB 目录解析失败");
+                break;
+            }
+        }
+    }
+    titles
+}
+
+/// EPUB 的内容文件
+struct ManifestItem {
+    href: String,
+    media_type: String,
+    properties: String,
+}
+
+/// 按章节拆分 EPUB
+///
+/// 按书脊（spine）的顺序读取内容文件，标题取自目录。不在目录中的文件通常是同一章拆成的多个文件，合并到上一章；
+/// 第一个目录项之前的文件（封面、扉页等）单独成篇。目录为空时每个文件一篇。
+///
+/// # Returns
+///
+/// * 书名和各章的标题、正文
+fn split_epub(path: &Path, file_title: &str) -> Result<(String, Vec<(String, String)>), AppError> {
+    let mut archive = ZipArchive::new(File::open(path)?)
+        .map_err(|_| AppError::InvalidArgument(Msg::new("document.invalid_epub")))?;
+
+    let container = read_epub_entry(&mut archive, "META-INF/container.xml")?;
+    let mut reader = xml_reader(&container);
+    let mut opf_path = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                opf_path = attribute(&e, b"full-path");
+                break;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    let opf_path = opf_path.ok_or_else(|| AppError::InvalidArgument(Msg::new("document.invalid_epub")))?;
+    let base = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir).to_string();
+
+    let opf = read_epub_entry(&mut archive, &opf_path)?;
+    let mut reader = xml_reader(&opf);
+    let mut title = String::new();
+    let mut in_title = false;
+    let mut manifest: HashMap<String, ManifestItem> = HashMap::new();
+    let mut spine: Vec<String> = Vec::new();
+    let mut ncx_id = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"title" && title.is_empty() => in_title = true,
+            Event::End(e) if e.local_name().as_ref() == b"title" => in_title = false,
+            Event::Text(e) if in_title => title.push_str(&e.unescape_with(resolve_html5_entity).unwrap_or_default()),
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"item" => {
+                    if let (Some(id), Some(href)) = (attribute(&e, b"id"), attribute(&e, b"href")) {
+                        manifest.insert(
+                            id,
+                            ManifestItem {
+                                href: resolve_href(&base, &href),
+                                media_type: attribute(&e, b"media-type").unwrap_or_default(),
+                                properties: attribute(&e, b"properties").unwrap_or_default(),
+                            },
+                        );
+                    }
+                }
+                b"spine" => ncx_id = attribute(&e, b"toc"),
+                b"itemref" if attribute(&e, b"linear").as_deref() != Some("no") => {
+                    spine.extend(attribute(&e, b"idref"));
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    let title = Some(clean_text(&title).replace('\n', " ")).filter(|t| !t.is_empty()).unwrap_or_else(|| file_title.to_string());
+
+    // 优先使用 EPUB 3 的导航文档，没有时使用 NCX
+    let toc_item = manifest
+        .values()
+        .find(|item| item.properties.split_whitespace().any(|p| p == "nav"))
+        .or_else(|| ncx_id.as_ref().and_then(|id| manifest.get(id)))
+        .or_else(|| manifest.values().find(|item| item.media_type == "application/x-dtbncx+xml"));
+    let toc = match toc_item {
+        Some(item) => {
+            let toc_base = item.href.rsplit_once('/').map_or("", |(dir, _)| dir).to_string();
+            match read_epub_entry(&mut archive, &item.href) {
+                Ok(xml) => read_toc(&xml, &toc_base),
+                Err(e) => {
+                    warn!(error = %e, "EPUB 目录读取失败");
+                    HashMap::new()
+                }
+            }
+        }
+        None => HashMap::new(),
+    };
+
+    let mut chapters: Vec<(String, String)> = Vec::new();
+    let mut in_chapter = false;
+    for id in &spine {
+        let Some(item) = manifest.get(id) else {
+            continue;
+        };
+        if !item.media_type.contains("html") {
+            continue;
+        }
+        let (text, heading) = xhtml_to_text(&read_epub_entry(&mut archive, &item.href)?);
+        match toc.get(&item.href) {
+            Some(chapter_title) => {
+                chapters.push((chapter_title.clone(), text));
+                in_chapter = true;
+            }
+            None if in_chapter => {
+                if let Some((_, body)) = chapters.last_mut() {
+                    body.push('\n');
+                    body.push_str(&text);
+                }
+            }
+            None => {
+                let chapter_title = heading.unwrap_or_else(|| format!("{} {}", title, chapters.len() + 1));
+                chapters.push((chapter_title, text));
+            }
+        }
+    }
+    Ok((title, chapters))
+}
+
+/// 读取文件，按章节生成待添加的课文
+///
+/// 支持 `.txt`、`.md`、`.epub`、`.srt` 和 `.vtt`。纯文本按“第一章”“Chapter 1”等标题拆分，
+/// Markdown 按标题拆分，EPUB 按目录拆分，字幕整个文件为一篇。正文只保留文字，可以直接用于配音。
+///
+/// # Arguments
+///
+/// * `path` - 文件路径
+/// * `max_chars` - 每篇课文的最大字数，超过时按行拆成多篇，0 表示不拆分
+///
+/// # Returns
+///
+/// * 成功返回解析结果
+/// * 格式不支持、文件过大、无法解析或没有文字时返回 `Err(AppError)`
+pub fn parse_file(path: &Path, max_chars: usize) -> Result<ParsedDocument, AppError> {
+    let format = DocumentFormat::from_path(path)
+        .ok_or_else(|| AppError::InvalidArgument(Msg::new("document.unsupported").arg("path", path.display())))?;
+    let name = path.display().to_string();
+    if fs::metadata(path)?.len() > MAX_FILE_SIZE {
+        return Err(AppError::InvalidArgument(Msg::new("document.too_large").arg("name", name)));
+    }
+    let file_title = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let (encoding, title, sections) = if format == DocumentFormat::Epub {
+        let (title, chapters) = split_epub(path, &file_title)?;
+        (UTF_8.name(), title, chapters)
+    } else {
+        let (text, encoding) = decode_text(&fs::read(path)?);
+        let (title, sections) = match format {
+            DocumentFormat::Markdown => {
+                let (title, sections) = split_markdown(&text, &file_title);
+                (title, sections.into_iter().map(|(t, body)| (t, markdown_to_text(&body))).collect())
+            }
+            DocumentFormat::Srt | DocumentFormat::Vtt => (file_title.clone(), vec![(file_title, subtitle_to_text(&text))]),
+            _ => (file_title.clone(), split_txt(&text, &file_title)),
+        };
+        (encoding, title, sections)
+    };
+
+    let lessons: Vec<LessonDraft> = sections
+        .iter()
+        .flat_map(|(title, content)| make_lessons(title, content, max_chars))
+        .collect();
+    if lessons.is_empty() {
+        return Err(AppError::InvalidArgument(Msg::new("document.empty").arg("name", name)));
+    }
+    Ok(ParsedDocument {
+        format,
+        encoding: encoding.to_string(),
+        title: clean_text(&title).replace('\n', " "),
+        lessons,
+    })
+}
+
+/// 从文件导入课文
+///
+/// 只解析文件，不写入数据库，返回的每篇课文在添加课文页面确认后配音添加
+///
+/// # Arguments
+///
+/// * `path` - `.txt`、`.md`、`.epub`、`.srt` 或 `.vtt` 文件的路径
+/// * `max_chars` - 每篇课文的最大字数，默认为3000，0 表示不拆分
+///
+/// # Returns
+///
+/// * 成功返回 `Ok(CustomResult::success)`，`data` 为识别出的格式、编码、标题和各篇课文
+/// * 失败返回 `Err(CustomResult)`
+#[tauri::command]
+#[tracing::instrument]
+pub async fn parse_lesson_file(path: String, max_chars: Option<usize>) -> Result<CustomResult, CustomResult> {
+    // 读取和解析大文件较慢，不占用异步运行时的线程
+    let document =
+        tauri::async_runtime::spawn_blocking(move || parse_file(Path::new(&path), max_chars.unwrap_or(DEFAULT_MAX_CHARS)))
+            .await
+            .map_err(|e| AppError::Io(Msg::new("error.io").arg("detail", e)))??;
+    info!(
+        format = ?document.format,
+        encoding = %document.encoding,
+        lessons = document.lessons.len(),
+        "已解析课文文件"
+    );
+
+    Ok(CustomResult::success(None, Some(json!(document))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    /// “我们今天学习中文。”的 GBK 编码
+    const GBK_TEXT: &[u8] = b"\xce\xd2\xc3\xc7\xbd\xf1\xcc\xec\xd1\xa7\xcf\xb0\xd6\xd0\xce\xc4\xa1\xa3";
+    /// “こんにちは、日本語の勉強です。”的 Shift-JIS 编码，按 GBK 也能解码
+    const SHIFT_JIS_TEXT: &[u8] =
+        b"\x82\xb1\x82\xf1\x82\xc9\x82\xbf\x82\xcd\x81\x41\x93\xfa\x96\x7b\x8c\xea\x82\xcc\x95\xd7\x8b\xad\x82\xc5\x82\xb7\x81\x42";
+
+    #[test]
+    fn decode_text_detects_encodings() {
+        assert_eq!(decode_text("中文 text".as_bytes()), ("中文 text".to_string(), "UTF-8"));
+        assert_eq!(decode_text(b"\xef\xbb\xbfBOM"), ("BOM".to_string(), "UTF-8"));
+        assert_eq!(decode_text(b"\xff\xfeh\x00i\x00"), ("hi".to_string(), "UTF-16LE"));
+        assert!(GBK.decode_without_bom_handling_and_without_replacement(SHIFT_JIS_TEXT).is_some());
+
+        assert_eq!(decode_text(GBK_TEXT), ("我们今天学习中文。".to_string(), "GBK"));
+        assert_eq!(decode_text(SHIFT_JIS_TEXT), ("こんにちは、日本語の勉強です。".to_string(), "Shift_JIS"));
+    }
+
+    #[test]
+    fn looks_japanese_counts_kana() {
+        assert!(looks_japanese("こんにちは、日本語の勉強です。"));
+        assert!(looks_japanese("漢字だけ"));
+        assert!(!looks_japanese("我们今天学习中文。"));
+        // 中文按 Shift-JIS 解码后的样子：半角片假名夹着汉字
+        let (misread, _) = SHIFT_JIS.decode_without_bom_handling(GBK_TEXT);
+        assert!(!looks_japanese(&misread));
+        assert!(!looks_japanese("ascii only"));
+    }
+
+    #[test]
+    fn split_txt_on_chapter_headings() {
+        let text = "开头的说明\n第一章 开始\n正文一\n  Chapter 2 The End  \nText two\n第一章节的内容很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长很长\n";
+        let sections = split_txt(text, "book");
+        let titles: Vec<&str> = sections.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(titles, ["book", "第一章 开始", "Chapter 2 The End"]);
+        assert_eq!(sections[0].1, "开头的说明\n");
+        assert_eq!(sections[1].1, "正文一\n");
+        // 过长的行不是标题
+        assert!(sections[2].1.starts_with("Text two\n第一章节"));
+
+        assert_eq!(split_txt("no headings\n", "book"), vec![("book".to_string(), "no headings\n".to_string())]);
+    }
+
+    #[test]
+    fn split_markdown_on_top_headings() {
+        // 唯一的一级标题作为文档标题，在二级标题处拆分，代码块中的 # 不是标题
+        let text = "# The Book\n## One\n**Bold** text\n```\n# not a heading\n```\n## Two\nmore\n";
+        let (title, sections) = split_markdown(text, "file");
+        assert_eq!(title, "The Book");
+        assert_eq!(
+            sections,
+            vec![
+                ("The Book".to_string(), String::new()),
+                ("One".to_string(), "**Bold** text\n```\n# not a heading\n```\n".to_string()),
+                ("Two".to_string(), "more\n".to_string()),
+            ]
+        );
+        assert_eq!(markdown_to_text(&sections[1].1), "Bold text\n");
+
+        // 多个一级标题时在一级标题处拆分，文档标题为文件名
+        let (title, sections) = split_markdown("intro\n# A\n## A.1\na\n# B\nb\n", "file");
+        assert_eq!(title, "file");
+        let titles: Vec<&str> = sections.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(titles, ["file", "A", "B"]);
+        assert_eq!(sections[1].1, "## A.1\na\n");
+    }
+
+    #[test]
+    fn subtitle_to_text_joins_sentences() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n<i>Hello</i>\nworld.\n\n2\n00:00:03,000 --> 00:00:04,000\n- How are\n- you?\n\n3\n00:00:05,000 --> 00:00:06,000\n{\\an8}Fine &amp; you\n";
+        assert_eq!(subtitle_to_text(srt), "Hello world.\nHow are you?\nFine & you");
+
+        // 分隔的空行中有空格，或只用 CR 换行，也不会把下一条的序号和时间轴当作文字
+        let spaced = srt.replace("\n\n", "\n  \t\n");
+        assert_eq!(subtitle_to_text(&spaced), "Hello world.\nHow are you?\nFine & you");
+        assert_eq!(subtitle_to_text(&srt.replace('\n', "\r")), "Hello world.\nHow are you?\nFine & you");
+        assert_eq!(subtitle_to_text(&spaced.replace('\n', "\r\n")), "Hello world.\nHow are you?\nFine & you");
+
+        let vtt = "WEBVTT\n\nNOTE 注释\n\nSTYLE\n::cue { color: red }\n\nintro\n00:01.000 --> 00:02.000 align:start\n<c.red>こんにちは。</c>\n\n00:02.000 --> 00:03.000\n<00:02.500>こんにちは。\n\n00:03.000 --> 00:04.000\n日本語の\n勉強です。\n";
+        assert_eq!(subtitle_to_text(vtt), "こんにちは。\n日本語の勉強です。");
+    }
+
+    /// 写入只有必需文件的 EPUB
+    fn write_epub(path: &Path, files: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn xhtml(title: &str, body: &str) -> String {
+        format!("<?xml version=\"1.0\"?><html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title>{}</title><style>p {{}}</style></head><body>{}</body></html>", title, body)
+    }
+
+    #[test]
+    fn split_epub_follows_spine_and_toc() {
+        let dir = fixtures::temp_dir("epub");
+        let path = dir.join("book.epub");
+        let container = r#"<?xml version="1.0"?><container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#;
+        let opf = r#"<?xml version="1.0"?><package><metadata><dc:title>My &amp; Book</dc:title></metadata><manifest>
+            <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+            <item id="cover" href="text/cover.xhtml" media-type="application/xhtml+xml"/>
+            <item id="c1" href="text/ch%201.xhtml" media-type="application/xhtml+xml"/>
+            <item id="c1b" href="text/ch1b.xhtml" media-type="application/xhtml+xml"/>
+            <item id="c2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+            <item id="img" href="images/a.png" media-type="image/png"/>
+            </manifest><spine><itemref idref="cover"/><itemref idref="nav" linear="no"/><itemref idref="c1"/>
+            <itemref idref="c1b"/><itemref idref="img"/><itemref idref="c2"/></spine></package>"#;
+        let nav = r#"<?xml version="1.0"?><html><body><nav><ol><li><a href="text/ch%201.xhtml#start">Chapter  One</a></li>
+            <li><a href="text/ch2.xhtml">Chapter Two</a></li></ol></nav></body></html>"#;
+        write_epub(
+            &path,
+            &[
+                ("META-INF/container.xml", container),
+                ("OEBPS/content.opf", opf),
+                ("OEBPS/nav.xhtml", nav),
+                ("OEBPS/text/cover.xhtml", &xhtml("Cover", "<h1>Welcome</h1><p>Cover page</p>")),
+                ("OEBPS/text/ch 1.xhtml", &xhtml("c1", "<h2>1</h2><p>First\n    part.</p><p>Second<br/>line</p>")),
+                ("OEBPS/text/ch1b.xhtml", &xhtml("c1b", "<p>Continued &amp; done.</p>")),
+                ("OEBPS/text/ch2.xhtml", &xhtml("c2", "<p>Last <ruby>漢<rt>かん</rt></ruby>字.</p>")),
+            ],
+        );
+
+        let (title, chapters) = split_epub(&path, "book").unwrap();
+        assert_eq!(title, "My & Book");
+        let chapters: Vec<(String, String)> = chapters.into_iter().map(|(t, body)| (t, clean_text(&body))).collect();
+        assert_eq!(
+            chapters,
+            vec![
+                ("Welcome".to_string(), "Welcome\n\nCover page".to_string()),
+                ("Chapter One".to_string(), "1\n\nFirst part.\n\nSecond\nline\n\nContinued & done.".to_string()),
+                ("Chapter Two".to_string(), "Last 漢字.".to_string()),
+            ]
+        );
+
+        let document = parse_file(&path, 0).unwrap();
+        assert_eq!((document.format, document.lessons.len()), (DocumentFormat::Epub, 3));
+
+        fs::write(&path, b"not a zip").unwrap();
+        assert!(matches!(split_epub(&path, "book"), Err(AppError::InvalidArgument(msg)) if msg.key == "document.invalid_epub"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
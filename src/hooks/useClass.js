@@ -1,6 +1,5 @@
-import { insert, select, update, deleteData } from "../utils/sqlite";
-import { remove } from "@tauri-apps/plugin-fs";
-import useWord from "./useWord";
+import { invoke } from '@tauri-apps/api/core';
+import { insert, select, update } from "../utils/sqlite";
 
 export default ()=>{
 
@@ -42,34 +41,83 @@ export default ()=>{
         return update("class", {translation: translation}, "id = ?", [id]);
     }
 
-    const deleteClass = (filePath, audioFileName, audioSrtJsonName, id)=>{
-        const audioFilePath = filePath + "/" + audioFileName,
-        audioSrtJsonPath = filePath + "/" + audioSrtJsonName;
-        const {deleteWordsByClassId, deleteWordBase64ByClassID} = useWord();
+    /**
+     * 删除课文，课文记录、单词、单词音频和音频字幕文件夹一起删除
+     * @param {number} id 课题ID
+     * @returns {Promise<Object>} 实际删除的内容
+     */
+    const deleteClass = (id)=>{
+        return new Promise((resolve, reject) => {
+            invoke('delete_lesson', {lessonId: id}).then((response)=>{
+                resolve(response.data);
+            }).catch((error)=>{
+                reject(error.msg || error);
+            });
+        })
+    }
+
+    /**
+     * 导出课程包
+     * @param {Array<number>} ids 需要导出的课文ID
+     * @param {string} path 课程包的保存路径
+     * @returns {Promise<Object>} 导出的统计
+     */
+    const exportLessons = (ids, path) => {
+        return new Promise((resolve, reject) => {
+            invoke('export_lessons', {lessonIds: ids, path}).then((response)=>{
+                resolve(response.data);
+            }).catch((error)=>{
+                reject(error.msg || error);
+            });
+        })
+    }
+
+    /**
+     * 导入课程包
+     * @param {string} path 课程包路径
+     * @param {string} policy 已有同名课文时的处理方式，skip 跳过，keep_both 同样导入
+     * @returns {Promise<Object>} 导入的统计和冲突
+     */
+    const importLessons = (path, policy = "skip") => {
+        return new Promise((resolve, reject) => {
+            invoke('import_lessons', {path, policy}).then((response)=>{
+                resolve(response.data);
+            }).catch((error)=>{
+                reject(error.msg || error);
+            });
+        })
+    }
+
+    /**
+     * 导出单词到 Anki 牌组或表格
+     * @param {Object} source 导出范围，{lesson: 课文ID} 或 {language: 语言ID}
+     * @param {string} path 保存路径
+     * @param {Object} options 导出选项，format 为 apkg、csv 或 tsv
+     * @returns {Promise<Object>} 导出的统计
+     */
+    const exportVocabulary = (source, path, options = {}) => {
+        return new Promise((resolve, reject) => {
+            invoke('export_vocabulary', {source, path, options}).then((response)=>{
+                resolve(response.data);
+            }).catch((error)=>{
+                reject(error.msg || error);
+            });
+        })
+    }
 
+    /**
+     * 从文件读取待添加的课文
+     * @param {string} path txt、md、epub、srt 或 vtt 文件的路径
+     * @param {number} maxChars 每篇课文的最大字数，不传时使用默认值，0 表示不拆分
+     * @returns {Promise<Object>} 识别出的格式、编码、标题和各篇课文
+     */
+    const parseLessonFile = (path, maxChars) => {
         return new Promise((resolve, reject) => {
-            // 删除字幕文件
-            remove(audioSrtJsonPath).then(()=>{
-                // 删除音频
-                return remove(audioFilePath);
-            }).then(()=>{
-                // 删除文件夹
-                return remove(filePath);
-            }).then(()=>{
-                // 文件删除成功，删除数据库
-                // 删除所有单词配音数据
-                return deleteWordBase64ByClassID(id);
-            }).then(()=>{
-                // 删除所有单词数据
-                return deleteWordsByClassId(id);
-            }).then(()=>{
-                // 删除课文
-                return deleteData("class", "id = ?", [id]);
-            }).then(()=>{
-                resolve();
+            invoke('parse_lesson_file', {path, maxChars}).then((response)=>{
+                resolve(response.data);
             }).catch((error)=>{
-                reject(error)
-            })
+                reject(error.msg || error);
+            });
         })
     }
 
@@ -81,6 +129,10 @@ export default ()=>{
         getALLClassBaseInfo,
         getALLClassBaseInfoByLanguageId,
         editTranslationById,
-        deleteClass
+        deleteClass,
+        exportLessons,
+        importLessons,
+        exportVocabulary,
+        parseLessonFile
     }
 }
\ No newline at end of file
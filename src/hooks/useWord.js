@@ -1,8 +1,12 @@
-import { insert, select, update, deleteData } from "../utils/sqlite";
+import { invoke } from '@tauri-apps/api/core';
+import { insert, select, update } from "../utils/sqlite";
 import { useLanguagesStore } from "../store/languages";
-import { useVoicesStore } from "../store/voices";
+import { readFile } from '@tauri-apps/plugin-fs';
 import { show_loading } from "../utils/function";
 
+// 已读取的单词音频地址，键为配音员和单词，同一个单词不重复读取文件
+const wordAudioUrls = new Map();
+
 export default ()=>{
 
     const addWord = (languageId, classId, data, sort) => {
@@ -12,19 +16,31 @@ export default ()=>{
         );
     }
 
-    const addWords = (languageId, classId, array) => {
-        return new Promise(async (resolve, reject) => {
-            try {
-                for(let i = 0; i < array.length; i++) {
-                    await addWord(languageId, classId, array[i], i);
-                }
-                resolve();
-            } catch (error) {
-                reject(error)
-            }
+    /**
+     * 保存课文的全部单词，新增、修改和删除在同一个事务中完成
+     * @param {number} classId 课题ID
+     * @param {Array} array 完整的单词列表，顺序即排序，id为0的单词为新增
+     * @param {Array<number>} deleIdArray 需要删除的单词ID
+     * @returns {Promise<Array<number>>} 每个单词的ID
+     */
+    const saveWords = (classId, array, deleIdArray) => {
+        return new Promise((resolve, reject) => {
+            invoke('save_lesson_words', {
+                lessonId: classId,
+                words: array,
+                deletedIds: deleIdArray,
+            }).then((response)=>{
+                resolve(response.data.ids);
+            }).catch((error)=>{
+                reject(error.msg || error);
+            });
         })
     }
 
+    const addWords = (languageId, classId, array) => {
+        return saveWords(classId, array, []);
+    }
+
     const getWordByWord = (word) => {
         return select("word", ["id", "inlineId", "oartOfSpeech", "pronunciation", "interpretation", "other", "spell"], "content = ? AND applicable = ? LIMIT 1", [word, 1]);
     }
@@ -47,88 +63,56 @@ export default ()=>{
         }, "id = ?", [id]);
     }
 
-    const deleWordById = (id) => {
-        return deleteData("word", "id = ?", [id]);
-    }
-
     const editWords = (data, deleIdArray, languageId, classId) => {
-        return new Promise(async (resolve, reject) => {
-            try {
-                for(let i = 0; i < data.length; i++){
-                    const item = data[i];
-                    // 判断ID
-                    if(item.id == 0){
-                        // 新增
-                        await addWord(languageId, classId, item, i);
-                    }else{
-                        // 修改
-                        await updateWordById(item.id, item, i);
-                    }
-                    
-                }
-                for(let i = 0; i < deleIdArray.length; i++){
-                    await deleWordById(deleIdArray[i]);
-                }
-                resolve();
-            } catch (error) {
-                reject(error)
-            }
-        })
+        return saveWords(classId, data, deleIdArray);
     }
 
-    const deleteWordsByClassId = (classId) => {
-        return deleteData("word", "classId = ?", [classId]);
-    }
-
-    const getWordBase64ByWord = (word, languageId, classId) =>{
+    /**
+     * 获取单词音频，后端按配音员和单词缓存，没有缓存时先配音
+     * @param {string} word 单词
+     * @param {number} languageId 语言ID，使用该语言的配音员
+     * @returns {Promise<string>} 可以直接作为audio的src的地址
+     */
+    const getWordAudioByWord = (word, languageId) =>{
         return new Promise((resolve, reject) => {
-            let base64 = "";
-            // 先从数据库wordBase64表获取
-            select("wordBase64", ["base64"],"word = ?", [word]).then(async (result)=>{
-                if(result.rows.length == 0){
-                    // 数据库中没有单词音频，配音
-                    const loadingObj = show_loading("正在配音……");
-                    const languagesStore = useLanguagesStore();
-                    const languageItem = languagesStore.getItemById(languageId);
-                    const voicesStore = useVoicesStore();
-                    try {
-                        const audioResult = await voicesStore.startTTS(languageItem.voice, word, false);
-                        if(audioResult.code == 200){
-                            // 配音成功，保存数据
-                            base64 = audioResult.data.audio;
-                            await insert("wordBase64", ["classId", "word", "base64"], [classId, word, base64]);
-
-                            resolve(base64);
-                        }else{
-                            throw Error(result.msg);
-                        }
-                    } catch (error) {
-                        reject(error);
-                    }
+            const languagesStore = useLanguagesStore();
+            const languageItem = languagesStore.getItemById(languageId);
+            const key = languageItem.voice + "\n" + word;
+            if(wordAudioUrls.has(key)){
+                resolve(wordAudioUrls.get(key));
+                return;
+            }
 
+            // 有缓存时很快返回，超过300毫秒才显示配音提示
+            let loadingObj = null;
+            const timer = setTimeout(()=>{
+                loadingObj = show_loading("正在配音……");
+            }, 300);
+            invoke('get_word_audio', {voice: languageItem.voice, text: word}).then((response)=>{
+                return readFile(response.data.path);
+            }).then((data)=>{
+                const url = URL.createObjectURL(new Blob([data], {type: "audio/mpeg"}));
+                wordAudioUrls.set(key, url);
+                resolve(url);
+            }).catch((error)=>{
+                reject(error.msg || error);
+            }).finally(()=>{
+                clearTimeout(timer);
+                if(loadingObj){
                     loadingObj.close();
-                }else{
-                    resolve(result.rows[0].base64);
                 }
-            }).catch((error)=>{
-                reject(error)
             })
         })
     }
 
-    const deleteWordBase64ByClassID = (id) => {
-        return deleteData("wordBase64", "classId = ?", [id]);
-    }
-
     return {
         addWord,
         addWords,
+        saveWords,
         getWordByWord,
         getWordsByClassId,
         updateWordById,
         editWords,
-        deleteWordsByClassId,
-        getWordBase64ByWord,
-        deleteWordBase64ByClassID
+        getWordAudioByWord
     }
 }
\ No newline at end of file
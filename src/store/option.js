@@ -52,15 +52,6 @@ export const useOptionStore = defineStore("option", {
         saveAIOption(datas){
             return this.saveOption(datas);
         },
-        getAIPromptOption(){
-            return {
-                annotation: this.option.annotationPrompt,
-                translation: this.option.translationPrompt
-            }
-        },
-        saveAIPromptOption(datas){
-            return this.saveOption(datas);
-        },
         getSoftOption(){
             return {
                 annotationRule: this.option.annotationRule,
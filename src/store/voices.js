@@ -264,14 +264,12 @@ export const useVoicesStore = defineStore("voices", {
                     return;
                 }
                 try {
-                    const root_path = localStorage.getItem("exe_path") == undefined ? "" : localStorage.getItem("exe_path");
                     const result = invoke("start_tts", {data: {
                         voice: voice,
                         text: text,
                         pitch: 0,
                         rate: 0,
                         volume: 0,
-                        root_path: root_path,
                         save_file
                     }})
 
@@ -97,23 +97,6 @@ function readClassData(classInfo){
     })
 }
 
-function getDefaultPrompt(){
-    return{
-        annotation: `你是一个语言专家，用户会把单词以数组形式发给你，请你以简体中文的形式返回给用户，并解释单词词性、单词读音、单词中文释义、附加说明、以及其适用性是仅限于本课文输出0，该语言通用输出1。
-以如下格式返回，每个单词之前用2个换行隔开，不要输出多余的话：
-单词：单词内容
-词性：可选输出 noun,numeral,measure_word,verb,adjective,distinguishing_word,adverb,conjunction,preposition,auxiliary,modal_particle,phrase,sentence_fragment,pronoun,interjection,onomatopoeia,morpheme,other
-读音：
-中文释义：
-附加说明：
-适用性：可选输出 0,1
-
-单词：\${var}`,
-        translation: `你可以把世界各国的语言翻译成简体中文，请翻译下面的文章，只输出译文，不要回答无关的信息
-文章：\${var}`
-    }
-}
-
 export {
-    show_error, show_loading, deepCopy, stringToBoolean, readClassData, getDefaultPrompt
+    show_error, show_loading, deepCopy, stringToBoolean, readClassData
 }
\ No newline at end of file
@@ -3,136 +3,7 @@ import Database from '@tauri-apps/plugin-sql';
 let db = null;
 let isConnect = false;
 
-// 基础的数据表
-const databseTable = [
-    {
-        name: 'language',
-        sql: `CREATE TABLE language(
-            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-            title TEXT NOT NULL,
-            languageText TEXT NOT NULL,
-            voice TEXT NOT NULL,
-            lastViewId INTEGER DEFAULT 0,
-            createTime TEXT DEFAULT (datetime('now', 'localtime'))
-        );
-        -- COMMENT ON TABLE language IS '存储语言的表';
-        -- COMMENT ON COLUMN language.id IS '主键';
-        -- COMMENT ON COLUMN language.title IS '语言名称';
-        -- COMMENT ON COLUMN language.language IS '语言代码';
-        -- COMMENT ON COLUMN language.voice IS '语音名称';
-        -- COMMENT ON COLUMN language.lastViewId IS '最后查看的课文ID';
-        -- COMMENT ON COLUMN language.createTime IS '创建时间';`
-    },{
-        name: 'class',
-        sql: `CREATE TABLE \`class\`(
-            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-            languageId INTEGER NOT NULL,
-            isFinish INTEGER DEFAULT 0,
-            title TEXT NOT NULL,
-            content TEXT NOT NULL,
-            translation TEXT,
-            filePath TEXT NOT NULL,
-            audioFileName TEXT NOT NULL,
-            audioSrtJsonName TEXT NOT NULL,
-            createTime TEXT DEFAULT (datetime('now', 'localtime'))
-        );
-        -- COMMENT ON TABLE \`class\` IS '存储课文的表';
-        -- COMMENT ON COLUMN \`class\`.id IS '主键';
-        -- COMMENT ON COLUMN \`class\`.languageId IS '语言ID';
-        -- COMMENT ON COLUMN \`class\`.isFinish IS '是否创建完成，0未完成，1已完成';
-        -- COMMENT ON COLUMN \`class\`.title IS '课题名称';
-        -- COMMENT ON COLUMN \`class\`.content IS '课题完整的正文';
-        -- COMMENT ON COLUMN \`class\`.translation IS '课题的翻译';
-        -- COMMENT ON COLUMN \`class\`.filePath IS '音频和字幕文件路径';
-        -- COMMENT ON COLUMN \`class\`.audioFileName IS '音频文件名称';
-        -- COMMENT ON COLUMN \`class\`.audioSrtJsonName IS '音频字幕JSON文件名称';
-        -- COMMENT ON COLUMN \`class\`.createTime IS '创建时间';`
-    },{
-        name: 'word',
-        sql: `CREATE TABLE word(
-            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-            classId INTEGER NOT NULL,
-            -- 冗余languageId，此表后续数据量会达到几十万，并且查询 languageId 下的通用单词是一个高频操作，在这种读密集型的应用场景下，查询性能是最应该优先考虑的因素
-            languageId INTEGER NOT NULL,
-            inlineId INTEGER,
-            content TEXT,
-            oartOfSpeech TEXT,
-            pronunciation TEXT,
-            interpretation TEXT,
-            other TEXT,
-            applicable INTEGER DEFAULT 1,
-            spell INTEGER DEFAULT 1,
-            startIndex INTEGER NOT NULL,
-            sort INTEGER DEFAULT 0,
-            createTime TEXT DEFAULT (datetime('now', 'localtime'))
-        );
-        -- 设置查询索引
-        CREATE INDEX idx_word_languageId_applicable ON word (languageId, applicable);
-        CREATE INDEX idx_word_classId ON word (classId);
-        -- COMMENT ON TABLE word IS '存储单词的表';
-        -- COMMENT ON COLUMN word.id IS '主键';
-        -- COMMENT ON COLUMN word.classId IS '课题ID';
-        -- COMMENT ON COLUMN word.languageId IS '语言ID';
-        -- CoMMENT ON COLUMN word.inlineId IS '单词的ID，用于适配同样的单词，避免重复添加';
-        -- COMMENT ON COLUMN word.content IS '单词';
-        -- COMMENT ON COLUMN word.oartOfSpeech IS '词性';
-        -- COMMENT ON COLUMN word.pronunciation IS '单词读音';
-        -- COMMENT ON COLUMN word.interpretation IS '单词意思';
-        -- COMMENT ON COLUMN word.other IS '单词附加说明';
-        -- COMMENT ON COLUMN word.applicable IS '单词的适用性，0只适用本课，1适用本语言全部课文';
-        -- COMMENT ON COLUMN word.spell IS '单词是否可以背写';
-        -- COMMENT ON COLUMN word.startIndex IS '单词在字幕文件中的数组的下标';
-        -- COMMENT ON COLUMN word.sort IS '排序';
-        -- COMMENT ON COLUMN word.createTime IS '创建时间';`
-    },{
-        name: 'option',
-        sql: `CREATE TABLE option(
-            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-            nowAiPlatform TEXT DEFAULT 'ChatGLM',
-            ChatGLM TEXT,
-            DeepSeek TEXT,
-            Groq TEXT,
-            Google TEXT,
-            ChatGPT TEXT,
-            annotationPrompt TEXT,
-            translationPrompt TEXT,
-            annotationRule TEXT DEFAULT 'skip',
-            annotationNumber INTEGER DEFAULT 20,
-            showOartOfSpeech INTEGER DEFAULT 1,
-            playSpeed TEXT DEFAULT '1.0'
-        );
-        -- COMMENT ON TABLE option IS '存储设置的表';
-        -- COMMENT ON COLUMN option.id IS '主键';
-        -- COMMENT ON COLUMN option.nowAiPlatform IS '当前使用的AI平台';
-        -- COMMENT ON COLUMN option.ChatGLM IS '存储ChatGLM的详细设置';
-        -- COMMENT ON COLUMN option.DeepSeek IS '存储DeepSeek的详细设置';
-        -- COMMENT ON COLUMN option.Groq IS '存储Groq的详细设置';
-        -- COMMENT ON COLUMN option.Google IS '存储Google的详细设置';
-        -- COMMENT ON COLUMN option.ChatGPT IS '存储ChatGPT的详细设置';
-        -- COMMENT ON COLUMN option.annotationPrompt IS '单词标注的AI提示词';
-        -- COMMENT ON COLUMN option.translationPrompt IS '翻译的AI提示词';
-        -- COMMENT ON COLUMN option.annotationRule IS 'AI标注单词的规则';
-        -- COMMENT ON COLUMN option.annotationNumber IS 'AI单词标注并发数量';
-        -- COMMENT ON COLUMN option.showOartOfSpeech IS '是否显示词性条-在显示页面单词下方的不同颜色的条块';
-        -- COMMENT ON COLUMN option.playSpeed IS '播放速度';
-        `
-    },{
-        name: 'wordBase64',
-        sql: `CREATE TABLE wordBase64(
-            id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
-            classId INTEGER NOT NULL,
-            word TEXT NOT NULL UNIQUE,
-            base64 TEXT NOT NULL
-        );
-        -- 设置wrod索引
-        CREATE INDEX idx_wordBase64_word ON wordBase64 (word);
-        -- COMMENT ON TABLE wordBase64 IS '存储单词音频的表';
-        -- COMMENT ON COLUMN wordBase64.id IS '主键';
-        -- COMMENT ON COLUMN wordBase64.classId IS '课文ID，仅用于删除课文时使用';
-        -- COMMENT ON COLUMN wordBase64.word IS '单词';
-        -- COMMENT ON COLUMN wordBase64.base64 IS '单词音频的base64';`
-    }
-]
+// 数据表由Rust端在启动时创建和升级（src-tauri/src/utils/db），前端只负责读写
 
 function connect(){
     return new Promise(async (resolve, reject) => {
@@ -141,15 +12,6 @@ function connect(){
         }else{
             try {
                 db = await Database.load('sqlite:database.db');
-                // 初始化数据库
-                for (let i = 0; i < databseTable.length; i++) {
-                    // 初始化数据库，如果出错，则立即中止，并退出程序
-                    await init(databseTable[i].name, databseTable[i].sql).catch((err) => {
-                        reject(err);
-                        return;
-                    });
-                }
-                // 初始化成功，连接成功
                 isConnect = true;
                 resolve();
 
@@ -160,27 +22,6 @@ function connect(){
     })
 }
 
-// 数据库初始化
-function init(name, sql) {
-    return new Promise(async function (resolve, reject) {
-        try {
-            const result = await db.select("SELECT name FROM sqlite_master WHERE type='table' AND name=$1", [name]);
-            if(result.length == 0){
-                // 如果表不存在，则创建表
-                await db.execute(sql);
-                if(name == "option"){
-                    // 设置表必须有一条初始数据
-                    await insert(name, ["nowAiPlatform"], ["ChatGLM"]);
-                }
-            }
-            resolve();
-        } catch (error) {
-            reject(error);
-        }
-    });
-}
-
-
 function disConnect(){
     return new Promise(async (resolve, reject) => {
         try {